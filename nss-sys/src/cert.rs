@@ -2,12 +2,15 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
 
-use libc::{c_char, c_uint, c_int, c_void};
-use nspr::{PLArenaPool, PRBool, PRUint32, PRCList};
+use libc::{c_char, c_uchar, c_uint, c_int, c_ulong, c_void, size_t};
+use nspr::{PLArenaPool, PRBool, PRInt32, PRInt64, PRUint32, PRUint64, PRCList, PRTime};
 use super::{SECItem, SECAlgorithmID, NSSTrustDomainStr, NSSCertificateStr, PK11SlotInfo, CK_OBJECT_HANDLE, SECStatus};
+use pk11::{SECKEYPrivateKey, SECKEYPublicKey, SECOidTag};
+use std::mem::ManuallyDrop;
 
 pub type CERTCertList = CERTCertListStr;
 pub type CERTCertListNode = CERTCertListNodeStr;
+pub type CERTCertificateList = CERTCertificateListStr;
 pub type CERTCertificate = CERTCertificateStr;
 pub type CERTSignedData = CERTSignedDataStr;
 pub type CERTName = CERTNameStr;
@@ -23,6 +26,18 @@ pub type CERTSubjectList = CERTSubjectListStr;
 pub type CERTSubjectNode = CERTSubjectNodeStr;
 pub type CERTAuthKeyID = CERTAuthKeyIDStr;
 pub type CERTGeneralName = CERTGeneralNameStr;
+pub type CERTDistNames = CERTDistNamesStr;
+pub type CERTOCSPResponse = CERTOCSPResponseStr;
+pub type CERTVerifyLog = CERTVerifyLogStr;
+pub type SECCertificateUsage = PRInt64;
+pub type SEC_OcspFailureMode = c_uint;
+pub type CERTOCSPCertID = CERTOCSPCertIDStr;
+pub type CERTSignedCrl = CERTSignedCrlStr;
+pub type CERTCrl = CERTCrlStr;
+pub type CERTCrlEntry = CERTCrlEntryStr;
+pub type CERTCertificateRequest = CERTCertificateRequestStr;
+pub type CERTAttribute = CERTAttributeStr;
+pub type CERTBasicConstraints = CERTBasicConstraintsStr;
 
 #[derive(Debug)]
 #[repr(C)]
@@ -39,6 +54,14 @@ pub struct CERTCertListNodeStr {
     pub appData: *mut c_void,
 }
 
+#[derive(Debug)]
+#[repr(C)]
+pub struct CERTCertificateListStr {
+    pub certs: *mut SECItem,
+    pub len: c_int,
+    pub arena: *mut PLArenaPool,
+}
+
 #[derive(Debug)]
 #[repr(C)]
 pub struct CERTCertificateStr {
@@ -100,6 +123,41 @@ pub struct CERTSignedDataStr {
     pub signature: SECItem,
 }
 
+#[derive(Debug)]
+#[repr(C)]
+pub struct CERTCrlStr {
+    pub arena: *mut PLArenaPool,
+    pub version: SECItem,
+    pub signatureAlg: SECAlgorithmID,
+    pub derName: SECItem,
+    pub name: CERTName,
+    pub lastUpdate: SECItem,
+    pub nextUpdate: SECItem,
+    pub entries: *mut *mut CERTCrlEntry,
+    pub extensions: *mut *mut CERTCertExtension,
+}
+
+pub enum CERTCrlEntryStr { }
+
+#[derive(Debug)]
+#[repr(C)]
+pub struct CERTSignedCrlStr {
+    pub arena: *mut PLArenaPool,
+    pub crl: CERTCrl,
+    pub reserved1: *mut c_void,
+    pub reserved2: PRBool,
+    pub isperm: PRBool,
+    pub istemp: PRBool,
+    pub referenceCount: c_int,
+    pub dbhandle: *mut CERTCertDBHandle,
+    pub signatureWrap: CERTSignedData,
+    pub url: *mut c_char,
+    pub derCrl: *mut SECItem,
+    pub slot: *mut PK11SlotInfo,
+    pub pkcs11ID: CK_OBJECT_HANDLE,
+    pub opaque: *mut c_void,
+}
+
 #[derive(Debug)]
 #[repr(C)]
 pub struct CERTNameStr {
@@ -160,13 +218,24 @@ pub struct CERTOKDomainNameStr {
 
 #[derive(Debug)]
 #[repr(C)]
-// This might need some accompanying enums....
 pub struct CERTCertTrustStr {
     pub sslFlags: c_uint,
     pub emailFlags: c_uint,
     pub objectSigningFlags: c_uint,
 }
 
+// The bits in each of those (certdb.h).
+pub const CERTDB_TERMINAL_RECORD: c_uint = 1 << 0;
+pub const CERTDB_TRUSTED: c_uint = 1 << 1;
+pub const CERTDB_SEND_WARN: c_uint = 1 << 2;
+pub const CERTDB_VALID_CA: c_uint = 1 << 3;
+pub const CERTDB_TRUSTED_CA: c_uint = 1 << 4;
+pub const CERTDB_NS_TRUSTED_CA: c_uint = 1 << 5;
+pub const CERTDB_USER: c_uint = 1 << 6;
+pub const CERTDB_TRUSTED_CLIENT_CA: c_uint = 1 << 7;
+
+pub const CK_INVALID_HANDLE: CK_OBJECT_HANDLE = 0;
+
 #[derive(Debug)]
 #[repr(C)]
 pub struct CERTSubjectListStr {
@@ -196,11 +265,424 @@ pub struct CERTAuthKeyIDStr {
     pub DERAuthCertIssuer: *mut *mut SECItem,
 }
 
-// FIXME -- has union
-pub enum CERTGeneralNameStr { }
+pub type CERTGeneralNameType = c_uint;
+pub const certOtherName: CERTGeneralNameType = 1;
+pub const certRFC822Name: CERTGeneralNameType = 2;
+pub const certDNSName: CERTGeneralNameType = 3;
+pub const certX400Address: CERTGeneralNameType = 4;
+pub const certDirectoryName: CERTGeneralNameType = 5;
+pub const certEDIPartyName: CERTGeneralNameType = 6;
+pub const certURI: CERTGeneralNameType = 7;
+pub const certIPAddress: CERTGeneralNameType = 8;
+pub const certRegisterID: CERTGeneralNameType = 9;
+
+#[derive(Debug)]
+#[repr(C)]
+pub struct OtherName {
+    pub name: SECItem,
+    pub oid: SECItem,
+}
+
+#[repr(C)]
+pub union CERTGeneralNameValue {
+    pub directoryName: ManuallyDrop<CERTName>,
+    pub OthName: ManuallyDrop<OtherName>,
+    pub other: ManuallyDrop<SECItem>,
+}
+
+// A node in a circular list, linked through `l`.
+#[repr(C)]
+pub struct CERTGeneralNameStr {
+    pub type_: CERTGeneralNameType,
+    pub name: CERTGeneralNameValue,
+    pub derDirectoryName: SECItem,
+    pub l: PRCList,
+}
+
+pub enum CERTDistNamesStr { }
+
+#[derive(Debug)]
+#[repr(C)]
+pub struct CERTCertificateRequestStr {
+    pub arena: *mut PLArenaPool,
+    pub version: SECItem,
+    pub subject: CERTName,
+    pub subjectPublicKeyInfo: CERTSubjectPublicKeyInfo,
+    pub attributes: *mut *mut CERTAttribute,
+}
+
+#[derive(Debug)]
+#[repr(C)]
+pub struct CERTAttributeStr {
+    pub attrType: SECItem,
+    pub attrValue: *mut *mut SECItem,
+}
+
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct CERTBasicConstraintsStr {
+    pub isCA: PRBool,
+    pub pathLenConstraint: c_int,
+}
+
+#[derive(Debug)]
+#[repr(C)]
+pub struct CERTPolicyQualifier {
+    pub oid: SECOidTag,
+    pub qualifierID: SECItem,
+    pub qualifierValue: SECItem,
+}
+
+#[derive(Debug)]
+#[repr(C)]
+pub struct CERTPolicyInfo {
+    pub oid: SECOidTag,
+    pub policyID: SECItem,
+    pub policyQualifiers: *mut *mut CERTPolicyQualifier,
+}
+
+#[derive(Debug)]
+#[repr(C)]
+pub struct CERTCertificatePolicies {
+    pub arena: *mut PLArenaPool,
+    pub policyInfos: *mut *mut CERTPolicyInfo,
+}
+
+// Only ever passed by address, to the ASN.1 encoder.
+#[repr(C)]
+pub struct SEC_ASN1Template(c_void);
+
+pub const SEC_OID_X509_KEY_USAGE: SECOidTag = 81;
+pub const SEC_OID_X509_SUBJECT_ALT_NAME: SECOidTag = 83;
+pub const SEC_OID_X509_BASIC_CONSTRAINTS: SECOidTag = 85;
+pub const SEC_OID_X509_CERTIFICATE_POLICIES: SECOidTag = 88;
+
+// These are only defined in private headers.
+pub enum CERTOCSPResponseStr { }
+pub enum CERTOCSPCertIDStr { }
+
+pub const SEC_CRL_TYPE: c_int = 1;
+
+// FIXME: has a public definition, but nothing uses it yet.
+pub enum CERTVerifyLogStr { }
+
+pub const certificateUsageSSLClient: SECCertificateUsage = 0x0001;
+pub const certificateUsageSSLServer: SECCertificateUsage = 0x0002;
+pub const certificateUsageSSLServerWithStepUp: SECCertificateUsage = 0x0004;
+pub const certificateUsageSSLCA: SECCertificateUsage = 0x0008;
+pub const certificateUsageEmailSigner: SECCertificateUsage = 0x0010;
+pub const certificateUsageEmailRecipient: SECCertificateUsage = 0x0020;
+pub const certificateUsageObjectSigner: SECCertificateUsage = 0x0040;
+pub const certificateUsageUserCertImport: SECCertificateUsage = 0x0080;
+pub const certificateUsageVerifyCA: SECCertificateUsage = 0x0100;
+pub const certificateUsageProtectedObjectSigner: SECCertificateUsage = 0x0200;
+pub const certificateUsageStatusResponder: SECCertificateUsage = 0x0400;
+pub const certificateUsageAnyCA: SECCertificateUsage = 0x0800;
+pub const certificateUsageIPsec: SECCertificateUsage = 0x1000;
+
+// nsCertType bits (certt.h); NSS also derives these from extendedKeyUsage.
+pub const NS_CERT_TYPE_SSL_CLIENT: PRUint32 = 0x80;
+pub const NS_CERT_TYPE_SSL_SERVER: PRUint32 = 0x40;
+pub const NS_CERT_TYPE_EMAIL: PRUint32 = 0x20;
+pub const NS_CERT_TYPE_OBJECT_SIGNING: PRUint32 = 0x10;
+
+pub type CERTValParamInType = c_uint;
+pub const cert_pi_end: CERTValParamInType = 0;
+pub const cert_pi_nbioContext: CERTValParamInType = 1;
+pub const cert_pi_nbioAbort: CERTValParamInType = 2;
+pub const cert_pi_certList: CERTValParamInType = 3;
+pub const cert_pi_policyOID: CERTValParamInType = 4;
+pub const cert_pi_policyFlags: CERTValParamInType = 5;
+pub const cert_pi_keyusage: CERTValParamInType = 6;
+pub const cert_pi_extendedKeyusage: CERTValParamInType = 7;
+pub const cert_pi_date: CERTValParamInType = 8;
+pub const cert_pi_revocationFlags: CERTValParamInType = 9;
+pub const cert_pi_certStores: CERTValParamInType = 10;
+pub const cert_pi_trustAnchors: CERTValParamInType = 11;
+pub const cert_pi_useAIACertFetch: CERTValParamInType = 12;
+pub const cert_pi_chainVerifyCallback: CERTValParamInType = 13;
+pub const cert_pi_useOnlyTrustAnchors: CERTValParamInType = 14;
+
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub union CERTValParamInScalar {
+    pub b: PRBool,
+    pub i: PRInt32,
+    pub ui: PRUint32,
+    pub l: PRInt64,
+    pub ul: PRUint64,
+    pub time: PRTime,
+}
+
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub union CERTValParamInPointer {
+    pub p: *const c_void,
+    pub s: *const c_char,
+    pub cert: *const CERTCertificate,
+    pub chain: *const CERTCertList,
+    pub revocation: *const CERTRevocationFlags,
+}
+
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub union CERTValParamInArray {
+    pub pi: *const PRInt32,
+    pub pui: *const PRUint32,
+    pub pl: *const PRInt64,
+    pub pul: *const PRUint64,
+}
+
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub struct CERTValParamInValue {
+    pub scalar: CERTValParamInScalar,
+    pub pointer: CERTValParamInPointer,
+    pub array: CERTValParamInArray,
+    pub arraySize: c_int,
+}
+
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub struct CERTValInParam {
+    pub type_: CERTValParamInType,
+    pub value: CERTValParamInValue,
+}
+
+pub type CERTValParamOutType = c_uint;
+pub const cert_po_end: CERTValParamOutType = 0;
+pub const cert_po_nbioContext: CERTValParamOutType = 1;
+pub const cert_po_trustAnchor: CERTValParamOutType = 2;
+pub const cert_po_certList: CERTValParamOutType = 3;
+pub const cert_po_policyOID: CERTValParamOutType = 4;
+pub const cert_po_errorLog: CERTValParamOutType = 5;
+pub const cert_po_usages: CERTValParamOutType = 6;
+pub const cert_po_keyUsage: CERTValParamOutType = 7;
+pub const cert_po_extendedKeyusage: CERTValParamOutType = 8;
+
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub union CERTValParamOutScalar {
+    pub b: PRBool,
+    pub i: PRInt32,
+    pub ui: PRUint32,
+    pub l: PRInt64,
+    pub ul: PRUint64,
+    pub usages: SECCertificateUsage,
+}
+
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub union CERTValParamOutPointer {
+    pub p: *mut c_void,
+    pub s: *mut c_char,
+    pub log: *mut CERTVerifyLog,
+    pub cert: *mut CERTCertificate,
+    pub chain: *mut CERTCertList,
+}
+
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub union CERTValParamOutArray {
+    pub p: *mut c_void,
+    pub usages: *mut SECCertificateUsage,
+}
+
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub struct CERTValParamOutValue {
+    pub scalar: CERTValParamOutScalar,
+    pub pointer: CERTValParamOutPointer,
+    pub array: CERTValParamOutArray,
+    pub arraySize: c_int,
+}
+
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub struct CERTValOutParam {
+    pub type_: CERTValParamOutType,
+    pub value: CERTValParamOutValue,
+}
+
+pub type CERTRevocationMethodIndex = c_uint;
+pub const cert_revocation_method_crl: CERTRevocationMethodIndex = 0;
+pub const cert_revocation_method_ocsp: CERTRevocationMethodIndex = 1;
+pub const cert_revocation_method_count: CERTRevocationMethodIndex = 2;
+
+pub const CERT_REV_M_DO_NOT_TEST_USING_THIS_METHOD: PRUint64 = 0;
+pub const CERT_REV_M_TEST_USING_THIS_METHOD: PRUint64 = 1;
+pub const CERT_REV_M_ALLOW_NETWORK_FETCHING: PRUint64 = 0;
+pub const CERT_REV_M_FORBID_NETWORK_FETCHING: PRUint64 = 2;
+pub const CERT_REV_M_ALLOW_IMPLICIT_DEFAULT_SOURCE: PRUint64 = 0;
+pub const CERT_REV_M_IGNORE_IMPLICIT_DEFAULT_SOURCE: PRUint64 = 4;
+pub const CERT_REV_M_SKIP_TEST_ON_MISSING_SOURCE: PRUint64 = 0;
+pub const CERT_REV_M_REQUIRE_INFO_ON_MISSING_SOURCE: PRUint64 = 8;
+pub const CERT_REV_M_IGNORE_MISSING_FRESH_INFO: PRUint64 = 0;
+pub const CERT_REV_M_FAIL_ON_MISSING_FRESH_INFO: PRUint64 = 16;
+pub const CERT_REV_M_STOP_TESTING_ON_FRESH_INFO: PRUint64 = 0;
+pub const CERT_REV_M_CONTINUE_TESTING_ON_FRESH_INFO: PRUint64 = 32;
+pub const CERT_REV_M_FORCE_POST_METHOD_FOR_OCSP: PRUint64 = 64;
+
+pub const CERT_REV_MI_TEST_EACH_METHOD_SEPARATELY: PRUint64 = 0;
+pub const CERT_REV_MI_TEST_ALL_LOCAL_INFORMATION_FIRST: PRUint64 = 1;
+pub const CERT_REV_MI_NO_OVERALL_INFO_REQUIREMENT: PRUint64 = 0;
+pub const CERT_REV_MI_REQUIRE_SOME_FRESH_INFO_AVAILABLE: PRUint64 = 2;
+
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+pub struct CERTRevocationTests {
+    pub number_of_defined_methods: PRUint32,
+    pub cert_rev_flags_per_method: *mut PRUint64,
+    pub number_of_preferred_methods: PRUint32,
+    pub preferred_methods: *mut CERTRevocationMethodIndex,
+    pub cert_rev_method_independent_flags: PRUint64,
+}
+
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+pub struct CERTRevocationFlags {
+    pub leafTests: CERTRevocationTests,
+    pub chainTests: CERTRevocationTests,
+}
+
+pub const ocspMode_FailureIsVerificationFailure: SEC_OcspFailureMode = 0;
+pub const ocspMode_FailureIsNotAVerificationFailure: SEC_OcspFailureMode = 1;
 
 extern "C" {
+    pub static CERT_CertificateTemplate: SEC_ASN1Template;
+    pub static CERT_CertificateRequestTemplate: SEC_ASN1Template;
+    pub static CERT_SignedDataTemplate: SEC_ASN1Template;
+
+    pub fn CERT_GetDefaultCertDB() -> *mut CERTCertDBHandle;
+    pub fn CERT_NewTempCertificate(handle: *mut CERTCertDBHandle, derCert: *mut SECItem,
+                                   nickname: *mut c_char, isperm: PRBool, copyDER: PRBool)
+                                   -> *mut CERTCertificate;
     pub fn CERT_DestroyCertificate(cert: *mut CERTCertificate);
+    pub fn CERT_DupCertificate(cert: *mut CERTCertificate) -> *mut CERTCertificate;
+    pub fn CERT_ExtractPublicKey(cert: *mut CERTCertificate) -> *mut SECKEYPublicKey;
     pub fn CERT_DestroyCertList(cert: *mut CERTCertList);
+    pub fn CERT_NewCertList() -> *mut CERTCertList;
+    pub fn CERT_AddCertToListTail(certs: *mut CERTCertList, cert: *mut CERTCertificate)
+                                  -> SECStatus;
+    pub fn CERT_GetCommonName(name: *const CERTName) -> *mut c_char;
+    pub fn CERT_GetCountryName(name: *const CERTName) -> *mut c_char;
+    pub fn CERT_GetLocalityName(name: *const CERTName) -> *mut c_char;
+    pub fn CERT_GetStateName(name: *const CERTName) -> *mut c_char;
+    pub fn CERT_GetOrgName(name: *const CERTName) -> *mut c_char;
+    pub fn CERT_GetOrgUnitName(name: *const CERTName) -> *mut c_char;
+    pub fn CERT_GetDomainComponentName(name: *const CERTName) -> *mut c_char;
+    pub fn CERT_GetCertUid(name: *const CERTName) -> *mut c_char;
+    pub fn CERT_GetCertEmailAddress(name: *const CERTName) -> *mut c_char;
+    pub fn CERT_VerifyCertificate(handle: *mut CERTCertDBHandle, cert: *mut CERTCertificate,
+                                  checkSig: PRBool, requiredUsages: SECCertificateUsage,
+                                  t: PRTime, wincx: *mut c_void, log: *mut CERTVerifyLog,
+                                  returnedUsages: *mut SECCertificateUsage) -> SECStatus;
+    pub fn CERT_PKIXVerifyCert(cert: *mut CERTCertificate, usages: SECCertificateUsage,
+                               paramsIn: *mut CERTValInParam, paramsOut: *mut CERTValOutParam,
+                               wincx: *mut c_void) -> SECStatus;
+    pub fn CERT_EnableOCSPChecking(handle: *mut CERTCertDBHandle) -> SECStatus;
+    pub fn CERT_DisableOCSPChecking(handle: *mut CERTCertDBHandle) -> SECStatus;
+    pub fn CERT_SetOCSPFailureMode(ocspFailureMode: SEC_OcspFailureMode) -> SECStatus;
+    pub fn CERT_SetOCSPTimeout(seconds: PRUint32) -> SECStatus;
+    pub fn CERT_SetOCSPDefaultResponder(handle: *mut CERTCertDBHandle, url: *const c_char,
+                                        name: *const c_char) -> SECStatus;
+    pub fn CERT_EnableOCSPDefaultResponder(handle: *mut CERTCertDBHandle) -> SECStatus;
+    pub fn CERT_DisableOCSPDefaultResponder(handle: *mut CERTCertDBHandle) -> SECStatus;
+    pub fn CERT_DecodeDERCrl(arena: *mut PLArenaPool, derSignedCrl: *mut SECItem, type_: c_int)
+                             -> *mut CERTSignedCrl;
+    pub fn CERT_CacheCRL(dbhandle: *mut CERTCertDBHandle, newcrl: *mut SECItem) -> SECStatus;
+    pub fn CERT_UncacheCRL(dbhandle: *mut CERTCertDBHandle, oldcrl: *mut SECItem) -> SECStatus;
+    pub fn CERT_FindCertByDERCert(handle: *mut CERTCertDBHandle, derCert: *mut SECItem)
+                                  -> *mut CERTCertificate;
+    pub fn CERT_FindCertByName(handle: *mut CERTCertDBHandle, name: *mut SECItem)
+                               -> *mut CERTCertificate;
+    pub fn CERT_VerifySignedData(sd: *mut CERTSignedData, cert: *mut CERTCertificate, t: PRTime,
+                                 wincx: *mut c_void) -> SECStatus;
+    pub fn SEC_DestroyCrl(crl: *mut CERTSignedCrl) -> SECStatus;
+    pub fn CERT_FindCertByNickname(handle: *mut CERTCertDBHandle, nickname: *const c_char)
+                                   -> *mut CERTCertificate;
+    pub fn PK11_ImportCert(slot: *mut PK11SlotInfo, cert: *mut CERTCertificate,
+                           key: CK_OBJECT_HANDLE, nickname: *const c_char, includeTrust: PRBool)
+                           -> SECStatus;
+    pub fn PK11_FindKeyByAnyCert(cert: *mut CERTCertificate, wincx: *mut c_void)
+                                 -> *mut SECKEYPrivateKey;
+    pub fn PK11_ListCertsInSlot(slot: *mut PK11SlotInfo) -> *mut CERTCertList;
+    pub fn CERT_DecodeTrustString(trust: *mut CERTCertTrust, trusts: *const c_char) -> SECStatus;
+    pub fn CERT_ChangeCertTrust(handle: *mut CERTCertDBHandle, cert: *mut CERTCertificate,
+                                trust: *mut CERTCertTrust) -> SECStatus;
+    pub fn CERT_DecodeOCSPResponse(src: *const SECItem) -> *mut CERTOCSPResponse;
+    pub fn CERT_DestroyOCSPResponse(response: *mut CERTOCSPResponse);
+    pub fn CERT_GetOCSPResponseStatus(response: *mut CERTOCSPResponse) -> SECStatus;
+    pub fn CERT_VerifyOCSPResponseSignature(response: *mut CERTOCSPResponse,
+                                            handle: *mut CERTCertDBHandle, pwArg: *mut c_void,
+                                            pSignerCert: *mut *mut CERTCertificate,
+                                            issuerCert: *mut CERTCertificate) -> SECStatus;
+    pub fn CERT_CreateOCSPCertID(cert: *mut CERTCertificate, time: PRTime)
+                                 -> *mut CERTOCSPCertID;
+    pub fn CERT_DestroyOCSPCertID(certID: *mut CERTOCSPCertID) -> SECStatus;
+    pub fn CERT_GetOCSPStatusForCertID(handle: *mut CERTCertDBHandle,
+                                       response: *mut CERTOCSPResponse,
+                                       certID: *mut CERTOCSPCertID,
+                                       signerCert: *mut CERTCertificate,
+                                       time: PRTime) -> SECStatus;
+    pub fn CERT_GetCertTimes(c: *const CERTCertificate, notBefore: *mut PRTime,
+                             notAfter: *mut PRTime) -> SECStatus;
+    pub fn CERT_CheckCertUsage(cert: *mut CERTCertificate, usage: c_uchar) -> SECStatus;
     pub fn CERT_VerifyCertName(cert: *const CERTCertificate, hn: *const c_char) -> SECStatus;
+    pub fn CERT_FindCertExtension(cert: *const CERTCertificate, tag: c_int, value: *mut SECItem)
+                                  -> SECStatus;
+    pub fn CERT_DecodeCertificatePoliciesExtension(extnValue: *const SECItem)
+                                                   -> *mut CERTCertificatePolicies;
+    pub fn CERT_DestroyCertificatePoliciesExtension(policies: *mut CERTCertificatePolicies);
+    pub fn CERT_GetOidString(oid: *const SECItem) -> *mut c_char;
+    pub fn CERT_AsciiToName(string: *const c_char) -> *mut CERTName;
+    pub fn CERT_DestroyName(name: *mut CERTName);
+    pub fn CERT_CreateCertificateRequest(name: *mut CERTName, spki: *mut CERTSubjectPublicKeyInfo,
+                                         attributes: *mut *mut SECItem)
+                                         -> *mut CERTCertificateRequest;
+    pub fn CERT_DestroyCertificateRequest(r: *mut CERTCertificateRequest);
+    pub fn CERT_StartCertificateRequestAttributes(req: *mut CERTCertificateRequest)
+                                                  -> *mut c_void;
+    pub fn CERT_FinishCertificateRequestAttributes(req: *mut CERTCertificateRequest)
+                                                   -> SECStatus;
+    pub fn CERT_GetCertificateRequestExtensions(req: *mut CERTCertificateRequest,
+                                                exts: *mut *mut *mut CERTCertExtension)
+                                                -> SECStatus;
+    pub fn CERT_NameToAscii(name: *mut CERTName) -> *mut c_char;
+    pub fn CERT_VerifySignedDataWithPublicKey(sd: *const CERTSignedData,
+                                              pubKey: *mut SECKEYPublicKey, wincx: *mut c_void)
+                                              -> SECStatus;
+    pub fn SEC_QuickDERDecodeItem(arena: *mut PLArenaPool, dest: *mut c_void,
+                                  t: *const SEC_ASN1Template, src: *const SECItem) -> SECStatus;
+    pub fn PORT_NewArena(chunksize: c_ulong) -> *mut PLArenaPool;
+    pub fn PORT_FreeArena(arena: *mut PLArenaPool, zero: PRBool);
+    pub fn PORT_ArenaZAlloc(arena: *mut PLArenaPool, size: size_t) -> *mut c_void;
+    pub fn SEC_ASN1DecodeItem(pool: *mut PLArenaPool, dest: *mut c_void,
+                              t: *const SEC_ASN1Template, src: *const SECItem) -> SECStatus;
+    pub fn CERT_CreateValidity(notBefore: PRTime, notAfter: PRTime) -> *mut CERTValidity;
+    pub fn CERT_DestroyValidity(v: *mut CERTValidity);
+    pub fn CERT_CreateCertificate(serialNumber: c_ulong, issuer: *mut CERTName,
+                                  validity: *mut CERTValidity, req: *mut CERTCertificateRequest)
+                                  -> *mut CERTCertificate;
+    pub fn CERT_StartCertExtensions(cert: *mut CERTCertificate) -> *mut c_void;
+    pub fn CERT_AddExtension(exthandle: *mut c_void, idtag: c_int, value: *mut SECItem,
+                             critical: PRBool, copyData: PRBool) -> SECStatus;
+    pub fn CERT_EncodeAndAddBitStrExtension(exthandle: *mut c_void, idtag: c_int,
+                                            value: *mut SECItem, critical: PRBool) -> SECStatus;
+    pub fn CERT_FinishExtensions(exthandle: *mut c_void) -> SECStatus;
+    pub fn CERT_EncodeAltNameExtension(arena: *mut PLArenaPool, value: *mut CERTGeneralName,
+                                       encodedValue: *mut SECItem) -> SECStatus;
+    pub fn CERT_DecodeAltNameExtension(reqArena: *mut PLArenaPool, EncodedAltName: *mut SECItem)
+                                       -> *mut CERTGeneralName;
+    pub fn CERT_GetNextGeneralName(current: *mut CERTGeneralName) -> *mut CERTGeneralName;
+    pub fn CERT_EncodeBasicConstraintValue(arena: *mut PLArenaPool,
+                                           value: *mut CERTBasicConstraints,
+                                           encodedValue: *mut SECItem) -> SECStatus;
+    pub fn SECKEY_CreateSubjectPublicKeyInfo(k: *const SECKEYPublicKey)
+                                             -> *mut CERTSubjectPublicKeyInfo;
+    pub fn SECKEY_DestroySubjectPublicKeyInfo(spki: *mut CERTSubjectPublicKeyInfo);
+    pub fn SECKEY_ExtractPublicKey(spki: *const CERTSubjectPublicKeyInfo)
+                                   -> *mut SECKEYPublicKey;
+    pub fn SEC_ASN1EncodeItem(pool: *mut PLArenaPool, dest: *mut SECItem, src: *const c_void,
+                              t: *const SEC_ASN1Template) -> *mut SECItem;
 }
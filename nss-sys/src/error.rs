@@ -343,4 +343,38 @@ pub const SSL_ERROR_RX_UNEXPECTED_HELLO_RETRY_REQUEST: PRErrorCode = -12134;
 pub const SSL_ERROR_RX_MALFORMED_HELLO_RETRY_REQUEST: PRErrorCode = -12133;
 pub const SSL_ERROR_BAD_2ND_CLIENT_HELLO: PRErrorCode = -12132;
 pub const SSL_ERROR_MISSING_SIGNATURE_ALGORITHMS_EXTENSION: PRErrorCode = -12131;
-pub const SSL_ERROR_END_OF_LIST: PRErrorCode = -12130;
+pub const SSL_ERROR_MALFORMED_PSK_KEY_EXCHANGE_MODES: PRErrorCode = -12130;
+pub const SSL_ERROR_MISSING_PSK_KEY_EXCHANGE_MODES: PRErrorCode = -12129;
+pub const SSL_ERROR_DOWNGRADE_WITH_EARLY_DATA: PRErrorCode = -12128;
+pub const SSL_ERROR_TOO_MUCH_EARLY_DATA: PRErrorCode = -12127;
+pub const SSL_ERROR_RX_UNEXPECTED_END_OF_EARLY_DATA: PRErrorCode = -12126;
+pub const SSL_ERROR_RX_MALFORMED_END_OF_EARLY_DATA: PRErrorCode = -12125;
+pub const SSL_ERROR_UNSUPPORTED_EXPERIMENTAL_API: PRErrorCode = -12124;
+pub const SSL_ERROR_APPLICATION_ABORT: PRErrorCode = -12123;
+pub const SSL_ERROR_APP_CALLBACK_ERROR: PRErrorCode = -12122;
+pub const SSL_ERROR_NO_TIMERS_FOUND: PRErrorCode = -12121;
+pub const SSL_ERROR_MISSING_COOKIE_EXTENSION: PRErrorCode = -12120;
+pub const SSL_ERROR_RX_UNEXPECTED_KEY_UPDATE: PRErrorCode = -12119;
+pub const SSL_ERROR_RX_MALFORMED_KEY_UPDATE: PRErrorCode = -12118;
+pub const SSL_ERROR_TOO_MANY_KEY_UPDATES: PRErrorCode = -12117;
+pub const SSL_ERROR_HANDSHAKE_FAILED: PRErrorCode = -12116;
+pub const SSL_ERROR_BAD_RESUMPTION_TOKEN_ERROR: PRErrorCode = -12115;
+pub const SSL_ERROR_RX_MALFORMED_DTLS_ACK: PRErrorCode = -12114;
+pub const SSL_ERROR_DH_KEY_TOO_LONG: PRErrorCode = -12113;
+pub const SSL_ERROR_RX_MALFORMED_ESNI_KEYS: PRErrorCode = -12112;
+pub const SSL_ERROR_RX_MALFORMED_ESNI_EXTENSION: PRErrorCode = -12111;
+pub const SSL_ERROR_MISSING_ESNI_EXTENSION: PRErrorCode = -12110;
+pub const SSL_ERROR_RX_UNEXPECTED_RECORD_TYPE: PRErrorCode = -12109;
+pub const SSL_ERROR_MISSING_POST_HANDSHAKE_AUTH_EXTENSION: PRErrorCode = -12108;
+pub const SSL_ERROR_RX_CERTIFICATE_REQUIRED_ALERT: PRErrorCode = -12107;
+pub const SSL_ERROR_DC_CERT_VERIFY_ALG_MISMATCH: PRErrorCode = -12106;
+pub const SSL_ERROR_DC_BAD_SIGNATURE: PRErrorCode = -12105;
+pub const SSL_ERROR_DC_INVALID_KEY_USAGE: PRErrorCode = -12104;
+pub const SSL_ERROR_DC_EXPIRED: PRErrorCode = -12103;
+pub const SSL_ERROR_DC_INAPPROPRIATE_VALIDITY_PERIOD: PRErrorCode = -12102;
+pub const SSL_ERROR_FEATURE_DISABLED: PRErrorCode = -12101;
+pub const SSL_ERROR_ECH_RETRY_WITH_ECH: PRErrorCode = -12100;
+pub const SSL_ERROR_ECH_RETRY_WITHOUT_ECH: PRErrorCode = -12099;
+pub const SSL_ERROR_ECH_FAILED: PRErrorCode = -12098;
+pub const SSL_ERROR_ECH_REQUIRED_ALERT: PRErrorCode = -12097;
+pub const SSL_ERROR_END_OF_LIST: PRErrorCode = -12096;
@@ -10,13 +10,40 @@ extern crate libc;
 pub mod cert;
 pub mod error;
 pub mod nspr;
+pub mod pk11;
 
-use libc::{c_char, c_uchar, c_uint, c_ulong, c_void};
-use nspr::{PRFileDesc, PRBool, PRInt32, PRUint16};
+use libc::{c_char, c_int, c_uchar, c_uint, c_ulong, c_void, size_t};
+use std::mem;
+use nspr::{PLArenaPool, PRFileDesc, PRBool, PRInt32, PRTime, PRUint16, PRUint32, PRUintn};
 
-pub use cert::{CERTCertificate, CERTCertList, CERTCertListNode,
-               CERT_DestroyCertificate, CERT_DestroyCertList,
-               CERT_VerifyCertName};
+pub use cert::{CERTCertificate, CERTCertificateList, CERTCertList, CERTCertListNode,
+               CERTDistNames, CERT_GetDefaultCertDB, CERT_NewTempCertificate,
+               CERT_DestroyCertificate, CERT_DupCertificate, CERT_DestroyCertList,
+               CERT_ExtractPublicKey, CERT_VerifyCertName, CERTName, CERT_GetCommonName,
+               CERT_GetCountryName, CERT_GetLocalityName, CERT_GetStateName, CERT_GetOrgName,
+               CERT_GetOrgUnitName, CERT_GetDomainComponentName, CERT_GetCertUid,
+               CERT_GetCertEmailAddress, CERTCertTrust,
+               CERT_DecodeTrustString, CERT_ChangeCertTrust,
+               CERTOCSPResponse, CERTOCSPCertID, CERT_DecodeOCSPResponse,
+               CERT_DestroyOCSPResponse, CERT_GetOCSPResponseStatus,
+               CERT_VerifyOCSPResponseSignature, CERT_CreateOCSPCertID, CERT_DestroyOCSPCertID,
+               CERT_GetOCSPStatusForCertID, CERTVerifyLog, SECCertificateUsage,
+               certificateUsageSSLClient, certificateUsageSSLServer, certificateUsageSSLCA,
+               certificateUsageEmailSigner, certificateUsageEmailRecipient,
+               certificateUsageObjectSigner, certificateUsageStatusResponder,
+               certificateUsageAnyCA, CERT_VerifyCertificate, SEC_OcspFailureMode,
+               ocspMode_FailureIsVerificationFailure, ocspMode_FailureIsNotAVerificationFailure,
+               CERT_EnableOCSPChecking, CERT_DisableOCSPChecking, CERT_SetOCSPFailureMode,
+               CERT_SetOCSPTimeout, CERT_SetOCSPDefaultResponder, CERT_EnableOCSPDefaultResponder,
+               CERT_DisableOCSPDefaultResponder, CERTSignedCrl, CERTCrl, SEC_CRL_TYPE,
+               CERT_DecodeDERCrl, CERT_PKIXVerifyCert, CERTValInParam, CERTValParamInValue,
+               CERTRevocationFlags, CERTRevocationTests,
+               CERT_CacheCRL, CERT_UncacheCRL, CERT_FindCertByName, CERT_FindCertByDERCert, CERT_VerifySignedData,
+               SEC_DestroyCrl};
+pub use pk11::{SECKEYPrivateKey, SECKEYPublicKey, PK11_GetInternalSlot, PK11_FreeSlot,
+               PK11_ImportDERPrivateKeyInfoAndReturnKey, SECKEY_DestroyPrivateKey,
+               SECKEY_CopyPrivateKey, SECKEY_ConvertToPublicKey, SECKEY_DestroyPublicKey,
+               SECKEY_EncodeDERSubjectPublicKeyInfo, SECOidTag, SEC_OID_SHA256, PK11_HashBuf};
 
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
 #[repr(C)]
@@ -82,6 +109,94 @@ pub type SSLAuthCertificate =
     Option<unsafe extern "C" fn(arg: *mut c_void, fd: *mut PRFileDesc,
                                 checkSig: PRBool, isServer: PRBool) -> SECStatus>;
 
+pub type SSLGetClientAuthData =
+    Option<unsafe extern "C" fn(arg: *mut c_void, fd: *mut PRFileDesc,
+                                caNames: *mut CERTDistNames,
+                                pRetCert: *mut *mut CERTCertificate,
+                                pRetKey: *mut *mut SECKEYPrivateKey) -> SECStatus>;
+
+pub type SSLCanFalseStartCallback =
+    Option<unsafe extern "C" fn(fd: *mut PRFileDesc, arg: *mut c_void,
+                                canFalseStart: *mut PRBool) -> SECStatus>;
+
+pub type SSLSNISocketConfig =
+    Option<unsafe extern "C" fn(fd: *mut PRFileDesc, srvNameArr: *const SECItem,
+                                srvNameArrSize: PRUint32, arg: *mut c_void) -> PRInt32>;
+pub const SSL_SNI_CURRENT_CONFIG_IS_USED: PRInt32 = -1;
+pub const SSL_SNI_SEND_ALERT: PRInt32 = -2;
+
+pub type SSLTimeFunc = Option<unsafe extern "C" fn(arg: *mut c_void) -> PRTime>;
+
+pub type SSLResumptionTokenCallback =
+    Option<unsafe extern "C" fn(fd: *mut PRFileDesc, resumptionToken: *const u8,
+                                len: c_uint, ctx: *mut c_void) -> SECStatus>;
+
+pub type SSLHandshakeCallback =
+    Option<unsafe extern "C" fn(fd: *mut PRFileDesc, client_data: *mut c_void)>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub struct SSLAlert {
+    pub level: u8,
+    pub description: u8,
+}
+
+pub type SSLAlertCallback =
+    Option<unsafe extern "C" fn(fd: *const PRFileDesc, arg: *mut c_void, alert: *const SSLAlert)>;
+
+pub type SSLExtensionType = c_uint;
+pub const ssl_server_name_xtn: SSLExtensionType = 0;
+pub const ssl_cert_status_xtn: SSLExtensionType = 5;
+pub const ssl_supported_groups_xtn: SSLExtensionType = 10;
+pub const ssl_ec_point_formats_xtn: SSLExtensionType = 11;
+pub const ssl_signature_algorithms_xtn: SSLExtensionType = 13;
+pub const ssl_use_srtp_xtn: SSLExtensionType = 14;
+pub const ssl_app_layer_protocol_xtn: SSLExtensionType = 16;
+pub const ssl_signed_cert_timestamp_xtn: SSLExtensionType = 18;
+pub const ssl_padding_xtn: SSLExtensionType = 21;
+pub const ssl_extended_master_secret_xtn: SSLExtensionType = 23;
+pub const ssl_record_size_limit_xtn: SSLExtensionType = 28;
+pub const ssl_session_ticket_xtn: SSLExtensionType = 35;
+pub const ssl_tls13_pre_shared_key_xtn: SSLExtensionType = 41;
+pub const ssl_tls13_early_data_xtn: SSLExtensionType = 42;
+pub const ssl_tls13_supported_versions_xtn: SSLExtensionType = 43;
+pub const ssl_tls13_cookie_xtn: SSLExtensionType = 44;
+pub const ssl_tls13_psk_key_exchange_modes_xtn: SSLExtensionType = 45;
+pub const ssl_tls13_certificate_authorities_xtn: SSLExtensionType = 47;
+pub const ssl_tls13_post_handshake_auth_xtn: SSLExtensionType = 49;
+pub const ssl_signature_algorithms_cert_xtn: SSLExtensionType = 50;
+pub const ssl_tls13_key_share_xtn: SSLExtensionType = 51;
+pub const ssl_renegotiation_info_xtn: SSLExtensionType = 0xff01;
+
+pub enum SSLAntiReplayContext { }
+
+// From pk11hpke.h, for ECH.
+pub type HpkeKemId = c_uint;
+pub const HpkeDhKemP256Sha256: HpkeKemId = 0x10;
+pub const HpkeDhKemX25519Sha256: HpkeKemId = 0x20;
+pub type HpkeKdfId = c_uint;
+pub const HpkeKdfHkdfSha256: HpkeKdfId = 1;
+pub const HpkeKdfHkdfSha384: HpkeKdfId = 2;
+pub const HpkeKdfHkdfSha512: HpkeKdfId = 3;
+pub type HpkeAeadId = c_uint;
+pub const HpkeAeadAes128Gcm: HpkeAeadId = 1;
+pub const HpkeAeadAes256Gcm: HpkeAeadId = 2;
+pub const HpkeAeadChaCha20Poly1305: HpkeAeadId = 3;
+
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+pub struct HpkeSymmetricSuite {
+    pub kdfId: HpkeKdfId,
+    pub aeadId: HpkeAeadId,
+}
+
+pub type SSLNextProtoState = c_uint;
+pub const SSL_NEXT_PROTO_NO_SUPPORT: SSLNextProtoState = 0;
+pub const SSL_NEXT_PROTO_NEGOTIATED: SSLNextProtoState = 1;
+pub const SSL_NEXT_PROTO_NO_OVERLAP: SSLNextProtoState = 2;
+pub const SSL_NEXT_PROTO_SELECTED: SSLNextProtoState = 3;
+pub const SSL_NEXT_PROTO_EARLY_VALUE: SSLNextProtoState = 4;
+
 // Options:
 pub const SSL_SECURITY: PRInt32 = 1;
 pub const SSL_SOCKS: PRInt32 = 2;
@@ -115,6 +230,17 @@ pub const SSL_ENABLE_EXTENDED_MASTER_SECRET: PRInt32 = 30;
 pub const SSL_ENABLE_SIGNED_CERT_TIMESTAMPS: PRInt32 = 31;
 pub const SSL_REQUIRE_DH_NAMED_GROUPS: PRInt32 = 32;
 pub const SSL_ENABLE_0RTT_DATA: PRInt32 = 33;
+pub const SSL_ENABLE_TLS13_COMPAT_MODE: PRInt32 = 35;
+// Newer than some NSS versions this might be linked with; setting
+// them there fails with SEC_ERROR_INVALID_ARGS.
+pub const SSL_ENABLE_GREASE: PRInt32 = 42;
+pub const SSL_ENABLE_CH_EXTENSION_PERMUTATION: PRInt32 = 43;
+
+// Values for SSL_ENABLE_RENEGOTIATION:
+pub const SSL_RENEGOTIATE_NEVER: PRInt32 = 0;
+pub const SSL_RENEGOTIATE_UNRESTRICTED: PRInt32 = 1;
+pub const SSL_RENEGOTIATE_REQUIRES_XTN: PRInt32 = 2;
+pub const SSL_RENEGOTIATE_TRANSITIONAL: PRInt32 = 3;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(C)]
@@ -124,6 +250,24 @@ pub enum SSLProtocolVariant {
 }
 pub use self::SSLProtocolVariant::*;
 
+#[derive(Debug)]
+#[repr(C)]
+pub struct SSLExtraServerCertData {
+    pub authType: SSLAuthType,
+    pub certChain: *const CERTCertificateList,
+    pub stapledOCSPResponses: *const SECItemArray,
+    pub signedCertTimestamps: *const SECItem,
+    pub delegCred: *const SECItem,
+    pub delegCredPrivKey: *const SECKEYPrivateKey,
+}
+
+#[derive(Debug)]
+#[repr(C)]
+pub struct SECItemArray {
+    pub items: *mut SECItem,
+    pub len: c_uint,
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(C)]
 pub struct SSLVersionRangeStr {
@@ -131,6 +275,124 @@ pub struct SSLVersionRangeStr {
     pub max: PRUint16,
 }
 
+// C enums with open-ended or version-dependent sets of values; these
+// are kept as integers so that unknown values from a newer NSS are
+// harmless.
+pub type SSLKEAType = c_uint;
+pub type SSLNamedGroup = c_uint;
+pub type SSLCipherAlgorithm = c_uint;
+pub type SSLMACAlgorithm = c_uint;
+pub type SSLCompressionMethod = c_uint;
+pub type SSLPskType = c_uint;
+pub type SSLSignatureScheme = c_uint;
+pub type SSLAuthType = c_uint;
+
+pub const ssl_auth_null: SSLAuthType = 0;
+pub const ssl_auth_rsa_decrypt: SSLAuthType = 1;
+pub const ssl_auth_dsa: SSLAuthType = 2;
+pub const ssl_auth_kea: SSLAuthType = 3;
+pub const ssl_auth_ecdsa: SSLAuthType = 4;
+pub const ssl_auth_ecdh_rsa: SSLAuthType = 5;
+pub const ssl_auth_ecdh_ecdsa: SSLAuthType = 6;
+pub const ssl_auth_rsa_sign: SSLAuthType = 7;
+pub const ssl_auth_rsa_pss: SSLAuthType = 8;
+pub const ssl_auth_psk: SSLAuthType = 9;
+pub const ssl_auth_tls13_any: SSLAuthType = 10;
+
+pub const ssl_grp_ec_secp256r1: SSLNamedGroup = 23;
+pub const ssl_grp_ec_secp384r1: SSLNamedGroup = 24;
+pub const ssl_grp_ec_secp521r1: SSLNamedGroup = 25;
+pub const ssl_grp_ec_curve25519: SSLNamedGroup = 29;
+pub const ssl_grp_ffdhe_2048: SSLNamedGroup = 256;
+pub const ssl_grp_ffdhe_3072: SSLNamedGroup = 257;
+pub const ssl_grp_ffdhe_4096: SSLNamedGroup = 258;
+pub const ssl_grp_ffdhe_6144: SSLNamedGroup = 259;
+pub const ssl_grp_ffdhe_8192: SSLNamedGroup = 260;
+pub const ssl_grp_none: SSLNamedGroup = 65537;
+pub const ssl_grp_ffdhe_custom: SSLNamedGroup = 65538;
+
+// The older, DHE-only way of choosing groups.
+pub type SSLDHEGroupType = c_uint;
+pub const ssl_dhe_group_none: SSLDHEGroupType = 0;
+pub const ssl_ff_dhe_2048_group: SSLDHEGroupType = 1;
+pub const ssl_ff_dhe_3072_group: SSLDHEGroupType = 2;
+pub const ssl_ff_dhe_4096_group: SSLDHEGroupType = 3;
+pub const ssl_ff_dhe_6144_group: SSLDHEGroupType = 4;
+pub const ssl_ff_dhe_8192_group: SSLDHEGroupType = 5;
+
+pub const ssl_sig_none: SSLSignatureScheme = 0;
+pub const ssl_sig_rsa_pkcs1_sha1: SSLSignatureScheme = 0x0201;
+pub const ssl_sig_rsa_pkcs1_sha256: SSLSignatureScheme = 0x0401;
+pub const ssl_sig_rsa_pkcs1_sha384: SSLSignatureScheme = 0x0501;
+pub const ssl_sig_rsa_pkcs1_sha512: SSLSignatureScheme = 0x0601;
+pub const ssl_sig_ecdsa_secp256r1_sha256: SSLSignatureScheme = 0x0403;
+pub const ssl_sig_ecdsa_secp384r1_sha384: SSLSignatureScheme = 0x0503;
+pub const ssl_sig_ecdsa_secp521r1_sha512: SSLSignatureScheme = 0x0603;
+pub const ssl_sig_rsa_pss_rsae_sha256: SSLSignatureScheme = 0x0804;
+pub const ssl_sig_rsa_pss_rsae_sha384: SSLSignatureScheme = 0x0805;
+pub const ssl_sig_rsa_pss_rsae_sha512: SSLSignatureScheme = 0x0806;
+pub const ssl_sig_ed25519: SSLSignatureScheme = 0x0807;
+pub const ssl_sig_ed448: SSLSignatureScheme = 0x0808;
+pub const ssl_sig_rsa_pss_pss_sha256: SSLSignatureScheme = 0x0809;
+pub const ssl_sig_rsa_pss_pss_sha384: SSLSignatureScheme = 0x080a;
+pub const ssl_sig_rsa_pss_pss_sha512: SSLSignatureScheme = 0x080b;
+pub const ssl_sig_dsa_sha1: SSLSignatureScheme = 0x0202;
+pub const ssl_sig_dsa_sha256: SSLSignatureScheme = 0x0402;
+pub const ssl_sig_dsa_sha384: SSLSignatureScheme = 0x0502;
+pub const ssl_sig_dsa_sha512: SSLSignatureScheme = 0x0602;
+pub const ssl_sig_ecdsa_sha1: SSLSignatureScheme = 0x0203;
+
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct SSLChannelInfo {
+    pub length: PRUint32,
+    pub protocolVersion: PRUint16,
+    pub cipherSuite: PRUint16,
+    pub authKeyBits: PRUint32,
+    pub keaKeyBits: PRUint32,
+    pub creationTime: PRUint32,
+    pub lastAccessTime: PRUint32,
+    pub expirationTime: PRUint32,
+    pub sessionIDLength: PRUint32,
+    pub sessionID: [c_uchar; 32],
+    // Added in NSS 3.12.5:
+    pub compressionMethodName: *const c_char,
+    pub compressionMethod: SSLCompressionMethod,
+    // Added in NSS 3.21:
+    pub extendedMasterSecretUsed: PRBool,
+    // Added in NSS 3.25:
+    pub earlyDataAccepted: PRBool,
+    // Added in NSS 3.28:
+    pub keaType: SSLKEAType,
+    pub keaGroup: SSLNamedGroup,
+    pub symCipher: SSLCipherAlgorithm,
+    pub macAlgorithm: SSLMACAlgorithm,
+    pub authType: SSLAuthType,
+    pub signatureScheme: SSLSignatureScheme,
+    // Added in NSS 3.34:
+    pub originalKeaGroup: SSLNamedGroup,
+    pub resumed: PRBool,
+    pub peerDelegCred: PRBool,
+    // Added in NSS 3.54:
+    pub pskType: SSLPskType,
+    // Added in NSS 3.60:
+    pub echAccepted: PRBool,
+}
+
+// Only the leading fields; the struct has grown over time, and NSS
+// takes its length as an argument.
+#[derive(Debug, Clone, Copy, Default)]
+#[repr(C)]
+pub struct SSLPreliminaryChannelInfo {
+    pub length: PRUint32,
+    pub valuesSet: PRUint32,
+    pub protocolVersion: PRUint16,
+    pub cipherSuite: PRUint16,
+}
+
+pub const ssl_preinfo_version: PRUint32 = 1 << 0;
+pub const ssl_preinfo_cipher_suite: PRUint32 = 1 << 1;
+
 pub const SSL_LIBRARY_VERSION_2: PRUint16 = 0x0002;
 pub const SSL_LIBRARY_VERSION_3_0: PRUint16 = 0x0300;
 pub const SSL_LIBRARY_VERSION_TLS_1_0: PRUint16 = 0x0301;
@@ -270,20 +532,97 @@ pub const TLS_AES_128_GCM_SHA256: PRUint16 = 0x1301;
 pub const TLS_AES_256_GCM_SHA384: PRUint16 = 0x1302;
 pub const TLS_CHACHA20_POLY1305_SHA256: PRUint16 = 0x1303;
 
+// For NSS_OptionSet and NSS_OptionGet.
+pub const NSS_RSA_MIN_KEY_SIZE: PRInt32 = 0x001;
+pub const NSS_DH_MIN_KEY_SIZE: PRInt32 = 0x002;
+pub const NSS_DSA_MIN_KEY_SIZE: PRInt32 = 0x004;
+
+pub type SECComparison = c_int;
+pub const SECLessThan: SECComparison = -1;
+pub const SECEqual: SECComparison = 0;
+pub const SECGreaterThan: SECComparison = 1;
+
 extern "C" {
+    pub fn SECITEM_AllocItem(arena: *mut PLArenaPool, item: *mut SECItem, len: c_uint)
+                             -> *mut SECItem;
+    pub fn SECITEM_FreeItem(zap: *mut SECItem, freeit: PRBool);
+    pub fn SECITEM_CompareItem(a: *const SECItem, b: *const SECItem) -> SECComparison;
+    pub fn PORT_Free(ptr: *mut c_void);
+    pub fn NSS_SecureMemcmp(a: *const c_void, b: *const c_void, n: size_t) -> c_int;
+    pub fn NSSBase64_DecodeBuffer(arenaOpt: *mut PLArenaPool, outItemOpt: *mut SECItem,
+                                  inStr: *const c_char, inLen: c_uint) -> *mut SECItem;
+
+    pub fn DER_GeneralizedTimeToTime(dst: *mut PRTime, time: *const SECItem) -> SECStatus;
+
     pub fn NSS_NoDB_Init(_configdir: *const c_char) -> SECStatus;
+    pub fn NSS_GetVersion() -> *const c_char;
+    pub fn NSS_VersionCheck(importedVersion: *const c_char) -> PRBool;
     pub fn NSS_SetDomesticPolicy() -> SECStatus;
+    pub fn NSS_OptionSet(which: PRInt32, value: PRInt32) -> SECStatus;
+    pub fn NSS_OptionGet(which: PRInt32, value: *mut PRInt32) -> SECStatus;
     pub fn SSL_ImportFD(model: *mut PRFileDesc, fd: *mut PRFileDesc) -> *mut PRFileDesc;
+    pub fn DTLS_ImportFD(model: *mut PRFileDesc, fd: *mut PRFileDesc) -> *mut PRFileDesc;
     pub fn SSL_PeerCertificate(fd: *mut PRFileDesc) -> *mut CERTCertificate;
+    pub fn SSL_LocalCertificate(fd: *mut PRFileDesc) -> *mut CERTCertificate;
+    pub fn SSL_ExportKeyingMaterial(fd: *mut PRFileDesc, label: *const c_char, labelLen: c_uint,
+                                    hasContext: PRBool, context: *const c_uchar,
+                                    contextLen: c_uint, out: *mut c_uchar, outLen: c_uint)
+                                    -> SECStatus;
     pub fn SSL_PeerCertificateChain(fd: *mut PRFileDesc) -> *mut CERTCertList;
     pub fn SSL_AuthCertificateHook(fd: *mut PRFileDesc, f: SSLAuthCertificate, arg: *mut c_void)
                                    -> SECStatus;
+    pub fn SSL_AuthCertificate(arg: *mut c_void, fd: *mut PRFileDesc, checkSig: PRBool,
+                               isServer: PRBool) -> SECStatus;
     pub fn SSL_BadCertHook(fd: *mut PRFileDesc, f: SSLBadCertHandler, arg: *mut c_void)
                            -> SECStatus;
+    pub fn SSL_SNISocketConfigHook(fd: *mut PRFileDesc, f: SSLSNISocketConfig, arg: *mut c_void)
+                                   -> SECStatus;
+    pub fn SSL_GetClientAuthDataHook(fd: *mut PRFileDesc, f: SSLGetClientAuthData,
+                                     arg: *mut c_void) -> SECStatus;
+    pub fn SSL_HandshakeCallback(fd: *mut PRFileDesc, cb: SSLHandshakeCallback,
+                                 client_data: *mut c_void) -> SECStatus;
+    pub fn SSL_AlertReceivedCallback(fd: *mut PRFileDesc, cb: SSLAlertCallback, arg: *mut c_void)
+                                     -> SECStatus;
+    pub fn SSL_AlertSentCallback(fd: *mut PRFileDesc, cb: SSLAlertCallback, arg: *mut c_void)
+                                 -> SECStatus;
+    pub fn SSL_HandshakeNegotiatedExtension(fd: *mut PRFileDesc, extId: SSLExtensionType,
+                                            yes: *mut PRBool) -> SECStatus;
+    pub fn SSL_SetCanFalseStartCallback(fd: *mut PRFileDesc, callback: SSLCanFalseStartCallback,
+                                        arg: *mut c_void) -> SECStatus;
+    pub fn SSL_GetChannelInfo(fd: *mut PRFileDesc, info: *mut SSLChannelInfo, len: PRUintn)
+                              -> SECStatus;
+    pub fn SSL_NamedGroupConfig(fd: *mut PRFileDesc, groups: *const SSLNamedGroup,
+                                num_groups: c_uint) -> SECStatus;
+    pub fn SSL_DHEGroupPrefSet(fd: *mut PRFileDesc, groups: *const SSLDHEGroupType,
+                               num_groups: PRUint16) -> SECStatus;
+    pub fn SSL_EnableWeakDHEPrimeGroup(fd: *mut PRFileDesc, enabled: PRBool) -> SECStatus;
+    pub fn SSL_SignatureSchemePrefSet(fd: *mut PRFileDesc, schemes: *const SSLSignatureScheme,
+                                      count: c_uint) -> SECStatus;
+    pub fn SSL_GetPreliminaryChannelInfo(fd: *mut PRFileDesc,
+                                         info: *mut SSLPreliminaryChannelInfo,
+                                         len: PRUintn) -> SECStatus;
     pub fn SSL_SetURL(fd: *mut PRFileDesc, url: *const c_char) -> SECStatus;
+    pub fn SSL_RevealURL(fd: *mut PRFileDesc) -> *mut c_char;
+    pub fn SSL_RevealCert(fd: *mut PRFileDesc) -> *mut CERTCertificate;
+    pub fn SSL_RevealPinArg(fd: *mut PRFileDesc) -> *mut c_void;
+    pub fn SSL_SetTrustAnchors(fd: *mut PRFileDesc, list: *mut CERTCertList) -> SECStatus;
+    pub fn SSL_SetNextProtoNego(fd: *mut PRFileDesc, data: *const c_uchar, length: c_uint)
+                                -> SECStatus;
+    pub fn SSL_GetNextProto(fd: *mut PRFileDesc, state: *mut SSLNextProtoState,
+                            buf: *mut c_uchar, bufLen: *mut c_uint, bufLenMax: c_uint)
+                            -> SECStatus;
+    pub fn SSL_ResetHandshake(fd: *mut PRFileDesc, asServer: PRBool) -> SECStatus;
+    pub fn SSL_ForceHandshake(fd: *mut PRFileDesc) -> SECStatus;
+    pub fn SSL_DataPending(fd: *mut PRFileDesc) -> c_int;
+    pub fn SSL_ConfigServerCert(fd: *mut PRFileDesc, cert: *mut CERTCertificate,
+                                key: *mut SECKEYPrivateKey, data: *const SSLExtraServerCertData,
+                                data_len: c_uint) -> SECStatus;
     pub fn SSL_OptionSet(fd: *mut PRFileDesc, option: PRInt32, on: PRBool) -> SECStatus;
     pub fn SSL_OptionGet(fd: *mut PRFileDesc, option: PRInt32, on: *mut PRBool) -> SECStatus;
     pub fn SSL_OptionSetDefault(option: PRInt32, on: PRBool) -> SECStatus;
+    pub fn SSL_ConfigMPServerSIDCache(maxCacheEntries: c_int, timeout: PRUint32,
+                                      ssl3_timeout: PRUint32, directory: *const c_char)
+                                      -> SECStatus;
     pub fn SSL_OptionGetDefault(option: PRInt32, on: *mut PRBool) -> SECStatus;
     pub fn SSL_VersionRangeSet(fd: *mut PRFileDesc, vrange: *const SSLVersionRange) -> SECStatus;
     pub fn SSL_VersionRangeGet(fd: *mut PRFileDesc, vrange: *mut SSLVersionRange) -> SECStatus;
@@ -300,8 +639,51 @@ extern "C" {
                              -> SECStatus;
     pub fn SSL_CipherPrefSetDefault(cipher: PRInt32, enabled: PRBool) -> SECStatus;
     pub fn SSL_CipherPrefGetDefault(cipher: PRInt32, enabled: *mut PRBool) -> SECStatus;
+    pub fn SSL_GetExperimentalAPI(name: *const c_char) -> *mut c_void;
+}
+
+// Experimental functions aren't exported; they're looked up by name,
+// like sslexp.h's SSL_EXPERIMENTAL_API macro does.
+macro_rules! experimental_api {
+    ($name:ident($($arg:ident: $argty:ty),*)) => {
+        pub unsafe fn $name($($arg: $argty),*) -> SECStatus {
+            type Fn = unsafe extern "C" fn($($argty),*) -> SECStatus;
+            let f = SSL_GetExperimentalAPI(concat!(stringify!($name), "\0").as_ptr()
+                                           as *const c_char);
+            if f.is_null() {
+                nspr::PR_SetError(error::SSL_ERROR_UNSUPPORTED_EXPERIMENTAL_API, 0);
+                return SECFailure;
+            }
+            mem::transmute::<*mut c_void, Fn>(f)($($arg),*)
+        }
+    }
 }
 
+experimental_api!(SSL_SetTimeFunc(fd: *mut PRFileDesc, f: SSLTimeFunc, arg: *mut c_void));
+experimental_api!(SSL_SetMaxEarlyDataSize(fd: *mut PRFileDesc, size: PRUint32));
+experimental_api!(SSL_SetResumptionTokenCallback(fd: *mut PRFileDesc,
+                                                 cb: SSLResumptionTokenCallback,
+                                                 ctx: *mut c_void));
+experimental_api!(SSL_SetResumptionToken(fd: *mut PRFileDesc, token: *const u8,
+                                         len: c_uint));
+experimental_api!(SSL_CreateAntiReplayContext(now: PRTime, window: PRTime, k: c_uint,
+                                              bits: c_uint,
+                                              ctx: *mut *mut SSLAntiReplayContext));
+experimental_api!(SSL_SetAntiReplayContext(fd: *mut PRFileDesc, ctx: *mut SSLAntiReplayContext));
+experimental_api!(SSL_ReleaseAntiReplayContext(ctx: *mut SSLAntiReplayContext));
+experimental_api!(SSL_SetClientEchConfigs(fd: *mut PRFileDesc, echConfigs: *const u8,
+                                          echConfigsLen: c_uint));
+experimental_api!(SSL_SetServerEchConfigs(fd: *mut PRFileDesc, pubKey: *const SECKEYPublicKey,
+                                          privKey: *const SECKEYPrivateKey, record: *const u8,
+                                          recordLen: c_uint));
+experimental_api!(SSL_GetEchRetryConfigs(fd: *mut PRFileDesc, out: *mut SECItem));
+experimental_api!(SSL_EncodeEchConfigId(configId: u8, publicName: *const c_char,
+                                        maxNameLen: c_uint, kemId: HpkeKemId,
+                                        pubKey: *const SECKEYPublicKey,
+                                        hpkeSuites: *const HpkeSymmetricSuite,
+                                        hpkeSuiteCount: c_uint, out: *mut u8,
+                                        outlen: *mut c_uint, maxlen: c_uint));
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -137,6 +137,12 @@ pub struct PRFileDesc {
 pub struct PRFilePrivate(c_void);
 
 pub type PRDescIdentity = PRIntn;
+pub const PR_INVALID_IO_LAYER: PRDescIdentity = -1;
+pub const PR_TOP_IO_LAYER: PRDescIdentity = -2;
+pub const PR_NSPR_IO_LAYER: PRDescIdentity = 0;
+
+// This is 64-bit on Win64, which isn't supported here yet anyway.
+pub type PROsfd = PRIntn;
 
 #[derive(Clone, Copy, Debug)]
 #[repr(C)]
@@ -349,6 +355,8 @@ pub enum PRSeekWhence {
 }
 pub use self::PRSeekWhence::*;
 
+pub const PR_MAX_IOVECTOR_SIZE: usize = 16;
+
 #[derive(Debug)]
 #[repr(C)]
 pub struct PRIOVec {
@@ -449,6 +457,25 @@ pub enum PRNetAddr { }
 // names here.  Also, I'm assuming the target isn't BeOS or OS/2 (this
 // changes some of the array sizes).
 
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+pub struct PRHostEnt {
+    pub h_name: *mut c_char,
+    pub h_aliases: *mut *mut c_char,
+    // These are PRInt16 on Windows.
+    pub h_addrtype: PRInt32,
+    pub h_length: PRInt32,
+    pub h_addr_list: *mut *mut c_char,
+}
+
+pub const PR_NETDB_BUF_SIZE: usize = 2048;
+
+// Read with PR_EnumerateAddrInfo.
+pub enum PRAddrInfo { }
+
+pub const PR_AI_ADDRCONFIG: PRIntn = 0x20;
+pub const PR_AI_NOCANONNAME: PRIntn = 0x8000;
+
 #[derive(Clone, Copy, Debug)]
 #[repr(C)]
 pub struct PRNetAddrRaw {
@@ -486,6 +513,19 @@ impl Clone for PRNetAddrLocal {
     fn clone(&self) -> Self { *self }
 }
 
+// The whole union, for the structs that embed a PRNetAddr by value;
+// it's as large as PRNetAddrLocal and aligned for PRIPv6Addr.
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub struct PRNetAddrStorage(pub [PRUint64; 14]);
+
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub struct PRMcastRequest {
+    pub mcaddr: PRNetAddrStorage,
+    pub ifaddr: PRNetAddrStorage,
+}
+
 // In C this is a union of [u64; 2], [u32, 4], [u16, 8], and [u8; 16].
 // In Rust, use transmute instead.
 #[derive(Clone, Copy, Debug)]
@@ -512,6 +552,13 @@ pub enum PRTransmitFileFlags {
 }
 pub use self::PRTransmitFileFlags::*;
 
+pub enum PRFileMap { }
+
+pub type PRFileMapProtect = c_uint;
+pub const PR_PROT_READONLY: PRFileMapProtect = 0;
+pub const PR_PROT_READWRITE: PRFileMapProtect = 1;
+pub const PR_PROT_WRITECOPY: PRFileMapProtect = 2;
+
 #[derive(Clone, Copy, Debug)]
 #[repr(C)]
 pub struct PRFileInfo {
@@ -559,8 +606,28 @@ pub enum PRThreadPriority
 }
 pub use self::PRThreadPriority::*;
 
+#[repr(C)]
+pub struct PRPollDesc {
+    pub fd: *mut PRFileDesc,
+    pub in_flags: PRInt16,
+    pub out_flags: PRInt16,
+}
+
 pub const PR_MSG_PEEK: PRIntn = 0x2;
 
+// The shutdown method takes these as a plain PRIntn.
+pub type PRShutdownHow = PRIntn;
+pub const PR_SHUTDOWN_RCV: PRShutdownHow = 0;
+pub const PR_SHUTDOWN_SEND: PRShutdownHow = 1;
+pub const PR_SHUTDOWN_BOTH: PRShutdownHow = 2;
+
+pub const PR_POLL_READ: PRInt16 = 0x1;
+pub const PR_POLL_WRITE: PRInt16 = 0x2;
+pub const PR_POLL_EXCEPT: PRInt16 = 0x4;
+pub const PR_POLL_ERR: PRInt16 = 0x8;
+pub const PR_POLL_NVAL: PRInt16 = 0x10;
+pub const PR_POLL_HUP: PRInt16 = 0x20;
+
 // This has a public definition in C, but we don't currently need it.
 pub enum PLArenaPool { }
 
@@ -582,6 +649,7 @@ extern "C" {
     pub fn PR_Init(_type: PRThreadType,
                    _priority: PRThreadPriority,
                    _maxPTDs: PRUintn);
+    pub fn PR_GetVersion() -> *const c_char;
 
     pub fn PR_GetError() -> PRErrorCode;
     pub fn PR_GetOSError() -> PRInt32;
@@ -590,6 +658,7 @@ extern "C" {
     // PR_LoadLibrary (and only on BeOS?), so not binding it.
     pub fn PR_ErrorToString(code: PRErrorCode, language: PRLanguageCode) -> *const c_char;
     pub fn PR_ErrorToName(code: PRErrorCode) -> *const c_char;
+    pub fn PR_smprintf_free(mem: *mut c_char);
 
     pub fn PR_GetUniqueIdentity(layer_name: *const c_char) -> PRDescIdentity;
     pub fn PR_TicksPerSecond() -> PRUint32;
@@ -597,14 +666,22 @@ extern "C" {
     pub fn PR_Close(fd: *mut PRFileDesc) -> PRStatus;
     pub fn PR_Read(fd: *mut PRFileDesc, buf: *mut c_void, amount: PRInt32) -> PRInt32;
     pub fn PR_Write(fd: *mut PRFileDesc, buf: *const c_void, amount: PRInt32) -> PRInt32;
+    pub fn PR_Writev(fd: *mut PRFileDesc, iov: *const PRIOVec, iov_size: PRInt32,
+                     timeout: PRIntervalTime) -> PRInt32;
     pub fn PR_Connect(fd: *mut PRFileDesc, addr: *const PRNetAddr, timeout: PRIntervalTime)
                       -> PRStatus;
     pub fn PR_Recv(fd: *mut PRFileDesc, buf: *mut c_void, amount: PRInt32, flags: PRIntn,
                    timeout: PRIntervalTime) -> PRInt32;
     pub fn PR_Send(fd: *mut PRFileDesc, buf: *const c_void, amount: PRInt32, flags: PRIntn,
                    timeout: PRIntervalTime) -> PRInt32;
+    pub fn PR_Shutdown(fd: *mut PRFileDesc, how: PRShutdownHow) -> PRStatus;
+    pub fn PR_Poll(pds: *mut PRPollDesc, npds: PRIntn, timeout: PRIntervalTime) -> PRInt32;
     pub fn PR_GetSockName(fd: *mut PRFileDesc, addr: *mut PRNetAddr) -> PRStatus;
     pub fn PR_GetPeerName(fd: *mut PRFileDesc, addr: *mut PRNetAddr) -> PRStatus;
+    pub fn PR_Bind(fd: *mut PRFileDesc, addr: *const PRNetAddr) -> PRStatus;
+    pub fn PR_Listen(fd: *mut PRFileDesc, backlog: PRIntn) -> PRStatus;
+    pub fn PR_Accept(fd: *mut PRFileDesc, addr: *mut PRNetAddr, timeout: PRIntervalTime)
+                     -> *mut PRFileDesc;
     pub fn PR_GetSocketOption(fd: *mut PRFileDesc, data: *mut PRSocketOptionData) -> PRStatus;
     pub fn PR_SetSocketOption(fd: *mut PRFileDesc, data: *const PRSocketOptionData) -> PRStatus;
 
@@ -612,4 +689,27 @@ extern "C" {
                          -> PRStatus;
     pub fn PR_OpenTCPSocket(af: PRIntn) -> *mut PRFileDesc;
     pub fn PR_OpenUDPSocket(af: PRIntn) -> *mut PRFileDesc;
+    pub fn PR_NewTCPSocketPair(fds: *mut [*mut PRFileDesc; 2]) -> PRStatus;
+
+    // From the "private" API (pprio.h), but exported and stable.
+    pub fn PR_ImportTCPSocket(osfd: PROsfd) -> *mut PRFileDesc;
+    pub fn PR_FileDesc2NativeHandle(fd: *mut PRFileDesc) -> PROsfd;
+    pub fn PR_ChangeFileDescNativeHandle(fd: *mut PRFileDesc, handle: PROsfd);
+    pub fn PR_GetTCPMethods() -> *const PRIOMethods;
+    pub fn PR_ImportFile(osfd: PROsfd) -> *mut PRFileDesc;
+    pub fn PR_GetOpenFileInfo64(fd: *mut PRFileDesc, info: *mut PRFileInfo64) -> PRStatus;
+    pub fn PR_CreateFileMap(fd: *mut PRFileDesc, size: PRInt64, prot: PRFileMapProtect)
+                            -> *mut PRFileMap;
+    pub fn PR_MemMap(fmap: *mut PRFileMap, offset: PROffset64, len: PRUint32) -> *mut c_void;
+    pub fn PR_MemUnmap(addr: *mut c_void, len: PRUint32) -> PRStatus;
+    pub fn PR_CloseFileMap(fmap: *mut PRFileMap) -> PRStatus;
+    pub fn PR_GetHostByName(hostname: *const c_char, buf: *mut c_char, bufsize: PRIntn,
+                            hostentry: *mut PRHostEnt) -> PRStatus;
+    pub fn PR_EnumerateHostEnt(enumIndex: PRIntn, hostEnt: *const PRHostEnt, port: PRUint16,
+                               address: *mut PRNetAddr) -> PRIntn;
+    pub fn PR_GetAddrInfoByName(hostname: *const c_char, af: PRUint16, flags: PRIntn)
+                                -> *mut PRAddrInfo;
+    pub fn PR_FreeAddrInfo(addrInfo: *mut PRAddrInfo);
+    pub fn PR_EnumerateAddrInfo(enumPtr: *mut c_void, addrInfo: *const PRAddrInfo,
+                                port: PRUint16, result: *mut PRNetAddr) -> *mut c_void;
 }
@@ -0,0 +1,274 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+use libc::{c_char, c_int, c_uchar, c_uint, c_ulong, c_void};
+use nspr::{PLArenaPool, PRBool, PRInt32, PRUint32};
+use super::{SECAlgorithmID, SECItem, SECStatus, PK11SlotInfo, CK_OBJECT_HANDLE, CK_ULONG};
+
+pub type SECKEYPrivateKey = SECKEYPrivateKeyStr;
+pub type SECKEYPublicKey = SECKEYPublicKeyStr;
+
+// These have public definitions in C, but nothing here needs to look inside.
+pub enum SECKEYPrivateKeyStr { }
+pub enum SECKEYPublicKeyStr { }
+
+// Key usage bits (certt.h); the import functions take these as `usage`.
+pub const KU_DIGITAL_SIGNATURE: c_uint = 0x80;
+pub const KU_NON_REPUDIATION: c_uint = 0x40;
+pub const KU_KEY_ENCIPHERMENT: c_uint = 0x20;
+pub const KU_DATA_ENCIPHERMENT: c_uint = 0x10;
+pub const KU_KEY_AGREEMENT: c_uint = 0x08;
+pub const KU_KEY_CERT_SIGN: c_uint = 0x04;
+pub const KU_CRL_SIGN: c_uint = 0x02;
+pub const KU_ENCIPHER_ONLY: c_uint = 0x01;
+pub const KU_ALL: c_uint = 0xff;
+
+// Only the hash algorithms so far; the full enum is in secoidt.h.
+pub type SECOidTag = c_uint;
+pub const SEC_OID_UNKNOWN: SECOidTag = 0;
+pub const SEC_OID_MD5: SECOidTag = 3;
+pub const SEC_OID_SHA1: SECOidTag = 4;
+pub const SEC_OID_SHA256: SECOidTag = 191;
+pub const SEC_OID_SHA384: SECOidTag = 192;
+pub const SEC_OID_SHA512: SECOidTag = 193;
+pub const SEC_OID_SHA224: SECOidTag = 309;
+pub const SEC_OID_PKCS1_MD5_WITH_RSA_ENCRYPTION: SECOidTag = 19;
+pub const SEC_OID_PKCS1_SHA1_WITH_RSA_ENCRYPTION: SECOidTag = 20;
+pub const SEC_OID_PKCS1_SHA256_WITH_RSA_ENCRYPTION: SECOidTag = 194;
+pub const SEC_OID_PKCS1_SHA384_WITH_RSA_ENCRYPTION: SECOidTag = 195;
+pub const SEC_OID_PKCS1_SHA512_WITH_RSA_ENCRYPTION: SECOidTag = 196;
+pub const SEC_OID_ANSIX962_ECDSA_SHA1_SIGNATURE: SECOidTag = 201;
+pub const SEC_OID_ANSIX962_ECDSA_SHA256_SIGNATURE: SECOidTag = 278;
+pub const SEC_OID_ANSIX962_ECDSA_SHA384_SIGNATURE: SECOidTag = 279;
+pub const SEC_OID_ANSIX962_ECDSA_SHA512_SIGNATURE: SECOidTag = 280;
+
+// Algorithm policy flags (secoidt.h).
+pub const NSS_USE_ALG_IN_CERT_SIGNATURE: PRUint32 = 0x00000001;
+pub const NSS_USE_ALG_IN_CMS_SIGNATURE: PRUint32 = 0x00000002;
+pub const NSS_USE_ALG_IN_SSL_KX: PRUint32 = 0x00000004;
+pub const NSS_USE_ALG_IN_SSL: PRUint32 = 0x00000008;
+pub const NSS_USE_POLICY_IN_SSL: PRUint32 = 0x00000010;
+pub const NSS_USE_ALG_IN_ANY_SIGNATURE: PRUint32 = 0x00000020;
+pub const NSS_USE_ALG_IN_PKCS12: PRUint32 = 0x00000040;
+pub const NSS_USE_ALG_IN_SIGNATURE: PRUint32 =
+    NSS_USE_ALG_IN_CERT_SIGNATURE | NSS_USE_ALG_IN_CMS_SIGNATURE | NSS_USE_ALG_IN_ANY_SIGNATURE;
+
+pub type SECSupportExtenTag = c_uint;
+
+#[derive(Debug)]
+#[repr(C)]
+pub struct SECOidData {
+    pub oid: SECItem,
+    pub offset: SECOidTag,
+    pub desc: *const c_char,
+    pub mechanism: c_ulong,
+    pub supportedExtension: SECSupportExtenTag,
+}
+
+pub type KeyType = c_uint;
+pub const nullKey: KeyType = 0;
+pub const rsaKey: KeyType = 1;
+pub const dsaKey: KeyType = 2;
+pub const dhKey: KeyType = 4;
+pub const ecKey: KeyType = 6;
+pub const rsaPssKey: KeyType = 7;
+pub const rsaOaepKey: KeyType = 8;
+
+pub enum PK11SymKey { }
+
+pub type CK_ATTRIBUTE_TYPE = CK_ULONG;
+pub const CKA_ENCRYPT: CK_ATTRIBUTE_TYPE = 0x104;
+pub const CKA_DECRYPT: CK_ATTRIBUTE_TYPE = 0x105;
+pub const CKA_WRAP: CK_ATTRIBUTE_TYPE = 0x106;
+pub const CKA_UNWRAP: CK_ATTRIBUTE_TYPE = 0x107;
+pub const CKA_SIGN: CK_ATTRIBUTE_TYPE = 0x108;
+pub const CKA_DERIVE: CK_ATTRIBUTE_TYPE = 0x10c;
+
+pub type CK_FLAGS = CK_ULONG;
+pub const CKF_ENCRYPT: CK_FLAGS = 0x100;
+pub const CKF_DECRYPT: CK_FLAGS = 0x200;
+pub const CKF_SIGN: CK_FLAGS = 0x800;
+pub const CKF_VERIFY: CK_FLAGS = 0x2000;
+pub const CKF_WRAP: CK_FLAGS = 0x20000;
+pub const CKF_UNWRAP: CK_FLAGS = 0x40000;
+pub const CKF_DERIVE: CK_FLAGS = 0x80000;
+
+pub type PK11AttrFlags = PRUint32;
+
+pub type PK11Origin = c_uint;
+pub const PK11_OriginNULL: PK11Origin = 0;
+pub const PK11_OriginDerive: PK11Origin = 1;
+pub const PK11_OriginGenerated: PK11Origin = 2;
+pub const PK11_OriginFortezzaHack: PK11Origin = 3;
+pub const PK11_OriginUnwrap: PK11Origin = 4;
+
+pub type CK_MECHANISM_TYPE = CK_ULONG;
+pub const CKM_RSA_PKCS_KEY_PAIR_GEN: CK_MECHANISM_TYPE = 0x0;
+pub const CKM_RSA_PKCS: CK_MECHANISM_TYPE = 0x1;
+pub const CKM_SHA256_RSA_PKCS: CK_MECHANISM_TYPE = 0x40;
+pub const CKM_SHA384_RSA_PKCS: CK_MECHANISM_TYPE = 0x41;
+pub const CKM_SHA512_RSA_PKCS: CK_MECHANISM_TYPE = 0x42;
+pub const CKM_SHA256: CK_MECHANISM_TYPE = 0x250;
+pub const CKM_SHA256_HMAC: CK_MECHANISM_TYPE = 0x251;
+pub const CKM_SHA384: CK_MECHANISM_TYPE = 0x260;
+pub const CKM_SHA512: CK_MECHANISM_TYPE = 0x270;
+pub const CKM_GENERIC_SECRET_KEY_GEN: CK_MECHANISM_TYPE = 0x350;
+pub const CKM_EC_KEY_PAIR_GEN: CK_MECHANISM_TYPE = 0x1040;
+pub const CKM_ECDSA: CK_MECHANISM_TYPE = 0x1041;
+pub const CKM_ECDSA_SHA256: CK_MECHANISM_TYPE = 0x1044;
+pub const CKM_ECDSA_SHA384: CK_MECHANISM_TYPE = 0x1045;
+pub const CKM_ECDSA_SHA512: CK_MECHANISM_TYPE = 0x1046;
+pub const CKM_AES_KEY_GEN: CK_MECHANISM_TYPE = 0x1080;
+pub const CKM_AES_CBC: CK_MECHANISM_TYPE = 0x1082;
+pub const CKM_AES_GCM: CK_MECHANISM_TYPE = 0x1087;
+pub const CKM_AES_KEY_WRAP: CK_MECHANISM_TYPE = 0x2109;
+pub const CKM_HKDF_DERIVE: CK_MECHANISM_TYPE = 0x402a;
+pub const CKM_HKDF_DATA: CK_MECHANISM_TYPE = 0x402b;
+
+pub type CK_BBOOL = c_uchar;
+pub const CK_FALSE: CK_BBOOL = 0;
+pub const CK_TRUE: CK_BBOOL = 1;
+
+pub const CKF_HKDF_SALT_NULL: CK_ULONG = 0x1;
+pub const CKF_HKDF_SALT_DATA: CK_ULONG = 0x2;
+pub const CKF_HKDF_SALT_KEY: CK_ULONG = 0x4;
+
+#[repr(C)]
+pub struct CK_HKDF_PARAMS {
+    pub bExtract: CK_BBOOL,
+    pub bExpand: CK_BBOOL,
+    pub prfHashMechanism: CK_MECHANISM_TYPE,
+    pub ulSaltType: CK_ULONG,
+    pub pSalt: *mut c_uchar,
+    pub ulSaltLen: CK_ULONG,
+    pub hSaltKey: CK_OBJECT_HANDLE,
+    pub pInfo: *mut c_uchar,
+    pub ulInfoLen: CK_ULONG,
+}
+
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct PK11RSAGenParams {
+    pub keySizeInBits: c_int,
+    pub pe: c_ulong,
+}
+
+// Only the leading fields (secmodt.h); these are only ever handled
+// by pointer, so the rest can be left out.
+#[repr(C)]
+pub struct SECMODModule {
+    pub arena: *mut PLArenaPool,
+    pub internal: PRBool,
+    pub loaded: PRBool,
+    pub isFIPS: PRBool,
+    pub dllName: *mut c_char,
+    pub commonName: *mut c_char,
+    pub library: *mut c_void,
+    pub functionList: *mut c_void,
+    pub refLock: *mut c_void,
+    pub refCount: c_int,
+    pub slots: *mut *mut PK11SlotInfo,
+    pub slotCount: c_int,
+}
+
+pub enum SECMODListLock { }
+
+// The DER-encoded curve OID.
+pub type SECKEYECParams = SECItem;
+
+extern "C" {
+    pub fn PK11_GetInternalSlot() -> *mut PK11SlotInfo;
+    pub fn PK11_FreeSlot(slot: *mut PK11SlotInfo);
+    pub fn PK11_GetInternalKeySlot() -> *mut PK11SlotInfo;
+    pub fn PK11_ReferenceSlot(slot: *mut PK11SlotInfo) -> *mut PK11SlotInfo;
+    pub fn PK11_GetTokenName(slot: *mut PK11SlotInfo) -> *mut c_char;
+    pub fn PK11_GetSlotName(slot: *mut PK11SlotInfo) -> *mut c_char;
+    pub fn PK11_NeedLogin(slot: *mut PK11SlotInfo) -> PRBool;
+    pub fn PK11_IsLoggedIn(slot: *mut PK11SlotInfo, wincx: *mut c_void) -> PRBool;
+    pub fn PK11_CheckUserPassword(slot: *mut PK11SlotInfo, pw: *const c_char) -> SECStatus;
+    pub fn PK11_Logout(slot: *mut PK11SlotInfo) -> SECStatus;
+    pub fn PK11_NeedUserInit(slot: *mut PK11SlotInfo) -> PRBool;
+    pub fn PK11_IsInternalKeySlot(slot: *mut PK11SlotInfo) -> PRBool;
+    pub fn PK11_InitPin(slot: *mut PK11SlotInfo, ssopw: *const c_char, pk11_userpwd: *const c_char)
+                        -> SECStatus;
+    pub fn PK11_ChangePW(slot: *mut PK11SlotInfo, oldpw: *const c_char, newpw: *const c_char)
+                         -> SECStatus;
+    pub fn SECMOD_OpenUserDB(moduleSpec: *const c_char) -> *mut PK11SlotInfo;
+    pub fn SECMOD_CloseUserDB(slot: *mut PK11SlotInfo) -> SECStatus;
+    pub fn SECMOD_LoadUserModule(moduleSpec: *mut c_char, parent: *mut SECMODModule,
+                                 recurse: PRBool) -> *mut SECMODModule;
+    pub fn SECMOD_UnloadUserModule(module: *mut SECMODModule) -> SECStatus;
+    pub fn SECMOD_DestroyModule(module: *mut SECMODModule);
+    pub fn SECMOD_GetDefaultModuleListLock() -> *mut SECMODListLock;
+    pub fn SECMOD_GetReadLock(lock: *mut SECMODListLock);
+    pub fn SECMOD_ReleaseReadLock(lock: *mut SECMODListLock);
+    pub fn PK11_ImportDERPrivateKeyInfoAndReturnKey(slot: *mut PK11SlotInfo,
+                                                    derPKI: *mut SECItem,
+                                                    nickname: *mut SECItem,
+                                                    publicValue: *mut SECItem,
+                                                    isPerm: PRBool,
+                                                    isPrivate: PRBool,
+                                                    usage: c_uint,
+                                                    privk: *mut *mut SECKEYPrivateKey,
+                                                    wincx: *mut c_void) -> SECStatus;
+    pub fn SECKEY_DestroyPrivateKey(key: *mut SECKEYPrivateKey);
+    pub fn SECKEY_CopyPrivateKey(key: *const SECKEYPrivateKey) -> *mut SECKEYPrivateKey;
+    pub fn SECKEY_ConvertToPublicKey(key: *mut SECKEYPrivateKey) -> *mut SECKEYPublicKey;
+    pub fn SECKEY_DestroyPublicKey(key: *mut SECKEYPublicKey);
+    pub fn SECKEY_EncodeDERSubjectPublicKeyInfo(key: *const SECKEYPublicKey) -> *mut SECItem;
+    pub fn SECKEY_GetPrivateKeyType(privKey: *const SECKEYPrivateKey) -> KeyType;
+    pub fn SECKEY_GetPublicKeyType(pubKey: *const SECKEYPublicKey) -> KeyType;
+    pub fn PK11_SignatureLen(key: *mut SECKEYPrivateKey) -> c_int;
+    pub fn PK11_SignWithMechanism(key: *mut SECKEYPrivateKey, mechanism: CK_MECHANISM_TYPE,
+                                  param: *const SECItem, sig: *mut SECItem, hash: *const SECItem)
+                                  -> SECStatus;
+    pub fn PK11_VerifyWithMechanism(key: *mut SECKEYPublicKey, mechanism: CK_MECHANISM_TYPE,
+                                    param: *const SECItem, sig: *const SECItem,
+                                    hash: *const SECItem, wincx: *mut c_void) -> SECStatus;
+    pub fn PK11_GenerateKeyPair(slot: *mut PK11SlotInfo, type_: CK_MECHANISM_TYPE,
+                                param: *mut c_void, pubk: *mut *mut SECKEYPublicKey,
+                                isPerm: PRBool, isSensitive: PRBool, wincx: *mut c_void)
+                                -> *mut SECKEYPrivateKey;
+    pub fn PK11_FreeSymKey(key: *mut PK11SymKey);
+    pub fn PK11_TokenKeyGenWithFlags(slot: *mut PK11SlotInfo, type_: CK_MECHANISM_TYPE,
+                                     param: *mut SECItem, keySize: c_int, keyid: *mut SECItem,
+                                     opFlags: CK_FLAGS, attrFlags: PK11AttrFlags,
+                                     wincx: *mut c_void) -> *mut PK11SymKey;
+    pub fn PK11_ImportSymKeyWithFlags(slot: *mut PK11SlotInfo, type_: CK_MECHANISM_TYPE,
+                                      origin: PK11Origin, operation: CK_ATTRIBUTE_TYPE,
+                                      key: *mut SECItem, flags: CK_FLAGS, isPerm: PRBool,
+                                      wincx: *mut c_void) -> *mut PK11SymKey;
+    pub fn PK11_WrapSymKey(type_: CK_MECHANISM_TYPE, params: *mut SECItem,
+                           wrappingKey: *mut PK11SymKey, symKey: *mut PK11SymKey,
+                           wrappedKey: *mut SECItem) -> SECStatus;
+    pub fn PK11_UnwrapSymKeyWithFlags(wrappingKey: *mut PK11SymKey, wrapType: CK_MECHANISM_TYPE,
+                                      param: *mut SECItem, wrappedKey: *mut SECItem,
+                                      target: CK_MECHANISM_TYPE, operation: CK_ATTRIBUTE_TYPE,
+                                      keySize: c_int, flags: CK_FLAGS) -> *mut PK11SymKey;
+    pub fn PK11_Derive(baseKey: *mut PK11SymKey, mechanism: CK_MECHANISM_TYPE,
+                       param: *mut SECItem, target: CK_MECHANISM_TYPE,
+                       operation: CK_ATTRIBUTE_TYPE, keySize: c_int) -> *mut PK11SymKey;
+    pub fn PK11_ExtractKeyValue(symKey: *mut PK11SymKey) -> SECStatus;
+    pub fn PK11_GetKeyData(symKey: *mut PK11SymKey) -> *mut SECItem;
+    pub fn PK11_GetKeyLength(key: *mut PK11SymKey) -> c_uint;
+    pub fn PK11_HashBuf(hashAlg: SECOidTag, out: *mut c_uchar, in_: *const c_uchar, len: PRInt32)
+                        -> SECStatus;
+    pub fn PK11_GenerateRandom(data: *mut c_uchar, len: c_int) -> SECStatus;
+    pub fn PK11_GenerateRandomOnSlot(slot: *mut PK11SlotInfo, data: *mut c_uchar, len: c_int)
+                                     -> SECStatus;
+    pub fn SEC_GetSignatureAlgorithmOidTag(keyType: KeyType, hashAlgTag: SECOidTag) -> SECOidTag;
+    pub fn NSS_GetAlgorithmPolicy(tag: SECOidTag, pValue: *mut PRUint32) -> SECStatus;
+    pub fn NSS_SetAlgorithmPolicy(tag: SECOidTag, setBits: PRUint32, clearBits: PRUint32)
+                                  -> SECStatus;
+    pub fn SECOID_GetAlgorithmTag(aid: *const SECAlgorithmID) -> SECOidTag;
+    pub fn SECOID_FindOIDByTag(tagnum: SECOidTag) -> *mut SECOidData;
+    pub fn SECOID_FindOID(oid: *const SECItem) -> *mut SECOidData;
+    pub fn SECOID_FindOIDTag(oid: *const SECItem) -> SECOidTag;
+    pub fn SEC_StringToOID(pool: *mut PLArenaPool, to: *mut SECItem, from: *const c_char,
+                           len: PRUint32) -> SECStatus;
+    pub fn SECOID_SetAlgorithmID(arena: *mut PLArenaPool, aid: *mut SECAlgorithmID,
+                                 tag: SECOidTag, params: *mut SECItem) -> SECStatus;
+    pub fn SEC_DerSignData(arena: *mut PLArenaPool, result: *mut SECItem, buf: *const c_uchar,
+                           len: c_int, pk: *mut SECKEYPrivateKey, algid: SECOidTag) -> SECStatus;
+}
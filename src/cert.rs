@@ -2,41 +2,100 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
 
-use super::{sec_item_as_slice, wrap_ffi, Result};
+use super::{
+    bit_string_as_slice, sec_item_as_slice, sec_item_from_slice, wrap_ffi, Error, ErrorCode,
+    GenStatus, Result,
+};
+use error::{
+    SEC_ERROR_BAD_DATA, SEC_ERROR_BAD_DER, SEC_ERROR_CERT_NICKNAME_COLLISION,
+    SEC_ERROR_EXPIRED_ISSUER_CERTIFICATE, SEC_ERROR_EXTENSION_NOT_FOUND,
+    SEC_ERROR_INADEQUATE_CERT_TYPE, SEC_ERROR_INVALID_ARGS, SEC_ERROR_INVALID_KEY,
+    SEC_ERROR_NO_KEY, SEC_ERROR_UNKNOWN_ISSUER, SEC_ERROR_UNTRUSTED_ISSUER,
+    SSL_ERROR_NO_CERTIFICATE,
+};
+use libc::{c_char, c_int, c_uint, c_ulong, c_void};
+use nspr::time::{system_time_from_nspr, system_time_to_nspr};
 use nspr::{ListIterator, ListNode, Listable};
 use nss_sys as ffi;
-use std::ffi::CStr;
+use oid::Oid;
+use pk11::{sha256, HashAlgorithm, PrivateKey, PublicKey, Slot};
+use std::ffi::{CStr, CString};
 use std::marker::PhantomData;
 use std::mem;
-use std::ops::Deref;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::ops::{BitOr, BitOrAssign, Deref};
 use std::ptr;
+use std::result;
+use std::slice;
+use std::time::{Duration, SystemTime};
+
+// The CA/Browser Forum's extended validation policy.
+const CABF_EV_POLICY_OID: &str = "2.23.140.1.1";
 
 pub struct Certificate(*mut ffi::CERTCertificate);
+// NSS certificates are reference-counted and locked internally.
+unsafe impl Send for Certificate {}
+unsafe impl Sync for Certificate {}
 
 impl Certificate {
+    // These take over one reference (as returned by, e.g.,
+    // CERT_DupCertificate or SSL_PeerCertificate), which is released
+    // on drop; a borrowed pointer needs CERT_DupCertificate first.
+    /// # Safety
+    ///
+    /// `ptr` must be a non-null certificate reference that the caller
+    /// owns; it's released on drop.
     pub unsafe fn from_raw_ptr(ptr: *mut ffi::CERTCertificate) -> Self {
-        assert!(ptr != ptr::null_mut());
+        assert!(!ptr.is_null());
         Certificate(ptr)
     }
+    /// # Safety
+    ///
+    /// As for `from_raw_ptr`, except that `ptr` may be null.
     pub unsafe fn from_raw_ptr_opt(ptr: *mut ffi::CERTCertificate) -> Option<Self> {
-        if ptr == ptr::null_mut() {
+        if ptr.is_null() {
             None
         } else {
             Some(Self::from_raw_ptr(ptr))
         }
     }
+    /// # Safety
+    ///
+    /// As for `from_raw_ptr`, except that `ptr` may be null.
+    pub unsafe fn from_raw_ptr_err(ptr: *mut ffi::CERTCertificate) -> GenStatus<Self> {
+        if ptr.is_null() {
+            GenStatus::ErrorFromC
+        } else {
+            GenStatus::Success(Self::from_raw_ptr(ptr))
+        }
+    }
     pub fn into_raw_ptr(self) -> *mut ffi::CERTCertificate {
         let ptr = self.0;
-        debug_assert!(ptr != ptr::null_mut());
+        debug_assert!(!ptr.is_null());
         mem::forget(self);
         ptr
     }
     pub fn as_raw_ptr(&self) -> *const ffi::CERTCertificate {
-        debug_assert!(self.0 != ptr::null_mut());
+        debug_assert!(!self.0.is_null());
         self.0
     }
     pub fn as_ffi_ref(&self) -> &ffi::CERTCertificate {
-        unsafe { mem::transmute(self.as_raw_ptr()) }
+        unsafe { &*self.as_raw_ptr() }
+    }
+
+    // The certificate is a temporary one in the default DB, which
+    // copies the DER, so `der` doesn't need to outlive the result.
+    pub fn from_der(der: &[u8]) -> Result<Self> {
+        let mut item = sec_item_from_slice(der);
+        wrap_ffi(|| unsafe {
+            Self::from_raw_ptr_err(ffi::CERT_NewTempCertificate(
+                ffi::CERT_GetDefaultCertDB(),
+                &mut item,
+                ptr::null_mut(),
+                ffi::nspr::PR_FALSE,
+                ffi::nspr::PR_TRUE,
+            ))
+        })
     }
 
     pub fn as_der(&self) -> &[u8] {
@@ -46,17 +105,653 @@ impl Certificate {
     pub fn verify_name(&self, host_name: &CStr) -> Result<()> {
         wrap_ffi(|| unsafe { ffi::CERT_VerifyCertName(self.as_raw_ptr(), host_name.as_ptr()) })
     }
+
+    // Verifies the certificate for use as a TLS server at `time`
+    // (default: now), against the trust settings and any other
+    // certificates (e.g., intermediates) currently in the cert DB.
+    // This doesn't check the name; see `verify_name`.
+    pub fn verify(&self, time: Option<SystemTime>) -> Result<()> {
+        let time = system_time_to_nspr(time.unwrap_or_else(SystemTime::now));
+        wrap_ffi(|| unsafe {
+            ffi::CERT_VerifyCertificate(
+                ffi::CERT_GetDefaultCertDB(),
+                self.as_raw_ptr() as *mut _,
+                ffi::nspr::PR_TRUE,
+                CertUsage::SslServer.certificate_usage(),
+                time,
+                ptr::null_mut(),
+                ptr::null_mut(),
+                ptr::null_mut(),
+            )
+        })
+    }
+
+    // Like `verify`, but for `usage`, with libpkix, and with the
+    // revocation checking and the rest controlled by `flags` instead
+    // of the process-wide OCSP settings.  Locally available CRLs (see
+    // `crl::import_der`) are always used.
+    pub fn verify_with_flags(
+        &self,
+        flags: VerifyFlags,
+        usage: CertUsage,
+        time: Option<SystemTime>,
+    ) -> Result<()> {
+        self.pkix_verify(usage, time, flags, None)
+    }
+
+    // Verifies for `usage` with libpkix, trusting only `anchors` (and
+    // ignoring the trust settings in the cert DB).  Intermediates can
+    // come from the DB or, for a TLS peer, its chain.
+    pub fn verify_with_anchors(
+        &self,
+        anchors: &CertList,
+        usage: CertUsage,
+        time: Option<SystemTime>,
+    ) -> Result<()> {
+        self.pkix_verify(usage, time, VerifyFlags::empty(), Some(anchors))
+    }
+
+    // Like `verify`, for `usage`, with `intermediates` available for
+    // building the chain even if they're nowhere else (e.g., the peer
+    // didn't send them).  A `Certificate` is a temporary cert that NSS
+    // can find while it's referenced, so these are held for the call
+    // and nothing is left behind.  Any valid chain to a trust anchor
+    // is accepted, including one through other certificates NSS has,
+    // such as an equivalent intermediate.
+    pub fn verify_with_intermediates(
+        &self,
+        intermediates: &[Certificate],
+        usage: CertUsage,
+        time: Option<SystemTime>,
+    ) -> Result<()> {
+        let held = intermediates.to_vec();
+        let res = self.pkix_verify(usage, time, VerifyFlags::empty(), None);
+        drop(held);
+        res
+    }
+
+    fn pkix_verify(
+        &self,
+        usage: CertUsage,
+        time: Option<SystemTime>,
+        flags: VerifyFlags,
+        anchors: Option<&CertList>,
+    ) -> Result<()> {
+        Verifier::new(flags, None)
+            .verify_chain(self, usage, time, anchors)
+            .map(|_| ())
+    }
+
+    // Checks only that the key usage and extended key usage (if
+    // present) allow `usage`, without building or verifying a chain.
+    pub fn check_usage(&self, usage: CertUsage) -> Result<()> {
+        let (key_usage, cert_type) = usage.requirements();
+        wrap_ffi(|| unsafe { ffi::cert::CERT_CheckCertUsage(self.0, key_usage) })?;
+        if self.as_ffi_ref().nsCertType & cert_type == 0 {
+            return Err(SEC_ERROR_INADEQUATE_CERT_TYPE.into());
+        }
+        Ok(())
+    }
+
+    // Stores the certificate permanently in the cert DB, which NSS
+    // must have been initialized with read-write, and sets its trust.
+    // Importing the same certificate again just changes the trust.
+    pub fn import_to_db(&self, nickname: &CStr, trust: TrustFlags) -> Result<()> {
+        self.import_to_slot(&Slot::internal_key()?, nickname, trust)
+    }
+
+    // As `import_to_db`, but to some other token, like a user DB
+    // opened separately; its password has to have been set, even if
+    // only to the empty string, for it to store trust.
+    pub fn import_to_slot(&self, slot: &Slot, nickname: &CStr, trust: TrustFlags) -> Result<()> {
+        let db = unsafe { ffi::CERT_GetDefaultCertDB() };
+        // Other tokens' nicknames are qualified with the token's name.
+        let full_name = if unsafe { ffi::pk11::PK11_IsInternalKeySlot(slot.as_raw_ptr()) } != 0 {
+            nickname.to_owned()
+        } else {
+            let mut name = slot.token_name().into_bytes();
+            name.push(b':');
+            name.extend_from_slice(nickname.to_bytes());
+            CString::new(name).map_err(|_| SEC_ERROR_INVALID_ARGS)?
+        };
+        let existing = unsafe {
+            Certificate::from_raw_ptr_opt(ffi::cert::CERT_FindCertByNickname(
+                db,
+                full_name.as_ptr(),
+            ))
+        };
+        if existing.map_or(false, |cert| cert.as_der() != self.as_der()) {
+            return Err(SEC_ERROR_CERT_NICKNAME_COLLISION.into());
+        }
+        wrap_ffi(|| unsafe {
+            ffi::cert::PK11_ImportCert(
+                slot.as_raw_ptr(),
+                self.0,
+                ffi::cert::CK_INVALID_HANDLE,
+                nickname.as_ptr(),
+                ffi::nspr::PR_FALSE,
+            )
+        })?;
+        let mut trust = trust.to_ffi();
+        wrap_ffi(|| unsafe { ffi::CERT_ChangeCertTrust(db, self.0, &mut trust) })
+    }
+
+    // The matching private key from whichever token holds it, such as
+    // a cert DB's key DB; that token has to be logged in, and
+    // `pin_arg` is passed to the password callback if it isn't.
+    pub fn private_key(&self, pin_arg: Option<*mut c_void>) -> Result<Option<PrivateKey>> {
+        let key = unsafe {
+            ffi::nspr::PR_SetError(0, 0);
+            let key = ffi::cert::PK11_FindKeyByAnyCert(self.0, pin_arg.unwrap_or(ptr::null_mut()));
+            PrivateKey::from_raw_ptr_opt(key)
+        };
+        if key.is_some() {
+            return Ok(key);
+        }
+        // Not finding one isn't always reported as an error, and a
+        // cert that isn't on any token is "no certificate".
+        let err = Error::last();
+        if err.nspr_error == ErrorCode(0)
+            || err.nspr_error == SEC_ERROR_NO_KEY
+            || err.nspr_error == SSL_ERROR_NO_CERTIFICATE
+        {
+            Ok(None)
+        } else {
+            Err(err)
+        }
+    }
+
+    pub fn public_key(&self) -> Result<PublicKey> {
+        wrap_ffi(|| unsafe {
+            PublicKey::from_raw_ptr_err(ffi::CERT_ExtractPublicKey(self.as_raw_ptr() as *mut _))
+        })
+    }
+
+    // NotBefore and NotAfter.
+    pub fn validity(&self) -> Result<(SystemTime, SystemTime)> {
+        let (not_before, not_after) = self.validity_prtime()?;
+        Ok((
+            system_time_from_nspr(not_before),
+            system_time_from_nspr(not_after),
+        ))
+    }
+
+    // The same as NSS has them, in microseconds since the epoch, to
+    // compare with PR_Now and the like.
+    pub fn validity_prtime(&self) -> Result<(i64, i64)> {
+        let mut not_before = 0;
+        let mut not_after = 0;
+        wrap_ffi(|| unsafe {
+            ffi::cert::CERT_GetCertTimes(self.as_raw_ptr(), &mut not_before, &mut not_after)
+        })?;
+        Ok((not_before, not_after))
+    }
+
+    // The SHA-256 hash of the DER SubjectPublicKeyInfo, as used for
+    // public key pinning (e.g., HPKP's `pin-sha256`).
+    pub fn spki_sha256(&self) -> Result<[u8; 32]> {
+        sha256(&self.public_key()?.spki_der()?)
+    }
+
+    // NSS's name for the algorithm the issuer signed this with, like
+    // "PKCS #1 SHA-256 With RSA Encryption"; or the dotted OID, if NSS
+    // doesn't know it.
+    pub fn signature_algorithm(&self) -> Result<String> {
+        let alg = &self.as_ffi_ref().signature;
+        unsafe {
+            let tag = ffi::pk11::SECOID_GetAlgorithmTag(alg);
+            let data = ffi::pk11::SECOID_FindOIDByTag(tag);
+            if tag != ffi::pk11::SEC_OID_UNKNOWN && !data.is_null() {
+                return Ok(CStr::from_ptr((*data).desc).to_string_lossy().into_owned());
+            }
+        }
+        dotted_oid(&alg.algorithm)
+    }
+
+    // The issuer's signature over the certificate, as it appears in
+    // the DER (e.g., for an ECDSA signature, its DER encoding).
+    pub fn signature(&self) -> &[u8] {
+        unsafe { bit_string_as_slice(&self.as_ffi_ref().signatureWrap.signature) }
+    }
+
+    // The hash for the RFC 5929 `tls-server-end-point` channel
+    // binding: the signature's, or SHA-256 instead of MD5 or SHA-1.
+    // `None` for signatures that don't name one this way, like
+    // RSA-PSS.
+    pub fn end_point_hash(&self) -> Option<HashAlgorithm> {
+        use ffi::pk11::*;
+        let tag = unsafe { SECOID_GetAlgorithmTag(&self.as_ffi_ref().signature) };
+        match tag {
+            SEC_OID_PKCS1_MD5_WITH_RSA_ENCRYPTION
+            | SEC_OID_PKCS1_SHA1_WITH_RSA_ENCRYPTION
+            | SEC_OID_PKCS1_SHA256_WITH_RSA_ENCRYPTION
+            | SEC_OID_ANSIX962_ECDSA_SHA1_SIGNATURE
+            | SEC_OID_ANSIX962_ECDSA_SHA256_SIGNATURE => Some(HashAlgorithm::Sha256),
+            SEC_OID_PKCS1_SHA384_WITH_RSA_ENCRYPTION | SEC_OID_ANSIX962_ECDSA_SHA384_SIGNATURE => {
+                Some(HashAlgorithm::Sha384)
+            }
+            SEC_OID_PKCS1_SHA512_WITH_RSA_ENCRYPTION | SEC_OID_ANSIX962_ECDSA_SHA512_SIGNATURE => {
+                Some(HashAlgorithm::Sha512)
+            }
+            _ => None,
+        }
+    }
+
+    // The certificatePolicies extension's policy OIDs, dotted, in
+    // order; empty if there's no such extension.
+    pub fn policy_oids(&self) -> Result<Vec<String>> {
+        let mut value = unsafe { mem::zeroed::<ffi::SECItem>() };
+        let found = wrap_ffi(|| unsafe {
+            ffi::cert::CERT_FindCertExtension(
+                self.as_raw_ptr(),
+                ffi::cert::SEC_OID_X509_CERTIFICATE_POLICIES as c_int,
+                &mut value,
+            )
+        });
+        match found {
+            Ok(()) => (),
+            Err(err) if err.nspr_error == SEC_ERROR_EXTENSION_NOT_FOUND => return Ok(vec![]),
+            Err(err) => return Err(err),
+        }
+        unsafe {
+            let policies = Policies::decode(&value);
+            ffi::SECITEM_FreeItem(&mut value, ffi::nspr::PR_FALSE);
+            let policies = policies?;
+            let mut oids = Vec::new();
+            let mut info = (*policies.0).policyInfos;
+            while !(*info).is_null() {
+                oids.push(dotted_oid(&(**info).policyID)?);
+                info = info.offset(1);
+            }
+            Ok(oids)
+        }
+    }
+
+    // Whether it claims to be EV, by the CA/Browser Forum's policy OID.
+    // NSS has no table of the CA-specific EV OIDs that some older
+    // certificates use (browsers ship their own), so those give false.
+    // This doesn't check that the chain verifies, or that its root is
+    // one trusted to issue EV certificates.
+    pub fn is_extended_validation(&self) -> Result<bool> {
+        Ok(self
+            .policy_oids()?
+            .iter()
+            .any(|oid| oid == CABF_EV_POLICY_OID))
+    }
+
+    pub fn subject_component(&self, oid: RdnOid) -> Result<Option<String>> {
+        let get: unsafe extern "C" fn(*const ffi::CERTName) -> *mut c_char = match oid {
+            RdnOid::CommonName => ffi::CERT_GetCommonName,
+            RdnOid::Country => ffi::CERT_GetCountryName,
+            RdnOid::Locality => ffi::CERT_GetLocalityName,
+            RdnOid::State => ffi::CERT_GetStateName,
+            RdnOid::Organization => ffi::CERT_GetOrgName,
+            RdnOid::OrganizationalUnit => ffi::CERT_GetOrgUnitName,
+            RdnOid::DomainComponent => ffi::CERT_GetDomainComponentName,
+            RdnOid::UserId => ffi::CERT_GetCertUid,
+            RdnOid::EmailAddress => ffi::CERT_GetCertEmailAddress,
+        };
+        unsafe {
+            let value = get(&self.as_ffi_ref().subject);
+            if value.is_null() {
+                return Ok(None);
+            }
+            let res = CStr::from_ptr(value)
+                .to_str()
+                .map(|value| Some(value.to_owned()))
+                .map_err(|_| SEC_ERROR_BAD_DATA.into());
+            ffi::PORT_Free(value as *mut _);
+            res
+        }
+    }
+}
+
+fn dotted_oid(oid: &ffi::SECItem) -> Result<String> {
+    Oid::from_bytes(unsafe { sec_item_as_slice(oid) }).to_dotted()
+}
+
+// Subject name attributes that `Certificate::subject_component` can
+// look up.  `EmailAddress` is PKCS#9 emailAddress or RFC 1274 mail,
+// and NSS lowercases it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RdnOid {
+    CommonName,
+    Country,
+    Locality,
+    State,
+    Organization,
+    OrganizationalUnit,
+    DomainComponent,
+    UserId,
+    EmailAddress,
+}
+
+// What an end-entity certificate is to be used for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CertUsage {
+    SslClient,
+    SslServer,
+    EmailSigner,
+    EmailRecipient,
+    ObjectSigner,
+}
+
+impl CertUsage {
+    fn certificate_usage(self) -> ffi::SECCertificateUsage {
+        match self {
+            CertUsage::SslClient => ffi::certificateUsageSSLClient,
+            CertUsage::SslServer => ffi::certificateUsageSSLServer,
+            CertUsage::EmailSigner => ffi::certificateUsageEmailSigner,
+            CertUsage::EmailRecipient => ffi::certificateUsageEmailRecipient,
+            CertUsage::ObjectSigner => ffi::certificateUsageObjectSigner,
+        }
+    }
+
+    // Any one of the key usage bits will do.  A TLS server's key can
+    // sign (ECDHE), decrypt (RSA key exchange) or agree (static DH).
+    fn requirements(self) -> (u8, u32) {
+        use ffi::cert::*;
+        use ffi::pk11::*;
+
+        let (key_usage, cert_type) = match self {
+            CertUsage::SslClient => (KU_DIGITAL_SIGNATURE, NS_CERT_TYPE_SSL_CLIENT),
+            CertUsage::SslServer => (
+                KU_DIGITAL_SIGNATURE | KU_KEY_ENCIPHERMENT | KU_KEY_AGREEMENT,
+                NS_CERT_TYPE_SSL_SERVER,
+            ),
+            CertUsage::EmailSigner => (
+                KU_DIGITAL_SIGNATURE | KU_NON_REPUDIATION,
+                NS_CERT_TYPE_EMAIL,
+            ),
+            CertUsage::EmailRecipient => {
+                (KU_KEY_ENCIPHERMENT | KU_KEY_AGREEMENT, NS_CERT_TYPE_EMAIL)
+            }
+            CertUsage::ObjectSigner => (KU_DIGITAL_SIGNATURE, NS_CERT_TYPE_OBJECT_SIGNING),
+        };
+        (key_usage as u8, cert_type)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct VerifyFlags(u32);
+
+impl VerifyFlags {
+    // Requires a current OCSP response for the end-entity certificate.
+    pub const REQUIRE_OCSP: VerifyFlags = VerifyFlags(1 << 0);
+    // Requires a current CRL for every certificate in the chain.
+    pub const REQUIRE_CRL: VerifyFlags = VerifyFlags(1 << 1);
+    // Allows fetching missing intermediates (via AIA), OCSP responses
+    // and CRLs over the network.
+    pub const FETCH: VerifyFlags = VerifyFlags(1 << 2);
+    // With a `Verifier`'s own anchors, also trusts the anchors in the
+    // cert DB, instead of only those.
+    pub const DB_ANCHORS: VerifyFlags = VerifyFlags(1 << 3);
+    // Accepts a chain whose CAs have expired since, as long as the
+    // certificate itself is valid at the time given and the chain was
+    // valid when the certificate was issued (its notBefore).  The
+    // chain, revocation included, is then checked as of that time.
+    pub const ALLOW_EXPIRED_INTERMEDIATES: VerifyFlags = VerifyFlags(1 << 4);
+
+    pub fn empty() -> Self {
+        VerifyFlags(0)
+    }
+
+    pub fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for VerifyFlags {
+    type Output = Self;
+    fn bitor(self, other: Self) -> Self {
+        VerifyFlags(self.0 | other.0)
+    }
+}
+
+impl BitOrAssign for VerifyFlags {
+    fn bitor_assign(&mut self, other: Self) {
+        self.0 |= other.0;
+    }
+}
+
+// Flags and anchors for libpkix verification, kept together for
+// verifying any number of certificates the same way;
+// `Certificate::verify_with_flags` and `verify_with_anchors` make a
+// new one each time.  This saves little: only the revocation settings
+// are built once, and NSS still allocates for each chain it builds
+// (that isn't exposed).  It isn't `Send` or `Sync`, so use one per
+// thread.
+pub struct Verifier {
+    flags: VerifyFlags,
+    anchors: Option<CertList>,
+    // Boxed, since `revocation` points into them.
+    _leaf_methods: Box<[u64; 2]>,
+    _chain_methods: Box<[u64; 2]>,
+    revocation: ffi::CERTRevocationFlags,
+}
+
+impl Verifier {
+    // With `anchors`, trusts only those, as `verify_with_anchors` does.
+    pub fn new(flags: VerifyFlags, anchors: Option<CertList>) -> Self {
+        use ffi::cert::*;
+
+        let fetch = if flags.contains(VerifyFlags::FETCH) {
+            CERT_REV_M_ALLOW_NETWORK_FETCHING
+        } else {
+            CERT_REV_M_FORBID_NETWORK_FETCHING
+        };
+        let required =
+            CERT_REV_M_REQUIRE_INFO_ON_MISSING_SOURCE | CERT_REV_M_FAIL_ON_MISSING_FRESH_INFO;
+        let mut crl = CERT_REV_M_TEST_USING_THIS_METHOD | fetch;
+        if flags.contains(VerifyFlags::REQUIRE_CRL) {
+            crl |= required;
+        }
+        let leaf_ocsp = if flags.contains(VerifyFlags::REQUIRE_OCSP) {
+            CERT_REV_M_TEST_USING_THIS_METHOD | fetch | required
+        } else {
+            CERT_REV_M_DO_NOT_TEST_USING_THIS_METHOD
+        };
+        // Indexed by `CERTRevocationMethodIndex`.
+        let mut leaf_methods = Box::new([crl, leaf_ocsp]);
+        let mut chain_methods = Box::new([crl, CERT_REV_M_DO_NOT_TEST_USING_THIS_METHOD]);
+        let tests = |methods: &mut [u64; 2]| ffi::CERTRevocationTests {
+            number_of_defined_methods: methods.len() as u32,
+            cert_rev_flags_per_method: methods.as_mut_ptr(),
+            number_of_preferred_methods: 0,
+            preferred_methods: ptr::null_mut(),
+            cert_rev_method_independent_flags: CERT_REV_MI_TEST_ALL_LOCAL_INFORMATION_FIRST,
+        };
+        let revocation = ffi::CERTRevocationFlags {
+            leafTests: tests(&mut leaf_methods),
+            chainTests: tests(&mut chain_methods),
+        };
+        Verifier {
+            flags,
+            anchors,
+            _leaf_methods: leaf_methods,
+            _chain_methods: chain_methods,
+            revocation,
+        }
+    }
+
+    // Verifies `cert` for `usage` at `time` (default: now).
+    pub fn verify(
+        &self,
+        cert: &Certificate,
+        usage: CertUsage,
+        time: Option<SystemTime>,
+    ) -> Result<()> {
+        self.verify_chain(cert, usage, time, self.anchors.as_ref())
+            .map(|_| ())
+    }
+
+    // The chain that was built, from `cert` up, and the trust anchor.
+    fn verify_chain(
+        &self,
+        cert: &Certificate,
+        usage: CertUsage,
+        time: Option<SystemTime>,
+        anchors: Option<&CertList>,
+    ) -> Result<(CertList, Option<Certificate>)> {
+        let time = system_time_to_nspr(time.unwrap_or_else(SystemTime::now));
+        match self.verify_chain_at(cert, usage, time, anchors) {
+            // libpkix passes over expired candidates while building the
+            // chain, so it usually reports an unknown issuer instead.
+            Err(err)
+                if (err.nspr_error == SEC_ERROR_EXPIRED_ISSUER_CERTIFICATE
+                    || err.nspr_error == SEC_ERROR_UNKNOWN_ISSUER)
+                    && self
+                        .flags
+                        .contains(VerifyFlags::ALLOW_EXPIRED_INTERMEDIATES) =>
+            {
+                let (not_before, not_after) = cert.validity_prtime()?;
+                if time < not_before || time > not_after {
+                    return Err(err);
+                }
+                self.verify_chain_at(cert, usage, not_before, anchors)
+            }
+            res => res,
+        }
+    }
+
+    fn verify_chain_at(
+        &self,
+        cert: &Certificate,
+        usage: CertUsage,
+        time: ffi::nspr::PRTime,
+        anchors: Option<&CertList>,
+    ) -> Result<(CertList, Option<Certificate>)> {
+        use ffi::cert::*;
+
+        let mut params: [ffi::CERTValInParam; 6] = unsafe { mem::zeroed() };
+        params[0].type_ = cert_pi_date;
+        params[0].value.scalar.time = time;
+        params[1].type_ = cert_pi_revocationFlags;
+        params[1].value.pointer.revocation = &self.revocation;
+        params[2].type_ = cert_pi_useAIACertFetch;
+        params[2].value.scalar.b = self.flags.contains(VerifyFlags::FETCH) as ffi::nspr::PRBool;
+        let mut end = 3;
+        if let Some(anchors) = anchors {
+            params[3].type_ = cert_pi_trustAnchors;
+            params[3].value.pointer.chain = anchors.as_raw_ptr();
+            params[4].type_ = cert_pi_useOnlyTrustAnchors;
+            params[4].value.scalar.b = !self.flags.contains(VerifyFlags::DB_ANCHORS) as _;
+            end = 5;
+        }
+        params[end].type_ = cert_pi_end;
+        let mut out: [ffi::cert::CERTValOutParam; 3] = unsafe { mem::zeroed() };
+        out[0].type_ = cert_po_trustAnchor;
+        out[1].type_ = cert_po_certList;
+        out[2].type_ = cert_po_end;
+        wrap_ffi(|| unsafe {
+            ffi::CERT_PKIXVerifyCert(
+                cert.as_raw_ptr() as *mut _,
+                usage.certificate_usage(),
+                params.as_mut_ptr(),
+                out.as_mut_ptr(),
+                ptr::null_mut(),
+            )
+        })?;
+        let anchor = unsafe { Certificate::from_raw_ptr_opt(out[0].value.pointer.cert) };
+        let chain = unsafe { CertList::from_raw_ptr_opt(out[1].value.pointer.chain) }
+            .ok_or(SEC_ERROR_UNKNOWN_ISSUER)?;
+        // libpkix can reuse a chain it built earlier with the DB's trust
+        // settings, despite `anchors`; so check what it ended up at.
+        match anchors {
+            Some(_) if self.flags.contains(VerifyFlags::DB_ANCHORS) => (),
+            Some(anchors) => {
+                let anchor = anchor.as_ref().ok_or(SEC_ERROR_UNTRUSTED_ISSUER)?;
+                if !anchors.iter().any(|cert| cert.as_der() == anchor.as_der()) {
+                    return Err(SEC_ERROR_UNTRUSTED_ISSUER.into());
+                }
+            }
+            None => (),
+        }
+        Ok((chain, anchor))
+    }
+}
+
+// What a certificate imported into the DB is trusted for, as in
+// certutil's trust strings.  With none of these, a CA is still usable
+// as an intermediate, but not as a trust anchor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct TrustFlags(u32);
+
+impl TrustFlags {
+    // Trusted to issue TLS server certificates ("C").
+    pub const SERVER_CA: TrustFlags = TrustFlags(1 << 0);
+    // Trusted to issue TLS client certificates ("T").
+    pub const CLIENT_CA: TrustFlags = TrustFlags(1 << 1);
+    // A TLS peer's certificate trusted as is, whatever issued it ("P").
+    pub const PEER: TrustFlags = TrustFlags(1 << 2);
+    pub const EMAIL_CA: TrustFlags = TrustFlags(1 << 3);
+    pub const OBJECT_SIGNING_CA: TrustFlags = TrustFlags(1 << 4);
+
+    pub fn empty() -> Self {
+        TrustFlags(0)
+    }
+
+    pub fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    fn to_ffi(self) -> ffi::CERTCertTrust {
+        use ffi::cert::*;
+
+        let ca = CERTDB_VALID_CA | CERTDB_TRUSTED_CA;
+        let mut trust = ffi::CERTCertTrust {
+            sslFlags: 0,
+            emailFlags: 0,
+            objectSigningFlags: 0,
+        };
+        if self.contains(TrustFlags::SERVER_CA) {
+            trust.sslFlags |= ca;
+        }
+        if self.contains(TrustFlags::CLIENT_CA) {
+            trust.sslFlags |= CERTDB_VALID_CA | CERTDB_TRUSTED_CLIENT_CA;
+        }
+        if self.contains(TrustFlags::PEER) {
+            trust.sslFlags |= CERTDB_TERMINAL_RECORD | CERTDB_TRUSTED;
+        }
+        if self.contains(TrustFlags::EMAIL_CA) {
+            trust.emailFlags |= ca;
+        }
+        if self.contains(TrustFlags::OBJECT_SIGNING_CA) {
+            trust.objectSigningFlags |= ca;
+        }
+        trust
+    }
+}
+
+impl BitOr for TrustFlags {
+    type Output = Self;
+    fn bitor(self, other: Self) -> Self {
+        TrustFlags(self.0 | other.0)
+    }
+}
+
+impl BitOrAssign for TrustFlags {
+    fn bitor_assign(&mut self, other: Self) {
+        self.0 |= other.0;
+    }
+}
+
+impl Clone for Certificate {
+    fn clone(&self) -> Self {
+        unsafe { Self::from_raw_ptr(ffi::CERT_DupCertificate(self.as_raw_ptr() as *mut _)) }
+    }
 }
 
 impl Drop for Certificate {
     fn drop(&mut self) {
         let ptr = mem::replace(&mut self.0, ptr::null_mut());
-        if ptr != ptr::null_mut() {
+        if !ptr.is_null() {
             unsafe { ffi::CERT_DestroyCertificate(ptr) }
         }
     }
 }
 
+#[allow(dead_code)] // Only accessed via the `Deref` transmute.
 pub struct BorrowedCertificate<'a>(*mut ffi::CERTCertificate, PhantomData<&'a Certificate>);
 
 impl<'a> Listable for BorrowedCertificate<'a> {
@@ -84,18 +779,45 @@ impl<'a> BorrowedCertificate<'a> {
 pub struct CertList(*mut ffi::CERTCertList);
 
 impl CertList {
+    // Holds a new reference to each of `certs`.
+    pub fn from_certs(certs: &[Certificate]) -> Result<Self> {
+        let list = wrap_ffi(|| unsafe {
+            Self::from_raw_ptr_opt(ffi::cert::CERT_NewCertList())
+                .map_or(GenStatus::ErrorFromC, GenStatus::Success)
+        })?;
+        for cert in certs {
+            let raw = cert.clone().into_raw_ptr();
+            // The list only takes the reference if this succeeds.
+            let res = wrap_ffi(|| unsafe { ffi::cert::CERT_AddCertToListTail(list.0, raw) });
+            if res.is_err() {
+                unsafe { ffi::CERT_DestroyCertificate(raw) };
+            }
+            res?;
+        }
+        Ok(list)
+    }
+    pub fn as_raw_ptr(&self) -> *mut ffi::CERTCertList {
+        self.0
+    }
+    /// # Safety
+    ///
+    /// `ptr` must be a non-null list that the caller owns; it's
+    /// destroyed on drop.
     pub unsafe fn from_raw_ptr(ptr: *mut ffi::CERTCertList) -> Self {
-        assert!(ptr != ptr::null_mut());
+        assert!(!ptr.is_null());
         CertList(ptr)
     }
+    /// # Safety
+    ///
+    /// As for `from_raw_ptr`, except that `ptr` may be null.
     pub unsafe fn from_raw_ptr_opt(ptr: *mut ffi::CERTCertList) -> Option<Self> {
-        if ptr == ptr::null_mut() {
+        if ptr.is_null() {
             None
         } else {
             Some(Self::from_raw_ptr(ptr))
         }
     }
-    pub fn iter(&self) -> ListIterator<BorrowedCertificate> {
+    pub fn iter(&self) -> ListIterator<'_, BorrowedCertificate<'_>> {
         self.into_iter()
     }
 }
@@ -103,7 +825,7 @@ impl CertList {
 impl Drop for CertList {
     fn drop(&mut self) {
         let ptr = mem::replace(&mut self.0, ptr::null_mut());
-        if ptr != ptr::null_mut() {
+        if !ptr.is_null() {
             unsafe { ffi::CERT_DestroyCertList(ptr) }
         }
     }
@@ -117,5 +839,1187 @@ impl<'a> IntoIterator for &'a CertList {
     }
 }
 
-// Could also do a consuming iterator on CertList that unlinks and frees nodes as it goes.
-// But I don't think anything actually needs that.
+// Yields a new reference to each certificate, so they outlive the list.
+impl IntoIterator for CertList {
+    type Item = Certificate;
+    type IntoIter = CertListIntoIter;
+    fn into_iter(self) -> Self::IntoIter {
+        let iter = unsafe { ListIterator::new(&(*self.0).list as *const _ as ListNode) };
+        CertListIntoIter { iter, _list: self }
+    }
+}
+
+pub struct CertListIntoIter {
+    // Points into `_list`, which is kept alive for it.
+    iter: ListIterator<'static, BorrowedCertificate<'static>>,
+    _list: CertList,
+}
+
+impl Iterator for CertListIntoIter {
+    type Item = Certificate;
+    fn next(&mut self) -> Option<Certificate> {
+        self.iter.next().map(|cert| cert.clone())
+    }
+}
+
+impl DoubleEndedIterator for CertListIntoIter {
+    fn next_back(&mut self) -> Option<Certificate> {
+        self.iter.next_back().map(|cert| cert.clone())
+    }
+}
+
+const PEM_CERT_BEGIN: &str = "-----BEGIN CERTIFICATE-----";
+const PEM_CERT_END: &str = "-----END CERTIFICATE-----";
+
+#[derive(Clone, Debug)]
+pub struct PemError {
+    // Which `BEGIN CERTIFICATE` block failed, counting from 0.
+    pub index: usize,
+    pub error: Error,
+}
+
+impl From<PemError> for Error {
+    fn from(err: PemError) -> Self {
+        err.error
+    }
+}
+
+fn cert_from_base64(b64: &str) -> Result<Certificate> {
+    assert!(b64.len() <= c_uint::MAX as usize);
+    unsafe {
+        let item = wrap_ffi(|| {
+            let item = ffi::NSSBase64_DecodeBuffer(
+                ptr::null_mut(),
+                ptr::null_mut(),
+                b64.as_ptr() as *const c_char,
+                b64.len() as c_uint,
+            );
+            if item.is_null() {
+                GenStatus::ErrorFromC
+            } else {
+                GenStatus::Success(item)
+            }
+        })?;
+        let cert = Certificate::from_der(sec_item_as_slice(&*item));
+        ffi::SECITEM_FreeItem(item, ffi::nspr::PR_TRUE);
+        cert
+    }
+}
+
+// Parses every `CERTIFICATE` block in a PEM bundle, in file order.
+// Anything between blocks (blank lines, comments, other headers) is
+// ignored.
+pub fn parse_chain_pem(pem: &str) -> result::Result<Vec<Certificate>, PemError> {
+    let mut certs = Vec::new();
+    let mut block: Option<String> = None;
+    for line in pem.lines().map(str::trim) {
+        let index = certs.len();
+        let fail = |error: Error| PemError { index, error };
+        match block.take() {
+            None => {
+                if line == PEM_CERT_BEGIN {
+                    block = Some(String::new());
+                }
+            }
+            Some(b64) => {
+                if line == PEM_CERT_END {
+                    certs.push(cert_from_base64(&b64).map_err(fail)?);
+                } else if line.starts_with("-----") {
+                    return Err(fail(SEC_ERROR_BAD_DER.into()));
+                } else {
+                    block = Some(b64 + line);
+                }
+            }
+        }
+    }
+    if block.is_some() {
+        return Err(PemError {
+            index: certs.len(),
+            error: SEC_ERROR_BAD_DER.into(),
+        });
+    }
+    Ok(certs)
+}
+
+struct Policies(*mut ffi::cert::CERTCertificatePolicies);
+
+impl Policies {
+    fn decode(value: &ffi::SECItem) -> Result<Self> {
+        wrap_ffi(|| unsafe {
+            let ptr = ffi::cert::CERT_DecodeCertificatePoliciesExtension(value);
+            if ptr.is_null() {
+                GenStatus::ErrorFromC
+            } else {
+                GenStatus::Success(Policies(ptr))
+            }
+        })
+    }
+}
+
+impl Drop for Policies {
+    fn drop(&mut self) {
+        let ptr = mem::replace(&mut self.0, ptr::null_mut());
+        if !ptr.is_null() {
+            unsafe { ffi::cert::CERT_DestroyCertificatePoliciesExtension(ptr) }
+        }
+    }
+}
+
+struct Name(*mut ffi::CERTName);
+
+impl Name {
+    // An RFC 1485 name, like "CN=localhost, O=Example".
+    fn from_ascii(name: &str) -> Result<Self> {
+        let name = CString::new(name).map_err(|_| Error::from(SEC_ERROR_INVALID_ARGS))?;
+        wrap_ffi(|| unsafe {
+            let ptr = ffi::cert::CERT_AsciiToName(name.as_ptr());
+            if ptr.is_null() {
+                GenStatus::ErrorFromC
+            } else {
+                GenStatus::Success(Name(ptr))
+            }
+        })
+    }
+}
+
+impl Drop for Name {
+    fn drop(&mut self) {
+        let ptr = mem::replace(&mut self.0, ptr::null_mut());
+        if !ptr.is_null() {
+            unsafe { ffi::cert::CERT_DestroyName(ptr) }
+        }
+    }
+}
+
+// A PKCS #10 certificate signing request, as made by `create_csr`.
+pub struct CertRequest(*mut ffi::cert::CERTCertificateRequest);
+
+impl CertRequest {
+    // Decodes a DER request, checking that it's signed by the key it's
+    // for.
+    pub fn from_der(der: &[u8]) -> Result<Self> {
+        let der = sec_item_from_slice(der);
+        unsafe {
+            let arena = ffi::cert::PORT_NewArena(REQUEST_ARENA_CHUNK);
+            if arena.is_null() {
+                return Err(Error::last());
+            }
+            let request = ffi::cert::PORT_ArenaZAlloc(
+                arena,
+                mem::size_of::<ffi::cert::CERTCertificateRequest>(),
+            ) as *mut ffi::cert::CERTCertificateRequest;
+            if request.is_null() {
+                let err = Error::last();
+                ffi::cert::PORT_FreeArena(arena, ffi::nspr::PR_FALSE);
+                return Err(err);
+            }
+            // From here, dropping it frees the arena.
+            (*request).arena = arena;
+            let request = CertRequest(request);
+            let signed =
+                ffi::cert::PORT_ArenaZAlloc(arena, mem::size_of::<ffi::cert::CERTSignedData>())
+                    as *mut ffi::cert::CERTSignedData;
+            if signed.is_null() {
+                return Err(Error::last());
+            }
+            wrap_ffi(|| {
+                ffi::cert::SEC_ASN1DecodeItem(
+                    arena,
+                    signed as *mut c_void,
+                    &ffi::cert::CERT_SignedDataTemplate,
+                    &der,
+                )
+            })?;
+            wrap_ffi(|| {
+                ffi::cert::SEC_ASN1DecodeItem(
+                    arena,
+                    request.0 as *mut c_void,
+                    &ffi::cert::CERT_CertificateRequestTemplate,
+                    &(*signed).data,
+                )
+            })?;
+            let pubkey = request.public_key()?;
+            wrap_ffi(|| {
+                ffi::cert::CERT_VerifySignedDataWithPublicKey(
+                    signed,
+                    pubkey.as_raw_ptr(),
+                    ptr::null_mut(),
+                )
+            })?;
+            Ok(request)
+        }
+    }
+
+    fn new(subject: &Name, pubkey: &PublicKey) -> Result<Self> {
+        unsafe {
+            let spki = ffi::cert::SECKEY_CreateSubjectPublicKeyInfo(pubkey.as_raw_ptr());
+            if spki.is_null() {
+                return Err(Error::last());
+            }
+            // The request has its own copy.
+            let ptr = ffi::cert::CERT_CreateCertificateRequest(subject.0, spki, ptr::null_mut());
+            let res = if ptr.is_null() {
+                Err(Error::last())
+            } else {
+                Ok(CertRequest(ptr))
+            };
+            ffi::cert::SECKEY_DestroySubjectPublicKeyInfo(spki);
+            res
+        }
+    }
+
+    // The subject, as an RFC 1485 name.
+    pub fn subject(&self) -> Result<String> {
+        unsafe {
+            let name = ffi::cert::CERT_NameToAscii(&mut (*self.0).subject);
+            if name.is_null() {
+                return Err(Error::last());
+            }
+            let res = CStr::from_ptr(name)
+                .to_str()
+                .map(str::to_owned)
+                .map_err(|_| SEC_ERROR_BAD_DATA.into());
+            ffi::PORT_Free(name as *mut c_void);
+            res
+        }
+    }
+
+    pub fn public_key(&self) -> Result<PublicKey> {
+        wrap_ffi(|| unsafe {
+            PublicKey::from_raw_ptr_err(ffi::cert::SECKEY_ExtractPublicKey(
+                &(*self.0).subjectPublicKeyInfo,
+            ))
+        })
+    }
+
+    // The names in the requested subject alternative name extension, in
+    // order; empty if there isn't one.  Kinds of name that
+    // `GeneralName` doesn't have are left out.  NSS only looks for the
+    // extensions in the first attribute, which is where both it and
+    // OpenSSL put them.
+    pub fn subject_alt_names(&self) -> Result<Vec<GeneralName>> {
+        unsafe {
+            // Both of these allocate from the request's arena.
+            let mut exts = ptr::null_mut();
+            wrap_ffi(|| ffi::cert::CERT_GetCertificateRequestExtensions(self.0, &mut exts))?;
+            let mut names = Vec::new();
+            if exts.is_null() {
+                return Ok(names);
+            }
+            let mut ext = exts;
+            while !(*ext).is_null() {
+                let id = Oid::from_bytes(sec_item_as_slice(&(**ext).id));
+                if id.tag() == Some(ffi::cert::SEC_OID_X509_SUBJECT_ALT_NAME) {
+                    let first = wrap_ffi(|| {
+                        let first = ffi::cert::CERT_DecodeAltNameExtension(
+                            (*self.0).arena,
+                            &mut (**ext).value,
+                        );
+                        if first.is_null() {
+                            GenStatus::ErrorFromC
+                        } else {
+                            GenStatus::Success(first)
+                        }
+                    })?;
+                    let mut name = first;
+                    loop {
+                        names.extend(GeneralName::from_ffi(&*name)?);
+                        name = ffi::cert::CERT_GetNextGeneralName(name);
+                        if name == first {
+                            break;
+                        }
+                    }
+                }
+                ext = ext.offset(1);
+            }
+            Ok(names)
+        }
+    }
+}
+
+// Enough for most requests to fit in one chunk.
+const REQUEST_ARENA_CHUNK: c_ulong = 2048;
+
+impl Drop for CertRequest {
+    fn drop(&mut self) {
+        let ptr = mem::replace(&mut self.0, ptr::null_mut());
+        if !ptr.is_null() {
+            unsafe { ffi::cert::CERT_DestroyCertificateRequest(ptr) }
+        }
+    }
+}
+
+struct Validity(*mut ffi::cert::CERTValidity);
+
+impl Validity {
+    fn new(not_before: SystemTime, not_after: SystemTime) -> Result<Self> {
+        wrap_ffi(|| unsafe {
+            let ptr = ffi::cert::CERT_CreateValidity(
+                system_time_to_nspr(not_before),
+                system_time_to_nspr(not_after),
+            );
+            if ptr.is_null() {
+                GenStatus::ErrorFromC
+            } else {
+                GenStatus::Success(Validity(ptr))
+            }
+        })
+    }
+}
+
+impl Drop for Validity {
+    fn drop(&mut self) {
+        let ptr = mem::replace(&mut self.0, ptr::null_mut());
+        if !ptr.is_null() {
+            unsafe { ffi::cert::CERT_DestroyValidity(ptr) }
+        }
+    }
+}
+
+// An entry in a subject alternative name extension.  Directory names
+// and the rarer kinds aren't supported.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum GeneralName {
+    Dns(String),
+    Ip(IpAddr),
+    Email(String),
+    Uri(String),
+}
+
+impl GeneralName {
+    fn to_ffi_value(&self) -> (ffi::cert::CERTGeneralNameType, Vec<u8>) {
+        match *self {
+            GeneralName::Dns(ref name) => (ffi::cert::certDNSName, name.as_bytes().to_vec()),
+            GeneralName::Ip(IpAddr::V4(ip)) => (ffi::cert::certIPAddress, ip.octets().to_vec()),
+            GeneralName::Ip(IpAddr::V6(ip)) => (ffi::cert::certIPAddress, ip.octets().to_vec()),
+            GeneralName::Email(ref name) => (ffi::cert::certRFC822Name, name.as_bytes().to_vec()),
+            GeneralName::Uri(ref name) => (ffi::cert::certURI, name.as_bytes().to_vec()),
+        }
+    }
+
+    // None for the kinds that aren't supported.
+    unsafe fn from_ffi(name: &ffi::cert::CERTGeneralName) -> Result<Option<Self>> {
+        let text = |value: &[u8]| {
+            String::from_utf8(value.to_vec()).map_err(|_| Error::from(SEC_ERROR_BAD_DATA))
+        };
+        let value = sec_item_as_slice(&name.name.other);
+        Ok(Some(match name.type_ {
+            ffi::cert::certDNSName => GeneralName::Dns(text(value)?),
+            ffi::cert::certRFC822Name => GeneralName::Email(text(value)?),
+            ffi::cert::certURI => GeneralName::Uri(text(value)?),
+            ffi::cert::certIPAddress if value.len() == 4 => {
+                GeneralName::Ip(Ipv4Addr::new(value[0], value[1], value[2], value[3]).into())
+            }
+            ffi::cert::certIPAddress if value.len() == 16 => {
+                let mut octets = [0; 16];
+                octets.copy_from_slice(value);
+                GeneralName::Ip(Ipv6Addr::from(octets).into())
+            }
+            ffi::cert::certIPAddress => return Err(SEC_ERROR_BAD_DATA.into()),
+            _ => return Ok(None),
+        }))
+    }
+}
+
+// GeneralNames, allocated from `arena`.
+unsafe fn encode_sans(
+    arena: *mut ffi::nspr::PLArenaPool,
+    sans: &[GeneralName],
+) -> Result<ffi::SECItem> {
+    let values: Vec<_> = sans.iter().map(GeneralName::to_ffi_value).collect();
+    let mut names: Vec<ffi::cert::CERTGeneralName> = values
+        .iter()
+        .map(|&(type_, ref value)| {
+            let mut name = mem::zeroed::<ffi::cert::CERTGeneralName>();
+            name.type_ = type_;
+            name.name.other = mem::ManuallyDrop::new(sec_item_from_slice(value));
+            name
+        })
+        .collect();
+    // NSS takes the first as the head of a circular list.
+    let len = names.len();
+    let base = names.as_mut_ptr();
+    for i in 0..len {
+        (*base.add(i)).l.next = &mut (*base.add((i + 1) % len)).l;
+        (*base.add(i)).l.prev = &mut (*base.add((i + len - 1) % len)).l;
+    }
+    let mut encoded = mem::zeroed();
+    wrap_ffi(|| ffi::cert::CERT_EncodeAltNameExtension(arena, base, &mut encoded))?;
+    Ok(encoded)
+}
+
+unsafe fn add_extensions(
+    cert: *mut ffi::CERTCertificate,
+    key_type: ffi::pk11::KeyType,
+    sans: &[GeneralName],
+) -> Result<()> {
+    use ffi::cert::*;
+    use ffi::nspr::{PR_FALSE, PR_TRUE};
+
+    let arena = (*cert).arena;
+    let handle = wrap_ffi(|| {
+        let handle = CERT_StartCertExtensions(cert);
+        if handle.is_null() {
+            GenStatus::ErrorFromC
+        } else {
+            GenStatus::Success(handle)
+        }
+    })?;
+    // Finishing (even after a failure) is what frees the handle; it
+    // also makes the certificate v3.
+    let res = (|| {
+        let mut constraints = CERTBasicConstraints {
+            isCA: PR_FALSE,
+            // Only CAs have a path length; NSS insists on -1 otherwise.
+            pathLenConstraint: -1,
+        };
+        let mut encoded = mem::zeroed();
+        wrap_ffi(|| CERT_EncodeBasicConstraintValue(arena, &mut constraints, &mut encoded))?;
+        wrap_ffi(|| {
+            CERT_AddExtension(
+                handle,
+                SEC_OID_X509_BASIC_CONSTRAINTS as c_int,
+                &mut encoded,
+                PR_TRUE,
+                PR_TRUE,
+            )
+        })?;
+
+        let mut usage = ffi::pk11::KU_DIGITAL_SIGNATURE as u8;
+        if key_type == ffi::pk11::rsaKey {
+            usage |= ffi::pk11::KU_KEY_ENCIPHERMENT as u8;
+        }
+        let mut usage = sec_item_from_slice(slice::from_ref(&usage));
+        wrap_ffi(|| {
+            CERT_EncodeAndAddBitStrExtension(
+                handle,
+                SEC_OID_X509_KEY_USAGE as c_int,
+                &mut usage,
+                PR_TRUE,
+            )
+        })?;
+
+        add_sans(handle, arena, sans)
+    })();
+    let finished = wrap_ffi(|| CERT_FinishExtensions(handle));
+    res.and(finished)
+}
+
+unsafe fn add_sans(
+    handle: *mut c_void,
+    arena: *mut ffi::nspr::PLArenaPool,
+    sans: &[GeneralName],
+) -> Result<()> {
+    if sans.is_empty() {
+        return Ok(());
+    }
+    let mut sans = encode_sans(arena, sans)?;
+    wrap_ffi(|| {
+        ffi::cert::CERT_AddExtension(
+            handle,
+            ffi::cert::SEC_OID_X509_SUBJECT_ALT_NAME as c_int,
+            &mut sans,
+            ffi::nspr::PR_FALSE,
+            ffi::nspr::PR_TRUE,
+        )
+    })
+}
+
+// How far back `generate_self_signed` starts the validity period.
+const CLOCK_SKEW: Duration = Duration::from_secs(5 * 60);
+
+// Creates and signs (with SHA-256) a certificate for `pubkey` whose
+// issuer is its own subject, valid for `validity` from now (and from a
+// few minutes ago, for peers whose clocks are behind).  `subject`
+// is an RFC 1485 name, like "CN=localhost, O=Example".  It's an
+// end-entity certificate, not a CA.
+pub fn generate_self_signed(
+    subject: &str,
+    key: &PrivateKey,
+    pubkey: &PublicKey,
+    sans: &[GeneralName],
+    validity: Duration,
+) -> Result<Certificate> {
+    let now = SystemTime::now();
+    let not_after = now
+        .checked_add(validity)
+        .ok_or_else(|| Error::from(SEC_ERROR_INVALID_ARGS))?;
+    let validity = Validity::new(now - CLOCK_SKEW, not_after)?;
+    let name = Name::from_ascii(subject)?;
+    let request = CertRequest::new(&name, pubkey)?;
+    unsafe {
+        let key_type = ffi::pk11::SECKEY_GetPrivateKeyType(key.as_raw_ptr());
+        let sig_alg = ffi::pk11::SEC_GetSignatureAlgorithmOidTag(key_type, ffi::SEC_OID_SHA256);
+        if sig_alg == ffi::pk11::SEC_OID_UNKNOWN {
+            return Err(SEC_ERROR_INVALID_KEY.into());
+        }
+        let mut serial = [0u8; 4];
+        wrap_ffi(|| ffi::pk11::PK11_GenerateRandom(serial.as_mut_ptr(), serial.len() as c_int))?;
+        // Keep it positive and nonzero.
+        let serial = u32::from_be_bytes(serial) >> 1 | 1;
+
+        let tbs = wrap_ffi(|| {
+            Certificate::from_raw_ptr_err(ffi::cert::CERT_CreateCertificate(
+                serial.into(),
+                name.0,
+                validity.0,
+                request.0,
+            ))
+        })?;
+        add_extensions(tbs.0, key_type, sans)?;
+
+        // Everything below is allocated from the certificate's arena.
+        let arena = (*tbs.0).arena;
+        wrap_ffi(|| {
+            ffi::pk11::SECOID_SetAlgorithmID(
+                arena,
+                &mut (*tbs.0).signature,
+                sig_alg,
+                ptr::null_mut(),
+            )
+        })?;
+        let mut der = mem::zeroed::<ffi::SECItem>();
+        wrap_ffi(|| {
+            let item = ffi::cert::SEC_ASN1EncodeItem(
+                arena,
+                &mut der,
+                tbs.0 as *const _,
+                &ffi::cert::CERT_CertificateTemplate,
+            );
+            if item.is_null() {
+                GenStatus::ErrorFromC
+            } else {
+                GenStatus::Success(())
+            }
+        })?;
+        let mut signed = mem::zeroed::<ffi::SECItem>();
+        wrap_ffi(|| {
+            ffi::pk11::SEC_DerSignData(
+                arena,
+                &mut signed,
+                der.data,
+                der.len as c_int,
+                key.as_raw_ptr(),
+                sig_alg,
+            )
+        })?;
+        Certificate::from_der(sec_item_as_slice(&signed))
+    }
+}
+
+// A PKCS #10 certificate signing request for `pubkey`, signed (with
+// SHA-256) by `key`, as DER.  `subject` is as for
+// `generate_self_signed`; the SANs are requested as an extension.
+pub fn create_csr(
+    subject: &str,
+    pubkey: &PublicKey,
+    key: &PrivateKey,
+    sans: &[GeneralName],
+) -> Result<Vec<u8>> {
+    let name = Name::from_ascii(subject)?;
+    let request = CertRequest::new(&name, pubkey)?;
+    unsafe {
+        let key_type = ffi::pk11::SECKEY_GetPrivateKeyType(key.as_raw_ptr());
+        let sig_alg = ffi::pk11::SEC_GetSignatureAlgorithmOidTag(key_type, ffi::SEC_OID_SHA256);
+        if sig_alg == ffi::pk11::SEC_OID_UNKNOWN {
+            return Err(SEC_ERROR_INVALID_KEY.into());
+        }
+        if !sans.is_empty() {
+            let handle = wrap_ffi(|| {
+                let handle = ffi::cert::CERT_StartCertificateRequestAttributes(request.0);
+                if handle.is_null() {
+                    GenStatus::ErrorFromC
+                } else {
+                    GenStatus::Success(handle)
+                }
+            })?;
+            let res = add_sans(handle, (*request.0).arena, sans);
+            let finished = wrap_ffi(|| ffi::cert::CERT_FinishExtensions(handle));
+            res.and(finished)?;
+            wrap_ffi(|| ffi::cert::CERT_FinishCertificateRequestAttributes(request.0))?;
+        }
+
+        // Everything below is allocated from the request's arena.
+        let arena = (*request.0).arena;
+        let mut der = mem::zeroed::<ffi::SECItem>();
+        wrap_ffi(|| {
+            let item = ffi::cert::SEC_ASN1EncodeItem(
+                arena,
+                &mut der,
+                request.0 as *const _,
+                &ffi::cert::CERT_CertificateRequestTemplate,
+            );
+            if item.is_null() {
+                GenStatus::ErrorFromC
+            } else {
+                GenStatus::Success(())
+            }
+        })?;
+        let mut signed = mem::zeroed::<ffi::SECItem>();
+        wrap_ffi(|| {
+            ffi::pk11::SEC_DerSignData(
+                arena,
+                &mut signed,
+                der.data,
+                der.len as c_int,
+                key.as_raw_ptr(),
+                sig_alg,
+            )
+        })?;
+        Ok(sec_item_as_slice(&signed).to_vec())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use error::{SEC_ERROR_EXPIRED_CERTIFICATE, SEC_ERROR_REVOKED_CERTIFICATE};
+    use init;
+    use pk11::{generate_ec_keypair, CkMechanism, EcCurve};
+    use std::time::{Duration, UNIX_EPOCH};
+    use test_util::{trust_test_root, TempDb};
+
+    static ROOT_PEM: &str = include_str!("../testdata/root-ca.pem");
+    static INTERMEDIATE_PEM: &str = include_str!("../testdata/intermediate.pem");
+    static LEAF_PEM: &str = include_str!("../testdata/leaf.pem");
+
+    #[test]
+    fn verify() {
+        init().unwrap();
+        let _root = trust_test_root();
+        let leaf = Certificate::from_der(include_bytes!("../testdata/leaf.der")).unwrap();
+        let _intermediate =
+            Certificate::from_der(include_bytes!("../testdata/intermediate.der")).unwrap();
+        leaf.verify(None).unwrap();
+    }
+
+    #[test]
+    fn import_to_db() {
+        use error::SEC_ERROR_UNTRUSTED_ISSUER;
+
+        init().unwrap();
+        let root = Certificate::from_der(include_bytes!("../testdata/import-root.der")).unwrap();
+        let leaf = Certificate::from_der(include_bytes!("../testdata/import-leaf.der")).unwrap();
+        let nickname = CStr::from_bytes_with_nul(b"nss-rs import root\0").unwrap();
+        // There's no DB to import into.
+        assert!(root.import_to_db(nickname, TrustFlags::SERVER_CA).is_err());
+        assert_eq!(
+            leaf.verify(None).unwrap_err().nspr_error,
+            SEC_ERROR_UNTRUSTED_ISSUER
+        );
+
+        let db = TempDb::new("import");
+        root.import_to_slot(&db.slot, nickname, TrustFlags::empty())
+            .unwrap();
+        assert_eq!(
+            leaf.verify(None).unwrap_err().nspr_error,
+            SEC_ERROR_UNTRUSTED_ISSUER
+        );
+        root.import_to_slot(&db.slot, nickname, TrustFlags::SERVER_CA)
+            .unwrap();
+        drop(root);
+        leaf.verify(None).unwrap();
+        assert_eq!(
+            leaf.import_to_slot(&db.slot, nickname, TrustFlags::PEER)
+                .unwrap_err()
+                .nspr_error,
+            SEC_ERROR_CERT_NICKNAME_COLLISION
+        );
+    }
+
+    #[test]
+    fn private_key() {
+        init().unwrap();
+        let leaf = Certificate::from_der(include_bytes!("../testdata/leaf.der")).unwrap();
+        let client = Certificate::from_der(include_bytes!("../testdata/client.der")).unwrap();
+        let db = TempDb::new("private-key");
+        let mut key_der = sec_item_from_slice(include_bytes!("../testdata/leaf.key.der"));
+        wrap_ffi(|| unsafe {
+            ffi::PK11_ImportDERPrivateKeyInfoAndReturnKey(
+                db.slot.as_raw_ptr(),
+                &mut key_der,
+                ptr::null_mut(),
+                ptr::null_mut(),
+                ffi::nspr::PR_TRUE,
+                ffi::nspr::PR_TRUE,
+                ffi::pk11::KU_ALL,
+                ptr::null_mut(),
+                ptr::null_mut(),
+            )
+        })
+        .unwrap();
+        let nickname = CStr::from_bytes_with_nul(b"nss-rs private key leaf\0").unwrap();
+        leaf.import_to_slot(&db.slot, nickname, TrustFlags::empty())
+            .unwrap();
+
+        let key = leaf.private_key(None).unwrap().unwrap();
+        let sig = key.sign(b"data", CkMechanism::RsaPkcs1Sha256).unwrap();
+        leaf.public_key()
+            .unwrap()
+            .verify(b"data", &sig, CkMechanism::RsaPkcs1Sha256)
+            .unwrap();
+        assert!(client.private_key(None).unwrap().is_none());
+    }
+
+    #[test]
+    fn subject_component() {
+        init().unwrap();
+        let leaf = Certificate::from_der(include_bytes!("../testdata/leaf.der")).unwrap();
+        let get = |oid| leaf.subject_component(oid).unwrap();
+        assert_eq!(get(RdnOid::Organization), Some("nss-rs Test".to_owned()));
+        assert_eq!(get(RdnOid::Country), Some("CA".to_owned()));
+        assert_eq!(get(RdnOid::CommonName), Some("localhost".to_owned()));
+        assert_eq!(get(RdnOid::EmailAddress), None);
+    }
+
+    #[test]
+    fn self_signed() {
+        init().unwrap();
+        let key = PrivateKey::from_pkcs8_der(include_bytes!("../testdata/leaf.key.der")).unwrap();
+        let public = key.public_key().unwrap();
+        let cert = generate_self_signed(
+            "CN=nss-rs dev, O=nss-rs Test",
+            &key,
+            &public,
+            &[
+                GeneralName::Dns("example.test".to_owned()),
+                GeneralName::Ip([127, 0, 0, 1].into()),
+            ],
+            Duration::from_secs(24 * 60 * 60),
+        )
+        .unwrap();
+        let ffi_cert = cert.as_ffi_ref();
+        // v3, self-issued, and not a CA.
+        assert_eq!(unsafe { sec_item_as_slice(&ffi_cert.version) }, &[2]);
+        assert_eq!(unsafe { sec_item_as_slice(&ffi_cert.derIssuer) }, unsafe {
+            sec_item_as_slice(&ffi_cert.derSubject)
+        });
+        assert_eq!(ffi_cert.isRoot, ffi::nspr::PR_TRUE);
+        assert_eq!(
+            cert.subject_component(RdnOid::CommonName).unwrap(),
+            Some("nss-rs dev".to_owned())
+        );
+        let (not_before, not_after) = cert.validity().unwrap();
+        assert!(not_before <= SystemTime::now() - Duration::from_secs(4 * 60));
+        assert!(not_after > SystemTime::now() + Duration::from_secs(23 * 60 * 60));
+        assert_eq!(
+            cert.spki_sha256().unwrap(),
+            sha256(&public.spki_der().unwrap()).unwrap()
+        );
+        for name in &["example.test", "127.0.0.1"] {
+            cert.verify_name(&CString::new(*name).unwrap()).unwrap();
+        }
+        assert!(cert
+            .verify_name(CStr::from_bytes_with_nul(b"other.test\0").unwrap())
+            .is_err());
+        wrap_ffi(|| unsafe {
+            ffi::CERT_VerifySignedData(
+                &ffi_cert.signatureWrap as *const _ as *mut _,
+                cert.as_raw_ptr() as *mut _,
+                system_time_to_nspr(SystemTime::now()),
+                ptr::null_mut(),
+            )
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn csr() {
+        init().unwrap();
+        let (public, key) = generate_ec_keypair(EcCurve::P256).unwrap();
+        let sans = [
+            GeneralName::Dns("csr.example".to_owned()),
+            GeneralName::Ip([127, 0, 0, 1].into()),
+        ];
+        let der = create_csr("CN=csr.example, O=Example", &public, &key, &sans).unwrap();
+        // The extension's value, which NSS encodes.
+        let expected: &[u8] = b"\x30\x13\x82\x0bcsr.example\x87\x04\x7f\x00\x00\x01";
+        assert!(der.windows(expected.len()).any(|w| w == expected));
+
+        let request = CertRequest::from_der(&der).unwrap();
+        assert_eq!(request.subject().unwrap(), "CN=csr.example,O=Example");
+        assert_eq!(request.subject_alt_names().unwrap(), sans);
+        assert_eq!(
+            request.public_key().unwrap().spki_der().unwrap(),
+            public.spki_der().unwrap()
+        );
+
+        let request = create_csr("CN=csr.example", &public, &key, &[]).unwrap();
+        let request = CertRequest::from_der(&request).unwrap();
+        assert_eq!(request.subject_alt_names().unwrap(), vec![]);
+
+        // A bad signature.
+        let mut bad = der.clone();
+        let last = bad.len() - 1;
+        bad[last] ^= 1;
+        assert!(CertRequest::from_der(&bad).is_err());
+        assert!(CertRequest::from_der(&der[..der.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn openssl_csr() {
+        init().unwrap();
+        let request = CertRequest::from_der(include_bytes!("../testdata/csr.der")).unwrap();
+        // OpenSSL puts the names the other way round.
+        assert_eq!(request.subject().unwrap(), "O=Example,CN=csr.example");
+        assert_eq!(
+            request.subject_alt_names().unwrap(),
+            vec![
+                GeneralName::Dns("csr.example".to_owned()),
+                GeneralName::Ip([127, 0, 0, 1].into()),
+                GeneralName::Ip("::1".parse().unwrap()),
+                GeneralName::Email("admin@csr.example".to_owned()),
+                GeneralName::Uri("https://csr.example/".to_owned()),
+            ]
+        );
+        let leaf = Certificate::from_der(include_bytes!("../testdata/leaf.der")).unwrap();
+        assert_eq!(
+            request.public_key().unwrap().spki_der().unwrap(),
+            leaf.public_key().unwrap().spki_der().unwrap()
+        );
+    }
+
+    #[test]
+    fn signature_algorithm() {
+        init().unwrap();
+        let leaf = Certificate::from_der(include_bytes!("../testdata/leaf.der")).unwrap();
+        assert_eq!(
+            leaf.signature_algorithm().unwrap(),
+            "PKCS #1 SHA-256 With RSA Encryption"
+        );
+    }
+
+    #[test]
+    fn signature() {
+        init().unwrap();
+        let leaf = Certificate::from_der(include_bytes!("../testdata/leaf.der")).unwrap();
+        let bits = leaf.as_ffi_ref().signatureWrap.signature.len as usize;
+        // RSA-2048.
+        assert_eq!(bits, 2048);
+        assert_eq!(leaf.signature().len(), (bits + 7) / 8);
+        assert!(leaf.as_der().ends_with(leaf.signature()));
+    }
+
+    #[test]
+    fn verify_with_intermediates() {
+        init().unwrap();
+        // The lock also keeps other tests from holding the intermediate,
+        // which only the tests that take it use.
+        let _root = trust_test_root();
+        let leaf = Certificate::from_der(include_bytes!("../testdata/short-leaf.der")).unwrap();
+        let (not_before, _) = leaf.validity().unwrap();
+        // While the intermediate is valid, too.
+        let time = Some(not_before + Duration::from_secs(60 * 60));
+        let err = leaf
+            .verify_with_intermediates(&[], CertUsage::SslServer, time)
+            .unwrap_err();
+        assert_eq!(err.nspr_error, SEC_ERROR_UNKNOWN_ISSUER);
+        let intermediates =
+            [
+                Certificate::from_der(include_bytes!("../testdata/short-intermediate.der"))
+                    .unwrap(),
+            ];
+        leaf.verify_with_intermediates(&intermediates, CertUsage::SslServer, time)
+            .unwrap();
+        // While something else holds it, NSS finds it without being
+        // given it, and an unrelated intermediate doesn't get in the way.
+        let other =
+            [Certificate::from_der(include_bytes!("../testdata/intermediate.der")).unwrap()];
+        leaf.verify_with_intermediates(&other, CertUsage::SslServer, time)
+            .unwrap();
+        let err = leaf
+            .verify_with_intermediates(&intermediates, CertUsage::SslClient, time)
+            .unwrap_err();
+        assert_eq!(err.nspr_error, SEC_ERROR_INADEQUATE_CERT_TYPE);
+    }
+
+    #[test]
+    fn verify_with_verifier() {
+        init().unwrap();
+        let _root = trust_test_root();
+        let _intermediate =
+            Certificate::from_der(include_bytes!("../testdata/intermediate.der")).unwrap();
+        let certs: Vec<_> = [
+            &include_bytes!("../testdata/leaf.der")[..],
+            include_bytes!("../testdata/ev-leaf.der"),
+            include_bytes!("../testdata/sha1-leaf.der"),
+            include_bytes!("../testdata/revoked.der"),
+            include_bytes!("../testdata/client.der"),
+            include_bytes!("../testdata/import-leaf.der"),
+        ]
+        .iter()
+        .map(|der| Certificate::from_der(der).unwrap())
+        .collect();
+        let verifier = Verifier::new(VerifyFlags::empty(), None);
+        let root = Certificate::from_der(include_bytes!("../testdata/root-ca.der")).unwrap();
+        // The same root the DB trusts, so the same results.
+        let anchored = Verifier::new(
+            VerifyFlags::empty(),
+            Some(CertList::from_certs(&[root]).unwrap()),
+        );
+        // Against the older, non-libpkix verifier, which only does this
+        // usage; the error codes differ between the two.
+        for cert in &certs {
+            let expected = cert.verify(None).is_ok();
+            for v in &[&verifier, &anchored] {
+                assert_eq!(v.verify(cert, CertUsage::SslServer, None).is_ok(), expected);
+            }
+        }
+        verifier
+            .verify(&certs[0], CertUsage::SslServer, None)
+            .unwrap();
+        assert!(verifier
+            .verify(&certs[0], CertUsage::SslClient, None)
+            .is_err());
+    }
+
+    #[test]
+    fn cert_list_into_iter() {
+        init().unwrap();
+        let certs = [
+            Certificate::from_der(include_bytes!("../testdata/leaf.der")).unwrap(),
+            Certificate::from_der(include_bytes!("../testdata/client.der")).unwrap(),
+            Certificate::from_der(include_bytes!("../testdata/root-ca.der")).unwrap(),
+        ];
+        let collected: Vec<Certificate> =
+            CertList::from_certs(&certs).unwrap().into_iter().collect();
+        assert_eq!(collected.len(), 3);
+        for (a, b) in collected.iter().zip(&certs) {
+            assert_eq!(a.as_der(), b.as_der());
+        }
+        let reversed: Vec<_> = CertList::from_certs(&certs)
+            .unwrap()
+            .into_iter()
+            .rev()
+            .collect();
+        assert_eq!(reversed[0].as_der(), certs[2].as_der());
+        assert_eq!(CertList::from_certs(&[]).unwrap().into_iter().count(), 0);
+    }
+
+    #[test]
+    fn verify_with_anchors() {
+        init().unwrap();
+        let root = Certificate::from_der(include_bytes!("../testdata/root-ca.der")).unwrap();
+        let other = Certificate::from_der(include_bytes!("../testdata/import-root.der")).unwrap();
+        let _intermediate =
+            Certificate::from_der(include_bytes!("../testdata/intermediate.der")).unwrap();
+        let leaf = Certificate::from_der(include_bytes!("../testdata/leaf.der")).unwrap();
+        let anchors = CertList::from_certs(&[root]).unwrap();
+        assert_eq!(anchors.iter().count(), 1);
+        leaf.verify_with_anchors(&anchors, CertUsage::SslServer, None)
+            .unwrap();
+        let anchors = CertList::from_certs(&[other]).unwrap();
+        assert_eq!(
+            leaf.verify_with_anchors(&anchors, CertUsage::SslServer, None)
+                .unwrap_err()
+                .nspr_error,
+            SEC_ERROR_UNTRUSTED_ISSUER
+        );
+    }
+
+    #[test]
+    fn validity() {
+        init().unwrap();
+        let leaf = Certificate::from_der(include_bytes!("../testdata/leaf.der")).unwrap();
+        let (not_before, not_after) = leaf.validity().unwrap();
+        let (raw_before, raw_after) = leaf.validity_prtime().unwrap();
+        assert!(raw_before < raw_after);
+        assert_eq!(system_time_to_nspr(not_before), raw_before);
+        assert_eq!(system_time_to_nspr(not_after), raw_after);
+        // 2026-10-16 and 2126-09-22 at 18:41:49 UTC.
+        assert_eq!(raw_before, 1_792_176_109_000_000);
+        assert_eq!(raw_after, 4_945_776_109_000_000);
+    }
+
+    #[test]
+    fn check_usage() {
+        use error::SEC_ERROR_CERT_USAGES_INVALID;
+
+        init().unwrap();
+        let leaf = Certificate::from_der(include_bytes!("../testdata/leaf.der")).unwrap();
+        let client = Certificate::from_der(include_bytes!("../testdata/client.der")).unwrap();
+        leaf.check_usage(CertUsage::SslServer).unwrap();
+        client.check_usage(CertUsage::SslClient).unwrap();
+        // Each has only its own extendedKeyUsage.
+        assert_eq!(
+            client
+                .check_usage(CertUsage::SslServer)
+                .unwrap_err()
+                .nspr_error,
+            SEC_ERROR_INADEQUATE_CERT_TYPE
+        );
+        assert_eq!(
+            leaf.check_usage(CertUsage::SslClient)
+                .unwrap_err()
+                .nspr_error,
+            SEC_ERROR_INADEQUATE_CERT_TYPE
+        );
+        // The intermediate's keyUsage is only for signing certs and CRLs.
+        let intermediate =
+            Certificate::from_der(include_bytes!("../testdata/intermediate.der")).unwrap();
+        assert_eq!(
+            intermediate
+                .check_usage(CertUsage::EmailRecipient)
+                .unwrap_err()
+                .nspr_error,
+            SEC_ERROR_CERT_USAGES_INVALID
+        );
+    }
+
+    #[test]
+    fn policy_oids() {
+        init().unwrap();
+        let leaf = Certificate::from_der(include_bytes!("../testdata/leaf.der")).unwrap();
+        // CA/Browser Forum organization-validated.
+        assert_eq!(
+            leaf.policy_oids().unwrap(),
+            vec!["2.23.140.1.2.2".to_owned()]
+        );
+        let intermediate =
+            Certificate::from_der(include_bytes!("../testdata/intermediate.der")).unwrap();
+        assert!(intermediate.policy_oids().unwrap().is_empty());
+    }
+
+    #[test]
+    fn is_extended_validation() {
+        init().unwrap();
+        let ev = Certificate::from_der(include_bytes!("../testdata/ev-leaf.der")).unwrap();
+        assert!(ev.is_extended_validation().unwrap());
+        // Organization-validated.
+        let leaf = Certificate::from_der(include_bytes!("../testdata/leaf.der")).unwrap();
+        assert!(!leaf.is_extended_validation().unwrap());
+        let intermediate =
+            Certificate::from_der(include_bytes!("../testdata/intermediate.der")).unwrap();
+        assert!(!intermediate.is_extended_validation().unwrap());
+    }
+
+    #[test]
+    fn spki_sha256() {
+        init().unwrap();
+        let leaf = Certificate::from_der(include_bytes!("../testdata/leaf.der")).unwrap();
+        // openssl x509 -in leaf.pem -noout -pubkey \
+        //     | openssl pkey -pubin -outform DER | openssl dgst -sha256
+        let expected = [
+            0x62, 0x34, 0xf6, 0xbd, 0xee, 0xe7, 0x9c, 0x8e, 0x5f, 0x1a, 0xfe, 0x56, 0xf5, 0xe2,
+            0xa9, 0x08, 0xbe, 0xc2, 0x06, 0x20, 0x2a, 0x92, 0x49, 0xe2, 0x17, 0xff, 0x5d, 0x1f,
+            0xdf, 0x93, 0x37, 0x7d,
+        ];
+        assert_eq!(leaf.spki_sha256().unwrap(), expected);
+    }
+
+    #[test]
+    fn verify_flags() {
+        init().unwrap();
+        let _root = trust_test_root();
+        let _intermediate =
+            Certificate::from_der(include_bytes!("../testdata/intermediate.der")).unwrap();
+        let leaf = Certificate::from_der(include_bytes!("../testdata/leaf.der")).unwrap();
+        leaf.verify_with_flags(VerifyFlags::empty(), CertUsage::SslServer, None)
+            .unwrap();
+
+        // There's no OCSP responder, nor a CRL from the root.
+        for &flags in &[VerifyFlags::REQUIRE_OCSP, VerifyFlags::REQUIRE_CRL] {
+            let err = leaf
+                .verify_with_flags(flags, CertUsage::SslServer, None)
+                .unwrap_err();
+            assert_eq!(err.nspr_error, SEC_ERROR_REVOKED_CERTIFICATE);
+        }
+
+        let before = UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+        let err = leaf
+            .verify_with_flags(VerifyFlags::empty(), CertUsage::SslServer, Some(before))
+            .unwrap_err();
+        assert_eq!(err.nspr_error, SEC_ERROR_EXPIRED_CERTIFICATE);
+    }
+
+    #[test]
+    fn verify_flags_expired_intermediate() {
+        init().unwrap();
+        let _root = trust_test_root();
+        let intermediate =
+            Certificate::from_der(include_bytes!("../testdata/expired-intermediate.der")).unwrap();
+        let leaf = Certificate::from_der(include_bytes!("../testdata/expired-leaf.der")).unwrap();
+        let (_, expiry) = intermediate.validity().unwrap();
+        let later = Some(expiry + Duration::from_secs(24 * 60 * 60));
+        let flags = VerifyFlags::ALLOW_EXPIRED_INTERMEDIATES;
+
+        let err = leaf
+            .verify_with_flags(VerifyFlags::empty(), CertUsage::SslServer, later)
+            .unwrap_err();
+        assert!(
+            err.nspr_error == SEC_ERROR_EXPIRED_ISSUER_CERTIFICATE
+                || err.nspr_error == SEC_ERROR_UNKNOWN_ISSUER,
+            "{:?}",
+            err
+        );
+        leaf.verify_with_flags(flags, CertUsage::SslServer, later)
+            .unwrap();
+        // The certificate's own usage and validity still count.
+        let err = leaf
+            .verify_with_flags(flags, CertUsage::SslClient, later)
+            .unwrap_err();
+        assert_eq!(err.nspr_error, SEC_ERROR_INADEQUATE_CERT_TYPE);
+        let (_, leaf_expiry) = leaf.validity().unwrap();
+        let too_late = Some(leaf_expiry + Duration::from_secs(24 * 60 * 60));
+        assert!(leaf
+            .verify_with_flags(flags, CertUsage::SslServer, too_late)
+            .is_err());
+    }
+
+    #[test]
+    fn verify_flags_db_anchors() {
+        init().unwrap();
+        let _root = trust_test_root();
+        let _intermediate =
+            Certificate::from_der(include_bytes!("../testdata/intermediate.der")).unwrap();
+        let leaf = Certificate::from_der(include_bytes!("../testdata/leaf.der")).unwrap();
+        let others =
+            [Certificate::from_der(include_bytes!("../testdata/import-root.der")).unwrap()];
+        let anchors = || Some(CertList::from_certs(&others).unwrap());
+
+        let only = Verifier::new(VerifyFlags::empty(), anchors());
+        assert_eq!(
+            only.verify(&leaf, CertUsage::SslServer, None)
+                .unwrap_err()
+                .nspr_error,
+            SEC_ERROR_UNTRUSTED_ISSUER
+        );
+        let with_db = Verifier::new(VerifyFlags::DB_ANCHORS, anchors());
+        with_db.verify(&leaf, CertUsage::SslServer, None).unwrap();
+    }
+
+    #[test]
+    fn pem_bundle() {
+        init().unwrap();
+        let bundle = format!(
+            "# Leaf\n{}\n\n# Intermediate\n{}\n   \n# Root\n\n{}",
+            LEAF_PEM, INTERMEDIATE_PEM, ROOT_PEM
+        );
+        let certs = parse_chain_pem(&bundle).unwrap();
+        assert_eq!(certs.len(), 3);
+        assert_eq!(
+            certs[0].as_der(),
+            &include_bytes!("../testdata/leaf.der")[..]
+        );
+        assert_eq!(
+            certs[1].as_der(),
+            &include_bytes!("../testdata/intermediate.der")[..]
+        );
+        assert_eq!(
+            certs[2].as_der(),
+            &include_bytes!("../testdata/root-ca.der")[..]
+        );
+    }
+
+    #[test]
+    fn pem_bundle_bad_block() {
+        init().unwrap();
+        let truncated = &INTERMEDIATE_PEM[..INTERMEDIATE_PEM.len() / 2];
+        let bundle = format!(
+            "{}{}\n-----END CERTIFICATE-----\n{}",
+            LEAF_PEM, truncated, ROOT_PEM
+        );
+        let err = parse_chain_pem(&bundle).err().unwrap();
+        assert_eq!(err.index, 1);
+    }
+}
@@ -0,0 +1,228 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+use cert::Certificate;
+use libc::c_int;
+use nspr::bool_to_nspr;
+use nspr::fd::File;
+use nspr::net::new_tcp_socket;
+use nss_sys as ffi;
+use pk11::PrivateKey;
+use std::ffi::{CStr, CString};
+use {NamedGroup, Result, TLSCipherSuite, TLSOption, TLSSocket, TLSVersion};
+use {
+    TLS_AES_128_GCM_SHA256, TLS_AES_256_GCM_SHA384, TLS_CHACHA20_POLY1305_SHA256,
+    TLS_ECDHE_ECDSA_WITH_AES_128_GCM_SHA256, TLS_ECDHE_ECDSA_WITH_AES_256_GCM_SHA384,
+    TLS_ECDHE_ECDSA_WITH_CHACHA20_POLY1305_SHA256, TLS_ECDHE_RSA_WITH_AES_128_GCM_SHA256,
+    TLS_ECDHE_RSA_WITH_AES_256_GCM_SHA384, TLS_ECDHE_RSA_WITH_CHACHA20_POLY1305_SHA256,
+    TLS_VERSION_1_2, TLS_VERSION_1_3,
+};
+
+// Socket settings collected up front and applied in one go; anything
+// left unset keeps NSS's (or the model's) value.
+#[derive(Clone, Debug, Default)]
+pub struct TLSConfig {
+    // By number, with their full integer values.
+    options: Vec<(i32, i32)>,
+    version_range: Option<(TLSVersion, TLSVersion)>,
+    ciphersuites: Option<Vec<TLSCipherSuite>>,
+    named_groups: Option<Vec<NamedGroup>>,
+    alpn_protocols: Option<Vec<Vec<u8>>>,
+    server_name: Option<CString>,
+}
+
+impl TLSConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // A hardened preset, which sets exactly this:
+    // - versions TLS 1.2 to 1.3;
+    // - only AEAD suites: the TLS 1.3 ones, and ECDHE with AES-GCM or
+    //   ChaCha20-Poly1305 (ECDSA or RSA) for 1.2;
+    // - groups X25519, P-256 and P-384, in that order;
+    // - renegotiation never allowed.
+    // Further settings can be chained on as usual.
+    pub fn modern() -> Self {
+        Self::new()
+            .option_raw(ffi::SSL_ENABLE_RENEGOTIATION, ffi::SSL_RENEGOTIATE_NEVER)
+            .version_range(TLS_VERSION_1_2, TLS_VERSION_1_3)
+            .ciphersuites(&[
+                TLS_AES_128_GCM_SHA256,
+                TLS_CHACHA20_POLY1305_SHA256,
+                TLS_AES_256_GCM_SHA384,
+                TLS_ECDHE_ECDSA_WITH_AES_128_GCM_SHA256,
+                TLS_ECDHE_RSA_WITH_AES_128_GCM_SHA256,
+                TLS_ECDHE_ECDSA_WITH_CHACHA20_POLY1305_SHA256,
+                TLS_ECDHE_RSA_WITH_CHACHA20_POLY1305_SHA256,
+                TLS_ECDHE_ECDSA_WITH_AES_256_GCM_SHA384,
+                TLS_ECDHE_RSA_WITH_AES_256_GCM_SHA384,
+            ])
+            .named_groups(&[
+                NamedGroup::X25519,
+                NamedGroup::Secp256r1,
+                NamedGroup::Secp384r1,
+            ])
+    }
+
+    // Later settings of the same option win.
+    pub fn option(self, option: TLSOption, value: bool) -> Self {
+        self.option_raw(option.to_ffi(), bool_to_nspr(value))
+    }
+
+    // As for `TLSSocketImpl::set_option_raw`, e.g. for
+    // SSL_ENABLE_RENEGOTIATION's other values.
+    pub fn option_raw(mut self, option: i32, value: i32) -> Self {
+        self.options.push((option, value));
+        self
+    }
+
+    pub fn version_range(mut self, min: TLSVersion, max: TLSVersion) -> Self {
+        self.version_range = Some((min, max));
+        self
+    }
+
+    // Enables exactly these suites; NSS has no per-socket order for
+    // them, so this is a set.
+    pub fn ciphersuites(mut self, suites: &[TLSCipherSuite]) -> Self {
+        self.ciphersuites = Some(suites.to_vec());
+        self
+    }
+
+    pub fn named_groups(mut self, groups: &[NamedGroup]) -> Self {
+        self.named_groups = Some(groups.to_vec());
+        self
+    }
+
+    pub fn alpn_protocols(mut self, protocols: &[&[u8]]) -> Self {
+        self.alpn_protocols = Some(protocols.iter().map(|proto| proto.to_vec()).collect());
+        self
+    }
+
+    // Sent with SNI, and checked against the server's certificate.
+    pub fn server_name(mut self, name: &CStr) -> Self {
+        self.server_name = Some(name.to_owned());
+        self
+    }
+
+    pub fn apply<Callbacks>(&self, sock: &mut TLSSocket<Callbacks>) -> Result<()> {
+        // Options first: the old SSL_ENABLE_SSL3/SSL_ENABLE_TLS ones
+        // change the version range as a side effect.
+        for &(option, value) in &self.options {
+            sock.set_option_raw(option, value)?;
+        }
+        if let Some((min, max)) = self.version_range {
+            sock.set_version_range(min, max)?;
+        }
+        if let Some(ref suites) = self.ciphersuites {
+            for &suite in TLSCipherSuite::implemented() {
+                sock.set_ciphersuite_enabled(suite, suites.contains(&suite))?;
+            }
+        }
+        if let Some(ref groups) = self.named_groups {
+            sock.set_named_groups(groups)?;
+        }
+        if let Some(ref protocols) = self.alpn_protocols {
+            let protocols: Vec<&[u8]> = protocols.iter().map(|proto| &proto[..]).collect();
+            sock.set_alpn_protocols(&protocols)?;
+        }
+        if let Some(ref name) = self.server_name {
+            sock.set_url(name)?;
+        }
+        Ok(())
+    }
+
+    // The handshake still needs to be started (or reset, for an
+    // already connected socket) as usual.
+    pub fn build<Callbacks>(
+        &self,
+        inner: File,
+        callbacks: Callbacks,
+    ) -> Result<TLSSocket<Callbacks>> {
+        let mut sock = TLSSocket::new(inner, callbacks)?;
+        self.apply(&mut sock)?;
+        Ok(sock)
+    }
+}
+
+// `TLSConfig::modern` (see there for what it sets) in one call.
+pub fn apply_modern_profile<Callbacks>(sock: &mut TLSSocket<Callbacks>) -> Result<()> {
+    TLSConfig::modern().apply(sock)
+}
+
+// A server's certificate and settings, made into a model socket once
+// so that each connection can be set up from it cheaply (see
+// `TLSSocket::new_with_model`).  Everything in a `TLSConfig` carries
+// over to the connections, as do the certificate, key and chain; hooks
+// and callbacks don't, and need setting on each connection.
+pub struct ServerConfig<'a> {
+    cert: &'a Certificate,
+    key: &'a PrivateKey,
+    cert_chain: &'a [Certificate],
+    config: TLSConfig,
+}
+
+impl<'a> ServerConfig<'a> {
+    pub fn new(cert: &'a Certificate, key: &'a PrivateKey) -> Self {
+        ServerConfig {
+            cert,
+            key,
+            cert_chain: &[],
+            config: TLSConfig::new(),
+        }
+    }
+
+    // As for `TLSSocketImpl::config_server_cert`.
+    pub fn cert_chain(mut self, cert_chain: &'a [Certificate]) -> Self {
+        self.cert_chain = cert_chain;
+        self
+    }
+
+    pub fn config(mut self, config: TLSConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    // `af` is the address family of the sockets that will use it.
+    pub fn build_model(&self, af: c_int) -> Result<TLSSocket<()>> {
+        let mut model = TLSSocket::new(new_tcp_socket(af)?, ())?;
+        self.config.apply(&mut model)?;
+        model.config_server_cert(self.cert, self.key, self.cert_chain)?;
+        Ok(model)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use init;
+    use libc::AF_INET;
+    use {TLS_ECDHE_RSA_WITH_AES_128_CBC_SHA, TLS_RSA_WITH_AES_128_CBC_SHA};
+
+    #[test]
+    fn modern_profile() {
+        init().unwrap();
+        let mut sock = TLSSocket::new(new_tcp_socket(AF_INET).unwrap(), ()).unwrap();
+        sock.set_ciphersuite_enabled(TLS_RSA_WITH_AES_128_CBC_SHA, true)
+            .unwrap();
+        apply_modern_profile(&mut sock).unwrap();
+        assert_eq!(
+            sock.get_version_range().unwrap(),
+            (TLS_VERSION_1_2, TLS_VERSION_1_3)
+        );
+        assert!(!sock
+            .is_ciphersuite_enabled(TLS_RSA_WITH_AES_128_CBC_SHA)
+            .unwrap());
+        assert!(!sock
+            .is_ciphersuite_enabled(TLS_ECDHE_RSA_WITH_AES_128_CBC_SHA)
+            .unwrap());
+        assert!(sock
+            .is_ciphersuite_enabled(TLS_ECDHE_RSA_WITH_AES_128_GCM_SHA256)
+            .unwrap());
+        assert_eq!(
+            sock.get_option_raw(ffi::SSL_ENABLE_RENEGOTIATION).unwrap(),
+            ffi::SSL_RENEGOTIATE_NEVER
+        );
+    }
+}
@@ -0,0 +1,162 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+use super::{sec_item_from_slice, wrap_ffi, Certificate, GenStatus, Result};
+use error::{
+    SEC_ERROR_BAD_SIGNATURE, SEC_ERROR_CRL_BAD_SIGNATURE, SEC_ERROR_CRL_NOT_FOUND,
+    SEC_ERROR_UNKNOWN_ISSUER,
+};
+use nspr::time::system_time_to_nspr;
+use nss_sys as ffi;
+use std::mem;
+use std::ptr;
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+struct SignedCrl(*mut ffi::CERTSignedCrl);
+
+impl SignedCrl {
+    fn decode(der: &[u8]) -> Result<Self> {
+        let mut item = sec_item_from_slice(der);
+        wrap_ffi(|| unsafe {
+            let ptr = ffi::CERT_DecodeDERCrl(ptr::null_mut(), &mut item, ffi::SEC_CRL_TYPE);
+            if ptr.is_null() {
+                GenStatus::ErrorFromC
+            } else {
+                GenStatus::Success(SignedCrl(ptr))
+            }
+        })
+    }
+}
+
+impl Drop for SignedCrl {
+    fn drop(&mut self) {
+        let ptr = mem::replace(&mut self.0, ptr::null_mut());
+        if !ptr.is_null() {
+            unsafe {
+                ffi::SEC_DestroyCrl(ptr);
+            }
+        }
+    }
+}
+
+// The cache keeps pointers to both the `SECItem` and its contents,
+// so they're kept here until the CRL is uncached.
+struct CachedCrl {
+    der: Box<[u8]>,
+    item: Box<ffi::SECItem>,
+}
+
+unsafe impl Send for CachedCrl {}
+
+lazy_static! {
+    static ref CACHED_CRLS: Mutex<Vec<CachedCrl>> = Mutex::new(Vec::new());
+}
+
+// Adds a DER-encoded CRL to the default cert DB's CRL cache, where
+// every later `Certificate::verify` in the process will consult it
+// until it's removed with `uncache_der`.
+//
+// The issuer must already be in the cert DB (e.g., imported as a
+// `Certificate` that's still alive), and the CRL's signature is
+// checked against it here: a bad signature fails with
+// `SEC_ERROR_CRL_BAD_SIGNATURE`, an unknown issuer with
+// `SEC_ERROR_UNKNOWN_ISSUER`, and a malformed CRL with some other
+// error (usually `SEC_ERROR_BAD_DER`).
+//
+// (This doesn't use `CERT_ImportCRL`, because that stores the CRL as a
+// token object, which needs a writable database.)
+pub fn import_der(crl_der: &[u8]) -> Result<()> {
+    let crl = SignedCrl::decode(crl_der)?;
+    let issuer = unsafe {
+        Certificate::from_raw_ptr_opt(ffi::CERT_FindCertByName(
+            ffi::CERT_GetDefaultCertDB(),
+            &mut (*crl.0).crl.derName,
+        ))
+    }
+    .ok_or(SEC_ERROR_UNKNOWN_ISSUER)?;
+    let now = system_time_to_nspr(SystemTime::now());
+    wrap_ffi(|| unsafe {
+        ffi::CERT_VerifySignedData(
+            &mut (*crl.0).signatureWrap,
+            issuer.as_raw_ptr() as *mut _,
+            now,
+            ptr::null_mut(),
+        )
+    })
+    .map_err(|err| {
+        if err.nspr_error == SEC_ERROR_BAD_SIGNATURE {
+            SEC_ERROR_CRL_BAD_SIGNATURE.into()
+        } else {
+            err
+        }
+    })?;
+
+    let der = crl_der.to_vec().into_boxed_slice();
+    let mut item = Box::new(sec_item_from_slice(&der));
+    let mut cached = CACHED_CRLS.lock().unwrap();
+    wrap_ffi(|| unsafe { ffi::CERT_CacheCRL(ffi::CERT_GetDefaultCertDB(), &mut *item) })?;
+    cached.push(CachedCrl { der, item });
+    Ok(())
+}
+
+// Removes a CRL added by `import_der` (given the same DER), and frees
+// it; fails with `SEC_ERROR_CRL_NOT_FOUND` if there's no such CRL.
+pub fn uncache_der(crl_der: &[u8]) -> Result<()> {
+    let mut cached = CACHED_CRLS.lock().unwrap();
+    let index = cached
+        .iter()
+        .position(|crl| &crl.der[..] == crl_der)
+        .ok_or(SEC_ERROR_CRL_NOT_FOUND)?;
+    wrap_ffi(|| unsafe {
+        ffi::CERT_UncacheCRL(ffi::CERT_GetDefaultCertDB(), &mut *cached[index].item)
+    })?;
+    cached.swap_remove(index);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use error::{SEC_ERROR_BAD_DER, SEC_ERROR_REVOKED_CERTIFICATE};
+    use init;
+    use test_util::trust_test_root;
+
+    static CRL_DER: &[u8] = include_bytes!("../testdata/intermediate.crl.der");
+
+    #[test]
+    fn revoked() {
+        init().unwrap();
+        let _root = trust_test_root();
+        let _intermediate =
+            Certificate::from_der(include_bytes!("../testdata/intermediate.der")).unwrap();
+        let revoked = Certificate::from_der(include_bytes!("../testdata/revoked.der")).unwrap();
+        revoked.verify(None).unwrap();
+
+        let mut bad_crl = CRL_DER.to_vec();
+        *bad_crl.last_mut().unwrap() ^= 1;
+        assert_eq!(
+            import_der(&bad_crl[..20]).unwrap_err().nspr_error,
+            SEC_ERROR_BAD_DER
+        );
+        assert_eq!(
+            import_der(&bad_crl).unwrap_err().nspr_error,
+            SEC_ERROR_CRL_BAD_SIGNATURE
+        );
+        revoked.verify(None).unwrap();
+
+        import_der(CRL_DER).unwrap();
+        assert_eq!(
+            revoked.verify(None).unwrap_err().nspr_error,
+            SEC_ERROR_REVOKED_CERTIFICATE
+        );
+
+        uncache_der(CRL_DER).unwrap();
+        revoked.verify(None).unwrap();
+        assert_eq!(
+            uncache_der(CRL_DER).unwrap_err().nspr_error,
+            SEC_ERROR_CRL_NOT_FOUND
+        );
+    }
+}
@@ -351,5 +351,39 @@ nss_errors! {
     SSL_ERROR_RX_MALFORMED_HELLO_RETRY_REQUEST,
     SSL_ERROR_BAD_2ND_CLIENT_HELLO,
     SSL_ERROR_MISSING_SIGNATURE_ALGORITHMS_EXTENSION,
+    SSL_ERROR_MALFORMED_PSK_KEY_EXCHANGE_MODES,
+    SSL_ERROR_MISSING_PSK_KEY_EXCHANGE_MODES,
+    SSL_ERROR_DOWNGRADE_WITH_EARLY_DATA,
+    SSL_ERROR_TOO_MUCH_EARLY_DATA,
+    SSL_ERROR_RX_UNEXPECTED_END_OF_EARLY_DATA,
+    SSL_ERROR_RX_MALFORMED_END_OF_EARLY_DATA,
+    SSL_ERROR_UNSUPPORTED_EXPERIMENTAL_API,
+    SSL_ERROR_APPLICATION_ABORT,
+    SSL_ERROR_APP_CALLBACK_ERROR,
+    SSL_ERROR_NO_TIMERS_FOUND,
+    SSL_ERROR_MISSING_COOKIE_EXTENSION,
+    SSL_ERROR_RX_UNEXPECTED_KEY_UPDATE,
+    SSL_ERROR_RX_MALFORMED_KEY_UPDATE,
+    SSL_ERROR_TOO_MANY_KEY_UPDATES,
+    SSL_ERROR_HANDSHAKE_FAILED,
+    SSL_ERROR_BAD_RESUMPTION_TOKEN_ERROR,
+    SSL_ERROR_RX_MALFORMED_DTLS_ACK,
+    SSL_ERROR_DH_KEY_TOO_LONG,
+    SSL_ERROR_RX_MALFORMED_ESNI_KEYS,
+    SSL_ERROR_RX_MALFORMED_ESNI_EXTENSION,
+    SSL_ERROR_MISSING_ESNI_EXTENSION,
+    SSL_ERROR_RX_UNEXPECTED_RECORD_TYPE,
+    SSL_ERROR_MISSING_POST_HANDSHAKE_AUTH_EXTENSION,
+    SSL_ERROR_RX_CERTIFICATE_REQUIRED_ALERT,
+    SSL_ERROR_DC_CERT_VERIFY_ALG_MISMATCH,
+    SSL_ERROR_DC_BAD_SIGNATURE,
+    SSL_ERROR_DC_INVALID_KEY_USAGE,
+    SSL_ERROR_DC_EXPIRED,
+    SSL_ERROR_DC_INAPPROPRIATE_VALIDITY_PERIOD,
+    SSL_ERROR_FEATURE_DISABLED,
+    SSL_ERROR_ECH_RETRY_WITH_ECH,
+    SSL_ERROR_ECH_RETRY_WITHOUT_ECH,
+    SSL_ERROR_ECH_FAILED,
+    SSL_ERROR_ECH_REQUIRED_ALERT,
     SSL_ERROR_END_OF_LIST,
 }
@@ -7,28 +7,56 @@ extern crate lazy_static;
 extern crate libc;
 extern crate nss_sys;
 pub mod cert;
+pub mod config;
+pub mod crl;
 pub mod error;
+pub mod listener;
 pub mod nspr;
+pub mod ocsp;
+pub mod oid;
+pub mod pk11;
+pub mod policy;
+pub mod secmod;
+#[cfg(test)]
+mod test_util;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
 
-use libc::c_void;
+use libc::{c_char, c_int, c_uint, c_void};
 use nss_sys as ffi;
 use std::any::Any;
 use std::borrow::Borrow;
 use std::cell::RefCell;
 use std::cmp;
-use std::ffi::CStr;
+use std::convert::TryFrom;
+use std::ffi::{CStr, CString};
 use std::mem;
+use std::net::{Shutdown, SocketAddr};
 use std::ops::{Deref, DerefMut};
 use std::panic;
 use std::ptr;
 use std::slice;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Mutex};
+use std::time::{Duration, SystemTime};
 
-pub use cert::{CertList, Certificate};
+pub use cert::{CertList, CertUsage, Certificate, TrustFlags, VerifyFlags};
+pub use config::{ServerConfig, TLSConfig};
 pub use error::{Error, Result};
-use error::{PR_UNKNOWN_ERROR, PR_WOULD_BLOCK_ERROR};
+use error::{
+    PR_CONNECT_ABORTED_ERROR, PR_END_OF_FILE_ERROR, PR_INVALID_STATE_ERROR, PR_IO_TIMEOUT_ERROR,
+    PR_OPERATION_NOT_SUPPORTED_ERROR, PR_UNKNOWN_ERROR, PR_WOULD_BLOCK_ERROR, SEC_ERROR_BAD_DATA,
+    SEC_ERROR_BAD_KEY, SEC_ERROR_INVALID_ALGORITHM, SEC_ERROR_INVALID_ARGS,
+    SEC_ERROR_UNTRUSTED_CERT, SSL_ERROR_HANDSHAKE_NOT_COMPLETED, SSL_ERROR_NO_CERTIFICATE,
+    SSL_ERROR_UNSUPPORTED_EXPERIMENTAL_API,
+};
+pub use listener::TLSListener;
 use nspr::fd::{BorrowedFile, RawFile};
 pub use nspr::fd::{File, FileMethods, FileWrapper};
+use nspr::time::{duration_opt_to_nspr, system_time_to_nspr};
 use nspr::{bool_from_nspr, bool_to_nspr};
+pub use pk11::PrivateKey;
+use pk11::PublicKey;
 
 #[derive(Clone, Copy, PartialEq, Eq)]
 pub struct ErrorCode(ffi::nspr::PRErrorCode);
@@ -120,10 +148,155 @@ pub fn init() -> Result<()> {
     wrap_ffi(|| unsafe { ffi::NSS_NoDB_Init(ptr::null()) })
 }
 
+// The linked NSS's version, like "3.87.1" or "3.98".
+pub fn nss_version() -> &'static str {
+    unsafe { CStr::from_ptr(ffi::NSS_GetVersion()) }
+        .to_str()
+        .unwrap()
+}
+
+// Whether the linked NSS is at least version `min` (e.g. "3.52"),
+// and compatible with it; neither needs `init`.
+pub fn nss_version_check(min: &CStr) -> bool {
+    bool_from_nspr(unsafe { ffi::NSS_VersionCheck(min.as_ptr()) })
+}
+
+// An ECHConfigList with one config, for `set_ech_configs` and
+// `set_server_ech_configs`, offering HKDF-SHA256 with AES-128-GCM or
+// ChaCha20-Poly1305.  `public` is the server's X25519 key (see
+// `EcCurve::X25519`); `public_name` is sent in the clear instead of
+// the real name, and the server's certificate must be valid for it in
+// case ECH is rejected.  Names up to `max_name_len` long are padded to
+// the same length.
+pub fn encode_ech_config(
+    config_id: u8,
+    public_name: &CStr,
+    max_name_len: u8,
+    public: &PublicKey,
+) -> Result<Vec<u8>> {
+    let suites = [
+        ffi::HpkeSymmetricSuite {
+            kdfId: ffi::HpkeKdfHkdfSha256,
+            aeadId: ffi::HpkeAeadAes128Gcm,
+        },
+        ffi::HpkeSymmetricSuite {
+            kdfId: ffi::HpkeKdfHkdfSha256,
+            aeadId: ffi::HpkeAeadChaCha20Poly1305,
+        },
+    ];
+    let mut buf = vec![0; ECH_CONFIG_MAX_LEN];
+    let mut len = 0;
+    wrap_ffi(|| unsafe {
+        ffi::SSL_EncodeEchConfigId(
+            config_id,
+            public_name.as_ptr(),
+            max_name_len as c_uint,
+            ffi::HpkeDhKemX25519Sha256,
+            public.as_raw_ptr(),
+            suites.as_ptr(),
+            suites.len() as c_uint,
+            buf.as_mut_ptr(),
+            &mut len,
+            buf.len() as c_uint,
+        )
+    })?;
+    buf.truncate(len as usize);
+    Ok(buf)
+}
+
+// Comfortably more than a config with a 255-byte name needs.
+const ECH_CONFIG_MAX_LEN: usize = 1024;
+
+// Sets up the server session ID cache (used for TLS 1.2 and earlier
+// resumption without tickets) in shared memory, so that servers in
+// several processes can resume each other's sessions.  It has to be
+// done once, before forking: children inherit it through fork, and
+// anything exec'd instead needs SSL_InheritMPServerSIDCache and the
+// SSL_INHERITANCE environment variable this sets.  The backing file
+// goes in `dir` (default /tmp), which must be on a local filesystem
+// writable by all the server processes.  Zero `max_entries` or
+// `timeout` means NSS's default; the timeout is clamped to between
+// 5 seconds and a day.
+pub fn config_mp_server_session_cache(
+    max_entries: i32,
+    timeout: Duration,
+    dir: Option<&CStr>,
+) -> Result<()> {
+    let timeout = cmp::min(timeout.as_secs(), u64::from(u32::MAX)) as u32;
+    wrap_ffi(|| unsafe {
+        ffi::SSL_ConfigMPServerSIDCache(
+            max_entries,
+            0,
+            timeout,
+            dir.map_or(ptr::null(), CStr::as_ptr),
+        )
+    })
+}
+
 // Caller must ensure this isn't one of the SECItems where the length
-// is actually bits instead of bytes.
+// is actually bits instead of bytes: those are the BIT STRINGs that
+// NSS's ASN.1 decoder fills in, like `signatureWrap.signature`,
+// `subjectPublicKeyInfo.subjectPublicKey`, and a certificate's
+// `issuerID` and `subjectID`.
+/// # Safety
+///
+/// Unless `len` is 0, `data` must point to `len` readable bytes that
+/// outlive the slice.
 pub unsafe fn sec_item_as_slice(item: &ffi::SECItem) -> &[u8] {
-    slice::from_raw_parts(item.data, item.len as usize)
+    if item.len == 0 {
+        // `data` may be null.
+        &[]
+    } else {
+        slice::from_raw_parts(item.data, item.len as usize)
+    }
+}
+
+// For those BIT STRINGs: the whole bytes, including the last one if
+// it's partly used.
+/// # Safety
+///
+/// Unless `len` is 0, `data` must point to `len` bits, rounded up to
+/// whole bytes, that outlive the slice.
+pub unsafe fn bit_string_as_slice(item: &ffi::SECItem) -> &[u8] {
+    if item.len == 0 {
+        &[]
+    } else {
+        slice::from_raw_parts(item.data, (item.len as usize + 7) / 8)
+    }
+}
+
+// Copies the contents; the same caveat as `sec_item_as_slice` applies.
+/// # Safety
+///
+/// As for `sec_item_as_slice`.
+pub unsafe fn sec_item_to_vec(item: &ffi::SECItem) -> Vec<u8> {
+    sec_item_as_slice(item).to_vec()
+}
+
+// For a heap-allocated SECItem that the caller owns (e.g., returned by
+// an NSS function without an arena): copies the contents, then frees
+// both them and the item itself.
+/// # Safety
+///
+/// `item` must be a non-null SECItem allocated by NSS that the caller
+/// owns and doesn't use afterwards, with contents as for
+/// `sec_item_as_slice`.
+pub unsafe fn owned_sec_item_to_vec(item: *mut ffi::SECItem) -> Vec<u8> {
+    assert!(!item.is_null());
+    let data = sec_item_to_vec(&*item);
+    ffi::SECITEM_FreeItem(item, ffi::nspr::PR_TRUE);
+    data
+}
+
+// For passing borrowed data to NSS functions that take a non-const
+// `SECItem*` but don't actually write through it.
+fn sec_item_from_slice(data: &[u8]) -> ffi::SECItem {
+    assert!(data.len() <= c_uint::MAX as usize);
+    ffi::SECItem {
+        type_: ffi::SECItemType::siBuffer,
+        data: data.as_ptr() as *mut _,
+        len: data.len() as c_uint,
+    }
 }
 
 // This is a newtype so that it can have traits on it.
@@ -134,8 +307,30 @@ pub type BorrowedTLSSocket<'a, Callbacks> = &'a TLSSocketImpl<Callbacks>;
 pub struct TLSSocketImpl<Callbacks> {
     file: File,
     callbacks: Callbacks,
+    client_auth: Option<(Certificate, PrivateKey)>,
+    trust_anchors: Option<CertList>,
+    // Whether `use_auth_certificate_hook` is in effect; it and
+    // `set_trust_anchors` would replace each other's hook.
+    custom_auth_hook: bool,
+    time_func: Option<TimeFunc>,
+    // NSS has no getter for these.
+    url: Mutex<Option<CString>>,
+    server_name: Mutex<Option<String>>,
+    // Set by `reset_handshake`; otherwise the SSL_HANDSHAKE_AS_SERVER
+    // option decides.
+    reset_as_server: AtomicBool,
+    trace: Mutex<Option<mpsc::Sender<HandshakeEvent>>>,
+    alert_received_hook: Option<AlertHook<Callbacks>>,
+    alert_sent_hook: Option<AlertHook<Callbacks>>,
 }
 
+type AlertHook<Callbacks> = fn(BorrowedTLSSocket<Callbacks>, TLSAlert);
+
+// ALPN protocol names are length-prefixed with a byte.
+const MAX_ALPN_LENGTH: usize = 255;
+
+type TimeFunc = Box<dyn Fn() -> SystemTime + Send + Sync>;
+
 impl<Callbacks> Deref for TLSSocket<Callbacks> {
     type Target = TLSSocketImpl<Callbacks>;
     fn deref(&self) -> &Self::Target {
@@ -164,17 +359,49 @@ impl<Callbacks> Borrow<File> for TLSSocket<Callbacks> {
 
 impl<Callbacks> TLSSocket<Callbacks> {
     pub fn new(inner: File, callbacks: Callbacks) -> Result<Self> {
-        Self::new_with_model(inner, callbacks, None)
+        Self::new_with_model::<File>(inner, callbacks, None)
     }
-    pub fn new_with_model(inner: File, callbacks: Callbacks, model: Option<Self>) -> Result<Self> {
-        if let Some(_) = model {
-            // This will copy the callbacks; need to unset or fix them.
-            unimplemented!();
-        }
-        let raw_model = model.map_or(nspr::fd::null(), |fd| fd.as_raw_prfd());
+
+    // The new socket copies the model's configuration: options,
+    // version range, ciphersuite/signature scheme/named group
+    // preferences, server certificates, URL, and the bad cert hook.
+    // Hooks that call back into Rust (the auth certificate, client
+    // certificate, false start and time function hooks) are tied to
+    // the model's callbacks, so they're reset here and must be set up
+    // again.  Likewise for the trace channel and alert hooks.
+    pub fn new_with_model<M>(inner: File, callbacks: Callbacks, model: Option<&M>) -> Result<Self>
+    where
+        M: Borrow<File>,
+    {
+        Self::import(inner, callbacks, model, SSLVariant::Stream)
+    }
+
+    // `inner` should be a UDP socket for `SSLVariant::Datagram`.
+    pub fn new_with_variant(
+        inner: File,
+        callbacks: Callbacks,
+        variant: SSLVariant,
+    ) -> Result<Self> {
+        Self::import::<File>(inner, callbacks, None, variant)
+    }
+
+    fn import<M>(
+        inner: File,
+        callbacks: Callbacks,
+        model: Option<&M>,
+        variant: SSLVariant,
+    ) -> Result<Self>
+    where
+        M: Borrow<File>,
+    {
+        let raw_model = model.map_or(nspr::fd::null(), |model| model.borrow().as_raw_prfd());
+        let import_fd = match variant {
+            SSLVariant::Stream => ffi::SSL_ImportFD,
+            SSLVariant::Datagram => ffi::DTLS_ImportFD,
+        };
         unsafe {
             let file = wrap_ffi(move || {
-                let raw = ffi::SSL_ImportFD(raw_model, inner.as_raw_prfd());
+                let raw = import_fd(raw_model, inner.as_raw_prfd());
                 // This call can "succeed" (return non-null) but have
                 // panicked in Rust.  And we retain ownership of
                 // `inner` if and only if SSL_ImportFD returned null.
@@ -187,26 +414,236 @@ impl<Callbacks> TLSSocket<Callbacks> {
                 }
                 File::from_raw_prfd_err(raw)
             })?;
-            Ok(TLSSocket(Box::new(TLSSocketImpl {
-                file: file,
-                callbacks: callbacks,
-            })))
+            if model.is_some() {
+                let fd = file.as_raw_prfd();
+                wrap_ffi(|| {
+                    ffi::SSL_AuthCertificateHook(
+                        fd,
+                        Some(ffi::SSL_AuthCertificate),
+                        ffi::CERT_GetDefaultCertDB() as *mut c_void,
+                    )
+                })?;
+                wrap_ffi(|| ffi::SSL_GetClientAuthDataHook(fd, None, ptr::null_mut()))?;
+                wrap_ffi(|| ffi::SSL_SetCanFalseStartCallback(fd, None, ptr::null_mut()))?;
+                wrap_ffi(|| ffi::SSL_HandshakeCallback(fd, None, ptr::null_mut()))?;
+                wrap_ffi(|| ffi::SSL_AlertReceivedCallback(fd, None, ptr::null_mut()))?;
+                wrap_ffi(|| ffi::SSL_AlertSentCallback(fd, None, ptr::null_mut()))?;
+                match wrap_ffi(|| ffi::SSL_SetTimeFunc(fd, None, ptr::null_mut())) {
+                    Err(ref err) if err.nspr_error == SSL_ERROR_UNSUPPORTED_EXPERIMENTAL_API => (),
+                    res => res?,
+                }
+                match wrap_ffi(|| ffi::SSL_SetResumptionTokenCallback(fd, None, ptr::null_mut())) {
+                    Err(ref err) if err.nspr_error == SSL_ERROR_UNSUPPORTED_EXPERIMENTAL_API => (),
+                    res => res?,
+                }
+            }
+            let sock = TLSSocket(Box::new(TLSSocketImpl {
+                file,
+                callbacks,
+                client_auth: None,
+                trust_anchors: None,
+                custom_auth_hook: false,
+                time_func: None,
+                url: Mutex::new(None),
+                server_name: Mutex::new(None),
+                reset_as_server: AtomicBool::new(false),
+                trace: Mutex::new(None),
+                alert_received_hook: None,
+                alert_sent_hook: None,
+            }));
+            // This replaces any hook from the model, which would point
+            // to the model's state.
+            let this: BorrowedTLSSocket<_> = &*sock;
+            wrap_ffi(|| {
+                ffi::SSL_SNISocketConfigHook(
+                    this.as_raw_prfd(),
+                    Some(raw_sni_hook::<Callbacks>),
+                    this as *const TLSSocketImpl<Callbacks> as *mut c_void,
+                )
+            })?;
+            Ok(sock)
         }
     }
 
+    // Fails with `PR_INVALID_STATE_ERROR` after `set_trust_anchors`,
+    // whose hook this would replace.
     pub fn use_auth_certificate_hook(&mut self) -> Result<()>
     where
         Callbacks: AuthCertificateHook,
     {
+        if self.0.trust_anchors.is_some() {
+            return Err(PR_INVALID_STATE_ERROR.into());
+        }
         let this: BorrowedTLSSocket<_> = &*self;
         wrap_ffi(|| unsafe {
             ffi::SSL_AuthCertificateHook(
                 self.as_raw_prfd(),
                 Some(raw_auth_certificate_hook::<Callbacks>),
-                mem::transmute(this),
+                this as *const TLSSocketImpl<Callbacks> as *mut c_void,
+            )
+        })?;
+        self.0.custom_auth_hook = true;
+        Ok(())
+    }
+}
+
+impl<Callbacks> TLSSocket<Callbacks> {
+    // Sends this certificate and key (PKCS#8) whenever the server asks
+    // for client authentication; the server's list of acceptable CAs
+    // is ignored.  Fails if the key isn't the certificate's.
+    pub fn set_client_certificate(&mut self, cert_der: &[u8], key_der: &[u8]) -> Result<()> {
+        let cert = Certificate::from_der(cert_der)?;
+        let key = PrivateKey::from_pkcs8_der(key_der)?;
+        if cert.public_key()?.spki_der()? != key.public_key()?.spki_der()? {
+            return Err(SEC_ERROR_BAD_KEY.into());
+        }
+        self.0.client_auth = Some((cert, key));
+        let this: BorrowedTLSSocket<_> = &*self;
+        wrap_ffi(|| unsafe {
+            ffi::SSL_GetClientAuthDataHook(
+                self.as_raw_prfd(),
+                Some(raw_client_auth_data::<Callbacks>),
+                this as *const TLSSocketImpl<Callbacks> as *mut c_void,
+            )
+        })
+    }
+
+    // Verifies the peer's certificate against only `anchors`, instead
+    // of the cert DB's trust settings, with its own auth certificate
+    // hook; a client still checks the name set with `set_url`.  A
+    // server also sends the anchors' names when asking for a client
+    // certificate.  Fails with `PR_INVALID_STATE_ERROR` while
+    // `use_auth_certificate_hook` is in effect, since it would replace
+    // that hook; an `AuthCertificateHook` can check anchors itself
+    // with `Certificate::verify_with_anchors`.
+    pub fn set_trust_anchors(&mut self, anchors: &[Certificate]) -> Result<()> {
+        if self.0.custom_auth_hook {
+            return Err(PR_INVALID_STATE_ERROR.into());
+        }
+        let list = CertList::from_certs(anchors)?;
+        wrap_ffi(|| unsafe { ffi::SSL_SetTrustAnchors(self.as_raw_prfd(), list.as_raw_ptr()) })?;
+        self.0.trust_anchors = Some(list);
+        let this: BorrowedTLSSocket<_> = &*self;
+        wrap_ffi(|| unsafe {
+            ffi::SSL_AuthCertificateHook(
+                self.as_raw_prfd(),
+                Some(raw_trust_anchors_hook::<Callbacks>),
+                this as *const TLSSocketImpl<Callbacks> as *mut c_void,
+            )
+        })
+    }
+
+    // Replaces the clock this socket uses (e.g., for certificate
+    // validity and session ticket lifetimes); mostly for testing.
+    pub fn set_time_func<F>(&mut self, f: F) -> Result<()>
+    where
+        F: Fn() -> SystemTime + Send + Sync + 'static,
+    {
+        let old = self.0.time_func.replace(Box::new(f));
+        let this: BorrowedTLSSocket<_> = &*self;
+        let res = wrap_ffi(|| unsafe {
+            ffi::SSL_SetTimeFunc(
+                self.as_raw_prfd(),
+                Some(raw_time_func::<Callbacks>),
+                this as *const TLSSocketImpl<Callbacks> as *mut c_void,
+            )
+        });
+        if res.is_err() {
+            self.0.time_func = old;
+        }
+        res
+    }
+
+    // Reports handshake progress on `tx`, for debugging; nothing is
+    // traced unless this is called.  Events are dropped once the
+    // receiver is gone.
+    pub fn set_trace_channel(&mut self, tx: mpsc::Sender<HandshakeEvent>) -> Result<()> {
+        *self.0.trace.lock().unwrap() = Some(tx);
+        let this: BorrowedTLSSocket<_> = &*self;
+        wrap_ffi(|| unsafe {
+            ffi::SSL_HandshakeCallback(
+                self.as_raw_prfd(),
+                Some(raw_handshake_callback::<Callbacks>),
+                this as *const TLSSocketImpl<Callbacks> as *mut c_void,
+            )
+        })?;
+        self.use_alert_callbacks()
+    }
+
+    pub fn use_alert_received_hook(&mut self) -> Result<()>
+    where
+        Callbacks: AlertCallback,
+    {
+        self.0.alert_received_hook = Some(|sock, alert| sock.callbacks.alert_received(sock, alert));
+        self.use_alert_callbacks()
+    }
+
+    pub fn use_alert_sent_hook(&mut self) -> Result<()>
+    where
+        Callbacks: AlertCallback,
+    {
+        self.0.alert_sent_hook = Some(|sock, alert| sock.callbacks.alert_sent(sock, alert));
+        self.use_alert_callbacks()
+    }
+
+    // Shared by tracing and the alert hooks, since NSS only has room
+    // for one of each callback.
+    fn use_alert_callbacks(&mut self) -> Result<()> {
+        let this: BorrowedTLSSocket<_> = &*self;
+        let fd = self.as_raw_prfd();
+        let arg = this as *const TLSSocketImpl<Callbacks> as *mut c_void;
+        wrap_ffi(|| unsafe {
+            ffi::SSL_AlertReceivedCallback(fd, Some(raw_alert_received_callback::<Callbacks>), arg)
+        })?;
+        wrap_ffi(|| unsafe {
+            ffi::SSL_AlertSentCallback(fd, Some(raw_alert_sent_callback::<Callbacks>), arg)
+        })
+    }
+
+    // NSS only false-starts if there's a callback to approve it, so
+    // this is needed in addition to `enable_false_start`.
+    pub fn use_false_start_check(&mut self) -> Result<()>
+    where
+        Callbacks: FalseStartCheck,
+    {
+        let this: BorrowedTLSSocket<_> = &*self;
+        wrap_ffi(|| unsafe {
+            ffi::SSL_SetCanFalseStartCallback(
+                self.as_raw_prfd(),
+                Some(raw_can_false_start_callback::<Callbacks>),
+                this as *const TLSSocketImpl<Callbacks> as *mut c_void,
+            )
+        })
+    }
+
+    // For a client's own session cache: NSS hands each resumption token
+    // to the callback instead of caching the session itself.  TLS 1.3
+    // tokens are tickets, which the server only sends with
+    // `SSL_ENABLE_SESSION_TICKETS` set.
+    pub fn use_resumption_token_callback(&mut self) -> Result<()>
+    where
+        Callbacks: ResumptionTokenCallback,
+    {
+        let this: BorrowedTLSSocket<_> = &*self;
+        wrap_ffi(|| unsafe {
+            ffi::SSL_SetResumptionTokenCallback(
+                self.as_raw_prfd(),
+                Some(raw_resumption_token_callback::<Callbacks>),
+                this as *const TLSSocketImpl<Callbacks> as *mut c_void,
             )
         })
     }
+
+    // Offers to resume the session `token` came from; call it before
+    // the handshake, after `set_url`.  Fails if the token is malformed
+    // or expired, in which case there's a full handshake as usual.
+    // Tokens are single-use (for privacy, in TLS 1.3), so the caller
+    // should forget it either way.
+    pub fn set_resumption_token(&mut self, token: &[u8]) -> Result<()> {
+        wrap_ffi(|| unsafe {
+            ffi::SSL_SetResumptionToken(self.as_raw_prfd(), token.as_ptr(), token.len() as c_uint)
+        })
+    }
 }
 
 impl<Callbacks> TLSSocketImpl<Callbacks> {
@@ -215,6 +652,15 @@ impl<Callbacks> TLSSocketImpl<Callbacks> {
     }
     // callbacks_mut would be sound, but would anything use it?
 
+    // A socket that's never connected, for use as a model with
+    // `TLSSocket::new_with_model`; see there for what's copied.
+    pub fn make_model(&self) -> Result<TLSSocket<()>> {
+        let inner = nspr::net::new_tcp_socket(libc::AF_INET)?;
+        TLSSocket::new_with_model(inner, (), Some(&self.file))
+    }
+
+    // Each call returns a new reference to the same certificate (and,
+    // for the chain, a new list), so these can be dropped freely.
     pub fn peer_cert(&self) -> Option<Certificate> {
         unsafe { Certificate::from_raw_ptr_opt(ffi::SSL_PeerCertificate(self.as_raw_prfd())) }
     }
@@ -227,8 +673,358 @@ impl<Callbacks> TLSSocketImpl<Callbacks> {
         unsafe { BorrowedFile::from_raw_prfd((*self.as_raw_prfd()).lower) }
     }
 
+    // Asks the layer below TLS, so this works whether or not the TLS
+    // layer passes `getpeername` down.
+    pub fn peer_addr(&self) -> Result<SocketAddr> {
+        self.cleartext().getpeername()
+    }
+
     pub fn set_url(&self, url: &CStr) -> Result<()> {
-        wrap_ffi(|| unsafe { ffi::SSL_SetURL(self.as_raw_prfd(), url.as_ptr()) })
+        wrap_ffi(|| unsafe { ffi::SSL_SetURL(self.as_raw_prfd(), url.as_ptr()) })?;
+        *self.url.lock().unwrap() = Some(url.to_owned());
+        Ok(())
+    }
+
+    // In order of preference; an empty list disables ALPN.
+    pub fn set_alpn_protocols(&mut self, protocols: &[&[u8]]) -> Result<()> {
+        // NSS moves the first protocol to the end (it was NPN's
+        // fallback), so undo that in advance.
+        let mut protocols = protocols.to_vec();
+        if !protocols.is_empty() {
+            protocols.rotate_right(1);
+        }
+        let mut data = Vec::new();
+        for proto in protocols {
+            if proto.is_empty() || proto.len() > MAX_ALPN_LENGTH {
+                return Err(SEC_ERROR_INVALID_ARGS.into());
+            }
+            data.push(proto.len() as u8);
+            data.extend_from_slice(proto);
+        }
+        wrap_ffi(|| unsafe {
+            ffi::SSL_SetNextProtoNego(self.as_raw_prfd(), data.as_ptr(), data.len() as c_uint)
+        })
+    }
+
+    // The protocol selected with ALPN, once the handshake is done.
+    pub fn alpn_protocol(&self) -> Result<Option<Vec<u8>>> {
+        let mut state = ffi::SSL_NEXT_PROTO_NO_SUPPORT;
+        let mut buf = [0; MAX_ALPN_LENGTH];
+        let mut len = 0;
+        wrap_ffi(|| unsafe {
+            ffi::SSL_GetNextProto(
+                self.as_raw_prfd(),
+                &mut state,
+                buf.as_mut_ptr(),
+                &mut len,
+                buf.len() as c_uint,
+            )
+        })?;
+        Ok(match state {
+            ffi::SSL_NEXT_PROTO_NEGOTIATED | ffi::SSL_NEXT_PROTO_SELECTED => {
+                Some(buf[..len as usize].to_vec())
+            }
+            _ => None,
+        })
+    }
+
+    // The hostname last passed to `set_url` on this socket, if any
+    // (not one inherited from a model).
+    pub fn url(&self) -> Result<Option<String>> {
+        match *self.url.lock().unwrap() {
+            Some(ref url) => url
+                .to_str()
+                .map(|url| Some(url.to_owned()))
+                .map_err(|_| SEC_ERROR_BAD_DATA.into()),
+            None => Ok(None),
+        }
+    }
+
+    // NSS's own view of the URL and peer certificate, as C callback
+    // code would get them; `url` and `peer_cert` are the usual way.
+    pub fn reveal_url(&self) -> Option<CString> {
+        unsafe {
+            let url = ffi::SSL_RevealURL(self.as_raw_prfd());
+            if url.is_null() {
+                return None;
+            }
+            let owned = CStr::from_ptr(url).to_owned();
+            ffi::PORT_Free(url as *mut c_void);
+            Some(owned)
+        }
+    }
+
+    pub fn reveal_cert(&self) -> Option<Certificate> {
+        unsafe { Certificate::from_raw_ptr_opt(ffi::SSL_RevealCert(self.as_raw_prfd())) }
+    }
+
+    // The argument for PKCS#11 password callbacks; this crate never
+    // sets one, so it's null unless C code did.
+    pub fn reveal_pin_arg(&self) -> *mut c_void {
+        unsafe { ffi::SSL_RevealPinArg(self.as_raw_prfd()) }
+    }
+
+    // On the server, the name the client asked for with SNI, once the
+    // handshake has gotten that far.
+    pub fn requested_server_name(&self) -> Result<Option<String>> {
+        Ok(self.server_name.lock().unwrap().clone())
+    }
+
+    fn trace(&self, event: HandshakeEvent) {
+        if let Some(ref tx) = *self.trace.lock().unwrap() {
+            let _ = tx.send(event);
+        }
+    }
+
+    // Needed if the underlying socket was already connected (or
+    // accepted) before being imported.
+    pub fn reset_handshake(&self, as_server: bool) -> Result<()> {
+        wrap_ffi(|| unsafe {
+            ffi::SSL_ResetHandshake(self.as_raw_prfd(), bool_to_nspr(as_server))
+        })?;
+        self.reset_as_server.store(as_server, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn is_server(&self) -> Result<bool> {
+        if self.reset_as_server.load(Ordering::Relaxed) {
+            return Ok(true);
+        }
+        self.get_option(SSL_HANDSHAKE_AS_SERVER)
+    }
+
+    // If the transport reaches EOF before the handshake completes,
+    // that's reported as PR_CONNECT_ABORTED_ERROR rather than NSS's
+    // PR_END_OF_FILE_ERROR, to distinguish it from an ordinary EOF.
+    pub fn force_handshake(&self) -> Result<()> {
+        wrap_ffi(|| unsafe { ffi::SSL_ForceHandshake(self.as_raw_prfd()) }).map_err(handshake_error)
+    }
+
+    // These shadow the `File` methods, which would otherwise be used
+    // through `Deref`, so that a handshake they drive reports EOF as
+    // `force_handshake` does.
+    pub fn read(&self, buf: &mut [u8]) -> Result<usize> {
+        FileMethods::read(&self.file, buf).map_err(|err| self.io_error(err))
+    }
+
+    pub fn write(&self, buf: &[u8]) -> Result<usize> {
+        FileMethods::write(&self.file, buf).map_err(|err| self.io_error(err))
+    }
+
+    pub fn recv(&self, buf: &mut [u8], peek: bool, timeout: Option<Duration>) -> Result<usize> {
+        FileMethods::recv(&self.file, buf, peek, timeout).map_err(|err| self.io_error(err))
+    }
+
+    pub fn send(&self, buf: &[u8], timeout: Option<Duration>) -> Result<usize> {
+        FileMethods::send(&self.file, buf, timeout).map_err(|err| self.io_error(err))
+    }
+
+    fn io_error(&self, err: Error) -> Error {
+        // NSS only fills in the channel info once the handshake is done.
+        match self.channel_info() {
+            Ok(ref info) if info.protocolVersion != 0 => err,
+            _ => handshake_error(err),
+        }
+    }
+
+    // For nonblocking sockets: does what it can of the handshake
+    // without blocking, and if that isn't all of it, says what to
+    // wait for on the underlying socket before trying again.
+    pub fn try_handshake(&self) -> Result<HandshakeProgress> {
+        use ffi::nspr::{PR_POLL_READ, PR_POLL_WRITE};
+
+        match self.force_handshake() {
+            Ok(()) => Ok(HandshakeProgress::Done),
+            Err(err) if err.nspr_error == PR_WOULD_BLOCK_ERROR => {
+                // Given both, the SSL layer's poll method keeps only the
+                // one the handshake is blocked on (or both, if the TCP
+                // connection is still in progress).
+                let fd = self.as_raw_prfd();
+                let flags = unsafe {
+                    let poll = (*(*fd).methods).poll.expect("SSL layer has no poll method");
+                    let mut out_flags = 0;
+                    poll(fd, PR_POLL_READ | PR_POLL_WRITE, &mut out_flags)
+                };
+                Ok(if flags & PR_POLL_WRITE != 0 {
+                    HandshakeProgress::WantWrite
+                } else {
+                    HandshakeProgress::WantRead
+                })
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    // Decrypted application data that NSS has buffered and can be
+    // read without touching the transport; an event loop waiting for
+    // the underlying socket to be readable should check this first.
+    pub fn bytes_pending(&self) -> Result<usize> {
+        let pending = unsafe { ffi::SSL_DataPending(self.as_raw_prfd()) };
+        if pending < 0 {
+            Err(Error::last())
+        } else {
+            Ok(pending as usize)
+        }
+    }
+
+    // Whether a read would return data, end-of-stream or block, found
+    // by peeking without waiting.  Unlike a plain `read`, this keeps
+    // end-of-stream apart from a stall, and PeerClosed stays once it's
+    // been seen.  That's either the peer's close_notify or the
+    // connection closing without one (which NSS reads as an end too,
+    // so a truncation attack looks the same); an `AlertCallback` can
+    // tell them apart.
+    pub fn read_state(&self) -> Result<ReadState> {
+        if self.bytes_pending()? > 0 {
+            return Ok(ReadState::Open);
+        }
+        let mut buf = [0; 1];
+        match self.recv(&mut buf, true, Some(Duration::from_secs(0))) {
+            Ok(0) => Ok(ReadState::PeerClosed),
+            Ok(_) => Ok(ReadState::Open),
+            Err(err)
+                if err.nspr_error == PR_WOULD_BLOCK_ERROR
+                    || err.nspr_error == PR_IO_TIMEOUT_ERROR =>
+            {
+                Ok(ReadState::WouldBlock)
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    // Whether a read would return without blocking: either NSS has
+    // data buffered (which polling the transport alone would miss) or
+    // the transport is readable, perhaps only with part of a record or
+    // an error.  Fails with PR_OPERATION_NOT_SUPPORTED_ERROR if the
+    // transport isn't a plain NSPR socket.
+    pub fn poll_readable(&self, timeout: Option<Duration>) -> Result<bool> {
+        if self.bytes_pending()? > 0 {
+            return Ok(true);
+        }
+        let lower = self.cleartext();
+        if lower.identity() != ffi::nspr::PR_NSPR_IO_LAYER {
+            return Err(PR_OPERATION_NOT_SUPPORTED_ERROR.into());
+        }
+        let mut desc = ffi::nspr::PRPollDesc {
+            fd: lower.as_raw_prfd(),
+            in_flags: ffi::nspr::PR_POLL_READ,
+            out_flags: 0,
+        };
+        let ready = wrap_ffi(|| unsafe {
+            ffi::nspr::PR_Poll(&mut desc, 1, duration_opt_to_nspr(timeout))
+        })?;
+        Ok(ready > 0)
+    }
+
+    // Sends close_notify: further writes fail, but reads still work,
+    // e.g. to wait for the peer's own close_notify.  NSS then passes
+    // the shutdown on to the transport, so a TCP one is half-closed
+    // too (a wrapped one gets `FileMethods::shutdown`); either way it
+    // stays open until the socket's dropped.
+    pub fn close_notify(&self) -> Result<()> {
+        self.shutdown(Shutdown::Write)
+    }
+
+    // Unlike `read` and `write`, these fail with PR_IO_TIMEOUT_ERROR
+    // instead of blocking indefinitely.  The timeout applies to each
+    // wait on the transport, including any for handshake messages
+    // that have to be exchanged first, so the total can be a few
+    // times it.
+    pub fn read_timeout(&self, buf: &mut [u8], timeout: Duration) -> Result<usize> {
+        self.recv(buf, false, Some(timeout))
+    }
+
+    pub fn write_timeout(&self, buf: &[u8], timeout: Duration) -> Result<usize> {
+        self.send(buf, Some(timeout))
+    }
+
+    // `cert_chain` is the rest of the chain to send after `cert`,
+    // normally the intermediates; NSS copies it, and it can be empty.
+    // The key's authentication type is detected from the certificate.
+    pub fn config_server_cert(
+        &mut self,
+        cert: &Certificate,
+        key: &PrivateKey,
+        cert_chain: &[Certificate],
+    ) -> Result<()> {
+        let mut certs: Vec<_> = Some(cert)
+            .into_iter()
+            .chain(cert_chain)
+            .map(|cert| sec_item_from_slice(cert.as_der()))
+            .collect();
+        let chain = ffi::CERTCertificateList {
+            certs: certs.as_mut_ptr(),
+            len: certs.len() as c_int,
+            arena: ptr::null_mut(),
+        };
+        let data = ffi::SSLExtraServerCertData {
+            authType: ffi::ssl_auth_null,
+            certChain: &chain,
+            stapledOCSPResponses: ptr::null(),
+            signedCertTimestamps: ptr::null(),
+            delegCred: ptr::null(),
+            delegCredPrivKey: ptr::null(),
+        };
+        wrap_ffi(|| unsafe {
+            ffi::SSL_ConfigServerCert(
+                self.as_raw_prfd(),
+                cert.as_raw_ptr() as *mut _,
+                key.as_raw_ptr(),
+                &data,
+                mem::size_of::<ffi::SSLExtraServerCertData>() as c_uint,
+            )
+        })
+    }
+
+    // Encrypted Client Hello (TLS 1.3 only), for a client: `configs` is
+    // an ECHConfigList, as from DNS or `encode_ech_config`.  These fail
+    // with `SSL_ERROR_UNSUPPORTED_EXPERIMENTAL_API` if NSS is too old.
+    pub fn set_ech_configs(&mut self, configs: &[u8]) -> Result<()> {
+        wrap_ffi(|| unsafe {
+            ffi::SSL_SetClientEchConfigs(
+                self.as_raw_prfd(),
+                configs.as_ptr(),
+                configs.len() as c_uint,
+            )
+        })
+    }
+
+    // For a server: `configs` was made with `public`, which is an
+    // X25519 key.
+    pub fn set_server_ech_configs(
+        &mut self,
+        public: &PublicKey,
+        private: &PrivateKey,
+        configs: &[u8],
+    ) -> Result<()> {
+        wrap_ffi(|| unsafe {
+            ffi::SSL_SetServerEchConfigs(
+                self.as_raw_prfd(),
+                public.as_raw_ptr(),
+                private.as_raw_ptr(),
+                configs.as_ptr(),
+                configs.len() as c_uint,
+            )
+        })
+    }
+
+    // After a client's handshake fails with
+    // `SSL_ERROR_ECH_RETRY_WITH_ECH` (the server didn't accept the
+    // configs, and sent an ech_required alert), the configs the server
+    // gave for one retry, on a new connection.
+    pub fn retry_ech_configs(&self) -> Result<Option<Vec<u8>>> {
+        let mut item = unsafe { mem::zeroed::<ffi::SECItem>() };
+        let res =
+            wrap_ffi(|| unsafe { ffi::SSL_GetEchRetryConfigs(self.as_raw_prfd(), &mut item) });
+        match res {
+            Ok(()) => unsafe {
+                let configs = sec_item_as_slice(&item).to_vec();
+                ffi::SECITEM_FreeItem(&mut item, ffi::nspr::PR_FALSE);
+                Ok(Some(configs))
+            },
+            Err(err) if err.nspr_error == SSL_ERROR_HANDSHAKE_NOT_COMPLETED => Ok(None),
+            Err(err) => Err(err),
+        }
     }
 
     pub fn unset_bad_cert_hook(&mut self) -> Result<()> {
@@ -239,6 +1035,68 @@ impl<Callbacks> TLSSocketImpl<Callbacks> {
     pub fn unset_auth_certificate_hook(&mut self) -> Result<()> {
         wrap_ffi(|| unsafe {
             ffi::SSL_AuthCertificateHook(self.as_raw_prfd(), None, ptr::null_mut())
+        })?;
+        self.custom_auth_hook = false;
+        Ok(())
+    }
+
+    pub fn enable_false_start(&mut self, enabled: bool) -> Result<()> {
+        self.set_option(SSL_ENABLE_FALSE_START, enabled)
+    }
+
+    // TLS 1.3's middlebox compatibility mode (RFC 8446, appendix D.4):
+    // a client sends a session ID and both sides send dummy
+    // ChangeCipherSpec messages, so the handshake looks more like
+    // TLS 1.2.  Off by default; a server just follows the client.
+    pub fn set_tls13_compat_mode(&mut self, enabled: bool) -> Result<()> {
+        self.set_option(SSL_ENABLE_TLS13_COMPAT_MODE, enabled)
+    }
+
+    // For a client retrying with a lower maximum version after a
+    // failed handshake: the server then rejects the connection if it
+    // supports a higher version than this, as a downgrade attack.  It
+    // shouldn't be set on a first attempt.
+    pub fn set_fallback_scsv(&mut self, enabled: bool) -> Result<()> {
+        self.set_option(SSL_ENABLE_FALLBACK_SCSV, enabled)
+    }
+
+    // These options are only known to newer NSS versions (3.89+); if
+    // the linked library predates them, this fails with
+    // PR_OPERATION_NOT_SUPPORTED_ERROR.
+    pub fn enable_grease(&mut self, enabled: bool) -> Result<()> {
+        self.set_newer_option(SSL_ENABLE_GREASE, enabled)
+    }
+
+    pub fn enable_ch_extension_permutation(&mut self, enabled: bool) -> Result<()> {
+        self.set_newer_option(SSL_ENABLE_CH_EXTENSION_PERMUTATION, enabled)
+    }
+
+    fn set_newer_option(&self, option: TLSOption, value: bool) -> Result<()> {
+        self.set_option(option, value).map_err(|err| {
+            if err.nspr_error == SEC_ERROR_INVALID_ARGS {
+                PR_OPERATION_NOT_SUPPORTED_ERROR.into()
+            } else {
+                err
+            }
+        })
+    }
+
+    // TLS 1.3 servers only: the most 0-RTT data to accept, as
+    // advertised in the session tickets this socket issues; 0 means
+    // none.  Early data also needs SSL_ENABLE_0RTT_DATA.
+    pub fn set_max_early_data_size(&mut self, size: u32) -> Result<()> {
+        wrap_ffi(|| unsafe { ffi::SSL_SetMaxEarlyDataSize(self.as_raw_prfd(), size) })
+    }
+
+    // Without one of these, a server rejects all early data.  The
+    // socket keeps its own reference to the context.
+    pub fn set_anti_replay(&mut self, ctx: &AntiReplayContext) -> Result<()> {
+        wrap_ffi(|| unsafe { ffi::SSL_SetAntiReplayContext(self.as_raw_prfd(), ctx.0) })
+    }
+
+    pub fn unset_false_start_check(&mut self) -> Result<()> {
+        wrap_ffi(|| unsafe {
+            ffi::SSL_SetCanFalseStartCallback(self.as_raw_prfd(), None, ptr::null_mut())
         })
     }
 
@@ -264,6 +1122,20 @@ impl<Callbacks> TLSSocketImpl<Callbacks> {
         })
     }
 
+    // Unchecked access to the options, by number and with their full
+    // integer values (some, like SSL_ENABLE_RENEGOTIATION, aren't just
+    // on/off), for ones newer than `TLSOption`.  Unknown options fail
+    // with SEC_ERROR_INVALID_ARGS.
+    pub fn set_option_raw(&mut self, option: i32, value: i32) -> Result<()> {
+        wrap_ffi(|| unsafe { ffi::SSL_OptionSet(self.as_raw_prfd(), option, value) })
+    }
+
+    pub fn get_option_raw(&self, option: i32) -> Result<i32> {
+        let mut value = 0;
+        wrap_ffi(|| unsafe { ffi::SSL_OptionGet(self.as_raw_prfd(), option, &mut value) })?;
+        Ok(value)
+    }
+
     pub fn set_version_range(&self, min: TLSVersion, max: TLSVersion) -> Result<()> {
         let range = ffi::SSLVersionRange {
             min: min.to_ffi(),
@@ -277,10 +1149,23 @@ impl<Callbacks> TLSSocketImpl<Callbacks> {
             min: 0xffff,
             max: 0,
         };
-        wrap_ffi(|| unsafe { ffi::SSL_VersionRangeSet(self.as_raw_prfd(), &mut range as *mut _) })?;
+        wrap_ffi(|| unsafe { ffi::SSL_VersionRangeGet(self.as_raw_prfd(), &mut range as *mut _) })?;
         Ok((TLSVersion(range.min), TLSVersion(range.max)))
     }
 
+    // The versions a client offers, in the order of its supported_versions
+    // extension: every version in the range, highest (and so preferred)
+    // first.  NSS has no way to reorder them or leave gaps.  If the
+    // maximum is below TLS 1.3, the extension isn't sent and only the
+    // maximum is offered, as the ClientHello's version.
+    pub fn offered_versions(&self) -> Result<Vec<TLSVersion>> {
+        let (min, max) = self.get_version_range()?;
+        if max < TLS_VERSION_1_3 {
+            return Ok(vec![max]);
+        }
+        Ok((min.0..=max.0).rev().map(TLSVersion).collect())
+    }
+
     pub fn limit_version(&self, min: Option<TLSVersion>, max: Option<TLSVersion>) -> Result<()> {
         let (abs_min, abs_max) = TLSVersion::supported_range()?;
         self.set_version_range(
@@ -289,26 +1174,280 @@ impl<Callbacks> TLSSocketImpl<Callbacks> {
         )
     }
 
-    pub fn set_ciphersuite_enabled(&self, suite: TLSCipherSuite, enabled: bool) -> Result<()> {
+    // Only meaningful once the handshake is done; before that it's
+    // mostly zeroes.
+    fn channel_info(&self) -> Result<ffi::SSLChannelInfo> {
+        let mut info: ffi::SSLChannelInfo = unsafe { mem::zeroed() };
         wrap_ffi(|| unsafe {
-            ffi::SSL_CipherPrefSet(self.as_raw_prfd(), suite.to_ffi(), bool_to_nspr(enabled))
-        })
+            ffi::SSL_GetChannelInfo(
+                self.as_raw_prfd(),
+                &mut info,
+                mem::size_of::<ffi::SSLChannelInfo>() as ffi::nspr::PRUintn,
+            )
+        })?;
+        Ok(info)
     }
 
-    pub fn is_ciphersuite_enabled(&self, suite: TLSCipherSuite) -> Result<bool> {
-        result_bool_getter(|bptr| unsafe {
+    // The scheme the server used to sign the handshake (i.e., what was
+    // used with the server's certificate key), or `SIG_NONE` if there
+    // was none, as when resuming a TLS 1.2 session.
+    pub fn signature_scheme(&self) -> Result<u16> {
+        Ok(self.channel_info()?.signatureScheme as u16)
+    }
+
+    // How the server authenticated itself, if `AuthType` knows it.
+    pub fn auth_type(&self) -> Result<Option<AuthType>> {
+        Ok(AuthType::from_ffi(self.channel_info()?.authType))
+    }
+
+    // In order of preference; this replaces NSS's default list.
+    pub fn set_signature_schemes(&mut self, schemes: &[u16]) -> Result<()> {
+        let schemes: Vec<ffi::SSLSignatureScheme> =
+            schemes.iter().map(|&scheme| scheme.into()).collect();
+        wrap_ffi(|| unsafe {
+            ffi::SSL_SignatureSchemePrefSet(
+                self.as_raw_prfd(),
+                schemes.as_ptr(),
+                schemes.len() as c_uint,
+            )
+        })
+    }
+
+    // Enables exactly these groups, in order of preference.
+    pub fn set_named_groups(&mut self, groups: &[NamedGroup]) -> Result<()> {
+        let groups: Vec<ffi::SSLNamedGroup> = groups.iter().map(|group| group.to_ffi()).collect();
+        wrap_ffi(|| unsafe {
+            ffi::SSL_NamedGroupConfig(self.as_raw_prfd(), groups.as_ptr(), groups.len() as c_uint)
+        })
+    }
+
+    // For a server's DHE suites: the FFDHE groups to use, most
+    // preferred first, when the client doesn't name any (with
+    // `set_named_groups`, these are only the fallback).  Other groups
+    // are rejected.  NSS has no way to read these back; `named_group`
+    // gives the one used.
+    pub fn set_dhe_groups(&mut self, groups: &[NamedGroup]) -> Result<()> {
+        let groups = groups
+            .iter()
+            .map(|group| group.to_dhe_group())
+            .collect::<Option<Vec<_>>>()
+            .ok_or(SEC_ERROR_INVALID_ARGS)?;
+        if groups.len() > u16::MAX as usize {
+            return Err(SEC_ERROR_INVALID_ARGS.into());
+        }
+        wrap_ffi(|| unsafe {
+            ffi::SSL_DHEGroupPrefSet(self.as_raw_prfd(), groups.as_ptr(), groups.len() as u16)
+        })
+    }
+
+    // Allows a 1024-bit DHE group, which NSS generates (slowly) on
+    // first use; only for peers that can't do better.
+    pub fn enable_weak_dhe_group(&mut self, enabled: bool) -> Result<()> {
+        wrap_ffi(|| unsafe {
+            ffi::SSL_EnableWeakDHEPrimeGroup(self.as_raw_prfd(), bool_to_nspr(enabled))
+        })
+    }
+
+    // Whether the server accepted Encrypted Client Hello.
+    pub fn ech_accepted(&self) -> Result<bool> {
+        Ok(bool_from_nspr(self.channel_info()?.echAccepted))
+    }
+
+    // The group used for the key exchange, if any and if it's one
+    // that `NamedGroup` knows about.  (When resuming, this is what the
+    // original handshake used.)
+    pub fn named_group(&self) -> Result<Option<NamedGroup>> {
+        let info = self.channel_info()?;
+        let group = if bool_from_nspr(info.resumed) {
+            info.originalKeaGroup
+        } else {
+            info.keaGroup
+        };
+        Ok(NamedGroup::from_ffi(group))
+    }
+
+    // RFC 5705 (and TLS 1.3's) exporter.  Without a context is not
+    // the same as with an empty one, except in TLS 1.3.  NSS doesn't
+    // take an empty context, so before TLS 1.3 that fails with
+    // SEC_ERROR_INVALID_ARGS.
+    pub fn export_keying_material(
+        &self,
+        label: &str,
+        context: Option<&[u8]>,
+        len: usize,
+    ) -> Result<Vec<u8>> {
+        let context = match context {
+            Some([]) => {
+                let version = self.channel_info()?.protocolVersion;
+                if version < ffi::SSL_LIBRARY_VERSION_TLS_1_3 {
+                    return Err(SEC_ERROR_INVALID_ARGS.into());
+                }
+                None
+            }
+            context => context,
+        };
+        let context_slice = context.unwrap_or(&[]);
+        if label.len() > c_uint::MAX as usize
+            || context_slice.len() > c_uint::MAX as usize
+            || len > c_uint::MAX as usize
+        {
+            return Err(SEC_ERROR_INVALID_ARGS.into());
+        }
+        let mut out = vec![0; len];
+        wrap_ffi(|| unsafe {
+            ffi::SSL_ExportKeyingMaterial(
+                self.as_raw_prfd(),
+                label.as_ptr() as *const c_char,
+                label.len() as c_uint,
+                bool_to_nspr(context.is_some()),
+                context_slice.as_ptr(),
+                context_slice.len() as c_uint,
+                out.as_mut_ptr(),
+                len as c_uint,
+            )
+        })?;
+        Ok(out)
+    }
+
+    // Channel binding data, for SASL mechanisms like SCRAM-*-PLUS;
+    // both ends of the connection get the same value.
+    pub fn channel_binding(&self, binding_type: ChannelBindingType) -> Result<Vec<u8>> {
+        match binding_type {
+            ChannelBindingType::TlsServerEndPoint => {
+                let cert = if self.is_server()? {
+                    unsafe {
+                        Certificate::from_raw_ptr_opt(ffi::SSL_LocalCertificate(self.as_raw_prfd()))
+                    }
+                } else {
+                    self.peer_cert()
+                };
+                let cert = cert.ok_or(SSL_ERROR_NO_CERTIFICATE)?;
+                let hash = cert.end_point_hash().ok_or(SEC_ERROR_INVALID_ALGORITHM)?;
+                pk11::hash(hash, cert.as_der())
+            }
+            // RFC 9266 says an empty context, so before TLS 1.3 this
+            // fails; see `export_keying_material`.
+            ChannelBindingType::TlsExporter => {
+                self.export_keying_material("EXPORTER-Channel-Binding", Some(&[]), 32)
+            }
+        }
+    }
+
+    pub fn set_ciphersuite_enabled(&self, suite: TLSCipherSuite, enabled: bool) -> Result<()> {
+        wrap_ffi(|| unsafe {
+            ffi::SSL_CipherPrefSet(self.as_raw_prfd(), suite.to_ffi(), bool_to_nspr(enabled))
+        })
+    }
+
+    pub fn is_ciphersuite_enabled(&self, suite: TLSCipherSuite) -> Result<bool> {
+        result_bool_getter(|bptr| unsafe {
             ffi::SSL_CipherPrefGet(self.as_raw_prfd(), suite.to_ffi(), bptr)
         })
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ReadState {
+    // There's data to read now.
+    Open,
+    // With close_notify or not; see `read_state`.
+    PeerClosed,
+    WouldBlock,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HandshakeProgress {
+    Done,
+    WantRead,
+    WantWrite,
+}
+
+fn handshake_error(err: Error) -> Error {
+    if err.nspr_error == PR_END_OF_FILE_ERROR {
+        PR_CONNECT_ABORTED_ERROR.into()
+    } else {
+        err
+    }
+}
+
+// Implement one of these: by default `auth_certificate` passes the
+// peer's chain, leaf first, to `auth_certificate_chain`, which rejects
+// everything unless overridden.
 pub trait AuthCertificateHook: Sized {
     fn auth_certificate(
         &self,
         sock: BorrowedTLSSocket<Self>,
         check_sig: bool,
         is_server: bool,
-    ) -> Result<()>;
+    ) -> Result<()> {
+        let chain: Vec<_> = sock
+            .peer_cert_chain()
+            .ok_or(SSL_ERROR_NO_CERTIFICATE)?
+            .into_iter()
+            .collect();
+        self.auth_certificate_chain(sock, &chain, check_sig, is_server)
+    }
+
+    fn auth_certificate_chain(
+        &self,
+        _sock: BorrowedTLSSocket<Self>,
+        _chain: &[Certificate],
+        _check_sig: bool,
+        _is_server: bool,
+    ) -> Result<()> {
+        Err(SEC_ERROR_UNTRUSTED_CERT.into())
+    }
+}
+
+// Public key pinning: accepts the peer only if a certificate it sent
+// has one of `pins` as its `spki_sha256`, and only after NSS's usual
+// verification (which checks the URL, if set) unless `pin_only`.
+pub struct PinnedCertHook {
+    pins: Vec<[u8; 32]>,
+    pin_only: bool,
+}
+
+impl PinnedCertHook {
+    pub fn new(pins: &[[u8; 32]]) -> Self {
+        PinnedCertHook {
+            pins: pins.to_vec(),
+            pin_only: false,
+        }
+    }
+
+    // With this, nothing else is checked: not the issuer, validity
+    // period, or name.
+    pub fn pin_only(mut self, pin_only: bool) -> Self {
+        self.pin_only = pin_only;
+        self
+    }
+}
+
+impl AuthCertificateHook for PinnedCertHook {
+    fn auth_certificate_chain(
+        &self,
+        sock: BorrowedTLSSocket<Self>,
+        chain: &[Certificate],
+        check_sig: bool,
+        is_server: bool,
+    ) -> Result<()> {
+        if !self.pin_only {
+            wrap_ffi(|| unsafe {
+                ffi::SSL_AuthCertificate(
+                    ffi::CERT_GetDefaultCertDB() as *mut c_void,
+                    sock.as_raw_prfd(),
+                    bool_to_nspr(check_sig),
+                    bool_to_nspr(is_server),
+                )
+            })?;
+        }
+        for cert in chain {
+            if self.pins.contains(&cert.spki_sha256()?) {
+                return Ok(());
+            }
+        }
+        Err(SEC_ERROR_UNTRUSTED_CERT.into())
+    }
 }
 
 unsafe extern "C" fn raw_auth_certificate_hook<Callbacks>(
@@ -322,7 +1461,7 @@ where
 {
     wrap_callback(ffi::SECFailure, || {
         // TODO: check identity?
-        let this: BorrowedTLSSocket<Callbacks> = mem::transmute(arg);
+        let this: BorrowedTLSSocket<Callbacks> = &*(arg as *const TLSSocketImpl<Callbacks>);
         assert_eq!(this.as_raw_prfd(), fd);
         this.callbacks
             .auth_certificate(this, bool_from_nspr(check_sig), bool_from_nspr(is_server))
@@ -330,6 +1469,344 @@ where
     })
 }
 
+unsafe extern "C" fn raw_trust_anchors_hook<Callbacks>(
+    arg: *mut c_void,
+    fd: *mut ffi::nspr::PRFileDesc,
+    _check_sig: ffi::nspr::PRBool,
+    is_server: ffi::nspr::PRBool,
+) -> ffi::SECStatus {
+    wrap_callback(ffi::SECFailure, || {
+        let this: BorrowedTLSSocket<Callbacks> = &*(arg as *const TLSSocketImpl<Callbacks>);
+        assert_eq!(this.as_raw_prfd(), fd);
+        let anchors = this
+            .trust_anchors
+            .as_ref()
+            .expect("hook set without anchors");
+        let cert = this
+            .peer_cert()
+            .ok_or_else(|| Error::from(SSL_ERROR_NO_CERTIFICATE))?;
+        let usage = if bool_from_nspr(is_server) {
+            CertUsage::SslClient
+        } else {
+            CertUsage::SslServer
+        };
+        cert.verify_with_anchors(anchors, usage, None)?;
+        if !bool_from_nspr(is_server) {
+            if let Some(ref url) = *this.url.lock().unwrap() {
+                cert.verify_name(url)?;
+            }
+        }
+        Ok(ffi::SECSuccess)
+    })
+}
+
+unsafe extern "C" fn raw_client_auth_data<Callbacks>(
+    arg: *mut c_void,
+    fd: *mut ffi::nspr::PRFileDesc,
+    _ca_names: *mut ffi::CERTDistNames,
+    ret_cert: *mut *mut ffi::CERTCertificate,
+    ret_key: *mut *mut ffi::SECKEYPrivateKey,
+) -> ffi::SECStatus {
+    wrap_callback(ffi::SECFailure, || {
+        let this: BorrowedTLSSocket<Callbacks> = &*(arg as *const TLSSocketImpl<Callbacks>);
+        assert_eq!(this.as_raw_prfd(), fd);
+        let (cert, key) = this
+            .client_auth
+            .as_ref()
+            .ok_or_else(|| Error::from(SSL_ERROR_NO_CERTIFICATE))?;
+        // NSS takes ownership of both of these.
+        let key = wrap_ffi(|| {
+            PrivateKey::from_raw_ptr_opt(ffi::SECKEY_CopyPrivateKey(key.as_raw_ptr()))
+                .map_or(GenStatus::ErrorFromC, GenStatus::Success)
+        })?;
+        *ret_cert = cert.clone().into_raw_ptr();
+        *ret_key = key.into_raw_ptr();
+        Ok(ffi::SECSuccess)
+    })
+}
+
+unsafe extern "C" fn raw_sni_hook<Callbacks>(
+    fd: *mut ffi::nspr::PRFileDesc,
+    names: *const ffi::SECItem,
+    num_names: u32,
+    arg: *mut c_void,
+) -> i32 {
+    wrap_callback(ffi::SSL_SNI_SEND_ALERT, || {
+        let this: BorrowedTLSSocket<Callbacks> = &*(arg as *const TLSSocketImpl<Callbacks>);
+        assert_eq!(this.as_raw_prfd(), fd);
+        // Only host_name is defined, and there can be only one of those.
+        if num_names > 0 {
+            let name = String::from_utf8_lossy(sec_item_as_slice(&*names)).into_owned();
+            *this.server_name.lock().unwrap() = Some(name);
+        }
+        Ok(ffi::SSL_SNI_CURRENT_CONFIG_IS_USED)
+    })
+}
+
+unsafe extern "C" fn raw_time_func<Callbacks>(arg: *mut c_void) -> ffi::nspr::PRTime {
+    // On panic, this returns the epoch, which should make anything
+    // time-sensitive fail until the panic is resumed.
+    wrap_callback(0, || {
+        let this: BorrowedTLSSocket<Callbacks> = &*(arg as *const TLSSocketImpl<Callbacks>);
+        let f = this.time_func.as_ref().expect("time function not set");
+        Ok(system_time_to_nspr(f()))
+    })
+}
+
+// Tracks ClientHellos seen within a window, to reject replayed 0-RTT
+// data; made by `init_anti_replay`.  Share one between all the server
+// sockets using the same session ticket keys.
+pub struct AntiReplayContext(*mut ffi::SSLAntiReplayContext);
+
+// NSS locks the filters internally.
+unsafe impl Send for AntiReplayContext {}
+unsafe impl Sync for AntiReplayContext {}
+
+// A pair of Bloom filters with `k` hashes and 2^`bits` bits each,
+// covering `window`.  Fails with `SEC_ERROR_INVALID_ARGS` if a value
+// doesn't fit NSS's types.
+pub fn init_anti_replay(window: Duration, k: usize, bits: usize) -> Result<AntiReplayContext> {
+    let window =
+        ffi::nspr::PRTime::try_from(window.as_micros()).map_err(|_| SEC_ERROR_INVALID_ARGS)?;
+    let k = c_uint::try_from(k).map_err(|_| SEC_ERROR_INVALID_ARGS)?;
+    let bits = c_uint::try_from(bits).map_err(|_| SEC_ERROR_INVALID_ARGS)?;
+    let mut ctx = ptr::null_mut();
+    wrap_ffi(|| unsafe {
+        ffi::SSL_CreateAntiReplayContext(
+            system_time_to_nspr(SystemTime::now()),
+            window,
+            k,
+            bits,
+            &mut ctx,
+        )
+    })?;
+    Ok(AntiReplayContext(ctx))
+}
+
+impl Drop for AntiReplayContext {
+    fn drop(&mut self) {
+        let _ = wrap_ffi(|| unsafe { ffi::SSL_ReleaseAntiReplayContext(self.0) });
+    }
+}
+
+// For diagnostics only; NSS has already acted on the alert.
+pub trait AlertCallback: Sized {
+    fn alert_received(&self, _sock: BorrowedTLSSocket<Self>, _alert: TLSAlert) {}
+    fn alert_sent(&self, _sock: BorrowedTLSSocket<Self>, _alert: TLSAlert) {}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TLSAlert {
+    pub level: u8,
+    pub description: u8,
+}
+
+impl TLSAlert {
+    pub fn from_ffi(alert: ffi::SSLAlert) -> Self {
+        TLSAlert {
+            level: alert.level,
+            description: alert.description,
+        }
+    }
+    pub fn is_fatal(&self) -> bool {
+        self.level == ALERT_FATAL
+    }
+}
+
+pub const ALERT_WARNING: u8 = 1;
+pub const ALERT_FATAL: u8 = 2;
+
+// Descriptions, from the TLS 1.3 RFC; not exhaustive.
+pub const ALERT_CLOSE_NOTIFY: u8 = 0;
+pub const ALERT_UNEXPECTED_MESSAGE: u8 = 10;
+pub const ALERT_BAD_RECORD_MAC: u8 = 20;
+pub const ALERT_HANDSHAKE_FAILURE: u8 = 40;
+pub const ALERT_BAD_CERTIFICATE: u8 = 42;
+pub const ALERT_CERTIFICATE_REVOKED: u8 = 44;
+pub const ALERT_CERTIFICATE_EXPIRED: u8 = 45;
+pub const ALERT_UNKNOWN_CA: u8 = 48;
+pub const ALERT_DECODE_ERROR: u8 = 50;
+pub const ALERT_DECRYPT_ERROR: u8 = 51;
+pub const ALERT_PROTOCOL_VERSION: u8 = 70;
+pub const ALERT_INTERNAL_ERROR: u8 = 80;
+pub const ALERT_MISSING_EXTENSION: u8 = 109;
+pub const ALERT_UNRECOGNIZED_NAME: u8 = 112;
+pub const ALERT_NO_APPLICATION_PROTOCOL: u8 = 120;
+
+// NSS has no hooks for individual handshake messages or extensions
+// that don't take over their processing, so the extension and
+// certificate events are reconstructed when the handshake finishes,
+// and sent just before `Done`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HandshakeEvent {
+    AlertSent(TLSAlert),
+    AlertReceived(TLSAlert),
+    // An extension this side sent that the peer also sent back (so,
+    // negotiated); NSS doesn't say which ones went unanswered, and
+    // with TLS 1.3 has already forgotten most of them by the end.
+    ExtensionSent(u16),
+    // The peer's certificate and however many others it sent with it.
+    CertReceived {
+        chain_length: usize,
+    },
+    // Also sent for each later handshake (e.g., renegotiation).
+    Done {
+        version: TLSVersion,
+        suite: TLSCipherSuite,
+        resumed: bool,
+    },
+}
+
+// The ones NSS knows about, in the usual ClientHello order.
+const TRACED_EXTENSIONS: &[ffi::SSLExtensionType] = &[
+    ffi::ssl_server_name_xtn,
+    ffi::ssl_cert_status_xtn,
+    ffi::ssl_supported_groups_xtn,
+    ffi::ssl_ec_point_formats_xtn,
+    ffi::ssl_signature_algorithms_xtn,
+    ffi::ssl_use_srtp_xtn,
+    ffi::ssl_app_layer_protocol_xtn,
+    ffi::ssl_signed_cert_timestamp_xtn,
+    ffi::ssl_padding_xtn,
+    ffi::ssl_extended_master_secret_xtn,
+    ffi::ssl_record_size_limit_xtn,
+    ffi::ssl_session_ticket_xtn,
+    ffi::ssl_tls13_early_data_xtn,
+    ffi::ssl_tls13_supported_versions_xtn,
+    ffi::ssl_tls13_cookie_xtn,
+    ffi::ssl_tls13_psk_key_exchange_modes_xtn,
+    ffi::ssl_tls13_certificate_authorities_xtn,
+    ffi::ssl_tls13_post_handshake_auth_xtn,
+    ffi::ssl_signature_algorithms_cert_xtn,
+    ffi::ssl_tls13_key_share_xtn,
+    ffi::ssl_renegotiation_info_xtn,
+    ffi::ssl_tls13_pre_shared_key_xtn,
+];
+
+unsafe extern "C" fn raw_handshake_callback<Callbacks>(fd: RawFile, arg: *mut c_void) {
+    wrap_callback((), || {
+        let this: BorrowedTLSSocket<Callbacks> = &*(arg as *const TLSSocketImpl<Callbacks>);
+        for &ext in TRACED_EXTENSIONS {
+            let mut yes = ffi::nspr::PR_FALSE;
+            wrap_ffi(|| ffi::SSL_HandshakeNegotiatedExtension(fd, ext, &mut yes))?;
+            if bool_from_nspr(yes) {
+                this.trace(HandshakeEvent::ExtensionSent(ext as u16));
+            }
+        }
+        if let Some(chain) = this.peer_cert_chain() {
+            this.trace(HandshakeEvent::CertReceived {
+                chain_length: chain.iter().count(),
+            });
+        }
+        let info = this.channel_info()?;
+        this.trace(HandshakeEvent::Done {
+            version: TLSVersion(info.protocolVersion),
+            suite: TLSCipherSuite(info.cipherSuite),
+            resumed: bool_from_nspr(info.resumed),
+        });
+        Ok(())
+    })
+}
+
+unsafe extern "C" fn raw_alert_received_callback<Callbacks>(
+    _fd: *const ffi::nspr::PRFileDesc,
+    arg: *mut c_void,
+    alert: *const ffi::SSLAlert,
+) {
+    wrap_callback((), || {
+        let this: BorrowedTLSSocket<Callbacks> = &*(arg as *const TLSSocketImpl<Callbacks>);
+        let alert = TLSAlert::from_ffi(*alert);
+        this.trace(HandshakeEvent::AlertReceived(alert));
+        if let Some(hook) = this.alert_received_hook {
+            hook(this, alert);
+        }
+        Ok(())
+    })
+}
+
+unsafe extern "C" fn raw_alert_sent_callback<Callbacks>(
+    _fd: *const ffi::nspr::PRFileDesc,
+    arg: *mut c_void,
+    alert: *const ffi::SSLAlert,
+) {
+    wrap_callback((), || {
+        let this: BorrowedTLSSocket<Callbacks> = &*(arg as *const TLSSocketImpl<Callbacks>);
+        let alert = TLSAlert::from_ffi(*alert);
+        this.trace(HandshakeEvent::AlertSent(alert));
+        if let Some(hook) = this.alert_sent_hook {
+            hook(this, alert);
+        }
+        Ok(())
+    })
+}
+
+pub trait FalseStartCheck: Sized {
+    // Called on the client once NSS has decided that false start
+    // would be safe for the negotiated parameters; returning `false`
+    // makes the handshake wait for the server's Finished as usual.
+    fn can_false_start(&self, sock: BorrowedTLSSocket<Self>, suite: TLSCipherSuite)
+        -> Result<bool>;
+}
+
+unsafe extern "C" fn raw_can_false_start_callback<Callbacks>(
+    fd: *mut ffi::nspr::PRFileDesc,
+    arg: *mut c_void,
+    can_false_start: *mut ffi::nspr::PRBool,
+) -> ffi::SECStatus
+where
+    Callbacks: FalseStartCheck,
+{
+    wrap_callback(ffi::SECFailure, || {
+        let this: BorrowedTLSSocket<Callbacks> = &*(arg as *const TLSSocketImpl<Callbacks>);
+        assert_eq!(this.as_raw_prfd(), fd);
+        let mut info = ffi::SSLPreliminaryChannelInfo::default();
+        wrap_ffi(|| {
+            ffi::SSL_GetPreliminaryChannelInfo(
+                fd,
+                &mut info,
+                mem::size_of::<ffi::SSLPreliminaryChannelInfo>() as ffi::nspr::PRUintn,
+            )
+        })?;
+        let approved = if info.valuesSet & ffi::ssl_preinfo_cipher_suite != 0 {
+            this.callbacks
+                .can_false_start(this, TLSCipherSuite(info.cipherSuite))?
+        } else {
+            false
+        };
+        *can_false_start = bool_to_nspr(approved);
+        Ok(ffi::SECSuccess)
+    })
+}
+
+pub trait ResumptionTokenCallback: Sized {
+    // Called on the client with each token it could resume this
+    // session from later; a TLS 1.3 server may send several, at any
+    // time after the handshake (so only when reading).
+    fn resumption_token(&self, sock: BorrowedTLSSocket<Self>, token: &[u8]) -> Result<()>;
+}
+
+unsafe extern "C" fn raw_resumption_token_callback<Callbacks>(
+    fd: *mut ffi::nspr::PRFileDesc,
+    token: *const u8,
+    len: c_uint,
+    arg: *mut c_void,
+) -> ffi::SECStatus
+where
+    Callbacks: ResumptionTokenCallback,
+{
+    wrap_callback(ffi::SECFailure, || {
+        let this: BorrowedTLSSocket<Callbacks> = &*(arg as *const TLSSocketImpl<Callbacks>);
+        assert_eq!(this.as_raw_prfd(), fd);
+        let token = if len == 0 {
+            &[]
+        } else {
+            slice::from_raw_parts(token, len as usize)
+        };
+        this.callbacks.resumption_token(this, token)?;
+        Ok(ffi::SECSuccess)
+    })
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub struct TLSOption(ffi::nspr::PRInt32);
 
@@ -376,6 +1853,34 @@ def_options! {
     SSL_ENABLE_SIGNED_CERT_TIMESTAMPS,
     SSL_REQUIRE_DH_NAMED_GROUPS,
     SSL_ENABLE_0RTT_DATA,
+    SSL_ENABLE_TLS13_COMPAT_MODE,
+    SSL_ENABLE_GREASE,
+    SSL_ENABLE_CH_EXTENSION_PERMUTATION,
+}
+
+// RFC 5929's `tls-server-end-point` and RFC 9266's `tls-exporter`;
+// NSS has no way to get `tls-unique`.  `tls-exporter` only works
+// with TLS 1.3: before that, it needs an empty exporter context, which
+// NSS doesn't take.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ChannelBindingType {
+    TlsServerEndPoint,
+    TlsExporter,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SSLVariant {
+    Stream,
+    Datagram,
+}
+
+impl SSLVariant {
+    pub fn to_ffi(self) -> ffi::SSLProtocolVariant {
+        match self {
+            SSLVariant::Stream => ffi::ssl_variant_stream,
+            SSLVariant::Datagram => ffi::ssl_variant_datagram,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
@@ -385,13 +1890,19 @@ impl TLSVersion {
     pub fn to_ffi(self) -> ffi::nspr::PRUint16 {
         self.0
     }
+    // What the linked NSS implements, as opposed to what's enabled.
     pub fn supported_range() -> Result<(Self, Self)> {
+        Self::supported_range_for(SSLVariant::Stream)
+    }
+    // DTLS versions are given as the TLS versions they correspond to
+    // (e.g., DTLS 1.2 is TLS_VERSION_1_2), as NSS does.
+    pub fn supported_range_for(variant: SSLVariant) -> Result<(Self, Self)> {
         let mut range = ffi::SSLVersionRange {
             min: 0xffff,
             max: 0,
         };
         wrap_ffi(|| unsafe {
-            ffi::SSL_VersionRangeGetSupported(ffi::ssl_variant_stream, &mut range as *mut _)
+            ffi::SSL_VersionRangeGetSupported(variant.to_ffi(), &mut range as *mut _)
         })?;
         Ok((TLSVersion(range.min), TLSVersion(range.max)))
     }
@@ -404,6 +1915,117 @@ pub const TLS_VERSION_1_1: TLSVersion = TLSVersion(ffi::SSL_LIBRARY_VERSION_TLS_
 pub const TLS_VERSION_1_2: TLSVersion = TLSVersion(ffi::SSL_LIBRARY_VERSION_TLS_1_2);
 pub const TLS_VERSION_1_3: TLSVersion = TLSVersion(ffi::SSL_LIBRARY_VERSION_TLS_1_3);
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NamedGroup {
+    Secp256r1,
+    Secp384r1,
+    Secp521r1,
+    X25519,
+    Ffdhe2048,
+    Ffdhe3072,
+    Ffdhe4096,
+    Ffdhe6144,
+    Ffdhe8192,
+}
+
+impl NamedGroup {
+    pub fn to_ffi(self) -> ffi::SSLNamedGroup {
+        match self {
+            NamedGroup::Secp256r1 => ffi::ssl_grp_ec_secp256r1,
+            NamedGroup::Secp384r1 => ffi::ssl_grp_ec_secp384r1,
+            NamedGroup::Secp521r1 => ffi::ssl_grp_ec_secp521r1,
+            NamedGroup::X25519 => ffi::ssl_grp_ec_curve25519,
+            NamedGroup::Ffdhe2048 => ffi::ssl_grp_ffdhe_2048,
+            NamedGroup::Ffdhe3072 => ffi::ssl_grp_ffdhe_3072,
+            NamedGroup::Ffdhe4096 => ffi::ssl_grp_ffdhe_4096,
+            NamedGroup::Ffdhe6144 => ffi::ssl_grp_ffdhe_6144,
+            NamedGroup::Ffdhe8192 => ffi::ssl_grp_ffdhe_8192,
+        }
+    }
+    pub fn from_ffi(group: ffi::SSLNamedGroup) -> Option<Self> {
+        match group {
+            ffi::ssl_grp_ec_secp256r1 => Some(NamedGroup::Secp256r1),
+            ffi::ssl_grp_ec_secp384r1 => Some(NamedGroup::Secp384r1),
+            ffi::ssl_grp_ec_secp521r1 => Some(NamedGroup::Secp521r1),
+            ffi::ssl_grp_ec_curve25519 => Some(NamedGroup::X25519),
+            ffi::ssl_grp_ffdhe_2048 => Some(NamedGroup::Ffdhe2048),
+            ffi::ssl_grp_ffdhe_3072 => Some(NamedGroup::Ffdhe3072),
+            ffi::ssl_grp_ffdhe_4096 => Some(NamedGroup::Ffdhe4096),
+            ffi::ssl_grp_ffdhe_6144 => Some(NamedGroup::Ffdhe6144),
+            ffi::ssl_grp_ffdhe_8192 => Some(NamedGroup::Ffdhe8192),
+            _ => None,
+        }
+    }
+    fn to_dhe_group(self) -> Option<ffi::SSLDHEGroupType> {
+        match self {
+            NamedGroup::Ffdhe2048 => Some(ffi::ssl_ff_dhe_2048_group),
+            NamedGroup::Ffdhe3072 => Some(ffi::ssl_ff_dhe_3072_group),
+            NamedGroup::Ffdhe4096 => Some(ffi::ssl_ff_dhe_4096_group),
+            NamedGroup::Ffdhe6144 => Some(ffi::ssl_ff_dhe_6144_group),
+            NamedGroup::Ffdhe8192 => Some(ffi::ssl_ff_dhe_8192_group),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AuthType {
+    Null,
+    RsaDecrypt,
+    Dsa,
+    Kea,
+    Ecdsa,
+    EcdhRsa,
+    EcdhEcdsa,
+    RsaSign,
+    RsaPss,
+    Psk,
+    Tls13Any,
+}
+
+impl AuthType {
+    pub fn from_ffi(auth: ffi::SSLAuthType) -> Option<Self> {
+        match auth {
+            ffi::ssl_auth_null => Some(AuthType::Null),
+            ffi::ssl_auth_rsa_decrypt => Some(AuthType::RsaDecrypt),
+            ffi::ssl_auth_dsa => Some(AuthType::Dsa),
+            ffi::ssl_auth_kea => Some(AuthType::Kea),
+            ffi::ssl_auth_ecdsa => Some(AuthType::Ecdsa),
+            ffi::ssl_auth_ecdh_rsa => Some(AuthType::EcdhRsa),
+            ffi::ssl_auth_ecdh_ecdsa => Some(AuthType::EcdhEcdsa),
+            ffi::ssl_auth_rsa_sign => Some(AuthType::RsaSign),
+            ffi::ssl_auth_rsa_pss => Some(AuthType::RsaPss),
+            ffi::ssl_auth_psk => Some(AuthType::Psk),
+            ffi::ssl_auth_tls13_any => Some(AuthType::Tls13Any),
+            _ => None,
+        }
+    }
+}
+
+macro_rules! def_sig_schemes {{ $($name:ident = $ffi_name:ident,)* } => {
+    $(pub const $name: u16 = ffi::$ffi_name as u16;)*
+}}
+
+def_sig_schemes! {
+    SIG_NONE = ssl_sig_none,
+    SIG_RSA_PKCS1_SHA1 = ssl_sig_rsa_pkcs1_sha1,
+    SIG_RSA_PKCS1_SHA256 = ssl_sig_rsa_pkcs1_sha256,
+    SIG_RSA_PKCS1_SHA384 = ssl_sig_rsa_pkcs1_sha384,
+    SIG_RSA_PKCS1_SHA512 = ssl_sig_rsa_pkcs1_sha512,
+    SIG_ECDSA_SECP256R1_SHA256 = ssl_sig_ecdsa_secp256r1_sha256,
+    SIG_ECDSA_SECP384R1_SHA384 = ssl_sig_ecdsa_secp384r1_sha384,
+    SIG_ECDSA_SECP521R1_SHA512 = ssl_sig_ecdsa_secp521r1_sha512,
+    SIG_RSA_PSS_RSAE_SHA256 = ssl_sig_rsa_pss_rsae_sha256,
+    SIG_RSA_PSS_RSAE_SHA384 = ssl_sig_rsa_pss_rsae_sha384,
+    SIG_RSA_PSS_RSAE_SHA512 = ssl_sig_rsa_pss_rsae_sha512,
+    SIG_ED25519 = ssl_sig_ed25519,
+    SIG_ED448 = ssl_sig_ed448,
+    SIG_RSA_PSS_PSS_SHA256 = ssl_sig_rsa_pss_pss_sha256,
+    SIG_RSA_PSS_PSS_SHA384 = ssl_sig_rsa_pss_pss_sha384,
+    SIG_RSA_PSS_PSS_SHA512 = ssl_sig_rsa_pss_pss_sha512,
+    SIG_ECDSA_SHA1 = ssl_sig_ecdsa_sha1,
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct TLSCipherSuite(ffi::nspr::PRUint16);
 
@@ -516,21 +2138,137 @@ def_ciphers! {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use error::{PR_END_OF_FILE_ERROR, PR_IS_CONNECTED_ERROR, PR_NOT_CONNECTED_ERROR};
-    use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+    use error::{
+        PR_IS_CONNECTED_ERROR, PR_NOT_CONNECTED_ERROR, SEC_ERROR_EXPIRED_CERTIFICATE,
+        SEC_ERROR_UNTRUSTED_ISSUER, SSL_ERROR_BAD_CERT_DOMAIN,
+    };
+    use nspr::net::import_tcp_socket;
+    use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4, TcpListener, TcpStream};
+    use std::os::unix::io::IntoRawFd;
     use std::sync::atomic::{AtomicBool, Ordering};
     use std::sync::{Arc, Mutex};
+    use std::thread;
     use std::time::Duration;
+    use test_util::{lock_global, trust_test_root};
 
     fn fake_addr() -> SocketAddr {
         SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::new(127, 0, 0, 1), 443))
     }
 
+    static LEAF_DER: &[u8] = include_bytes!("../testdata/leaf.der");
+    static LEAF_KEY_DER: &[u8] = include_bytes!("../testdata/leaf.key.der");
+    static INTERMEDIATE_DER: &[u8] = include_bytes!("../testdata/intermediate.der");
+
+    // Not PR_NewTCPSocketPair, because that's AF_UNIX on Unix and
+    // NSS wants an IP peer address.
+    fn tcp_pair() -> (File, File) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (server, _) = listener.accept().unwrap();
+        unsafe {
+            (
+                import_tcp_socket(client.into_raw_fd()).unwrap(),
+                import_tcp_socket(server.into_raw_fd()).unwrap(),
+            )
+        }
+    }
+
+    fn server_socket(inner: File) -> TLSSocket<()> {
+        let leaf = Certificate::from_der(LEAF_DER).unwrap();
+        let intermediate = Certificate::from_der(INTERMEDIATE_DER).unwrap();
+        let key = PrivateKey::from_pkcs8_der(LEAF_KEY_DER).unwrap();
+        let mut ssl = TLSSocket::new(inner, ()).unwrap();
+        ssl.set_option(SSL_NO_CACHE, true).unwrap();
+        ssl.config_server_cert(&leaf, &key, &[intermediate])
+            .unwrap();
+        ssl.reset_handshake(true).unwrap();
+        ssl
+    }
+
+    fn client_socket<Callbacks>(inner: File, callbacks: Callbacks) -> TLSSocket<Callbacks> {
+        let mut ssl = TLSSocket::new(inner, callbacks).unwrap();
+        ssl.set_url(CStr::from_bytes_with_nul(b"localhost\0").unwrap())
+            .unwrap();
+        ssl.disable_security().unwrap();
+        ssl.reset_handshake(false).unwrap();
+        ssl
+    }
+
     #[test]
     fn just_init() {
         init().unwrap();
     }
 
+    #[test]
+    fn sec_item_vec() {
+        init().unwrap();
+        let data = b"some bytes";
+        assert_eq!(unsafe { sec_item_to_vec(&sec_item_from_slice(data)) }, data);
+        assert_eq!(unsafe { sec_item_to_vec(&sec_item_from_slice(b"")) }, b"");
+        unsafe {
+            let item =
+                ffi::SECITEM_AllocItem(ptr::null_mut(), ptr::null_mut(), data.len() as c_uint);
+            assert!(!item.is_null());
+            ptr::copy_nonoverlapping(data.as_ptr(), (*item).data, data.len());
+            assert_eq!(owned_sec_item_to_vec(item), data);
+        }
+    }
+
+    #[test]
+    fn try_handshake() {
+        // Never has anything to read; writes can be turned off.
+        struct FakeSocket {
+            writable: Arc<AtomicBool>,
+            written: Arc<Mutex<Vec<u8>>>,
+        }
+
+        impl FileMethods for FakeSocket {
+            fn read(&self, _buf: &mut [u8]) -> Result<usize> {
+                Err(PR_WOULD_BLOCK_ERROR.into())
+            }
+            fn write(&self, buf: &[u8]) -> Result<usize> {
+                if !self.writable.load(Ordering::SeqCst) {
+                    return Err(PR_WOULD_BLOCK_ERROR.into());
+                }
+                self.written.lock().unwrap().extend_from_slice(buf);
+                Ok(buf.len())
+            }
+            fn send(&self, buf: &[u8], _timeout: Option<Duration>) -> Result<usize> {
+                self.write(buf)
+            }
+            fn recv(
+                &self,
+                buf: &mut [u8],
+                _peek: bool,
+                _timeout: Option<Duration>,
+            ) -> Result<usize> {
+                self.read(buf)
+            }
+            fn get_nonblocking(&self) -> Result<bool> {
+                Ok(true)
+            }
+            fn getpeername(&self) -> Result<SocketAddr> {
+                Ok(fake_addr())
+            }
+        }
+
+        init().unwrap();
+        let writable = Arc::new(AtomicBool::new(false));
+        let written = Arc::new(Mutex::new(Vec::new()));
+        let sock = FileWrapper::new(nspr::fd::PR_DESC_SOCKET_TCP).wrap(FakeSocket {
+            writable: writable.clone(),
+            written: written.clone(),
+        });
+        let ssl = client_socket(sock, ());
+        assert_eq!(ssl.try_handshake().unwrap(), HandshakeProgress::WantWrite);
+        assert!(written.lock().unwrap().is_empty());
+        writable.store(true, Ordering::SeqCst);
+        // The ClientHello goes out; then it's waiting for the server.
+        assert_eq!(ssl.try_handshake().unwrap(), HandshakeProgress::WantRead);
+        assert_eq!(written.lock().unwrap()[0], 22);
+        assert_eq!(ssl.try_handshake().unwrap(), HandshakeProgress::WantRead);
+    }
+
     #[test]
     fn handshake() {
         struct FakeSocket {
@@ -566,6 +2304,9 @@ mod tests {
             ) -> Result<usize> {
                 self.read(buf)
             }
+            fn get_nonblocking(&self) -> Result<bool> {
+                Ok(false)
+            }
             fn getpeername(&self) -> Result<SocketAddr> {
                 if self.connected.load(Ordering::SeqCst) {
                     Ok(fake_addr())
@@ -589,14 +2330,1183 @@ mod tests {
         let buf = inner.written.clone();
         let sock_factory = FileWrapper::new(nspr::fd::PR_DESC_SOCKET_TCP);
         let sock = sock_factory.wrap(inner);
+        assert!(Arc::ptr_eq(
+            &sock.downcast_ref::<FakeSocket>().unwrap().written,
+            &buf
+        ));
         let ssl = TLSSocket::new(sock, ()).unwrap();
         ssl.connect(fake_addr(), None).unwrap();
-        assert_eq!(ssl.write(&[]).unwrap_err().nspr_error, PR_END_OF_FILE_ERROR);
+        // Empty writes don't touch the handshake at all.
+        assert_eq!(ssl.write(&[]).unwrap(), 0);
+        assert!(buf.lock().unwrap().is_empty());
+        // The fake peer "closes" after the ClientHello is sent, and
+        // that's the same whatever started the handshake.
+        assert_eq!(
+            ssl.write(b"hello").unwrap_err().nspr_error,
+            PR_CONNECT_ABORTED_ERROR
+        );
+        assert!(!buf.lock().unwrap().is_empty());
+        assert_eq!(
+            ssl.read(&mut [0; 1]).unwrap_err().nspr_error,
+            PR_CONNECT_ABORTED_ERROR
+        );
+        assert_eq!(
+            ssl.force_handshake().unwrap_err().nspr_error,
+            PR_CONNECT_ABORTED_ERROR
+        );
         println!("DATA: {:?}", &buf.lock().unwrap()[..]);
     }
 
     #[test]
-    #[should_panic(expected = "not yet implemented")]
+    fn server_cert_chain() {
+        init().unwrap();
+        let (client, server) = tcp_pair();
+        let server = thread::spawn(move || {
+            let ssl = server_socket(server);
+            ssl.force_handshake().unwrap();
+        });
+        let ssl = client_socket(client, ());
+        ssl.force_handshake().unwrap();
+        let chain: Vec<Vec<u8>> = ssl
+            .peer_cert_chain()
+            .unwrap()
+            .iter()
+            .map(|cert| cert.as_der().to_vec())
+            .collect();
+        server.join().unwrap();
+        assert_eq!(chain, vec![LEAF_DER.to_vec(), INTERMEDIATE_DER.to_vec()]);
+    }
+
+    #[test]
+    fn client_certificate() {
+        init().unwrap();
+        let (client, server) = tcp_pair();
+        let server = thread::spawn(move || {
+            let mut ssl = server_socket(server);
+            ssl.set_option(SSL_REQUEST_CERTIFICATE, true).unwrap();
+            ssl.set_option(SSL_REQUIRE_CERTIFICATE, true).unwrap();
+            ssl.disable_security().unwrap();
+            ssl.force_handshake().unwrap();
+            ssl.peer_cert().unwrap().as_der().to_vec()
+        });
+        let mut ssl = client_socket(client, ());
+        assert_eq!(
+            ssl.set_client_certificate(INTERMEDIATE_DER, LEAF_KEY_DER)
+                .unwrap_err()
+                .nspr_error,
+            SEC_ERROR_BAD_KEY
+        );
+        ssl.set_client_certificate(LEAF_DER, LEAF_KEY_DER).unwrap();
+        ssl.force_handshake().unwrap();
+        assert_eq!(server.join().unwrap(), LEAF_DER);
+    }
+
+    #[test]
+    fn signature_schemes() {
+        init().unwrap();
+        let (client, server) = tcp_pair();
+        let server = thread::spawn(move || {
+            let mut ssl = server_socket(server);
+            ssl.set_signature_schemes(&[SIG_RSA_PSS_RSAE_SHA384, SIG_RSA_PSS_RSAE_SHA256])
+                .unwrap();
+            ssl.force_handshake().unwrap();
+            ssl.signature_scheme().unwrap()
+        });
+        let mut ssl = client_socket(client, ());
+        ssl.set_signature_schemes(&[SIG_ECDSA_SECP256R1_SHA256, SIG_RSA_PSS_RSAE_SHA384])
+            .unwrap();
+        ssl.force_handshake().unwrap();
+        assert_eq!(ssl.signature_scheme().unwrap(), SIG_RSA_PSS_RSAE_SHA384);
+        assert_eq!(ssl.auth_type().unwrap(), Some(AuthType::RsaSign));
+        assert_eq!(server.join().unwrap(), SIG_RSA_PSS_RSAE_SHA384);
+        assert_eq!(AuthType::from_ffi(0x1234), None);
+    }
+
+    #[test]
+    fn named_groups() {
+        init().unwrap();
+        let (client, server) = tcp_pair();
+        let server = thread::spawn(move || {
+            let mut ssl = server_socket(server);
+            ssl.set_named_groups(&[NamedGroup::Secp256r1, NamedGroup::X25519])
+                .unwrap();
+            ssl.force_handshake().unwrap();
+            ssl.named_group().unwrap()
+        });
+        let mut ssl = client_socket(client, ());
+        ssl.set_named_groups(&[NamedGroup::X25519]).unwrap();
+        ssl.force_handshake().unwrap();
+        assert_eq!(ssl.named_group().unwrap(), Some(NamedGroup::X25519));
+        assert_eq!(server.join().unwrap(), Some(NamedGroup::X25519));
+    }
+
+    #[test]
+    fn config() {
+        init().unwrap();
+        let alpn: &[&[u8]] = &[b"h2", b"http/1.1"];
+        let (client, server) = tcp_pair();
+        let server = thread::spawn(move || {
+            let mut ssl = server_socket(server);
+            ssl.set_alpn_protocols(alpn).unwrap();
+            ssl.force_handshake().unwrap();
+            ssl.alpn_protocol().unwrap()
+        });
+        let mut ssl = TLSConfig::new()
+            .option(SSL_ENABLE_SESSION_TICKETS, false)
+            .version_range(TLS_VERSION_1_2, TLS_VERSION_1_2)
+            .ciphersuites(&[TLS_ECDHE_RSA_WITH_AES_128_GCM_SHA256])
+            .named_groups(&[NamedGroup::Secp256r1])
+            .alpn_protocols(alpn)
+            .server_name(CStr::from_bytes_with_nul(b"localhost\0").unwrap())
+            .build(client, ())
+            .unwrap();
+        assert_eq!(ssl.url().unwrap(), Some("localhost".to_owned()));
+        assert!(!ssl.is_ciphersuite_enabled(TLS_AES_128_GCM_SHA256).unwrap());
+        ssl.disable_security().unwrap();
+        ssl.reset_handshake(false).unwrap();
+        ssl.force_handshake().unwrap();
+        assert_eq!(ssl.named_group().unwrap(), Some(NamedGroup::Secp256r1));
+        assert_eq!(ssl.alpn_protocol().unwrap(), Some(b"h2".to_vec()));
+        assert_eq!(server.join().unwrap(), Some(b"h2".to_vec()));
+    }
+
+    #[test]
+    fn self_signed() {
+        use cert::{generate_self_signed, GeneralName};
+        use pk11::{generate_ec_keypair, EcCurve};
+
+        init().unwrap();
+        let (public, private) = generate_ec_keypair(EcCurve::P256).unwrap();
+        let cert = generate_self_signed(
+            "CN=localhost",
+            &private,
+            &public,
+            &[GeneralName::Dns("localhost".to_owned())],
+            Duration::from_secs(3600),
+        )
+        .unwrap();
+        let der = cert.as_der().to_vec();
+        let (client, server) = tcp_pair();
+        let server = thread::spawn(move || {
+            let mut ssl = TLSSocket::new(server, ()).unwrap();
+            ssl.set_option(SSL_NO_CACHE, true).unwrap();
+            ssl.config_server_cert(&cert, &private, &[]).unwrap();
+            ssl.reset_handshake(true).unwrap();
+            ssl.force_handshake().unwrap();
+        });
+        let ssl = client_socket(client, ());
+        ssl.force_handshake().unwrap();
+        assert_eq!(ssl.peer_cert().unwrap().as_der(), &der[..]);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn time_func() {
+        init().unwrap();
+        let _root = trust_test_root();
+        let day = Duration::from_secs(24 * 60 * 60);
+        let connect = |time: SystemTime| {
+            let (client, server) = tcp_pair();
+            let server = thread::spawn(move || {
+                let ssl = server_socket(server);
+                let _ = ssl.force_handshake();
+            });
+            let mut ssl = TLSSocket::new(client, ()).unwrap();
+            ssl.set_url(CStr::from_bytes_with_nul(b"localhost\0").unwrap())
+                .unwrap();
+            ssl.set_time_func(move || time).unwrap();
+            ssl.reset_handshake(false).unwrap();
+            let res = ssl.force_handshake();
+            drop(ssl);
+            server.join().unwrap();
+            res
+        };
+        connect(SystemTime::now() + day).unwrap();
+        let err = connect(SystemTime::now() + 200 * 365 * day).unwrap_err();
+        assert_eq!(err.nspr_error, SEC_ERROR_EXPIRED_CERTIFICATE);
+    }
+
+    #[test]
+    fn pinned_cert_hook() {
+        init().unwrap();
+        let _root = trust_test_root();
+        let connect = |hook: PinnedCertHook, url: &[u8]| {
+            let (client, server) = tcp_pair();
+            let server = thread::spawn(move || {
+                let ssl = server_socket(server);
+                let _ = ssl.force_handshake();
+            });
+            let mut ssl = TLSSocket::new(client, hook).unwrap();
+            ssl.set_url(CStr::from_bytes_with_nul(url).unwrap())
+                .unwrap();
+            ssl.use_auth_certificate_hook().unwrap();
+            ssl.reset_handshake(false).unwrap();
+            let res = ssl.force_handshake();
+            drop(ssl);
+            server.join().unwrap();
+            res
+        };
+        let leaf_pin = Certificate::from_der(LEAF_DER)
+            .unwrap()
+            .spki_sha256()
+            .unwrap();
+        let intermediate_pin = Certificate::from_der(INTERMEDIATE_DER)
+            .unwrap()
+            .spki_sha256()
+            .unwrap();
+        connect(PinnedCertHook::new(&[leaf_pin]), b"localhost\0").unwrap();
+        connect(
+            PinnedCertHook::new(&[[0; 32], intermediate_pin]),
+            b"localhost\0",
+        )
+        .unwrap();
+        assert_eq!(
+            connect(PinnedCertHook::new(&[[0; 32]]), b"localhost\0")
+                .unwrap_err()
+                .nspr_error,
+            SEC_ERROR_UNTRUSTED_CERT
+        );
+        // The usual checks still apply, unless turned off.
+        assert_eq!(
+            connect(PinnedCertHook::new(&[leaf_pin]), b"example.com\0")
+                .unwrap_err()
+                .nspr_error,
+            SSL_ERROR_BAD_CERT_DOMAIN
+        );
+        connect(
+            PinnedCertHook::new(&[leaf_pin]).pin_only(true),
+            b"example.com\0",
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn url() {
+        init().unwrap();
+        let (client, server) = tcp_pair();
+        let server = thread::spawn(move || {
+            let ssl = server_socket(server);
+            assert_eq!(ssl.requested_server_name().unwrap(), None);
+            ssl.force_handshake().unwrap();
+            ssl.requested_server_name().unwrap()
+        });
+        let mut ssl = TLSSocket::new(client, ()).unwrap();
+        assert_eq!(ssl.url().unwrap(), None);
+        ssl.set_url(CStr::from_bytes_with_nul(b"localhost\0").unwrap())
+            .unwrap();
+        assert_eq!(ssl.url().unwrap(), Some("localhost".to_owned()));
+        ssl.disable_security().unwrap();
+        ssl.reset_handshake(false).unwrap();
+        ssl.force_handshake().unwrap();
+        assert_eq!(server.join().unwrap(), Some("localhost".to_owned()));
+    }
+
+    #[test]
+    fn bytes_pending() {
+        init().unwrap();
+        let (client, server) = tcp_pair();
+        let server = thread::spawn(move || {
+            let ssl = server_socket(server);
+            ssl.force_handshake().unwrap();
+            assert_eq!(ssl.write(&[0x5a; 100]).unwrap(), 100);
+        });
+        let ssl = client_socket(client, ());
+        ssl.force_handshake().unwrap();
+        assert_eq!(ssl.bytes_pending().unwrap(), 0);
+        server.join().unwrap();
+        let mut buf = [0; 10];
+        assert_eq!(ssl.read(&mut buf).unwrap(), 10);
+        assert_eq!(ssl.bytes_pending().unwrap(), 90);
+    }
+
+    #[test]
+    fn reveal() {
+        #[derive(Default)]
+        struct Revealer(Mutex<Option<(CString, Vec<u8>)>>);
+        impl AuthCertificateHook for Revealer {
+            fn auth_certificate(
+                &self,
+                sock: BorrowedTLSSocket<Self>,
+                _check_sig: bool,
+                _is_server: bool,
+            ) -> Result<()> {
+                let url = sock.reveal_url().unwrap();
+                let cert = sock.reveal_cert().unwrap();
+                *self.0.lock().unwrap() = Some((url, cert.as_der().to_vec()));
+                Ok(())
+            }
+        }
+
+        init().unwrap();
+        let (client, server) = tcp_pair();
+        let server = thread::spawn(move || {
+            let ssl = server_socket(server);
+            ssl.force_handshake().unwrap();
+        });
+        let mut ssl = TLSSocket::new(client, Revealer::default()).unwrap();
+        assert_eq!(ssl.reveal_url(), None);
+        assert!(ssl.reveal_cert().is_none());
+        assert!(ssl.reveal_pin_arg().is_null());
+        ssl.set_url(CStr::from_bytes_with_nul(b"localhost\0").unwrap())
+            .unwrap();
+        ssl.use_auth_certificate_hook().unwrap();
+        ssl.reset_handshake(false).unwrap();
+        ssl.force_handshake().unwrap();
+        let (url, cert) = ssl.callbacks().0.lock().unwrap().take().unwrap();
+        assert_eq!(url.to_bytes(), b"localhost");
+        assert_eq!(cert, LEAF_DER);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn auth_certificate_chain() {
+        #[derive(Default)]
+        struct ChainSaver(Mutex<Vec<Vec<u8>>>);
+        impl AuthCertificateHook for ChainSaver {
+            fn auth_certificate_chain(
+                &self,
+                _sock: BorrowedTLSSocket<Self>,
+                chain: &[Certificate],
+                _check_sig: bool,
+                _is_server: bool,
+            ) -> Result<()> {
+                *self.0.lock().unwrap() = chain.iter().map(|c| c.as_der().to_vec()).collect();
+                Ok(())
+            }
+        }
+
+        init().unwrap();
+        let (client, server) = tcp_pair();
+        let server = thread::spawn(move || {
+            let ssl = server_socket(server);
+            ssl.force_handshake().unwrap();
+        });
+        let mut ssl = TLSSocket::new(client, ChainSaver::default()).unwrap();
+        ssl.set_url(CStr::from_bytes_with_nul(b"localhost\0").unwrap())
+            .unwrap();
+        ssl.use_auth_certificate_hook().unwrap();
+        ssl.reset_handshake(false).unwrap();
+        ssl.force_handshake().unwrap();
+        let chain = ssl.callbacks().0.lock().unwrap().clone();
+        assert_eq!(chain, vec![LEAF_DER.to_vec(), INTERMEDIATE_DER.to_vec()]);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn versions() {
+        let is_version = |version: &str| {
+            let parts: Vec<_> = version.split('.').collect();
+            (2..=3).contains(&parts.len()) && parts.iter().all(|part| part.parse::<u32>().is_ok())
+        };
+        assert!(is_version(nss_version()), "{}", nss_version());
+        assert!(is_version(nspr::version()), "{}", nspr::version());
+        assert!(nss_version_check(
+            CStr::from_bytes_with_nul(b"3.0\0").unwrap()
+        ));
+        assert!(!nss_version_check(
+            CStr::from_bytes_with_nul(b"99.0\0").unwrap()
+        ));
+    }
+
+    #[test]
+    fn option_raw() {
+        init().unwrap();
+        let (client, _server) = tcp_pair();
+        let mut ssl = TLSSocket::new(client, ()).unwrap();
+        ssl.set_option_raw(ffi::SSL_SECURITY, 0).unwrap();
+        assert_eq!(ssl.get_option_raw(ffi::SSL_SECURITY).unwrap(), 0);
+        assert!(!ssl.get_option(SSL_SECURITY).unwrap());
+        ssl.set_option_raw(ffi::SSL_SECURITY, 1).unwrap();
+        assert!(ssl.get_option(SSL_SECURITY).unwrap());
+        // SSL_RENEGOTIATE_REQUIRES_XTN
+        ssl.set_option_raw(ffi::SSL_ENABLE_RENEGOTIATION, 2)
+            .unwrap();
+        assert_eq!(
+            ssl.get_option_raw(ffi::SSL_ENABLE_RENEGOTIATION).unwrap(),
+            2
+        );
+        let err = ssl.set_option_raw(9999, 1).unwrap_err();
+        assert_eq!(err.nspr_error, SEC_ERROR_INVALID_ARGS);
+    }
+
+    #[test]
+    fn poll_readable() {
+        init().unwrap();
+        let (client, server) = tcp_pair();
+        let (done_tx, done_rx) = mpsc::channel();
+        let server = thread::spawn(move || {
+            let ssl = server_socket(server);
+            ssl.force_handshake().unwrap();
+            assert_eq!(ssl.write(&[0x5a; 100]).unwrap(), 100);
+            done_rx.recv().unwrap();
+        });
+        let ssl = client_socket(client, ());
+        ssl.force_handshake().unwrap();
+        assert!(ssl.poll_readable(None).unwrap());
+        let mut buf = [0; 10];
+        assert_eq!(ssl.read(&mut buf).unwrap(), 10);
+        // The whole record has been taken off the transport.
+        let err = ssl
+            .cleartext()
+            .recv(&mut [0; 1], true, Some(Duration::from_secs(0)))
+            .unwrap_err();
+        assert_eq!(err.nspr_error, PR_IO_TIMEOUT_ERROR);
+        assert!(ssl.poll_readable(Some(Duration::from_secs(0))).unwrap());
+        let mut buf = [0; 90];
+        assert_eq!(ssl.read(&mut buf).unwrap(), 90);
+        assert!(!ssl.poll_readable(Some(Duration::from_secs(0))).unwrap());
+        done_tx.send(()).unwrap();
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn stream_variant() {
+        init().unwrap();
+        let (client, server) = tcp_pair();
+        let server = thread::spawn(move || {
+            let ssl = server_socket(server);
+            ssl.force_handshake().unwrap();
+        });
+        let mut ssl = TLSSocket::new_with_variant(client, (), SSLVariant::Stream).unwrap();
+        let (other, _other_server) = tcp_pair();
+        assert_eq!(
+            ssl.get_version_range().unwrap(),
+            TLSSocket::new(other, ())
+                .unwrap()
+                .get_version_range()
+                .unwrap()
+        );
+        ssl.disable_security().unwrap();
+        ssl.reset_handshake(false).unwrap();
+        ssl.force_handshake().unwrap();
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn supported_versions() {
+        init().unwrap();
+        let (min, max) = TLSVersion::supported_range().unwrap();
+        assert!(min <= TLS_VERSION_1_2 && TLS_VERSION_1_2 <= max);
+        let (min, max) = TLSVersion::supported_range_for(SSLVariant::Datagram).unwrap();
+        assert!(min <= TLS_VERSION_1_2 && TLS_VERSION_1_2 <= max);
+    }
+
+    #[test]
+    fn peer_cert_refcount() {
+        use cert::generate_self_signed;
+        use pk11::{generate_ec_keypair, EcCurve};
+
+        init().unwrap();
+        // A certificate no other test has, so nothing else holds it.
+        let (public, private) = generate_ec_keypair(EcCurve::P256).unwrap();
+        let cert = generate_self_signed(
+            "CN=localhost",
+            &private,
+            &public,
+            &[],
+            Duration::from_secs(60),
+        )
+        .unwrap();
+        let der = cert.as_der().to_vec();
+        let (client, server) = tcp_pair();
+        let server = thread::spawn(move || {
+            let mut ssl = TLSSocket::new(server, ()).unwrap();
+            ssl.set_option(SSL_NO_CACHE, true).unwrap();
+            ssl.config_server_cert(&cert, &private, &[]).unwrap();
+            ssl.reset_handshake(true).unwrap();
+            ssl.force_handshake().unwrap();
+        });
+        let ssl = client_socket(client, ());
+        ssl.force_handshake().unwrap();
+        server.join().unwrap();
+
+        let first = ssl.peer_cert().unwrap();
+        for _ in 0..1000 {
+            let cert = ssl.peer_cert().unwrap();
+            assert_eq!(cert.as_raw_ptr(), first.as_raw_ptr());
+            assert_eq!(ssl.peer_cert_chain().unwrap().iter().count(), 1);
+        }
+        assert_eq!(first.as_der(), &der[..]);
+        drop(first);
+        drop(ssl);
+        // With the last reference gone, NSS forgets the certificate.
+        let mut item = sec_item_from_slice(&der);
+        let found = unsafe {
+            Certificate::from_raw_ptr_opt(ffi::CERT_FindCertByDERCert(
+                ffi::CERT_GetDefaultCertDB(),
+                &mut item,
+            ))
+        };
+        assert!(found.is_none());
+    }
+
+    #[test]
+    fn peer_addr() {
+        init().unwrap();
+        let (client, server) = tcp_pair();
+        let client_addr = client.getsockname().unwrap();
+        let server_addr = server.getsockname().unwrap();
+        let server = thread::spawn(move || {
+            let ssl = server_socket(server);
+            ssl.force_handshake().unwrap();
+            ssl.peer_addr().unwrap()
+        });
+        let ssl = client_socket(client, ());
+        ssl.force_handshake().unwrap();
+        assert_eq!(ssl.peer_addr().unwrap(), server_addr);
+        assert_eq!(server.join().unwrap(), client_addr);
+    }
+
+    #[test]
+    fn channel_binding() {
+        init().unwrap();
+        let (client, server) = tcp_pair();
+        let server = thread::spawn(move || {
+            let ssl = server_socket(server);
+            ssl.force_handshake().unwrap();
+            (
+                ssl.channel_binding(ChannelBindingType::TlsExporter)
+                    .unwrap(),
+                ssl.channel_binding(ChannelBindingType::TlsServerEndPoint)
+                    .unwrap(),
+            )
+        });
+        let ssl = client_socket(client, ());
+        ssl.force_handshake().unwrap();
+        let exporter = ssl
+            .channel_binding(ChannelBindingType::TlsExporter)
+            .unwrap();
+        let end_point = ssl
+            .channel_binding(ChannelBindingType::TlsServerEndPoint)
+            .unwrap();
+        assert_eq!(exporter.len(), 32);
+        assert_eq!(end_point, pk11::sha256(LEAF_DER).unwrap());
+        assert_eq!(server.join().unwrap(), (exporter.clone(), end_point));
+        assert_eq!(
+            ssl.export_keying_material("EXPORTER-Channel-Binding", Some(b""), 32)
+                .unwrap(),
+            exporter
+        );
+        assert_ne!(
+            ssl.export_keying_material("EXPORTER-Channel-Binding", Some(b"x"), 32)
+                .unwrap(),
+            exporter
+        );
+
+        let (client, server) = tcp_pair();
+        let server = thread::spawn(move || server_socket(server).force_handshake());
+        let ssl = client_socket(client, ());
+        ssl.set_version_range(TLS_VERSION_1_2, TLS_VERSION_1_2)
+            .unwrap();
+        ssl.force_handshake().unwrap();
+        server.join().unwrap().unwrap();
+        let err = ssl
+            .channel_binding(ChannelBindingType::TlsExporter)
+            .unwrap_err();
+        assert_eq!(err.nspr_error, SEC_ERROR_INVALID_ARGS);
+    }
+
+    #[test]
+    fn dhe_groups() {
+        init().unwrap();
+        let (client, server) = tcp_pair();
+        let server = thread::spawn(move || {
+            let mut ssl = server_socket(server);
+            ssl.set_option(SSL_ENABLE_SERVER_DHE, true).unwrap();
+            assert_eq!(
+                ssl.set_dhe_groups(&[NamedGroup::X25519])
+                    .err()
+                    .unwrap()
+                    .nspr_error,
+                SEC_ERROR_INVALID_ARGS
+            );
+            ssl.set_dhe_groups(&[NamedGroup::Ffdhe3072]).unwrap();
+            ssl.force_handshake().unwrap();
+            ssl.named_group().unwrap()
+        });
+        let mut ssl = client_socket(client, ());
+        ssl.set_version_range(TLS_VERSION_1_2, TLS_VERSION_1_2)
+            .unwrap();
+        for &suite in TLSCipherSuite::implemented() {
+            ssl.set_ciphersuite_enabled(suite, suite == TLS_DHE_RSA_WITH_AES_128_GCM_SHA256)
+                .unwrap();
+        }
+        // Only EC groups, so the server falls back on its own choice
+        // (which the client can't name).
+        ssl.set_named_groups(&[NamedGroup::X25519]).unwrap();
+        ssl.force_handshake().unwrap();
+        assert_eq!(ssl.named_group().unwrap(), None);
+        assert_eq!(server.join().unwrap(), Some(NamedGroup::Ffdhe3072));
+    }
+
+    #[test]
+    fn trust_anchors() {
+        init().unwrap();
+        let root = Certificate::from_der(include_bytes!("../testdata/root-ca.der")).unwrap();
+        let other = Certificate::from_der(include_bytes!("../testdata/import-root.der")).unwrap();
+        for (anchor, ok) in [(root, true), (other, false)] {
+            let (client, server) = tcp_pair();
+            let server = thread::spawn(move || {
+                let ssl = server_socket(server);
+                let _ = ssl.force_handshake();
+            });
+            let mut ssl = TLSSocket::new(client, ()).unwrap();
+            ssl.set_url(CStr::from_bytes_with_nul(b"localhost\0").unwrap())
+                .unwrap();
+            ssl.set_trust_anchors(&[anchor]).unwrap();
+            ssl.reset_handshake(false).unwrap();
+            let res = ssl.force_handshake();
+            if ok {
+                res.unwrap();
+            } else {
+                assert_eq!(res.unwrap_err().nspr_error, SEC_ERROR_UNTRUSTED_ISSUER);
+            }
+            drop(ssl);
+            server.join().unwrap();
+        }
+
+        // The two hooks would replace each other.
+        let roots = [Certificate::from_der(include_bytes!("../testdata/root-ca.der")).unwrap()];
+        let mut ssl = TLSSocket::new(tcp_pair().0, PinnedCertHook::new(&[])).unwrap();
+        ssl.use_auth_certificate_hook().unwrap();
+        let err = ssl.set_trust_anchors(&roots).unwrap_err();
+        assert_eq!(err.nspr_error, PR_INVALID_STATE_ERROR);
+        ssl.unset_auth_certificate_hook().unwrap();
+        ssl.set_trust_anchors(&roots).unwrap();
+        let err = ssl.use_auth_certificate_hook().unwrap_err();
+        assert_eq!(err.nspr_error, PR_INVALID_STATE_ERROR);
+    }
+
+    #[test]
+    fn read_state() {
+        init().unwrap();
+        let (client, server) = tcp_pair();
+        let (go_tx, go_rx) = mpsc::channel();
+        let server = thread::spawn(move || {
+            let ssl = server_socket(server);
+            ssl.force_handshake().unwrap();
+            go_rx.recv().unwrap();
+            assert_eq!(ssl.write(b"!").unwrap(), 1);
+            go_rx.recv().unwrap();
+            // Closing sends close_notify.
+        });
+        let ssl = client_socket(client, ());
+        ssl.force_handshake().unwrap();
+        assert_eq!(ssl.read_state().unwrap(), ReadState::WouldBlock);
+        go_tx.send(()).unwrap();
+        while ssl.read_state().unwrap() == ReadState::WouldBlock {
+            thread::yield_now();
+        }
+        assert_eq!(ssl.read_state().unwrap(), ReadState::Open);
+        let mut buf = [0; 1];
+        assert_eq!(ssl.read(&mut buf).unwrap(), 1);
+        assert_eq!(ssl.read_state().unwrap(), ReadState::WouldBlock);
+        go_tx.send(()).unwrap();
+        server.join().unwrap();
+        while ssl.read_state().unwrap() == ReadState::WouldBlock {
+            thread::yield_now();
+        }
+        assert_eq!(ssl.read_state().unwrap(), ReadState::PeerClosed);
+        assert_eq!(ssl.read(&mut buf).unwrap(), 0);
+    }
+
+    #[test]
+    fn close_notify() {
+        init().unwrap();
+        let (client, server) = tcp_pair();
+        let server = thread::spawn(move || {
+            let ssl = server_socket(server);
+            let mut buf = [0; 1];
+            assert_eq!(ssl.read(&mut buf).unwrap(), 1);
+            assert_eq!(ssl.read(&mut buf).unwrap(), 0);
+            assert_eq!(ssl.read_state().unwrap(), ReadState::PeerClosed);
+            // Only the client's side is closed.
+            assert_eq!(ssl.write(b"!").unwrap(), 1);
+        });
+        let ssl = client_socket(client, ());
+        assert_eq!(ssl.write(b"?").unwrap(), 1);
+        ssl.close_notify().unwrap();
+        assert!(ssl.write(b"?").is_err());
+        let mut buf = [0; 1];
+        assert_eq!(ssl.read(&mut buf).unwrap(), 1);
+        assert_eq!(&buf, b"!");
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn read_timeout() {
+        use std::time::Instant;
+
+        init().unwrap();
+        let (client, server) = tcp_pair();
+        let (done_tx, done_rx) = mpsc::channel();
+        let server = thread::spawn(move || {
+            let ssl = server_socket(server);
+            let mut buf = [0; 1];
+            assert_eq!(ssl.read(&mut buf).unwrap(), 1);
+            // Stay open, but quiet, until the client's done.
+            done_rx.recv().unwrap();
+        });
+        let ssl = client_socket(client, ());
+        let timeout = Duration::from_millis(100);
+        assert_eq!(ssl.write_timeout(b"?", timeout).unwrap(), 1);
+        let start = Instant::now();
+        let mut buf = [0; 1];
+        let err = ssl.read_timeout(&mut buf, timeout).unwrap_err();
+        assert_eq!(err.nspr_error, PR_IO_TIMEOUT_ERROR);
+        assert!(start.elapsed() >= timeout);
+        done_tx.send(()).unwrap();
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn trace_channel() {
+        init().unwrap();
+        let (client, server) = tcp_pair();
+        let server = thread::spawn(move || {
+            let ssl = server_socket(server);
+            ssl.force_handshake().unwrap();
+            let mut buf = [0; 1];
+            assert_eq!(ssl.read(&mut buf).unwrap(), 0);
+        });
+        let (tx, rx) = mpsc::channel();
+        let mut ssl = client_socket(client, ());
+        // TLS 1.2, which still remembers the extensions at the end.
+        ssl.set_version_range(TLS_VERSION_1_2, TLS_VERSION_1_2)
+            .unwrap();
+        ssl.set_trace_channel(tx).unwrap();
+        ssl.force_handshake().unwrap();
+        let events: Vec<_> = rx.try_iter().collect();
+        match *events.last().unwrap() {
+            HandshakeEvent::Done {
+                version, resumed, ..
+            } => {
+                assert_eq!(version, TLS_VERSION_1_2);
+                assert!(!resumed);
+            }
+            event => panic!("unexpected event {:?}", event),
+        }
+        let ems = ffi::ssl_extended_master_secret_xtn as u16;
+        assert!(events.contains(&HandshakeEvent::ExtensionSent(ems)));
+        // The leaf and the intermediate.
+        assert!(events.contains(&HandshakeEvent::CertReceived { chain_length: 2 }));
+        drop(ssl);
+        server.join().unwrap();
+        assert_eq!(
+            rx.try_iter().collect::<Vec<_>>(),
+            vec![HandshakeEvent::AlertSent(TLSAlert {
+                level: ALERT_WARNING,
+                description: ALERT_CLOSE_NOTIFY,
+            })]
+        );
+    }
+
+    #[test]
+    fn socket_pair_handshake() {
+        init().unwrap();
+        let (client, server) = nspr::net::socket_pair().unwrap();
+        client.set_nonblocking(true).unwrap();
+        server.set_nonblocking(true).unwrap();
+        let (client, server) =
+            testing::new_test_pair((client, server), &TLSConfig::new(), (), ()).unwrap();
+        testing::handshake(&client, &server).unwrap();
+        (**server).set_nonblocking(false).unwrap();
+        client.write(b"hello").unwrap();
+        let mut buf = [0; 5];
+        assert_eq!(server.read(&mut buf).unwrap(), 5);
+        assert_eq!(&buf, b"hello");
+    }
+
+    #[test]
+    fn ech() {
+        use error::{SSL_ERROR_ECH_REQUIRED_ALERT, SSL_ERROR_ECH_RETRY_WITH_ECH};
+        use pk11::{generate_ec_keypair, EcCurve};
+
+        fn config(id: u8) -> (PublicKey, PrivateKey, Vec<u8>) {
+            let public_name = CStr::from_bytes_with_nul(b"localhost\0").unwrap();
+            let (public, private) = generate_ec_keypair(EcCurve::X25519).unwrap();
+            let config = encode_ech_config(id, public_name, 64, &public).unwrap();
+            (public, private, config)
+        }
+
+        init().unwrap();
+        let (_, _, wrong_config) = config(2);
+        // The keys aren't `Send`, so the server's are made in its thread.
+        let serve = |inner: File, expect_ok: bool| {
+            let (tx, rx) = mpsc::channel();
+            let server = thread::spawn(move || {
+                let (public, private, config) = config(1);
+                tx.send(config.clone()).unwrap();
+                let mut ssl = server_socket(inner);
+                ssl.set_server_ech_configs(&public, &private, &config)
+                    .unwrap();
+                if expect_ok {
+                    ssl.force_handshake().unwrap();
+                    assert!(ssl.ech_accepted().unwrap());
+                } else {
+                    // The client's alert can come after the server's
+                    // side of the handshake is done.
+                    let err = ssl.read(&mut [0; 1]).unwrap_err();
+                    assert_eq!(err.nspr_error, SSL_ERROR_ECH_REQUIRED_ALERT);
+                }
+            });
+            (rx.recv().unwrap(), server)
+        };
+
+        let (client, server) = tcp_pair();
+        let (config, server) = serve(server, true);
+        let mut ssl = client_socket(client, ());
+        assert_eq!(ssl.retry_ech_configs().unwrap(), None);
+        ssl.set_ech_configs(&config).unwrap();
+        ssl.force_handshake().unwrap();
+        assert!(ssl.ech_accepted().unwrap());
+        assert_eq!(ssl.retry_ech_configs().unwrap(), None);
+        drop(ssl);
+        server.join().unwrap();
+
+        let (client, server) = tcp_pair();
+        let (config, server) = serve(server, false);
+        let mut ssl = client_socket(client, ());
+        ssl.set_ech_configs(&wrong_config).unwrap();
+        assert_eq!(
+            ssl.force_handshake().unwrap_err().nspr_error,
+            SSL_ERROR_ECH_RETRY_WITH_ECH
+        );
+        assert_eq!(ssl.retry_ech_configs().unwrap(), Some(config));
+        drop(ssl);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn offered_versions() {
+        // What a server that only has `server_max` picks, if anything.
+        let negotiate = |client_range: (TLSVersion, TLSVersion), server_max: TLSVersion| {
+            let files = testing::memory_socket_pair();
+            let (mut client, server) =
+                testing::new_test_pair(files, &TLSConfig::new(), (), ()).unwrap();
+            client
+                .set_version_range(client_range.0, client_range.1)
+                .unwrap();
+            server
+                .set_version_range(TLS_VERSION_1_0, server_max)
+                .unwrap();
+            let (tx, rx) = mpsc::channel();
+            client.set_trace_channel(tx).unwrap();
+            let offered = client.offered_versions().unwrap();
+            testing::handshake(&client, &server).ok()?;
+            let version = rx.try_iter().find_map(|event| match event {
+                HandshakeEvent::Done { version, .. } => Some(version),
+                _ => None,
+            });
+            assert!(offered.contains(version.as_ref().unwrap()));
+            version
+        };
+
+        init().unwrap();
+        let both = (TLS_VERSION_1_2, TLS_VERSION_1_3);
+        let (sock, _) = testing::new_test_pair(
+            testing::memory_socket_pair(),
+            &TLSConfig::new().version_range(both.0, both.1),
+            (),
+            (),
+        )
+        .unwrap();
+        assert_eq!(
+            sock.offered_versions().unwrap(),
+            vec![TLS_VERSION_1_3, TLS_VERSION_1_2]
+        );
+        assert_eq!(negotiate(both, TLS_VERSION_1_3), Some(TLS_VERSION_1_3));
+        assert_eq!(negotiate(both, TLS_VERSION_1_2), Some(TLS_VERSION_1_2));
+
+        // Without supported_versions, it's only the maximum.
+        let old = (TLS_VERSION_1_1, TLS_VERSION_1_2);
+        sock.set_version_range(old.0, old.1).unwrap();
+        assert_eq!(sock.offered_versions().unwrap(), vec![TLS_VERSION_1_2]);
+        assert_eq!(negotiate(old, TLS_VERSION_1_3), Some(TLS_VERSION_1_2));
+
+        let only = (TLS_VERSION_1_3, TLS_VERSION_1_3);
+        assert_eq!(negotiate(only, TLS_VERSION_1_2), None);
+    }
+
+    #[test]
+    fn mp_session_cache() {
+        init().unwrap();
+        // This can only be set up once per process.
+        let _lock = lock_global();
+        config_mp_server_session_cache(0, Duration::from_secs(60), None).unwrap();
+        let config = TLSConfig::new()
+            .option(SSL_ENABLE_SESSION_TICKETS, false)
+            .version_range(TLS_VERSION_1_2, TLS_VERSION_1_2);
+        let connect = || -> bool {
+            let (mut client, server) =
+                testing::new_test_pair(testing::memory_socket_pair(), &config, (), ()).unwrap();
+            server.set_option(SSL_NO_CACHE, false).unwrap();
+            let (tx, rx) = mpsc::channel();
+            client.set_trace_channel(tx).unwrap();
+            testing::handshake(&client, &server).unwrap();
+            rx.try_iter()
+                .find_map(|event| match event {
+                    HandshakeEvent::Done { resumed, .. } => Some(resumed),
+                    _ => None,
+                })
+                .unwrap()
+        };
+        assert!(!connect());
+        assert!(connect());
+    }
+
+    #[test]
+    fn resumption_token() {
+        #[derive(Default)]
+        struct Tokens(Mutex<Vec<Vec<u8>>>);
+        impl ResumptionTokenCallback for Tokens {
+            fn resumption_token(&self, _sock: BorrowedTLSSocket<Self>, token: &[u8]) -> Result<()> {
+                self.0.lock().unwrap().push(token.to_vec());
+                Ok(())
+            }
+        }
+
+        init().unwrap();
+        let config = TLSConfig::new()
+            .option(SSL_ENABLE_SESSION_TICKETS, true)
+            .version_range(TLS_VERSION_1_3, TLS_VERSION_1_3);
+        let connect = |token: Option<Vec<u8>>| -> (bool, Vec<Vec<u8>>) {
+            let files = testing::memory_socket_pair();
+            let (mut ssl, server) =
+                testing::new_test_pair(files, &config, Tokens::default(), ()).unwrap();
+            server.set_option(SSL_NO_CACHE, false).unwrap();
+            let (tx, rx) = mpsc::channel();
+            ssl.set_trace_channel(tx).unwrap();
+            ssl.use_resumption_token_callback().unwrap();
+            if let Some(token) = token {
+                ssl.set_resumption_token(&token).unwrap();
+            }
+            testing::handshake(&ssl, &server).unwrap();
+            // The ticket comes after the handshake, so read for it.
+            server.write(b"x").unwrap();
+            let mut buf = [0; 1];
+            assert_eq!(ssl.read(&mut buf).unwrap(), 1);
+            let resumed = rx
+                .try_iter()
+                .find_map(|event| match event {
+                    HandshakeEvent::Done { resumed, .. } => Some(resumed),
+                    _ => None,
+                })
+                .unwrap();
+            let tokens = ssl.callbacks().0.lock().unwrap().clone();
+            (resumed, tokens)
+        };
+        let (resumed, tokens) = connect(None);
+        assert!(!resumed);
+        assert!(!tokens.is_empty());
+        let (resumed, _) = connect(tokens.into_iter().next());
+        assert!(resumed);
+        assert!(TLSSocket::new(tcp_pair().0, ())
+            .unwrap()
+            .set_resumption_token(b"garbage")
+            .is_err());
+    }
+
+    #[test]
+    fn alert_hooks() {
+        #[derive(Default)]
+        struct Alerts {
+            received: Mutex<Vec<TLSAlert>>,
+            sent: Mutex<Vec<TLSAlert>>,
+        }
+        impl AlertCallback for Alerts {
+            fn alert_received(&self, _sock: BorrowedTLSSocket<Self>, alert: TLSAlert) {
+                self.received.lock().unwrap().push(alert);
+            }
+            fn alert_sent(&self, _sock: BorrowedTLSSocket<Self>, alert: TLSAlert) {
+                self.sent.lock().unwrap().push(alert);
+            }
+        }
+
+        init().unwrap();
+        let (client, server) = tcp_pair();
+        let server = thread::spawn(move || {
+            let ssl = server_socket(server);
+            ssl.set_version_range(TLS_VERSION_1_3, TLS_VERSION_1_3)
+                .unwrap();
+            assert!(ssl.force_handshake().is_err());
+        });
+        let mut ssl = client_socket(client, Alerts::default());
+        ssl.set_version_range(TLS_VERSION_1_2, TLS_VERSION_1_2)
+            .unwrap();
+        ssl.use_alert_received_hook().unwrap();
+        ssl.use_alert_sent_hook().unwrap();
+        assert!(ssl.force_handshake().is_err());
+        server.join().unwrap();
+        let alert = TLSAlert {
+            level: ALERT_FATAL,
+            description: ALERT_PROTOCOL_VERSION,
+        };
+        assert_eq!(*ssl.callbacks().received.lock().unwrap(), vec![alert]);
+        assert!(alert.is_fatal());
+        assert!(ssl.callbacks().sent.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn tls13_compat_mode() {
+        init().unwrap();
+        let (client, _server) = tcp_pair();
+        let mut ssl = client_socket(client, ());
+        assert!(!ssl.get_option(SSL_ENABLE_TLS13_COMPAT_MODE).unwrap());
+        ssl.set_tls13_compat_mode(true).unwrap();
+        assert!(ssl.get_option(SSL_ENABLE_TLS13_COMPAT_MODE).unwrap());
+        ssl.set_tls13_compat_mode(false).unwrap();
+        assert!(!ssl.get_option(SSL_ENABLE_TLS13_COMPAT_MODE).unwrap());
+
+        let config = TLSConfig::new()
+            .option(SSL_ENABLE_TLS13_COMPAT_MODE, true)
+            .version_range(TLS_VERSION_1_3, TLS_VERSION_1_3);
+        let (client, server) = testing::test_pair(&config).unwrap();
+        assert!(client.get_option(SSL_ENABLE_TLS13_COMPAT_MODE).unwrap());
+        assert_eq!(client.write(b"x").unwrap(), 1);
+        let mut buf = [0; 1];
+        assert_eq!(server.read(&mut buf).unwrap(), 1);
+    }
+
+    #[test]
+    fn max_early_data_size() {
+        init().unwrap();
+        let (_client, server) = tcp_pair();
+        let mut ssl = server_socket(server);
+        ssl.set_option(SSL_ENABLE_0RTT_DATA, true).unwrap();
+        ssl.set_max_early_data_size(16384).unwrap();
+        ssl.set_max_early_data_size(0).unwrap();
+    }
+
+    #[test]
+    fn anti_replay() {
+        init().unwrap();
+        let ctx = init_anti_replay(Duration::from_secs(10), 1, 3).unwrap();
+        for _ in 0..2 {
+            let (_client, server) = tcp_pair();
+            let mut ssl = server_socket(server);
+            ssl.set_anti_replay(&ctx).unwrap();
+        }
+        drop(ctx);
+
+        // Too long to count in microseconds as a PRTime.
+        let err = init_anti_replay(Duration::from_secs(u64::MAX), 1, 3)
+            .err()
+            .unwrap();
+        assert_eq!(err.nspr_error, SEC_ERROR_INVALID_ARGS);
+    }
+
+    #[test]
+    fn model() {
+        init().unwrap();
+        let (client, _server) = tcp_pair();
+        let ssl = TLSSocket::new(client, ()).unwrap();
+        ssl.set_version_range(TLS_VERSION_1_2, TLS_VERSION_1_2)
+            .unwrap();
+        ssl.set_ciphersuite_enabled(TLS_ECDHE_RSA_WITH_AES_128_GCM_SHA256, false)
+            .unwrap();
+        let model = ssl.make_model().unwrap();
+        drop(ssl);
+
+        for _ in 0..2 {
+            let (client, _server) = tcp_pair();
+            let ssl = TLSSocket::new_with_model(client, (), Some(&model)).unwrap();
+            assert_eq!(
+                ssl.get_version_range().unwrap(),
+                (TLS_VERSION_1_2, TLS_VERSION_1_2)
+            );
+            assert!(!ssl
+                .is_ciphersuite_enabled(TLS_ECDHE_RSA_WITH_AES_128_GCM_SHA256)
+                .unwrap());
+        }
+    }
+
+    #[test]
+    fn false_start() {
+        struct Approver(Mutex<Option<TLSCipherSuite>>);
+        impl FalseStartCheck for Approver {
+            fn can_false_start(
+                &self,
+                _sock: BorrowedTLSSocket<Self>,
+                suite: TLSCipherSuite,
+            ) -> Result<bool> {
+                *self.0.lock().unwrap() = Some(suite);
+                Ok(true)
+            }
+        }
+
+        init().unwrap();
+        let (client, server) = tcp_pair();
+        let server = thread::spawn(move || {
+            let ssl = server_socket(server);
+            ssl.force_handshake().unwrap();
+        });
+        let mut ssl = client_socket(client, Approver(Mutex::new(None)));
+        // TLS 1.3 doesn't have false start.
+        ssl.set_version_range(TLS_VERSION_1_2, TLS_VERSION_1_2)
+            .unwrap();
+        ssl.enable_false_start(true).unwrap();
+        assert!(ssl.get_option(SSL_ENABLE_FALSE_START).unwrap());
+        ssl.use_false_start_check().unwrap();
+        ssl.force_handshake().unwrap();
+        server.join().unwrap();
+        let suite = ssl.callbacks().0.lock().unwrap().unwrap();
+        assert!(TLSCipherSuite::implemented().contains(&suite));
+    }
+
+    #[test]
+    fn fallback_scsv() {
+        use error::SSL_ERROR_INAPPROPRIATE_FALLBACK_ALERT;
+
+        init().unwrap();
+        let (client, server) = tcp_pair();
+        let server = thread::spawn(move || {
+            let ssl = server_socket(server);
+            ssl.force_handshake().unwrap_err();
+        });
+        let mut ssl = client_socket(client, ());
+        assert!(!ssl.get_option(SSL_ENABLE_FALLBACK_SCSV).unwrap());
+        ssl.set_fallback_scsv(true).unwrap();
+        assert!(ssl.get_option(SSL_ENABLE_FALLBACK_SCSV).unwrap());
+        // The server could have done TLS 1.3.
+        ssl.set_version_range(TLS_VERSION_1_2, TLS_VERSION_1_2)
+            .unwrap();
+        assert_eq!(
+            ssl.force_handshake().unwrap_err().nspr_error,
+            SSL_ERROR_INAPPROPRIATE_FALLBACK_ALERT
+        );
+        server.join().unwrap();
+        ssl.set_fallback_scsv(false).unwrap();
+        assert!(!ssl.get_option(SSL_ENABLE_FALLBACK_SCSV).unwrap());
+    }
+
+    #[test]
+    fn grease_options() {
+        fn check<F>(ssl: &mut TLSSocket<()>, option: TLSOption, toggle: F)
+        where
+            F: Fn(&mut TLSSocket<()>, bool) -> Result<()>,
+        {
+            match toggle(ssl, true) {
+                Ok(()) => {
+                    assert!(ssl.get_option(option).unwrap());
+                    toggle(ssl, false).unwrap();
+                    assert!(!ssl.get_option(option).unwrap());
+                }
+                Err(err) => assert_eq!(err.nspr_error, PR_OPERATION_NOT_SUPPORTED_ERROR),
+            }
+        }
+
+        init().unwrap();
+        let (client, _server) = tcp_pair();
+        let mut ssl = TLSSocket::new(client, ()).unwrap();
+        check(&mut ssl, SSL_ENABLE_GREASE, |ssl, on| ssl.enable_grease(on));
+        check(&mut ssl, SSL_ENABLE_CH_EXTENSION_PERMUTATION, |ssl, on| {
+            ssl.enable_ch_extension_permutation(on)
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "not implemented")]
     fn inner_panic1() {
         struct BrokenSocket;
         impl FileMethods for BrokenSocket {
@@ -611,7 +3521,7 @@ mod tests {
     }
 
     #[test]
-    #[should_panic(expected = "not yet implemented")]
+    #[should_panic(expected = "not implemented")]
     fn inner_panic2() {
         struct BrokenSocket;
         impl FileMethods for BrokenSocket {
@@ -0,0 +1,172 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+use cert::Certificate;
+use config::ServerConfig;
+use libc::{AF_INET, AF_INET6};
+use nspr::fd::{File, FileMethods};
+use nspr::net::new_tcp_socket;
+use pk11::PrivateKey;
+use std::net::SocketAddr;
+use {Result, TLSSocket};
+
+const BACKLOG: i32 = 128;
+
+// A blocking TLS server socket: each accepted connection is set up
+// from a model socket holding the server certificate.
+pub struct TLSListener {
+    socket: File,
+    model: TLSSocket<()>,
+}
+
+impl TLSListener {
+    // `cert_chain` is as for `config_server_cert`.
+    pub fn bind(
+        addr: SocketAddr,
+        cert: &Certificate,
+        key: &PrivateKey,
+        cert_chain: &[Certificate],
+    ) -> Result<Self> {
+        Self::bind_with_config(addr, &ServerConfig::new(cert, key).cert_chain(cert_chain))
+    }
+
+    pub fn bind_with_config(addr: SocketAddr, config: &ServerConfig) -> Result<Self> {
+        let af = match addr {
+            SocketAddr::V4(_) => AF_INET,
+            SocketAddr::V6(_) => AF_INET6,
+        };
+        let socket = new_tcp_socket(af)?;
+        socket.bind(addr)?;
+        socket.listen(BACKLOG)?;
+        let model = config.build_model(af)?;
+        Ok(TLSListener { socket, model })
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        self.socket.getsockname()
+    }
+
+    // For further configuration (e.g., with `TLSConfig::apply`); this
+    // affects connections accepted afterwards.
+    pub fn model_mut(&mut self) -> &mut TLSSocket<()> {
+        &mut self.model
+    }
+
+    // The handshake hasn't started yet, so a slow or silent client
+    // can't hold up the next `accept`; it happens on the first read or
+    // write, or with `force_handshake` (from the connection's thread).
+    pub fn accept(&self) -> Result<(TLSSocket<()>, SocketAddr)> {
+        let (inner, addr) = self.socket.accept(None)?;
+        let ssl = TLSSocket::new_with_model(inner, (), Some(&self.model))?;
+        ssl.reset_handshake(true)?;
+        Ok((ssl, addr))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use config::TLSConfig;
+    use init;
+    use std::ffi::CStr;
+    use std::net::{Ipv4Addr, Ipv6Addr, SocketAddrV4, SocketAddrV6, TcpStream};
+    use std::thread;
+    use {SSL_NO_CACHE, TLS_VERSION_1_2};
+
+    fn connect_client(addr: SocketAddr) -> thread::JoinHandle<Vec<u8>> {
+        thread::spawn(move || {
+            let af = if addr.is_ipv6() { AF_INET6 } else { AF_INET };
+            let mut ssl = TLSSocket::new(new_tcp_socket(af).unwrap(), ()).unwrap();
+            ssl.set_url(CStr::from_bytes_with_nul(b"localhost\0").unwrap())
+                .unwrap();
+            ssl.disable_security().unwrap();
+            ssl.connect(addr, None).unwrap();
+            ssl.force_handshake().unwrap();
+            ssl.peer_cert().unwrap().as_der().to_vec()
+        })
+    }
+
+    #[test]
+    fn server_config() {
+        init().unwrap();
+        let leaf = Certificate::from_der(include_bytes!("../testdata/leaf.der")).unwrap();
+        let key = PrivateKey::from_pkcs8_der(include_bytes!("../testdata/leaf.key.der")).unwrap();
+        let config = ServerConfig::new(&leaf, &key).config(
+            TLSConfig::new()
+                .option(SSL_NO_CACHE, true)
+                .version_range(TLS_VERSION_1_2, TLS_VERSION_1_2),
+        );
+        let addr = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 0));
+        let listener = TLSListener::bind_with_config(addr, &config).unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        for _ in 0..2 {
+            let client = connect_client(addr);
+            let (ssl, _) = listener.accept().unwrap();
+            ssl.force_handshake().unwrap();
+            assert_eq!(
+                ssl.get_version_range().unwrap(),
+                (TLS_VERSION_1_2, TLS_VERSION_1_2)
+            );
+            assert_eq!(client.join().unwrap(), leaf.as_der());
+        }
+    }
+
+    #[test]
+    fn ipv6() {
+        init().unwrap();
+        let leaf = Certificate::from_der(include_bytes!("../testdata/leaf.der")).unwrap();
+        let key = PrivateKey::from_pkcs8_der(include_bytes!("../testdata/leaf.key.der")).unwrap();
+        let addr = SocketAddr::V6(SocketAddrV6::new(Ipv6Addr::LOCALHOST, 0, 0, 0));
+        // No IPv6 here.
+        let probe = match new_tcp_socket(AF_INET6) {
+            Ok(probe) => probe,
+            Err(_) => return,
+        };
+        if probe.bind(addr).is_err() {
+            return;
+        }
+        drop(probe);
+        let listener = TLSListener::bind(addr, &leaf, &key, &[]).unwrap();
+        let client = connect_client(listener.local_addr().unwrap());
+        let (ssl, peer) = listener.accept().unwrap();
+        assert!(peer.is_ipv6());
+        ssl.force_handshake().unwrap();
+        assert_eq!(client.join().unwrap(), leaf.as_der());
+    }
+
+    #[test]
+    fn accept() {
+        init().unwrap();
+        let leaf = Certificate::from_der(include_bytes!("../testdata/leaf.der")).unwrap();
+        let intermediate =
+            Certificate::from_der(include_bytes!("../testdata/intermediate.der")).unwrap();
+        let key = PrivateKey::from_pkcs8_der(include_bytes!("../testdata/leaf.key.der")).unwrap();
+        let addr = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 0));
+        let listener = TLSListener::bind(addr, &leaf, &key, &[intermediate]).unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        // One that never says anything doesn't hold up the next.
+        let _silent = TcpStream::connect(addr).unwrap();
+        let _ = listener.accept().unwrap();
+        let client = thread::spawn(move || {
+            let mut ssl = TLSSocket::new(new_tcp_socket(AF_INET).unwrap(), ()).unwrap();
+            ssl.set_url(CStr::from_bytes_with_nul(b"localhost\0").unwrap())
+                .unwrap();
+            ssl.disable_security().unwrap();
+            ssl.connect(addr, None).unwrap();
+            assert_eq!(ssl.write(b"?").unwrap(), 1);
+            let mut buf = [0; 1];
+            assert_eq!(ssl.read(&mut buf).unwrap(), 1);
+            buf[0]
+        });
+        let (ssl, peer) = listener.accept().unwrap();
+        assert_eq!(peer.ip(), addr.ip());
+        let mut buf = [0; 1];
+        assert_eq!(ssl.read(&mut buf).unwrap(), 1);
+        assert_eq!(&buf, b"?");
+        assert_eq!(ssl.write(b"!").unwrap(), 1);
+        assert_eq!(client.join().unwrap(), b'!');
+    }
+}
@@ -4,11 +4,17 @@
 
 use libc::c_char;
 use nss_sys::nspr as ffi;
+use std::backtrace::Backtrace;
+#[cfg(feature = "backtrace")]
+use std::env;
 use std::error;
 use std::ffi::CStr;
 use std::fmt;
 use std::io;
 use std::result;
+#[cfg(feature = "backtrace")]
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
 
 pub use ErrorCode;
 
@@ -85,48 +91,122 @@ macro_rules! error_kinds {
     }
 }
 
-#[derive(Clone, Copy, Debug)]
+// With the "backtrace" feature, errors made while the environment
+// variable `NSS_RS_BACKTRACE` was set (to anything but "0") at the
+// first error capture a backtrace.  The field for it is there either
+// way, so that the feature doesn't change the type: `Error` isn't
+// `Copy` and can't be made with a struct literal; use `Error::new`.
+#[derive(Clone)]
 pub struct Error {
     pub nspr_error: ErrorCode,
     pub os_error: i32,
+    backtrace: Option<Arc<Backtrace>>,
 }
 impl Error {
-    pub fn last() -> Self {
+    pub fn new(nspr_error: ErrorCode, os_error: i32) -> Self {
         Error {
-            nspr_error: ErrorCode::last(),
-            os_error: unsafe { ffi::PR_GetOSError() },
+            nspr_error,
+            os_error,
+            backtrace: capture_backtrace(),
         }
     }
+    pub fn last() -> Self {
+        // Both are read before anything else can change them.
+        let (nspr_error, os_error) = unsafe { (ErrorCode::last(), ffi::PR_GetOSError()) };
+        Self::new(nspr_error, os_error)
+    }
+    // Where this was made, if it was captured; always `None` without
+    // the "backtrace" feature.
+    pub fn backtrace(&self) -> Option<&Backtrace> {
+        self.backtrace.as_deref()
+    }
+    // Makes this the thread's current error, as C code expects of a
+    // function or callback that fails.  Errors returned from Rust
+    // callbacks this crate installs (e.g., `FileMethods` impls) are
+    // already set this way; this is for anything hand-written.
     pub fn set(self) {
-        unsafe {
-            ffi::PR_SetError(self.nspr_error.0, self.os_error);
+        set_error(self.nspr_error, self.os_error)
+    }
+    pub fn kind(&self) -> ErrorKind {
+        match self.nspr_error.0 {
+            ffi::PR_WOULD_BLOCK_ERROR => ErrorKind::WouldBlock,
+            ffi::PR_IO_TIMEOUT_ERROR | ffi::PR_CONNECT_TIMEOUT_ERROR => ErrorKind::TimedOut,
+            ffi::PR_CONNECT_REFUSED_ERROR => ErrorKind::ConnectionRefused,
+            ffi::PR_CONNECT_RESET_ERROR => ErrorKind::ConnectionReset,
+            ffi::PR_NOT_CONNECTED_ERROR => ErrorKind::NotConnected,
+            ffi::PR_ADDRESS_NOT_SUPPORTED_ERROR => ErrorKind::AddressNotSupported,
+            ffi::PR_END_OF_FILE_ERROR => ErrorKind::EndOfFile,
+            code => ErrorKind::Other(code),
         }
     }
 }
+
+// The NSPR errors callers most often need to tell apart; unlike
+// io::ErrorKind, anything else keeps its original code.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorKind {
+    WouldBlock,
+    TimedOut,
+    ConnectionRefused,
+    ConnectionReset,
+    NotConnected,
+    AddressNotSupported,
+    EndOfFile,
+    Other(i32),
+}
+impl fmt::Debug for Error {
+    // Just the codes, even with a backtrace.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Error")
+            .field("nspr_error", &self.nspr_error)
+            .field("os_error", &self.os_error)
+            .finish()
+    }
+}
+
+// 0 until the environment is read, then 1 for off or 2 for on.
+#[cfg(feature = "backtrace")]
+static CAPTURE: AtomicUsize = AtomicUsize::new(0);
+
+#[cfg(feature = "backtrace")]
+fn capture_backtrace() -> Option<Arc<Backtrace>> {
+    // This doesn't touch NSPR's error state.
+    if CAPTURE.load(Ordering::Relaxed) == 0 {
+        let on = match env::var_os("NSS_RS_BACKTRACE") {
+            Some(ref val) => val != "0",
+            None => false,
+        };
+        let _ = CAPTURE.compare_exchange(0, on as usize + 1, Ordering::Relaxed, Ordering::Relaxed);
+    }
+    if CAPTURE.load(Ordering::Relaxed) != 2 {
+        return None;
+    }
+    Some(Arc::new(Backtrace::force_capture()))
+}
+
+#[cfg(not(feature = "backtrace"))]
+fn capture_backtrace() -> Option<Arc<Backtrace>> {
+    None
+}
+
 impl From<ErrorCode> for Error {
     fn from(err: ErrorCode) -> Self {
-        Error {
-            nspr_error: err,
-            os_error: 0,
-        }
+        Self::new(err, 0)
     }
 }
 // Are From/Into really right for lossy conversions like these?
-impl Into<io::Error> for Error {
-    fn into(self) -> io::Error {
-        if self.os_error != 0 {
-            io::Error::from_raw_os_error(self.os_error)
+impl From<Error> for io::Error {
+    fn from(err: Error) -> Self {
+        if err.os_error != 0 {
+            io::Error::from_raw_os_error(err.os_error)
         } else {
-            io::Error::new(self.nspr_error.kind(), Box::new(self.nspr_error))
+            io::Error::new(err.nspr_error.kind(), Box::new(err.nspr_error))
         }
     }
 }
 impl From<io::Error> for Error {
     fn from(err: io::Error) -> Self {
-        Error {
-            nspr_error: err.kind().into(),
-            os_error: err.raw_os_error().unwrap_or(0),
-        }
+        Self::new(err.kind().into(), err.raw_os_error().unwrap_or(0))
     }
 }
 
@@ -231,3 +311,64 @@ error_kinds! {
     Interrupted       = PR_PENDING_INTERRUPT_ERROR,
     UnexpectedEof     = PR_END_OF_FILE_ERROR | PR_NO_MORE_FILES_ERROR
 }
+
+// See `Error::set`.
+pub fn set_error(code: ErrorCode, os_error: i32) {
+    unsafe {
+        ffi::PR_SetError(code.0, os_error);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kinds() {
+        let kind = |code: ErrorCode| Error::from(code).kind();
+        assert_eq!(kind(PR_WOULD_BLOCK_ERROR), ErrorKind::WouldBlock);
+        assert_eq!(kind(PR_IO_TIMEOUT_ERROR), ErrorKind::TimedOut);
+        assert_eq!(kind(PR_CONNECT_TIMEOUT_ERROR), ErrorKind::TimedOut);
+        assert_eq!(kind(PR_CONNECT_REFUSED_ERROR), ErrorKind::ConnectionRefused);
+        assert_eq!(kind(PR_END_OF_FILE_ERROR), ErrorKind::EndOfFile);
+        assert_eq!(
+            kind(PR_FILE_NOT_FOUND_ERROR),
+            ErrorKind::Other(PR_FILE_NOT_FOUND_ERROR.0)
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "backtrace")]
+    fn backtrace() {
+        // Instead of the environment, which other tests can be reading.
+        CAPTURE.store(2, Ordering::Relaxed);
+        set_error(PR_CONNECT_REFUSED_ERROR, 111);
+        let err = Error::last();
+        CAPTURE.store(1, Ordering::Relaxed);
+        let copy = err.clone();
+        assert!(err.backtrace().is_some());
+        assert!(copy.backtrace().is_some());
+        assert_eq!(
+            format!("{:?}", err),
+            "Error { nspr_error: PR_CONNECT_REFUSED_ERROR, os_error: 111 }"
+        );
+        // The thread's error is still there.
+        assert_eq!(Error::last().nspr_error, PR_CONNECT_REFUSED_ERROR);
+        assert!(Error::last().backtrace().is_none());
+    }
+
+    #[test]
+    #[cfg(not(feature = "backtrace"))]
+    fn no_backtrace() {
+        set_error(PR_CONNECT_REFUSED_ERROR, 111);
+        assert!(Error::last().backtrace().is_none());
+    }
+
+    #[test]
+    fn set_last() {
+        set_error(PR_CONNECT_REFUSED_ERROR, 111);
+        let err = Error::last();
+        assert_eq!(err.nspr_error, PR_CONNECT_REFUSED_ERROR);
+        assert_eq!(err.os_error, 111);
+    }
+}
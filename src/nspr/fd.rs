@@ -2,21 +2,27 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
 
-use libc::c_void;
+use libc::{c_char, c_int, c_void};
 use nspr::bool_from_nspr;
-use nspr::error::{Result, PR_ADDRESS_NOT_SUPPORTED_ERROR};
+use nspr::error::{
+    Result, PR_BUFFER_OVERFLOW_ERROR, PR_INVALID_ARGUMENT_ERROR, PR_INVALID_METHOD_ERROR,
+    PR_NOT_IMPLEMENTED_ERROR,
+};
 use nspr::net::{read_net_addr, write_net_addr, NetAddrStorage};
 use nspr::time::duration_opt_to_nspr;
 use nss_sys::nspr as ffi;
+use std::any::TypeId;
+use std::cmp;
 use std::ffi::CString;
-use std::i32;
 use std::marker::PhantomData;
 use std::mem;
-use std::net::SocketAddr;
+use std::net::{Shutdown, SocketAddr};
 use std::ops::{Deref, DerefMut};
 use std::ptr;
+use std::result;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use {wrap_ffi, GenStatus};
 
 pub type RawFile = *mut ffi::PRFileDesc;
@@ -28,7 +34,7 @@ unsafe impl Send for File {}
 impl Drop for File {
     fn drop(&mut self) {
         let fd = mem::replace(&mut self.0, null());
-        if fd != null() {
+        if !fd.is_null() {
             let _status: Result<()> = wrap_ffi(|| unsafe { ffi::PR_Close(fd) });
         }
     }
@@ -36,13 +42,20 @@ impl Drop for File {
 
 #[allow(dead_code)]
 impl File {
+    /// # Safety
+    ///
+    /// The file's `secret` must point to a live `Inner`, as it does for
+    /// a file wrapped by a `FileWrapper<Inner>`.
     pub unsafe fn unsafe_get_ref<Inner>(&self) -> &Inner {
         let ptr: *mut ffi::PRFilePrivate = (*self.as_raw_prfd()).secret;
-        mem::transmute(ptr)
+        &*(ptr as *const Inner)
     }
+    /// # Safety
+    ///
+    /// As for `unsafe_get_ref`.
     pub unsafe fn unsafe_get_mut<Inner>(&mut self) -> &mut Inner {
         let ptr: *mut ffi::PRFilePrivate = (*self.as_raw_prfd()).secret;
-        mem::transmute(ptr)
+        &mut *(ptr as *mut Inner)
     }
 
     pub fn into_raw_prfd(self) -> RawFile {
@@ -51,22 +64,143 @@ impl File {
         fd
     }
     pub fn as_raw_prfd(&self) -> RawFile {
-        debug_assert!(self.0 != null());
+        debug_assert!(!self.0.is_null());
         self.0
     }
+    /// # Safety
+    ///
+    /// `fd` must be a non-null file descriptor that the caller owns;
+    /// it's closed on drop.
     pub unsafe fn from_raw_prfd(fd: RawFile) -> Self {
-        assert!(fd != null());
+        assert!(!fd.is_null());
         File(fd)
     }
+
+    pub fn desc_type(&self) -> FileType {
+        unsafe { (*(*self.as_raw_prfd()).methods).file_type }
+    }
+    pub fn identity(&self) -> ffi::PRDescIdentity {
+        unsafe { (*self.as_raw_prfd()).identity }
+    }
+
+    // Recovers the `Inner` of a `File` made by `FileWrapper::wrap`; returns
+    // `None` for any other kind of file or a wrapper of a different type.
+    pub fn downcast_ref<Inner: FileMethods + 'static>(&self) -> Option<&Inner> {
+        if self.identity() != *WRAPPED_FILE_IDENT {
+            return None;
+        }
+        unsafe {
+            let secret = (*self.as_raw_prfd()).secret;
+            let header = &*(secret as *const WrappedFileHeader);
+            if header.type_id != TypeId::of::<Inner>() {
+                return None;
+            }
+            Some(&*(secret as *const WrappedFileImpl<Inner>))
+        }
+    }
+    // Takes back the `Inner` given to `FileWrapper::wrap`, without
+    // dropping it; otherwise (including if a layer was pushed on top,
+    // as TLS is) returns `self` unchanged.
+    pub fn into_inner<Inner: FileMethods + 'static>(self) -> result::Result<Inner, File> {
+        if self.downcast_ref::<Inner>().is_none() {
+            return Err(self);
+        }
+        let fd = self.into_raw_prfd();
+        unsafe {
+            let raw_box = (*fd).secret as *mut WrappedFileImpl<Inner>;
+            // As in `close`.
+            assert_eq!(&mut (*raw_box).prfd as *mut ffi::PRFileDesc, fd);
+            let WrappedFileImpl { inner, .. } = *Box::from_raw(raw_box);
+            Ok(inner)
+        }
+    }
+
+    // At most `PR_MAX_IOVECTOR_SIZE` buffers, or this fails with
+    // `PR_BUFFER_OVERFLOW_ERROR`.  Like `write`, this can be short: in
+    // particular, buffers past the first 2GiB in total aren't written.
+    // Files without a real writev (pipes, and those made by
+    // `FileWrapper`) get a `send` per buffer with what's left of
+    // `timeout`, stopping at the first short one; without a timeout,
+    // or for pipes, which can't `send`, it's a `write` per buffer.
+    pub fn writev(&self, bufs: &[&[u8]], timeout: Option<Duration>) -> Result<usize> {
+        if bufs.len() > ffi::PR_MAX_IOVECTOR_SIZE {
+            return Err(PR_BUFFER_OVERFLOW_ERROR.into());
+        }
+        if unsafe { (*(*self.as_raw_prfd()).methods).writev.is_none() } {
+            return self.write_each(bufs, timeout);
+        }
+        let mut total = 0;
+        let iov: Vec<_> = bufs
+            .iter()
+            .map(|buf| {
+                let len = cmp::min(buf.len(), MAX_IO_LEN - total);
+                total += len;
+                ffi::PRIOVec {
+                    iov_base: buf.as_ptr() as *mut c_char,
+                    iov_len: len as c_int,
+                }
+            })
+            .collect();
+        let res = wrap_ffi(|| unsafe {
+            ffi::PR_Writev(
+                self.as_raw_prfd(),
+                iov.as_ptr(),
+                iov.len() as i32,
+                duration_opt_to_nspr(timeout),
+            )
+        });
+        match res {
+            Err(err) if err.nspr_error == PR_INVALID_METHOD_ERROR => self.write_each(bufs, timeout),
+            res => res,
+        }
+    }
+
+    fn write_each(&self, bufs: &[&[u8]], timeout: Option<Duration>) -> Result<usize> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        let mut can_send = deadline.is_some();
+        let mut done = 0;
+        for buf in bufs.iter().filter(|buf| !buf.is_empty()) {
+            let res = match deadline {
+                Some(deadline) if can_send => {
+                    let remaining = deadline.saturating_duration_since(Instant::now());
+                    match self.send(buf, Some(remaining)) {
+                        Err(err) if err.nspr_error == PR_INVALID_METHOD_ERROR => {
+                            can_send = false;
+                            self.write(buf)
+                        }
+                        res => res,
+                    }
+                }
+                _ => self.write(buf),
+            };
+            match res {
+                Ok(n) => {
+                    done += n;
+                    if n < buf.len() {
+                        break;
+                    }
+                }
+                Err(err) => return if done == 0 { Err(err) } else { Ok(done) },
+            }
+        }
+        Ok(done)
+    }
+
+    /// # Safety
+    ///
+    /// As for `from_raw_prfd`, except that `fd` may be null.
     pub unsafe fn from_raw_prfd_opt(fd: RawFile) -> Option<Self> {
-        if fd == null() {
+        if fd.is_null() {
             None
         } else {
             Some(Self::from_raw_prfd(fd))
         }
     }
+    /// # Safety
+    ///
+    /// As for `from_raw_prfd`, except that `fd` may be null.
     pub unsafe fn from_raw_prfd_err(fd: RawFile) -> GenStatus<Self> {
-        if fd == null() {
+        if fd.is_null() {
             GenStatus::ErrorFromC
         } else {
             GenStatus::Success(Self::from_raw_prfd(fd))
@@ -77,6 +211,7 @@ impl File {
 // Like `File`, but with no `drop`; for use in callbacks from C where
 // the caller owns the file and the callee must not close it.  In
 // general this should be used only via `&File` borrows.
+#[allow(dead_code)] // Only accessed via the `Deref` transmute.
 pub struct BorrowedFile<Secret = ()>(RawFile, PhantomData<Secret>);
 // This could get the same `unsafe impl`s as owned `File`, but
 // probably doesn't need them.
@@ -94,10 +229,17 @@ impl<Secret> DerefMut for BorrowedFile<Secret> {
 }
 
 impl<Secret> BorrowedFile<Secret> {
+    /// # Safety
+    ///
+    /// `fd` must be a non-null file descriptor that stays open while
+    /// this is in use, with a `secret` as `Secret` needs.
     pub unsafe fn from_raw_prfd(fd: RawFile) -> Self {
-        assert!(fd != null());
+        assert!(!fd.is_null());
         BorrowedFile(fd, PhantomData)
     }
+    /// # Safety
+    ///
+    /// As for `from_raw_prfd`.
     pub unsafe fn from_raw_prfd_checked(fd: RawFile, ident: ffi::PRDescIdentity) -> Self {
         assert_eq!((*fd).identity, ident);
         Self::from_raw_prfd(fd)
@@ -131,6 +273,25 @@ pub trait FileMethods {
     fn write(&self, _buf: &[u8]) -> Result<usize> {
         unimplemented!()
     }
+    // NSPR has no readv, so this is a `read` per buffer, stopping at the
+    // first short one.  Unlike a real readv it isn't atomic: another
+    // reader could interleave, and if a later read fails, what was
+    // already read is returned instead of the error.
+    fn readv(&self, bufs: &mut [&mut [u8]]) -> Result<usize> {
+        let mut done = 0;
+        for buf in bufs.iter_mut().filter(|buf| !buf.is_empty()) {
+            match self.read(buf) {
+                Ok(n) => {
+                    done += n;
+                    if n < buf.len() {
+                        break;
+                    }
+                }
+                Err(err) => return if done == 0 { Err(err) } else { Ok(done) },
+            }
+        }
+        Ok(done)
+    }
     fn connect(&self, _addr: SocketAddr, _timeout: Option<Duration>) -> Result<()> {
         unimplemented!()
     }
@@ -141,6 +302,16 @@ pub trait FileMethods {
     fn send(&self, _buf: &[u8], _timeout: Option<Duration>) -> Result<usize> {
         unimplemented!()
     }
+    // Not every stream can be half-closed, so this one isn't a panic.
+    fn shutdown(&self, _how: Shutdown) -> Result<()> {
+        Err(PR_NOT_IMPLEMENTED_ERROR.into())
+    }
+    // For a wrapped listening socket; what's returned is up to the
+    // implementation, and typically won't be wrapped itself (but see
+    // `File::accept_wrapped`).  Named so as not to hide `File::accept`.
+    fn accept_conn(&self, _timeout: Option<Duration>) -> Result<(File, SocketAddr)> {
+        Err(PR_NOT_IMPLEMENTED_ERROR.into())
+    }
     fn getsockname(&self) -> Result<SocketAddr> {
         unimplemented!()
     }
@@ -152,26 +323,75 @@ pub trait FileMethods {
     }
 }
 
+fn shutdown_to_nspr(how: Shutdown) -> ffi::PRShutdownHow {
+    match how {
+        Shutdown::Read => ffi::PR_SHUTDOWN_RCV,
+        Shutdown::Write => ffi::PR_SHUTDOWN_SEND,
+        Shutdown::Both => ffi::PR_SHUTDOWN_BOTH,
+    }
+}
+
+fn shutdown_from_nspr(how: ffi::PRShutdownHow) -> Result<Shutdown> {
+    match how {
+        ffi::PR_SHUTDOWN_RCV => Ok(Shutdown::Read),
+        ffi::PR_SHUTDOWN_SEND => Ok(Shutdown::Write),
+        ffi::PR_SHUTDOWN_BOTH => Ok(Shutdown::Both),
+        _ => Err(PR_INVALID_ARGUMENT_ERROR.into()),
+    }
+}
+
+// NSPR's I/O lengths are `PRInt32`, so larger buffers are handled as
+// a series of calls.  This stops at the first short (or failed) call,
+// since waiting for more could block; an error is returned only if
+// nothing was transferred.
+const MAX_IO_LEN: usize = i32::MAX as usize;
+
+fn chunked_io<F>(len: usize, limit: usize, mut f: F) -> Result<usize>
+where
+    F: FnMut(usize, usize) -> Result<usize>,
+{
+    let mut done = 0;
+    loop {
+        let chunk = cmp::min(len - done, limit);
+        match f(done, chunk) {
+            Ok(n) => {
+                done += n;
+                if n < chunk || done == len {
+                    return Ok(done);
+                }
+            }
+            Err(err) => return if done == 0 { Err(err) } else { Ok(done) },
+        }
+    }
+}
+
 impl FileMethods for File {
     fn read(&self, buf: &mut [u8]) -> Result<usize> {
-        assert!(buf.len() <= i32::MAX as usize);
-        wrap_ffi(|| unsafe {
-            ffi::PR_Read(
-                self.as_raw_prfd(),
-                buf.as_mut_ptr() as *mut c_void,
-                buf.len() as i32,
-            )
+        chunked_io(buf.len(), MAX_IO_LEN, |offset, len| {
+            wrap_ffi(|| unsafe {
+                ffi::PR_Read(
+                    self.as_raw_prfd(),
+                    buf[offset..].as_mut_ptr() as *mut c_void,
+                    len as i32,
+                )
+            })
         })
     }
 
     fn write(&self, buf: &[u8]) -> Result<usize> {
-        assert!(buf.len() <= i32::MAX as usize);
-        wrap_ffi(|| unsafe {
-            ffi::PR_Write(
-                self.as_raw_prfd(),
-                buf.as_ptr() as *const c_void,
-                buf.len() as i32,
-            )
+        // Otherwise, on a TLS socket this would drive the handshake
+        // and could fail with errors from the read side.
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        chunked_io(buf.len(), MAX_IO_LEN, |offset, len| {
+            wrap_ffi(|| unsafe {
+                ffi::PR_Write(
+                    self.as_raw_prfd(),
+                    buf[offset..].as_ptr() as *const c_void,
+                    len as i32,
+                )
+            })
         })
     }
 
@@ -188,49 +408,63 @@ impl FileMethods for File {
     }
 
     fn recv(&self, buf: &mut [u8], peek: bool, timeout: Option<Duration>) -> Result<usize> {
-        assert!(buf.len() <= i32::MAX as usize);
         let flags = if peek { ffi::PR_MSG_PEEK } else { 0 };
-        wrap_ffi(|| unsafe {
-            ffi::PR_Recv(
-                self.as_raw_prfd(),
-                buf.as_mut_ptr() as *mut c_void,
-                buf.len() as i32,
-                flags,
-                duration_opt_to_nspr(timeout),
-            )
+        // Peeking again would just see the same data.
+        let buf_len = if peek {
+            cmp::min(buf.len(), MAX_IO_LEN)
+        } else {
+            buf.len()
+        };
+        chunked_io(buf_len, MAX_IO_LEN, |offset, len| {
+            wrap_ffi(|| unsafe {
+                ffi::PR_Recv(
+                    self.as_raw_prfd(),
+                    buf[offset..].as_mut_ptr() as *mut c_void,
+                    len as i32,
+                    flags,
+                    duration_opt_to_nspr(timeout),
+                )
+            })
         })
     }
 
     fn send(&self, buf: &[u8], timeout: Option<Duration>) -> Result<usize> {
-        assert!(buf.len() <= i32::MAX as usize);
+        // As for `write`.
+        if buf.is_empty() {
+            return Ok(0);
+        }
         let flags = 0;
-        wrap_ffi(|| unsafe {
-            ffi::PR_Send(
-                self.as_raw_prfd(),
-                buf.as_ptr() as *const c_void,
-                buf.len() as i32,
-                flags,
-                duration_opt_to_nspr(timeout),
-            )
+        chunked_io(buf.len(), MAX_IO_LEN, |offset, len| {
+            wrap_ffi(|| unsafe {
+                ffi::PR_Send(
+                    self.as_raw_prfd(),
+                    buf[offset..].as_ptr() as *const c_void,
+                    len as i32,
+                    flags,
+                    duration_opt_to_nspr(timeout),
+                )
+            })
         })
     }
 
+    fn shutdown(&self, how: Shutdown) -> Result<()> {
+        wrap_ffi(|| unsafe { ffi::PR_Shutdown(self.as_raw_prfd(), shutdown_to_nspr(how)) })
+    }
+
+    fn accept_conn(&self, timeout: Option<Duration>) -> Result<(File, SocketAddr)> {
+        self.accept(timeout)
+    }
+
     fn getsockname(&self) -> Result<SocketAddr> {
         let mut buf = NetAddrStorage::new();
         wrap_ffi(|| unsafe { ffi::PR_GetSockName(self.as_raw_prfd(), buf.as_mut_ptr()) })?;
-        match unsafe { read_net_addr(buf.as_ptr()) } {
-            Some(addr) => Ok(addr),
-            None => Err(PR_ADDRESS_NOT_SUPPORTED_ERROR.into()),
-        }
+        unsafe { read_net_addr(buf.as_ptr()) }
     }
 
     fn getpeername(&self) -> Result<SocketAddr> {
         let mut buf = NetAddrStorage::new();
         wrap_ffi(|| unsafe { ffi::PR_GetPeerName(self.as_raw_prfd(), buf.as_mut_ptr()) })?;
-        match unsafe { read_net_addr(buf.as_ptr()) } {
-            Some(addr) => Ok(addr),
-            None => Err(PR_ADDRESS_NOT_SUPPORTED_ERROR.into()),
-        }
+        unsafe { read_net_addr(buf.as_ptr()) }
     }
 
     fn get_nonblocking(&self) -> Result<bool> {
@@ -248,12 +482,32 @@ pub use nss_sys::nspr::{
 
 pub struct FileWrapper<Inner: FileMethods> {
     methods_ref: Arc<ffi::PRIOMethods>,
+    timeouts: Timeouts,
     phantom: PhantomData<fn(Inner)>,
 }
 
+// Used when the caller didn't give a timeout; see
+// `FileWrapper::timeouts`.
+#[derive(Clone, Copy, Debug, Default)]
+struct Timeouts {
+    read: Option<Duration>,
+    write: Option<Duration>,
+}
+
+// `repr(C)` so that `downcast_ref` can look at the `TypeId` through a
+// `WrappedFileHeader` without already knowing `Inner`.
+#[repr(C)]
+struct WrappedFileHeader {
+    prfd: ffi::PRFileDesc,
+    type_id: TypeId,
+}
+
+#[repr(C)]
 struct WrappedFileImpl<Inner: FileMethods> {
     prfd: ffi::PRFileDesc,
+    type_id: TypeId,
     _methods_ref: Arc<ffi::PRIOMethods>,
+    timeouts: Timeouts,
     inner: Inner,
 }
 
@@ -272,7 +526,7 @@ impl<Inner: FileMethods> DerefMut for WrappedFileImpl<Inner> {
 impl<Inner: FileMethods> FileWrapper<Inner> {
     pub fn new(file_type: FileType) -> Self {
         let methods = ffi::PRIOMethods {
-            file_type: file_type,
+            file_type,
             close: Some(wrapper_methods::close::<Inner>),
             read: Some(wrapper_methods::read::<Inner>),
             write: Some(wrapper_methods::write::<Inner>),
@@ -285,10 +539,10 @@ impl<Inner: FileMethods> FileWrapper<Inner> {
             fileInfo64: None,
             writev: None,
             connect: Some(wrapper_methods::connect::<Inner>),
-            accept: None,
+            accept: Some(wrapper_methods::accept::<Inner>),
             bind: None,
             listen: None,
-            shutdown: None,
+            shutdown: Some(wrapper_methods::shutdown::<Inner>),
             recv: Some(wrapper_methods::recv::<Inner>),
             send: Some(wrapper_methods::send::<Inner>),
             recvfrom: None,
@@ -312,10 +566,21 @@ impl<Inner: FileMethods> FileWrapper<Inner> {
 
         FileWrapper {
             methods_ref: Arc::new(methods),
+            timeouts: Timeouts::default(),
             phantom: PhantomData,
         }
     }
 
+    // Timeouts for files wrapped after this, used whenever the caller
+    // didn't give one: `read` and `write` become `recv` and `send`
+    // with these, and so do `recv` and `send` without a timeout (as a
+    // TLS layer on top does for its own `read` and `write`).  So
+    // `Inner` needs `recv` and `send` for whichever is set.
+    pub fn timeouts(mut self, read: Option<Duration>, write: Option<Duration>) -> Self {
+        self.timeouts = Timeouts { read, write };
+        self
+    }
+
     pub fn wrap(&self, inner: Inner) -> File
     // FIXME: is this all the bounds I need to be soundly boxed-trait-like?
     where
@@ -331,8 +596,10 @@ impl<Inner: FileMethods> FileWrapper<Inner> {
                 dtor: None,
                 identity: *WRAPPED_FILE_IDENT,
             },
+            type_id: TypeId::of::<Inner>(),
             _methods_ref: self.methods_ref.clone(),
-            inner: inner,
+            timeouts: self.timeouts,
+            inner,
         });
         unsafe {
             let raw = &mut boxed.prfd as RawFile;
@@ -342,11 +609,157 @@ impl<Inner: FileMethods> FileWrapper<Inner> {
     }
 }
 
+impl<Inner: FileMethods> FileWrapper<Counting<Inner>> {
+    // Wraps `inner` in a `Counting` layer, for when only one file needs
+    // it; the counts stay readable after the file is gone.
+    pub fn new_counting(file_type: FileType, inner: Inner) -> (File, Arc<ByteCounts>)
+    where
+        Inner: Send + Sync + 'static,
+    {
+        let inner = Counting::new(inner);
+        let counts = inner.counts();
+        (Self::new(file_type).wrap(inner), counts)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ByteCounts {
+    read: AtomicUsize,
+    written: AtomicUsize,
+}
+
+impl ByteCounts {
+    pub fn bytes_read(&self) -> usize {
+        self.read.load(Ordering::Relaxed)
+    }
+    pub fn bytes_written(&self) -> usize {
+        self.written.load(Ordering::Relaxed)
+    }
+}
+
+// A pass-through layer that counts the bytes successfully read
+// (`read`/`recv`) and written (`write`/`send`) through it.  The counts
+// are shared, so they can still be read after the layer is wrapped
+// (with a plain `FileWrapper`, like any other layer, or with
+// `FileWrapper::new_counting`).
+pub struct Counting<Inner: FileMethods> {
+    inner: Inner,
+    counts: Arc<ByteCounts>,
+}
+
+impl<Inner: FileMethods> Counting<Inner> {
+    pub fn new(inner: Inner) -> Self {
+        Counting {
+            inner,
+            counts: Arc::new(ByteCounts::default()),
+        }
+    }
+    pub fn counts(&self) -> Arc<ByteCounts> {
+        self.counts.clone()
+    }
+    fn count_read(&self, result: Result<usize>) -> Result<usize> {
+        if let Ok(len) = result {
+            self.counts.read.fetch_add(len, Ordering::Relaxed);
+        }
+        result
+    }
+    fn count_written(&self, result: Result<usize>) -> Result<usize> {
+        if let Ok(len) = result {
+            self.counts.written.fetch_add(len, Ordering::Relaxed);
+        }
+        result
+    }
+}
+
+impl<Inner: FileMethods> From<Inner> for Counting<Inner> {
+    fn from(inner: Inner) -> Self {
+        Self::new(inner)
+    }
+}
+
+impl<Inner: FileMethods> FileMethods for Counting<Inner> {
+    fn read(&self, buf: &mut [u8]) -> Result<usize> {
+        self.count_read(self.inner.read(buf))
+    }
+    fn write(&self, buf: &[u8]) -> Result<usize> {
+        self.count_written(self.inner.write(buf))
+    }
+    fn connect(&self, addr: SocketAddr, timeout: Option<Duration>) -> Result<()> {
+        self.inner.connect(addr, timeout)
+    }
+    fn recv(&self, buf: &mut [u8], peek: bool, timeout: Option<Duration>) -> Result<usize> {
+        let result = self.inner.recv(buf, peek, timeout);
+        if peek {
+            result
+        } else {
+            self.count_read(result)
+        }
+    }
+    fn send(&self, buf: &[u8], timeout: Option<Duration>) -> Result<usize> {
+        self.count_written(self.inner.send(buf, timeout))
+    }
+    fn shutdown(&self, how: Shutdown) -> Result<()> {
+        self.inner.shutdown(how)
+    }
+    // The connection's bytes aren't counted here.
+    fn accept_conn(&self, timeout: Option<Duration>) -> Result<(File, SocketAddr)> {
+        self.inner.accept_conn(timeout)
+    }
+    fn getsockname(&self) -> Result<SocketAddr> {
+        self.inner.getsockname()
+    }
+    fn getpeername(&self) -> Result<SocketAddr> {
+        self.inner.getpeername()
+    }
+    fn get_nonblocking(&self) -> Result<bool> {
+        self.inner.get_nonblocking()
+    }
+}
+
+// So that things like `LineReader` can borrow a file rather than
+// owning it.
+impl<F: FileMethods + ?Sized> FileMethods for &F {
+    fn read(&self, buf: &mut [u8]) -> Result<usize> {
+        (**self).read(buf)
+    }
+    fn write(&self, buf: &[u8]) -> Result<usize> {
+        (**self).write(buf)
+    }
+    fn readv(&self, bufs: &mut [&mut [u8]]) -> Result<usize> {
+        (**self).readv(bufs)
+    }
+    fn connect(&self, addr: SocketAddr, timeout: Option<Duration>) -> Result<()> {
+        (**self).connect(addr, timeout)
+    }
+    fn recv(&self, buf: &mut [u8], peek: bool, timeout: Option<Duration>) -> Result<usize> {
+        (**self).recv(buf, peek, timeout)
+    }
+    fn send(&self, buf: &[u8], timeout: Option<Duration>) -> Result<usize> {
+        (**self).send(buf, timeout)
+    }
+    fn shutdown(&self, how: Shutdown) -> Result<()> {
+        (**self).shutdown(how)
+    }
+    fn accept_conn(&self, timeout: Option<Duration>) -> Result<(File, SocketAddr)> {
+        (**self).accept_conn(timeout)
+    }
+    fn getsockname(&self) -> Result<SocketAddr> {
+        (**self).getsockname()
+    }
+    fn getpeername(&self) -> Result<SocketAddr> {
+        (**self).getpeername()
+    }
+    fn get_nonblocking(&self) -> Result<bool> {
+        (**self).get_nonblocking()
+    }
+}
+
 mod wrapper_methods {
-    use super::{BorrowedFile, FileMethods, WrappedFileImpl, WRAPPED_FILE_IDENT};
+    use super::{
+        shutdown_from_nspr, BorrowedFile, FileMethods, WrappedFileImpl, WRAPPED_FILE_IDENT,
+    };
     use libc::c_void;
     use nspr::bool_to_nspr;
-    use nspr::error::PR_ADDRESS_NOT_SUPPORTED_ERROR;
     use nspr::net::{read_net_addr, write_net_addr};
     use nspr::time::duration_opt_from_nspr;
     use nss_sys::nspr::{
@@ -354,6 +767,7 @@ mod wrapper_methods {
         PRSocketOptionData, PRStatus, PR_SockOpt_Nonblocking, PR_FAILURE, PR_MSG_PEEK, PR_SUCCESS,
     };
     use std::mem;
+    use std::ptr;
     use std::slice;
     use wrap_callback;
 
@@ -388,12 +802,15 @@ mod wrapper_methods {
         wrap_callback(-1, || {
             let this = xlate_fd::<Inner>(fd);
             assert!(amount >= 0);
-            this.get_ref()
-                .read(slice::from_raw_parts_mut(buf as *mut u8, amount as usize))
-                .map(|len| {
-                    assert!(len <= amount as usize);
-                    len as PRInt32
-                })
+            let buf = slice::from_raw_parts_mut(buf as *mut u8, amount as usize);
+            match this.get_ref().timeouts.read {
+                Some(timeout) => this.get_ref().recv(buf, false, Some(timeout)),
+                None => this.get_ref().read(buf),
+            }
+            .map(|len| {
+                assert!(len <= amount as usize);
+                len as PRInt32
+            })
         })
     }
 
@@ -405,12 +822,15 @@ mod wrapper_methods {
         wrap_callback(-1, || {
             let this = xlate_fd::<Inner>(fd);
             assert!(amount >= 0);
-            this.get_ref()
-                .write(slice::from_raw_parts(buf as *mut u8, amount as usize))
-                .map(|len| {
-                    assert!(len <= amount as usize);
-                    len as PRInt32
-                })
+            let buf = slice::from_raw_parts(buf as *mut u8, amount as usize);
+            match this.get_ref().timeouts.write {
+                Some(timeout) => this.get_ref().send(buf, Some(timeout)),
+                None => this.get_ref().write(buf),
+            }
+            .map(|len| {
+                assert!(len <= amount as usize);
+                len as PRInt32
+            })
         })
     }
 
@@ -421,13 +841,10 @@ mod wrapper_methods {
     ) -> PRStatus {
         wrap_callback(PR_FAILURE, || {
             let this = xlate_fd::<Inner>(fd);
-            if let Some(rust_addr) = read_net_addr(addr) {
-                this.get_ref()
-                    .connect(rust_addr, duration_opt_from_nspr(timeout))
-                    .map(|()| PR_SUCCESS)
-            } else {
-                Err(PR_ADDRESS_NOT_SUPPORTED_ERROR.into())
-            }
+            let rust_addr = read_net_addr(addr)?;
+            this.get_ref()
+                .connect(rust_addr, duration_opt_from_nspr(timeout))
+                .map(|()| PR_SUCCESS)
         })
     }
 
@@ -446,7 +863,7 @@ mod wrapper_methods {
                 .recv(
                     slice::from_raw_parts_mut(buf as *mut u8, amount as usize),
                     peek,
-                    duration_opt_from_nspr(timeout),
+                    duration_opt_from_nspr(timeout).or(this.get_ref().timeouts.read),
                 )
                 .map(|len| {
                     assert!(len <= amount as usize);
@@ -468,7 +885,7 @@ mod wrapper_methods {
             this.get_ref()
                 .send(
                     slice::from_raw_parts(buf as *mut u8, amount as usize),
-                    duration_opt_from_nspr(timeout),
+                    duration_opt_from_nspr(timeout).or(this.get_ref().timeouts.write),
                 )
                 .map(|len| {
                     assert!(len <= amount as usize);
@@ -477,6 +894,34 @@ mod wrapper_methods {
         })
     }
 
+    pub unsafe extern "C" fn accept<Inner: FileMethods>(
+        fd: *mut PRFileDesc,
+        addr: *mut PRNetAddr,
+        timeout: PRIntervalTime,
+    ) -> *mut PRFileDesc {
+        wrap_callback(ptr::null_mut(), || {
+            let this = xlate_fd::<Inner>(fd);
+            let (file, rust_addr) = this
+                .get_ref()
+                .accept_conn(duration_opt_from_nspr(timeout))?;
+            if !addr.is_null() {
+                write_net_addr(addr, rust_addr);
+            }
+            Ok(file.into_raw_prfd())
+        })
+    }
+
+    pub unsafe extern "C" fn shutdown<Inner: FileMethods>(
+        fd: *mut PRFileDesc,
+        how: PRIntn,
+    ) -> PRStatus {
+        wrap_callback(PR_FAILURE, || {
+            let this = xlate_fd::<Inner>(fd);
+            let how = shutdown_from_nspr(how)?;
+            this.get_ref().shutdown(how).map(|()| PR_SUCCESS)
+        })
+    }
+
     pub unsafe extern "C" fn getsockname<Inner: FileMethods>(
         fd: *mut PRFileDesc,
         addr: *mut PRNetAddr,
@@ -537,7 +982,7 @@ mod tests {
     use std::mem;
 
     fn pipe_test<F: FileMethods>(reader: F, writer: F) {
-        static TEST: &'static str = "Testing…";
+        static TEST: &str = "Testing…";
 
         assert_eq!(writer.write(TEST.as_bytes()).unwrap(), TEST.len());
         let mut read_buf = vec![0u8; TEST.len()];
@@ -562,6 +1007,252 @@ mod tests {
         pipe_test(wrapper.wrap(reader), wrapper.wrap(writer));
     }
 
+    #[test]
+    fn pipe_readv() {
+        let (reader, writer) = new_pipe().unwrap();
+        assert_eq!(writer.write(b"abcdefghij").unwrap(), 10);
+        let (mut a, mut b, mut c) = ([0; 3], [0; 4], [0; 5]);
+        let n = reader
+            .readv(&mut [&mut a, &mut [], &mut b, &mut c])
+            .unwrap();
+        assert_eq!(n, 10);
+        assert_eq!((&a, &b, &c), (b"abc", b"defg", b"hij\0\0"));
+    }
+
+    #[test]
+    fn chunking() {
+        use error::PR_WOULD_BLOCK_ERROR;
+
+        let mut calls = vec![];
+        let res = chunked_io(10, 4, |offset, len| {
+            calls.push((offset, len));
+            Ok(len)
+        });
+        assert_eq!(res.unwrap(), 10);
+        assert_eq!(calls, [(0, 4), (4, 4), (8, 2)]);
+
+        // Short transfers and EOF end it early:
+        let mut calls = vec![];
+        let res = chunked_io(10, 4, |offset, len| {
+            calls.push((offset, len));
+            Ok(if offset == 0 { len } else { 1 })
+        });
+        assert_eq!(res.unwrap(), 5);
+        assert_eq!(calls, [(0, 4), (4, 4)]);
+        assert_eq!(chunked_io(10, 4, |_, _| Ok(0)).unwrap(), 0);
+
+        // Errors are reported only if nothing was transferred:
+        let res = chunked_io(10, 4, |offset, len| {
+            if offset == 0 {
+                Ok(len)
+            } else {
+                Err(PR_WOULD_BLOCK_ERROR.into())
+            }
+        });
+        assert_eq!(res.unwrap(), 4);
+        let res = chunked_io(10, 4, |_, _| Err(PR_WOULD_BLOCK_ERROR.into()));
+        assert_eq!(res.unwrap_err().nspr_error, PR_WOULD_BLOCK_ERROR);
+
+        assert_eq!(chunked_io(0, 4, |_, len| Ok(len)).unwrap(), 0);
+    }
+
+    #[test]
+    fn wrapped_connect_error() {
+        use error::{Error, PR_CONNECT_REFUSED_ERROR};
+        use libc::ECONNREFUSED;
+
+        struct Refusing;
+        impl FileMethods for Refusing {
+            fn connect(&self, _addr: SocketAddr, _timeout: Option<Duration>) -> Result<()> {
+                Err(Error::new(PR_CONNECT_REFUSED_ERROR, ECONNREFUSED))
+            }
+        }
+
+        let wrapper = FileWrapper::new(PR_DESC_SOCKET_TCP);
+        let file = wrapper.wrap(Refusing);
+        let err = file
+            .connect("127.0.0.1:1".parse().unwrap(), None)
+            .unwrap_err();
+        assert_eq!(err.nspr_error, PR_CONNECT_REFUSED_ERROR);
+        assert_eq!(err.os_error, ECONNREFUSED);
+        assert_eq!(
+            file.shutdown(Shutdown::Both).unwrap_err().nspr_error,
+            PR_NOT_IMPLEMENTED_ERROR
+        );
+        assert_eq!(
+            file.accept(None).err().unwrap().nspr_error,
+            PR_NOT_IMPLEMENTED_ERROR
+        );
+    }
+
+    #[test]
+    fn wrapped_pipe_desc() {
+        let wrapper = FileWrapper::new(PR_DESC_PIPE);
+        let (reader, _writer) = new_pipe().unwrap();
+        assert_eq!(reader.desc_type(), PR_DESC_PIPE);
+        assert_ne!(reader.identity(), *WRAPPED_FILE_IDENT);
+        let reader = wrapper.wrap(reader);
+        assert_eq!(reader.desc_type(), PR_DESC_PIPE);
+        assert_eq!(reader.identity(), *WRAPPED_FILE_IDENT);
+    }
+
+    #[test]
+    fn wrapped_pipe_downcast() {
+        let wrapper = FileWrapper::new(PR_DESC_PIPE);
+        let (reader, writer) = new_pipe().unwrap();
+        assert!(reader.downcast_ref::<File>().is_none());
+        let reader = wrapper.wrap(reader);
+        assert!(reader.downcast_ref::<Counting<File>>().is_none());
+        let inner = reader.downcast_ref::<File>().unwrap();
+        assert_eq!(inner.desc_type(), PR_DESC_PIPE);
+        assert_ne!(inner.identity(), *WRAPPED_FILE_IDENT);
+        pipe_test(reader, writer);
+    }
+
+    #[test]
+    fn wrapped_into_inner() {
+        use std::sync::atomic::AtomicBool;
+
+        struct FakeSocket {
+            id: u32,
+            dropped: Arc<AtomicBool>,
+        }
+        impl FileMethods for FakeSocket {}
+        impl Drop for FakeSocket {
+            fn drop(&mut self) {
+                self.dropped.store(true, Ordering::SeqCst);
+            }
+        }
+
+        let dropped = Arc::new(AtomicBool::new(false));
+        let wrapper = FileWrapper::new(PR_DESC_SOCKET_TCP);
+        let file = wrapper.wrap(FakeSocket {
+            id: 17,
+            dropped: dropped.clone(),
+        });
+        let file = FileWrapper::new(PR_DESC_SOCKET_TCP).wrap(file);
+        // Only the outermost wrapper can be unwrapped.
+        let file = file.into_inner::<FakeSocket>().err().unwrap();
+        let file = file.into_inner::<File>().ok().unwrap();
+        let sock = file.into_inner::<FakeSocket>().ok().unwrap();
+        assert_eq!(sock.id, 17);
+        assert!(!dropped.load(Ordering::SeqCst));
+        drop(sock);
+        assert!(dropped.load(Ordering::SeqCst));
+
+        let (reader, _writer) = new_pipe().unwrap();
+        assert!(reader.into_inner::<File>().is_err());
+    }
+
+    #[test]
+    fn wrapped_timeouts() {
+        use std::sync::Mutex;
+
+        // Records which method was called, with what timeout.
+        type Call = (&'static str, Option<Duration>);
+        #[derive(Default)]
+        struct FakeSocket(Arc<Mutex<Vec<Call>>>);
+        impl FakeSocket {
+            fn record(&self, method: &'static str, timeout: Option<Duration>) -> Result<usize> {
+                self.0.lock().unwrap().push((method, timeout));
+                Ok(0)
+            }
+        }
+        impl FileMethods for FakeSocket {
+            fn read(&self, _buf: &mut [u8]) -> Result<usize> {
+                self.record("read", None)
+            }
+            fn write(&self, _buf: &[u8]) -> Result<usize> {
+                self.record("write", None)
+            }
+            fn recv(
+                &self,
+                _buf: &mut [u8],
+                _peek: bool,
+                timeout: Option<Duration>,
+            ) -> Result<usize> {
+                self.record("recv", timeout)
+            }
+            fn send(&self, _buf: &[u8], timeout: Option<Duration>) -> Result<usize> {
+                self.record("send", timeout)
+            }
+        }
+
+        let (read, write) = (Duration::from_secs(5), Duration::from_secs(7));
+        let explicit = Duration::from_secs(1);
+        let mut buf = [0; 1];
+        let sock = FakeSocket::default();
+        let calls = sock.0.clone();
+        let file = FileWrapper::new(PR_DESC_SOCKET_TCP).wrap(sock);
+        file.read(&mut buf).unwrap();
+        file.write(b"x").unwrap();
+        file.recv(&mut buf, false, None).unwrap();
+        assert_eq!(
+            *calls.lock().unwrap(),
+            [("read", None), ("write", None), ("recv", None)]
+        );
+
+        let sock = FakeSocket::default();
+        let calls = sock.0.clone();
+        let file = FileWrapper::new(PR_DESC_SOCKET_TCP)
+            .timeouts(Some(read), Some(write))
+            .wrap(sock);
+        file.read(&mut buf).unwrap();
+        file.write(b"x").unwrap();
+        file.recv(&mut buf, false, None).unwrap();
+        file.recv(&mut buf, false, Some(explicit)).unwrap();
+        file.send(b"x", None).unwrap();
+        assert_eq!(
+            *calls.lock().unwrap(),
+            [
+                ("recv", Some(read)),
+                ("send", Some(write)),
+                ("recv", Some(read)),
+                ("recv", Some(explicit)),
+                ("send", Some(write)),
+            ]
+        );
+    }
+
+    #[test]
+    fn writev_each_timeout() {
+        use std::sync::Mutex;
+
+        // Records the timeout each `send` got.
+        #[derive(Default)]
+        struct FakeSocket(Arc<Mutex<Vec<Option<Duration>>>>);
+        impl FileMethods for FakeSocket {
+            fn write(&self, buf: &[u8]) -> Result<usize> {
+                self.0.lock().unwrap().push(None);
+                Ok(buf.len())
+            }
+            fn send(&self, buf: &[u8], timeout: Option<Duration>) -> Result<usize> {
+                self.0.lock().unwrap().push(timeout);
+                Ok(buf.len())
+            }
+        }
+
+        let timeout = Duration::from_secs(1);
+        let sock = FakeSocket::default();
+        let calls = sock.0.clone();
+        let file = FileWrapper::new(PR_DESC_SOCKET_TCP).wrap(sock);
+        assert_eq!(file.writev(&[b"ab", b"", b"c"], None).unwrap(), 3);
+        assert_eq!(*calls.lock().unwrap(), [None, None]);
+        calls.lock().unwrap().clear();
+        assert_eq!(file.writev(&[b"ab", b"c"], Some(timeout)).unwrap(), 3);
+        let sent = calls.lock().unwrap().clone();
+        assert_eq!(sent.len(), 2);
+        assert!(sent[0].unwrap() <= timeout);
+        assert!(sent[1].unwrap() <= sent[0].unwrap());
+
+        // Pipes can't `send`, so the timeout can't apply.
+        let (reader, writer) = new_pipe().unwrap();
+        assert_eq!(writer.writev(&[b"ab", b"c"], Some(timeout)).unwrap(), 3);
+        let mut buf = [0; 3];
+        assert_eq!(reader.read(&mut buf).unwrap(), 3);
+        assert_eq!(&buf, b"abc");
+    }
+
     #[test]
     fn very_wrapped_pipe_rdwr() {
         let wrapper = FileWrapper::new(PR_DESC_PIPE);
@@ -572,4 +1263,28 @@ mod tests {
         }
         pipe_test(reader, writer);
     }
+
+    #[test]
+    fn counting_pipe() {
+        let wrapper = FileWrapper::new(PR_DESC_PIPE);
+        let (reader, writer) = new_pipe().unwrap();
+        let reader = Counting::new(reader);
+        let read_counts = reader.counts();
+        let reader = wrapper.wrap(reader);
+        let (writer, write_counts) = FileWrapper::new_counting(PR_DESC_PIPE, writer);
+
+        assert_eq!(writer.write(b"abcdef").unwrap(), 6);
+        assert_eq!(writer.write(b"gh").unwrap(), 2);
+        let mut buf = [0u8; 5];
+        assert_eq!(reader.read(&mut buf).unwrap(), 5);
+        assert_eq!(read_counts.bytes_read(), 5);
+        assert_eq!(reader.read(&mut buf).unwrap(), 3);
+        mem::drop(writer);
+        assert_eq!(reader.read(&mut buf).unwrap(), 0);
+
+        assert_eq!(read_counts.bytes_read(), 8);
+        assert_eq!(read_counts.bytes_written(), 0);
+        assert_eq!(write_counts.bytes_read(), 0);
+        assert_eq!(write_counts.bytes_written(), 8);
+    }
 }
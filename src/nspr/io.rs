@@ -0,0 +1,464 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+use nspr::error::{
+    Error, Result, PR_BUFFER_OVERFLOW_ERROR, PR_FILE_TOO_BIG_ERROR, PR_INVALID_ARGUMENT_ERROR,
+    PR_IO_ERROR, PR_NO_ACCESS_RIGHTS_ERROR,
+};
+use nspr::fd::{File, FileMethods};
+use nss_sys::nspr as ffi;
+use std::borrow::Cow;
+#[cfg(unix)]
+use std::convert::TryFrom;
+#[cfg(unix)]
+use std::fs;
+#[cfg(unix)]
+use std::io;
+use std::marker::PhantomData;
+use std::mem;
+#[cfg(unix)]
+use std::os::unix::io::IntoRawFd;
+use std::ptr;
+use std::slice;
+use std::time::Duration;
+use {wrap_ffi, GenStatus};
+
+#[cfg(unix)]
+impl TryFrom<fs::File> for File {
+    type Error = io::Error;
+    fn try_from(file: fs::File) -> io::Result<Self> {
+        super::init();
+        Ok(wrap_ffi(|| unsafe {
+            File::from_raw_prfd_err(ffi::PR_ImportFile(file.into_raw_fd()))
+        })?)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MapAccess {
+    ReadOnly,
+    // Writes go to the file (and other mappings of it).
+    ReadWrite,
+}
+
+// A memory mapping of an entire file, which must not change size
+// while it's mapped.
+pub struct FileMap<'f> {
+    fmap: *mut ffi::PRFileMap,
+    addr: *mut u8,
+    len: usize,
+    access: MapAccess,
+    _file: PhantomData<&'f File>,
+}
+
+impl<'f> FileMap<'f> {
+    pub fn new(file: &'f File, access: MapAccess) -> Result<Self> {
+        let info = unsafe {
+            let mut info = mem::MaybeUninit::uninit();
+            wrap_ffi(|| ffi::PR_GetOpenFileInfo64(file.as_raw_prfd(), info.as_mut_ptr()))?;
+            info.assume_init()
+        };
+        // PR_MemMap's length is 32-bit.
+        if info.size as u64 > u64::from(u32::MAX) {
+            return Err(PR_FILE_TOO_BIG_ERROR.into());
+        }
+        let len = info.size as usize;
+        let prot = match access {
+            MapAccess::ReadOnly => ffi::PR_PROT_READONLY,
+            MapAccess::ReadWrite => ffi::PR_PROT_READWRITE,
+        };
+        let mut map = FileMap {
+            fmap: wrap_ffi(|| unsafe {
+                let fmap = ffi::PR_CreateFileMap(file.as_raw_prfd(), info.size, prot);
+                if fmap.is_null() {
+                    GenStatus::ErrorFromC
+                } else {
+                    GenStatus::Success(fmap)
+                }
+            })?,
+            addr: ptr::null_mut(),
+            len,
+            access,
+            _file: PhantomData,
+        };
+        // Zero-length mappings aren't allowed.
+        if len > 0 {
+            map.addr = wrap_ffi(|| unsafe {
+                let addr = ffi::PR_MemMap(map.fmap, 0, len as u32);
+                if addr.is_null() {
+                    GenStatus::ErrorFromC
+                } else {
+                    GenStatus::Success(addr as *mut u8)
+                }
+            })?;
+        }
+        Ok(map)
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        if self.addr.is_null() {
+            &[]
+        } else {
+            unsafe { slice::from_raw_parts(self.addr, self.len) }
+        }
+    }
+
+    pub fn as_mut_slice(&mut self) -> Result<&mut [u8]> {
+        if self.access != MapAccess::ReadWrite {
+            return Err(Error::from(PR_NO_ACCESS_RIGHTS_ERROR));
+        }
+        Ok(if self.addr.is_null() {
+            &mut []
+        } else {
+            unsafe { slice::from_raw_parts_mut(self.addr, self.len) }
+        })
+    }
+}
+
+impl<'f> Drop for FileMap<'f> {
+    fn drop(&mut self) {
+        let addr = mem::replace(&mut self.addr, ptr::null_mut());
+        if !addr.is_null() {
+            let _ = wrap_ffi(|| unsafe { ffi::PR_MemUnmap(addr as *mut _, self.len as u32) });
+        }
+        let fmap = mem::replace(&mut self.fmap, ptr::null_mut());
+        if !fmap.is_null() {
+            let _ = wrap_ffi(|| unsafe { ffi::PR_CloseFileMap(fmap) });
+        }
+    }
+}
+
+// Reads `\n`-terminated lines, for text protocols like SMTP; a `\r`
+// before the `\n` is dropped too.  Lines must be UTF-8.  For a TLS
+// socket, borrow its file: `LineReader::new(&**sock)`.  Writes can go
+// to `get_ref()`, but anything read past the last line returned is
+// only in the buffer here.
+pub struct LineReader<F: FileMethods> {
+    inner: F,
+    buf: Vec<u8>,
+    max_line: usize,
+}
+
+const LINE_READ_SIZE: usize = 4096;
+pub const DEFAULT_MAX_LINE: usize = 64 * 1024;
+
+impl<F: FileMethods> LineReader<F> {
+    pub fn new(inner: F) -> Self {
+        Self::with_max_line(inner, DEFAULT_MAX_LINE)
+    }
+
+    // A line longer than `max_line` bytes (not counting the `\n`) fails
+    // with `PR_BUFFER_OVERFLOW_ERROR` instead of being buffered without
+    // limit; there's no telling where the next line starts after that,
+    // so the reader is best dropped.
+    pub fn with_max_line(inner: F, max_line: usize) -> Self {
+        LineReader {
+            inner,
+            buf: Vec::new(),
+            max_line,
+        }
+    }
+
+    pub fn get_ref(&self) -> &F {
+        &self.inner
+    }
+
+    // `None` at EOF; a last line with no newline is still returned.
+    pub fn read_line(&mut self) -> Result<Option<String>> {
+        let mut searched = 0;
+        loop {
+            if let Some(pos) = self.buf[searched..].iter().position(|&b| b == b'\n') {
+                if searched + pos > self.max_line {
+                    return Err(PR_BUFFER_OVERFLOW_ERROR.into());
+                }
+                let mut line: Vec<u8> = self.buf.drain(..searched + pos + 1).collect();
+                line.pop();
+                if line.last() == Some(&b'\r') {
+                    line.pop();
+                }
+                return line_to_string(line).map(Some);
+            }
+            searched = self.buf.len();
+            if searched > self.max_line {
+                return Err(PR_BUFFER_OVERFLOW_ERROR.into());
+            }
+            self.buf.resize(searched + LINE_READ_SIZE, 0);
+            let read = match self.inner.read(&mut self.buf[searched..]) {
+                Ok(read) => read,
+                Err(err) => {
+                    self.buf.truncate(searched);
+                    return Err(err);
+                }
+            };
+            self.buf.truncate(searched + read);
+            if read == 0 {
+                return if self.buf.is_empty() {
+                    Ok(None)
+                } else if self.buf.len() > self.max_line {
+                    Err(PR_BUFFER_OVERFLOW_ERROR.into())
+                } else {
+                    line_to_string(mem::take(&mut self.buf)).map(Some)
+                };
+            }
+        }
+    }
+}
+
+fn line_to_string(line: Vec<u8>) -> Result<String> {
+    String::from_utf8(line).map_err(|_| PR_INVALID_ARGUMENT_ERROR.into())
+}
+
+// Byte strings, owned or borrowed, to be written together with
+// `File::writev`, like an HTTP response's status line, headers, and
+// body.  Nothing is copied into one buffer.
+#[derive(Debug, Default)]
+pub struct IoSlices<'a> {
+    segments: Vec<Cow<'a, [u8]>>,
+}
+
+impl<'a> IoSlices<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, bytes: &'a [u8]) -> &mut Self {
+        self.segments.push(Cow::Borrowed(bytes));
+        self
+    }
+
+    pub fn push_owned(&mut self, bytes: Vec<u8>) -> &mut Self {
+        self.segments.push(Cow::Owned(bytes));
+        self
+    }
+
+    // "name: value\r\n"
+    pub fn header(&mut self, name: &str, value: &str) -> &mut Self {
+        self.push_owned(format!("{}: {}\r\n", name, value).into_bytes())
+    }
+
+    // The blank line that ends the headers, then `body`.
+    pub fn body(&mut self, body: &'a [u8]) -> &mut Self {
+        self.push(b"\r\n").push(body)
+    }
+
+    pub fn len(&self) -> usize {
+        self.segments.iter().map(|seg| seg.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Writes everything, with a `writev` per `PR_MAX_IOVECTOR_SIZE`
+    // segments or so (more if a write is short).  `timeout` is per
+    // call.  On error, how much was written isn't known.
+    pub fn write_all_to(&self, file: &File, timeout: Option<Duration>) -> Result<()> {
+        let mut segments = self.segments.iter().map(|seg| &seg[..]);
+        let mut pending: Vec<&[u8]> = Vec::with_capacity(ffi::PR_MAX_IOVECTOR_SIZE);
+        loop {
+            while pending.len() < ffi::PR_MAX_IOVECTOR_SIZE {
+                match segments.next() {
+                    Some([]) => (),
+                    Some(seg) => pending.push(seg),
+                    None => break,
+                }
+            }
+            if pending.is_empty() {
+                return Ok(());
+            }
+            let mut written = file.writev(&pending, timeout)?;
+            if written == 0 {
+                return Err(PR_IO_ERROR.into());
+            }
+            // Drop what was written, keeping the rest of a partial one.
+            let done = pending
+                .iter()
+                .take_while(|seg| {
+                    let whole = seg.len() <= written;
+                    if whole {
+                        written -= seg.len();
+                    }
+                    whole
+                })
+                .count();
+            pending.drain(..done);
+            if written > 0 {
+                pending[0] = &pending[0][written..];
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs::OpenOptions;
+    use std::io::{Read, Seek, SeekFrom, Write};
+    use std::process;
+
+    fn temp_file(name: &str, contents: &[u8]) -> (fs::File, ::std::path::PathBuf) {
+        let path = env::temp_dir().join(format!("nss-rs-{}-{}", name, process::id()));
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)
+            .unwrap();
+        file.write_all(contents).unwrap();
+        (file, path)
+    }
+
+    #[test]
+    fn io_slices() {
+        use nspr::fd::new_pipe;
+        use nspr::net::socket_pair;
+
+        let body = b"hello, world";
+        let len = body.len().to_string();
+        let mut response = IoSlices::new();
+        response
+            .push(b"HTTP/1.1 200 OK\r\n")
+            .header("Content-Type", "text/plain")
+            .header("Content-Length", &len);
+        // More than fit in one writev.
+        for i in 0..20 {
+            response.header(&format!("X-Pad-{}", i), "");
+        }
+        response.body(body);
+        let mut expected = b"HTTP/1.1 200 OK\r\n\
+            Content-Type: text/plain\r\n\
+            Content-Length: 12\r\n"
+            .to_vec();
+        for i in 0..20 {
+            expected.extend(format!("X-Pad-{}: \r\n", i).bytes());
+        }
+        expected.extend(b"\r\nhello, world");
+        assert_eq!(response.len(), expected.len());
+
+        let read_all = |reader: File| {
+            let mut buf = vec![0; expected.len() + 1];
+            let mut read = 0;
+            loop {
+                match reader.read(&mut buf[read..]).unwrap() {
+                    0 => break,
+                    n => read += n,
+                }
+            }
+            buf.truncate(read);
+            buf
+        };
+        // Pipes don't have writev, but sockets do.
+        let (reader, writer) = new_pipe().unwrap();
+        response.write_all_to(&writer, None).unwrap();
+        drop(writer);
+        assert_eq!(read_all(reader), expected);
+        let (writer, reader) = socket_pair().unwrap();
+        response.write_all_to(&writer, None).unwrap();
+        drop(writer);
+        assert_eq!(read_all(reader), expected);
+
+        let (_reader, writer) = new_pipe().unwrap();
+        let bufs = [&b"x"[..]; ffi::PR_MAX_IOVECTOR_SIZE + 1];
+        assert_eq!(
+            writer.writev(&bufs, None).unwrap_err().nspr_error,
+            PR_BUFFER_OVERFLOW_ERROR
+        );
+    }
+
+    #[test]
+    fn line_reader() {
+        use nspr::fd::new_pipe;
+
+        let (reader, writer) = new_pipe().unwrap();
+        let mut lines = LineReader::new(&reader);
+        writer.write(b"one\r\ntw").unwrap();
+        assert_eq!(lines.read_line().unwrap().unwrap(), "one");
+        writer.write(b"o\n\nthr").unwrap();
+        assert_eq!(lines.read_line().unwrap().unwrap(), "two");
+        assert_eq!(lines.read_line().unwrap().unwrap(), "");
+        writer.write(b"ee").unwrap();
+        drop(writer);
+        assert_eq!(lines.read_line().unwrap().unwrap(), "three");
+        assert_eq!(lines.read_line().unwrap(), None);
+
+        let (reader, writer) = new_pipe().unwrap();
+        writer.write(b"\xff\nok\n").unwrap();
+        let mut lines = LineReader::new(reader);
+        assert_eq!(
+            lines.read_line().unwrap_err().nspr_error,
+            PR_INVALID_ARGUMENT_ERROR
+        );
+        assert_eq!(lines.read_line().unwrap().unwrap(), "ok");
+
+        let (reader, writer) = new_pipe().unwrap();
+        writer.write(b"12345\n123456\n").unwrap();
+        let mut lines = LineReader::with_max_line(reader, 5);
+        assert_eq!(lines.read_line().unwrap().unwrap(), "12345");
+        assert_eq!(
+            lines.read_line().unwrap_err().nspr_error,
+            PR_BUFFER_OVERFLOW_ERROR
+        );
+        let (reader, writer) = new_pipe().unwrap();
+        writer.write(b"123456").unwrap();
+        drop(writer);
+        let mut lines = LineReader::with_max_line(reader, 5);
+        assert_eq!(
+            lines.read_line().unwrap_err().nspr_error,
+            PR_BUFFER_OVERFLOW_ERROR
+        );
+
+        // Without a newline in sight, it stops reading.
+        let (reader, writer) = new_pipe().unwrap();
+        writer.write(&[b'x'; 3 * LINE_READ_SIZE]).unwrap();
+        let mut lines = LineReader::with_max_line(reader, LINE_READ_SIZE);
+        assert_eq!(
+            lines.read_line().unwrap_err().nspr_error,
+            PR_BUFFER_OVERFLOW_ERROR
+        );
+        assert!(lines.buf.len() <= 2 * LINE_READ_SIZE);
+    }
+
+    #[test]
+    fn map_read_only() {
+        let (file, path) = temp_file("map-ro", b"mapped contents");
+        let file = File::try_from(file).unwrap();
+        {
+            let mut map = FileMap::new(&file, MapAccess::ReadOnly).unwrap();
+            assert_eq!(map.as_slice(), b"mapped contents");
+            assert_eq!(
+                map.as_mut_slice().unwrap_err().nspr_error,
+                PR_NO_ACCESS_RIGHTS_ERROR
+            );
+        }
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn map_read_write() {
+        let (mut std_file, path) = temp_file("map-rw", b"hello");
+        let file = File::try_from(std_file.try_clone().unwrap()).unwrap();
+        {
+            let mut map = FileMap::new(&file, MapAccess::ReadWrite).unwrap();
+            map.as_mut_slice().unwrap().copy_from_slice(b"jello");
+        }
+        let mut contents = vec![];
+        std_file.seek(SeekFrom::Start(0)).unwrap();
+        std_file.read_to_end(&mut contents).unwrap();
+        assert_eq!(contents, b"jello");
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn map_empty() {
+        let (file, path) = temp_file("map-empty", b"");
+        let file = File::try_from(file).unwrap();
+        assert_eq!(
+            FileMap::new(&file, MapAccess::ReadOnly).unwrap().as_slice(),
+            b""
+        );
+        fs::remove_file(path).unwrap();
+    }
+}
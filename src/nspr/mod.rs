@@ -4,11 +4,14 @@
 
 pub mod error;
 pub mod fd;
+pub mod io;
 pub mod net;
+pub mod pool;
 pub mod time;
 
 use nss_sys::nspr as ffi;
 
+use std::ffi::CStr;
 use std::marker::PhantomData;
 use std::sync::Once;
 
@@ -50,6 +53,14 @@ impl From<i32> for GenStatus<usize> {
     }
 }
 
+// The linked NSPR's version, like "4.35"; there may or may not be a
+// patch number.
+pub fn version() -> &'static str {
+    unsafe { CStr::from_ptr(ffi::PR_GetVersion()) }
+        .to_str()
+        .unwrap()
+}
+
 pub fn bool_from_nspr(b: ffi::PRBool) -> bool {
     match b {
         ffi::PR_FALSE => false,
@@ -69,6 +80,10 @@ pub fn bool_to_nspr(b: bool) -> ffi::PRBool {
 pub type ListNode = *mut ffi::PRCList;
 
 pub trait Listable {
+    /// # Safety
+    ///
+    /// `node` must be the list link embedded in a live `Self`, as NSS
+    /// lays it out.
     unsafe fn from_list_node(node: ListNode) -> Self;
 }
 
@@ -80,6 +95,10 @@ pub struct ListIterator<'l, L: Listable + 'l> {
 }
 
 impl<'l, L: Listable + 'l> ListIterator<'l, L> {
+    /// # Safety
+    ///
+    /// `list` must be the head of a well-formed circular list of `L`s,
+    /// which outlives the iterator.
     pub unsafe fn new(list: ListNode) -> Self {
         ListIterator {
             next: (*list).next,
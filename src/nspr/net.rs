@@ -2,20 +2,46 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
 
-use libc::{c_int, AF_INET, AF_INET6};
-use nspr::error::Result;
-use nspr::fd::File;
+use libc::{c_char, c_int, AF_INET, AF_INET6, AF_UNSPEC};
+use nspr::error::{
+    Error, Result, PR_ADDRESS_NOT_SUPPORTED_ERROR, PR_BUFFER_OVERFLOW_ERROR,
+    PR_DIRECTORY_LOOKUP_ERROR, PR_INSUFFICIENT_RESOURCES_ERROR, PR_INVALID_ARGUMENT_ERROR,
+    PR_IO_TIMEOUT_ERROR,
+};
+use nspr::fd::{File, FileMethods, FileWrapper, PR_DESC_SOCKET_TCP};
+use nspr::time::{duration_from_nspr, duration_opt_to_nspr, duration_to_nspr};
+use nspr::{bool_from_nspr, bool_to_nspr};
 use nss_sys::nspr as ffi;
+#[cfg(unix)]
+use std::convert::TryFrom;
+use std::ffi::CString;
+use std::io;
 use std::mem;
-use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6};
-use std::u16;
-use wrap_ffi;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6, TcpStream};
+#[cfg(unix)]
+use std::os::unix::io::{FromRawFd, IntoRawFd};
+use std::ptr;
+#[cfg(unix)]
+use std::result;
+use std::time::{Duration, Instant};
+use {wrap_ffi, GenStatus};
 
 // FIXME is this going to be a "strict aliasing" problem?
-pub struct NetAddrStorage(ffi::PRNetAddrInet6);
+//
+// This has to be as large as a `PRNetAddr` of any family, not just the
+// ones `read_net_addr` understands: NSPR will write, e.g., an AF_LOCAL
+// peer address into it.
+#[allow(dead_code)] // Only accessed through pointer casts.
+pub struct NetAddrStorage(ffi::PRNetAddrStorage);
+impl Default for NetAddrStorage {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 impl NetAddrStorage {
     pub fn new() -> Self {
-        unsafe { mem::MaybeUninit::uninit().assume_init_read() }
+        // All-zero is a valid (if meaningless) value for the C structs.
+        unsafe { mem::zeroed() }
     }
     pub fn as_ptr(&self) -> *const ffi::PRNetAddr {
         self as *const NetAddrStorage as *const _
@@ -25,43 +51,53 @@ impl NetAddrStorage {
     }
 }
 
-pub unsafe fn read_net_addr(ptr: *const ffi::PRNetAddr) -> Option<SocketAddr> {
+// IPv4-mapped IPv6 addresses stay IPv6, as they are in NSPR, and
+// link-local scope IDs are preserved in both directions.
+/// # Safety
+///
+/// `ptr` must point to an initialized address, at least as large as its
+/// family needs.
+pub unsafe fn read_net_addr(ptr: *const ffi::PRNetAddr) -> Result<SocketAddr> {
     // This is kind of ridiculous given that they're almost the same structure internally....
     let family = (*(ptr as *const ffi::PRNetAddrRaw)).family;
     if family == AF_INET as u16 {
         let ptr = ptr as *const ffi::PRNetAddrInet;
         let port = u16::from_be((*ptr).port);
-        let ip: [u8; 4] = mem::transmute((*ptr).ip);
-        Some(SocketAddr::V4(SocketAddrV4::new(
+        let ip: [u8; 4] = (*ptr).ip.to_ne_bytes();
+        Ok(SocketAddr::V4(SocketAddrV4::new(
             Ipv4Addr::new(ip[0], ip[1], ip[2], ip[3]),
             port,
         )))
     } else if family == AF_INET6 as u16 {
         let ptr = ptr as *const ffi::PRNetAddrInet6;
         let port = u16::from_be((*ptr).port);
-        let mut ip: [u16; 8] = mem::transmute((*ptr).ip);
+        let mut ip: [u16; 8] = mem::transmute::<ffi::PRIPv6Addr, [u16; 8]>((*ptr).ip);
         for seg in ip.iter_mut() {
             *seg = u16::from_be(*seg)
         }
-        Some(SocketAddr::V6(SocketAddrV6::new(
+        Ok(SocketAddr::V6(SocketAddrV6::new(
             Ipv6Addr::new(ip[0], ip[1], ip[2], ip[3], ip[4], ip[5], ip[6], ip[7]),
             port,
             (*ptr).flowinfo,
             (*ptr).scope_id,
         )))
     } else {
-        None
+        Err(PR_ADDRESS_NOT_SUPPORTED_ERROR.into())
     }
 }
 
+/// # Safety
+///
+/// `ptr` must point to writable storage large enough for `addr`, such
+/// as a `NetAddrStorage`.
 pub unsafe fn write_net_addr(ptr: *mut ffi::PRNetAddr, addr: SocketAddr) {
     match addr {
         SocketAddr::V4(addr) => {
             *(ptr as *mut _) = ffi::PRNetAddrInet {
                 family: AF_INET as u16,
                 port: u16::to_be(addr.port()),
-                ip: mem::transmute(addr.ip().octets()),
-                pad: mem::MaybeUninit::uninit().assume_init_read(),
+                ip: u32::from_ne_bytes(addr.ip().octets()),
+                pad: [0; 8],
             }
         }
         SocketAddr::V6(addr) => {
@@ -73,13 +109,153 @@ pub unsafe fn write_net_addr(ptr: *mut ffi::PRNetAddr, addr: SocketAddr) {
                 family: AF_INET6 as u16,
                 port: u16::to_be(addr.port()),
                 flowinfo: addr.flowinfo(),
-                ip: mem::transmute(ip),
+                ip: mem::transmute::<[u16; 8], ffi::PRIPv6Addr>(ip),
                 scope_id: addr.scope_id(),
             }
         }
     }
 }
 
+fn ipv4_net_addr(ip: Ipv4Addr) -> ffi::PRNetAddrStorage {
+    let mut addr = ffi::PRNetAddrStorage([0; 14]);
+    unsafe {
+        write_net_addr(
+            &mut addr as *mut _ as *mut ffi::PRNetAddr,
+            SocketAddr::V4(SocketAddrV4::new(ip, 0)),
+        )
+    };
+    addr
+}
+
+// Resolves `name` the way NSPR's PR_GetHostByName does (i.e., with
+// gethostbyname and its hosts file / resolver configuration), rather
+// than with getaddrinfo.
+pub fn get_host_by_name(name: &str) -> Result<Vec<IpAddr>> {
+    super::init();
+    let name = CString::new(name).map_err(|_| Error::from(PR_INVALID_ARGUMENT_ERROR))?;
+    let mut buf = vec![0u8; ffi::PR_NETDB_BUF_SIZE];
+    let mut hostent: ffi::PRHostEnt = unsafe { mem::zeroed() };
+    // The results (names, addresses) are stored in `buf`; if it's too
+    // small, try again with a bigger one.
+    loop {
+        let res = wrap_ffi(|| unsafe {
+            ffi::PR_GetHostByName(
+                name.as_ptr(),
+                buf.as_mut_ptr() as *mut c_char,
+                buf.len() as c_int,
+                &mut hostent,
+            )
+        });
+        match res {
+            Err(ref err)
+                if (err.nspr_error == PR_INSUFFICIENT_RESOURCES_ERROR
+                    || err.nspr_error == PR_BUFFER_OVERFLOW_ERROR)
+                    && buf.len() < MAX_NETDB_BUF_SIZE =>
+            {
+                let len = buf.len() * 2;
+                buf.resize(len, 0);
+            }
+            res => break res?,
+        }
+    }
+
+    let mut addrs = Vec::new();
+    let mut index = 0;
+    loop {
+        let mut addr = NetAddrStorage::new();
+        index = unsafe { ffi::PR_EnumerateHostEnt(index, &hostent, 0, addr.as_mut_ptr()) };
+        match index {
+            0 => return Ok(addrs),
+            -1 => return Err(Error::last()),
+            _ => addrs.push(unsafe { read_net_addr(addr.as_ptr()) }?.ip()),
+        }
+    }
+}
+
+const MAX_NETDB_BUF_SIZE: usize = 1 << 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AddrFamily {
+    Any,
+    V4,
+    V6,
+}
+
+// Resolves `name` with getaddrinfo, in the order it gives.  NSPR can
+// only ask for IPv4 or any family, so IPv6 is filtered from the
+// latter.
+pub fn get_addr_info_by_name(name: &str, family: AddrFamily) -> Result<Vec<IpAddr>> {
+    super::init();
+    let name = CString::new(name).map_err(|_| Error::from(PR_INVALID_ARGUMENT_ERROR))?;
+    let af = match family {
+        AddrFamily::V4 => AF_INET,
+        AddrFamily::Any | AddrFamily::V6 => AF_UNSPEC,
+    };
+    let info = wrap_ffi(|| unsafe {
+        let info = ffi::PR_GetAddrInfoByName(
+            name.as_ptr(),
+            af as u16,
+            ffi::PR_AI_ADDRCONFIG | ffi::PR_AI_NOCANONNAME,
+        );
+        if info.is_null() {
+            GenStatus::ErrorFromC
+        } else {
+            GenStatus::Success(info)
+        }
+    })?;
+
+    let mut addrs = Vec::new();
+    let mut iter = ptr::null_mut();
+    let result = loop {
+        let mut addr = NetAddrStorage::new();
+        iter = unsafe { ffi::PR_EnumerateAddrInfo(iter, info, 0, addr.as_mut_ptr()) };
+        if iter.is_null() {
+            break Ok(addrs);
+        }
+        let ip = match unsafe { read_net_addr(addr.as_ptr()) } {
+            Ok(addr) => addr.ip(),
+            Err(err) => break Err(err),
+        };
+        if (family != AddrFamily::V6 || ip.is_ipv6()) && !addrs.contains(&ip) {
+            addrs.push(ip);
+        }
+    };
+    unsafe { ffi::PR_FreeAddrInfo(info) };
+    result
+}
+
+// Resolves `hostname` and tries each address in turn until a TCP
+// connection succeeds; if none does, the last error is returned.
+// `timeout` covers all the attempts together, but not the lookup.
+pub fn connect_hostname(
+    hostname: &str,
+    port: u16,
+    family: AddrFamily,
+    timeout: Option<Duration>,
+) -> Result<File> {
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+    let mut last_err = Error::from(PR_DIRECTORY_LOOKUP_ERROR);
+    for ip in get_addr_info_by_name(hostname, family)? {
+        let remaining = match deadline {
+            None => None,
+            Some(deadline) => match deadline.checked_duration_since(Instant::now()) {
+                Some(remaining) if remaining > Duration::ZERO => Some(remaining),
+                _ => return Err(PR_IO_TIMEOUT_ERROR.into()),
+            },
+        };
+        let af = if ip.is_ipv4() { AF_INET } else { AF_INET6 };
+        let attempt = new_tcp_socket(af).and_then(|sock| {
+            sock.connect(SocketAddr::new(ip, port), remaining)
+                .map(|()| sock)
+        });
+        match attempt {
+            Ok(sock) => return Ok(sock),
+            Err(err) => last_err = err,
+        }
+    }
+    Err(last_err)
+}
+
 pub fn new_tcp_socket(af: c_int) -> Result<File> {
     super::init();
     wrap_ffi(|| unsafe { File::from_raw_prfd_err(ffi::PR_OpenTCPSocket(af)) })
@@ -90,10 +266,229 @@ pub fn new_udp_socket(af: c_int) -> Result<File> {
     wrap_ffi(|| unsafe { File::from_raw_prfd_err(ffi::PR_OpenUDPSocket(af)) })
 }
 
+// Two connected TCP sockets on loopback, the connecting end first,
+// made with a listener that's closed before returning.  Unlike a pipe,
+// each end is a real socket that reads and writes.
+pub fn socket_pair() -> Result<(File, File)> {
+    let listener = new_tcp_socket(AF_INET)?;
+    listener.bind(SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 0)))?;
+    listener.listen(1)?;
+    let client = new_tcp_socket(AF_INET)?;
+    client.connect(listener.getsockname()?, None)?;
+    let (server, _) = listener.accept(None)?;
+    Ok((client, server))
+}
+
+// The `File` takes ownership of the OS socket and will close it.
+/// # Safety
+///
+/// `osfd` must be an open TCP socket that nothing else will close.
+pub unsafe fn import_tcp_socket(osfd: ffi::PROsfd) -> Result<File> {
+    super::init();
+    wrap_ffi(|| File::from_raw_prfd_err(ffi::PR_ImportTCPSocket(osfd)))
+}
+
+// As `import_tcp_socket`, but the caller keeps ownership of the OS
+// socket: the `File` gets its own duplicate of `osfd`, so closing it,
+// or a layer over it like TLS, leaves `osfd` open.  The two still
+// share socket state, such as the non-blocking flag NSPR sets.
+/// # Safety
+///
+/// `osfd` must be an open TCP socket.
+#[cfg(unix)]
+pub unsafe fn import_tcp_socket_borrowed(osfd: ffi::PROsfd) -> Result<File> {
+    let dup = libc::dup(osfd);
+    if dup < 0 {
+        return Err(Error::from(io::Error::last_os_error()));
+    }
+    import_tcp_socket(dup).map_err(|err| {
+        libc::close(dup);
+        err
+    })
+}
+
+#[cfg(unix)]
+impl TryFrom<TcpStream> for File {
+    type Error = io::Error;
+    fn try_from(stream: TcpStream) -> io::Result<Self> {
+        let fd = stream.into_raw_fd();
+        let file = unsafe {
+            import_tcp_socket(fd).map_err(|err| {
+                libc::close(fd);
+                err
+            })?
+        };
+        Ok(file)
+    }
+}
+
+impl File {
+    pub fn bind(&self, addr: SocketAddr) -> Result<()> {
+        let mut addrbuf = NetAddrStorage::new();
+        wrap_ffi(|| unsafe {
+            write_net_addr(addrbuf.as_mut_ptr(), addr);
+            ffi::PR_Bind(self.as_raw_prfd(), addrbuf.as_ptr())
+        })
+    }
+
+    pub fn listen(&self, backlog: i32) -> Result<()> {
+        wrap_ffi(|| unsafe { ffi::PR_Listen(self.as_raw_prfd(), backlog) })
+    }
+
+    // SO_LINGER: `None` is off.  The OS keeps whole seconds, so the
+    // rest of `linger` is dropped.
+    pub fn set_linger(&self, linger: Option<Duration>) -> Result<()> {
+        let value = ffi::PRLinger {
+            polarity: bool_to_nspr(linger.is_some()),
+            linger: linger.map_or(0, duration_to_nspr),
+        };
+        let buf = ffi::PRSocketOptionCase::new(ffi::PR_SockOpt_Linger, value);
+        wrap_ffi(|| unsafe { ffi::PR_SetSocketOption(self.as_raw_prfd(), buf.as_ptr()) })
+    }
+
+    pub fn linger(&self) -> Result<Option<Duration>> {
+        let value = ffi::PRLinger {
+            polarity: ffi::PR_FALSE,
+            linger: 0,
+        };
+        let mut buf = ffi::PRSocketOptionCase::new(ffi::PR_SockOpt_Linger, value);
+        wrap_ffi(|| unsafe { ffi::PR_GetSocketOption(self.as_raw_prfd(), buf.as_mut_ptr()) })?;
+        Ok(if bool_from_nspr(buf.value.polarity) {
+            Some(duration_from_nspr(buf.value.linger))
+        } else {
+            None
+        })
+    }
+
+    // The other half of `FileMethods::get_nonblocking`.
+    pub fn set_nonblocking(&self, on: bool) -> Result<()> {
+        let buf = ffi::PRSocketOptionCase::new(ffi::PR_SockOpt_Nonblocking, bool_to_nspr(on));
+        wrap_ffi(|| unsafe { ffi::PR_SetSocketOption(self.as_raw_prfd(), buf.as_ptr()) })
+    }
+
+    // IPv4 multicast, for UDP sockets; NSPR has no IPv6 equivalents.
+    // An unspecified `interface` lets the OS choose.
+    pub fn join_multicast_v4(&self, group: Ipv4Addr, interface: Ipv4Addr) -> Result<()> {
+        self.set_mcast_request(ffi::PR_SockOpt_AddMember, group, interface)
+    }
+
+    pub fn leave_multicast_v4(&self, group: Ipv4Addr, interface: Ipv4Addr) -> Result<()> {
+        self.set_mcast_request(ffi::PR_SockOpt_DropMember, group, interface)
+    }
+
+    fn set_mcast_request(
+        &self,
+        option: ffi::PRSockOption,
+        group: Ipv4Addr,
+        interface: Ipv4Addr,
+    ) -> Result<()> {
+        let value = ffi::PRMcastRequest {
+            mcaddr: ipv4_net_addr(group),
+            ifaddr: ipv4_net_addr(interface),
+        };
+        let buf = ffi::PRSocketOptionCase::new(option, value);
+        wrap_ffi(|| unsafe { ffi::PR_SetSocketOption(self.as_raw_prfd(), buf.as_ptr()) })
+    }
+
+    // The interface multicast is sent from.
+    pub fn set_multicast_if_v4(&self, interface: Ipv4Addr) -> Result<()> {
+        let buf =
+            ffi::PRSocketOptionCase::new(ffi::PR_SockOpt_McastInterface, ipv4_net_addr(interface));
+        wrap_ffi(|| unsafe { ffi::PR_SetSocketOption(self.as_raw_prfd(), buf.as_ptr()) })
+    }
+
+    pub fn multicast_if_v4(&self) -> Result<Ipv4Addr> {
+        let mut buf = ffi::PRSocketOptionCase::new(
+            ffi::PR_SockOpt_McastInterface,
+            ipv4_net_addr(Ipv4Addr::UNSPECIFIED),
+        );
+        wrap_ffi(|| unsafe { ffi::PR_GetSocketOption(self.as_raw_prfd(), buf.as_mut_ptr()) })?;
+        match unsafe { read_net_addr(&buf.value as *const _ as *const ffi::PRNetAddr) }? {
+            SocketAddr::V4(addr) => Ok(*addr.ip()),
+            SocketAddr::V6(_) => Err(PR_ADDRESS_NOT_SUPPORTED_ERROR.into()),
+        }
+    }
+
+    pub fn set_multicast_ttl_v4(&self, ttl: u32) -> Result<()> {
+        let buf = ffi::PRSocketOptionCase::new(ffi::PR_SockOpt_McastTimeToLive, ttl);
+        wrap_ffi(|| unsafe { ffi::PR_SetSocketOption(self.as_raw_prfd(), buf.as_ptr()) })
+    }
+
+    pub fn multicast_ttl_v4(&self) -> Result<u32> {
+        let mut buf =
+            ffi::PRSocketOptionCase::new(ffi::PR_SockOpt_McastTimeToLive, 0 as ffi::PRUintn);
+        wrap_ffi(|| unsafe { ffi::PR_GetSocketOption(self.as_raw_prfd(), buf.as_mut_ptr()) })?;
+        Ok(buf.value)
+    }
+
+    // Whether this host's own members of the group get what's sent.
+    pub fn set_multicast_loop_v4(&self, on: bool) -> Result<()> {
+        let buf = ffi::PRSocketOptionCase::new(ffi::PR_SockOpt_McastLoopback, bool_to_nspr(on));
+        wrap_ffi(|| unsafe { ffi::PR_SetSocketOption(self.as_raw_prfd(), buf.as_ptr()) })
+    }
+
+    pub fn multicast_loop_v4(&self) -> Result<bool> {
+        let mut buf = ffi::PRSocketOptionCase::new(ffi::PR_SockOpt_McastLoopback, ffi::PR_FALSE);
+        wrap_ffi(|| unsafe { ffi::PR_GetSocketOption(self.as_raw_prfd(), buf.as_mut_ptr()) })?;
+        Ok(bool_from_nspr(buf.value))
+    }
+
+    pub fn accept(&self, timeout: Option<Duration>) -> Result<(File, SocketAddr)> {
+        let mut addrbuf = NetAddrStorage::new();
+        let file = wrap_ffi(|| unsafe {
+            File::from_raw_prfd_err(ffi::PR_Accept(
+                self.as_raw_prfd(),
+                addrbuf.as_mut_ptr(),
+                duration_opt_to_nspr(timeout),
+            ))
+        })?;
+        Ok((file, unsafe { read_net_addr(addrbuf.as_ptr()) }?))
+    }
+
+    // NSPR makes the accepted socket without regard to any layers
+    // `self` has: for a wrapped listener it's whatever the wrapper's
+    // `accept_conn` returns, and a layer pushed onto a plain listener isn't
+    // copied.  This wraps the accepted socket with `wrapper` instead.
+    pub fn accept_wrapped<Inner>(
+        &self,
+        wrapper: &FileWrapper<Inner>,
+        timeout: Option<Duration>,
+    ) -> Result<(File, SocketAddr)>
+    where
+        Inner: FileMethods + From<File> + Send + Sync + 'static,
+    {
+        let (file, addr) = self.accept(timeout)?;
+        Ok((wrapper.wrap(Inner::from(file)), addr))
+    }
+
+    // Only for a plain NSPR TCP socket, not one with layers (e.g.,
+    // TLS) pushed on it; those would be lost.  The stream gets a
+    // duplicate of the OS socket, and `self` closes the original.  On
+    // failure, `self` is given back unchanged.
+    #[cfg(unix)]
+    pub fn into_tcp_stream(self) -> result::Result<TcpStream, (Error, File)> {
+        if self.desc_type() != PR_DESC_SOCKET_TCP || self.identity() != ffi::PR_NSPR_IO_LAYER {
+            return Err((PR_INVALID_ARGUMENT_ERROR.into(), self));
+        }
+        let osfd = unsafe { ffi::PR_FileDesc2NativeHandle(self.as_raw_prfd()) };
+        if osfd < 0 {
+            return Err((Error::last(), self));
+        }
+        let dup = unsafe { libc::dup(osfd) };
+        if dup < 0 {
+            return Err((io::Error::last_os_error().into(), self));
+        }
+        // As when dropping it, a failure to close doesn't matter: the
+        // stream has its own descriptor either way.
+        drop(self);
+        Ok(unsafe { TcpStream::from_raw_fd(dup) })
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use libc::AF_INET;
+    use libc::{AF_INET, AF_INET6};
     use nss_sys::nspr as ffi;
     use std::mem;
     use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6};
@@ -103,6 +498,163 @@ mod tests {
         let _fd = new_tcp_socket(AF_INET).unwrap();
     }
 
+    #[test]
+    fn accept_wrapped() {
+        use nspr::fd::Counting;
+        use std::io::Write;
+
+        let listener = new_tcp_socket(AF_INET).unwrap();
+        listener
+            .bind(SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 0)))
+            .unwrap();
+        listener.listen(1).unwrap();
+        let wrapper = FileWrapper::new(PR_DESC_SOCKET_TCP);
+        let listener = wrapper.wrap(Counting::new(listener));
+        let addr = listener.getsockname().unwrap();
+
+        let mut client = TcpStream::connect(addr).unwrap();
+        client.write_all(b"hello").unwrap();
+        let (conn, peer) = listener.accept_wrapped(&wrapper, None).unwrap();
+        assert_eq!(peer, client.local_addr().unwrap());
+        let mut buf = [0; 5];
+        assert_eq!(conn.read(&mut buf).unwrap(), 5);
+        assert_eq!(&buf, b"hello");
+        let counts = conn.downcast_ref::<Counting<File>>().unwrap().counts();
+        assert_eq!(counts.bytes_read(), 5);
+    }
+
+    #[test]
+    fn socket_pair() {
+        let (client, server) = super::socket_pair().unwrap();
+        assert_eq!(client.getpeername().unwrap(), server.getsockname().unwrap());
+        assert_eq!(client.write(b"ping").unwrap(), 4);
+        let mut buf = [0; 4];
+        assert_eq!(server.read(&mut buf).unwrap(), 4);
+        assert_eq!(&buf, b"ping");
+        assert_eq!(server.write(b"pong").unwrap(), 4);
+        drop(server);
+        assert_eq!(client.read(&mut buf).unwrap(), 4);
+        assert_eq!(&buf, b"pong");
+        assert_eq!(client.read(&mut buf).unwrap(), 0);
+    }
+
+    #[test]
+    fn linger() {
+        let sock = new_tcp_socket(AF_INET).unwrap();
+        assert_eq!(sock.linger().unwrap(), None);
+        sock.set_linger(Some(Duration::from_secs(5))).unwrap();
+        assert_eq!(sock.linger().unwrap(), Some(Duration::from_secs(5)));
+        sock.set_linger(None).unwrap();
+        assert_eq!(sock.linger().unwrap(), None);
+    }
+
+    #[test]
+    fn multicast_options() {
+        let sock = new_udp_socket(AF_INET).unwrap();
+        sock.bind(SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 0)))
+            .unwrap();
+        sock.set_multicast_ttl_v4(4).unwrap();
+        assert_eq!(sock.multicast_ttl_v4().unwrap(), 4);
+        sock.set_multicast_loop_v4(false).unwrap();
+        assert!(!sock.multicast_loop_v4().unwrap());
+        sock.set_multicast_loop_v4(true).unwrap();
+        assert!(sock.multicast_loop_v4().unwrap());
+
+        // Loopback may not do multicast (e.g., in a container), so the
+        // rest is best-effort.
+        let group = Ipv4Addr::new(239, 255, 77, 77);
+        if sock.join_multicast_v4(group, Ipv4Addr::LOCALHOST).is_err() {
+            return;
+        }
+        sock.set_multicast_if_v4(Ipv4Addr::LOCALHOST).unwrap();
+        assert_eq!(sock.multicast_if_v4().unwrap(), Ipv4Addr::LOCALHOST);
+        sock.leave_multicast_v4(group, Ipv4Addr::LOCALHOST).unwrap();
+        assert!(sock.leave_multicast_v4(group, Ipv4Addr::LOCALHOST).is_err());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn tcp_stream_round_trip() {
+        use nspr::fd::{new_pipe, FileMethods};
+        use std::io::{Read, Write};
+        use std::net::TcpListener;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (mut server, _) = listener.accept().unwrap();
+        let local_addr = client.local_addr().unwrap();
+
+        let file = File::try_from(client).unwrap();
+        assert_eq!(file.getsockname().unwrap(), local_addr);
+        assert_eq!(file.write(b"nspr").unwrap(), 4);
+        let mut client = file.into_tcp_stream().map_err(|(err, _)| err).unwrap();
+        assert_eq!(client.local_addr().unwrap(), local_addr);
+        client.write_all(b" std").unwrap();
+        drop(client);
+
+        let mut buf = String::new();
+        server.read_to_string(&mut buf).unwrap();
+        assert_eq!(buf, "nspr std");
+
+        let (reader, writer) = new_pipe().unwrap();
+        let (err, reader) = reader.into_tcp_stream().unwrap_err();
+        assert_eq!(err.nspr_error, PR_INVALID_ARGUMENT_ERROR);
+        // It's still open.
+        assert_eq!(writer.write(b"pipe").unwrap(), 4);
+        let mut buf = [0; 4];
+        assert_eq!(reader.read(&mut buf).unwrap(), 4);
+        assert_eq!(&buf, b"pipe");
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn borrowed_tcp_socket() {
+        use nspr::fd::FileMethods;
+        use std::io::{Read, Write};
+        use std::net::TcpListener;
+        use std::os::unix::io::AsRawFd;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (mut server, _) = listener.accept().unwrap();
+
+        let file = unsafe { import_tcp_socket_borrowed(client.as_raw_fd()) }.unwrap();
+        assert_eq!(file.write(b"nspr").unwrap(), 4);
+        let status = unsafe { ffi::PR_Close(file.into_raw_prfd()) };
+        assert_eq!(status, ffi::PR_SUCCESS);
+        client.write_all(b" std").unwrap();
+        drop(client);
+
+        let mut buf = String::new();
+        server.read_to_string(&mut buf).unwrap();
+        assert_eq!(buf, "nspr std");
+    }
+
+    #[test]
+    fn localhost_by_name() {
+        let addrs = get_host_by_name("localhost").unwrap();
+        assert!(!addrs.is_empty());
+        assert!(addrs.iter().all(|addr| addr.is_loopback()), "{:?}", addrs);
+        assert!(get_host_by_name("local\0host").is_err());
+    }
+
+    #[test]
+    fn connect_hostname() {
+        use std::net::TcpListener;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let timeout = Some(Duration::from_secs(10));
+        for &family in &[AddrFamily::Any, AddrFamily::V4] {
+            let sock = super::connect_hostname("localhost", addr.port(), family, timeout).unwrap();
+            assert_eq!(sock.getpeername().unwrap(), addr);
+        }
+        drop(listener);
+        assert!(
+            super::connect_hostname("localhost", addr.port(), AddrFamily::V4, timeout).is_err()
+        );
+    }
+
     #[test]
     fn drop_udp() {
         let _fd = new_udp_socket(AF_INET).unwrap();
@@ -133,5 +685,64 @@ mod tests {
         assert_eq!(got_addr, addr);
     }
 
+    #[test]
+    fn v4_mapped_addr_rdwr() {
+        let mut buf = NetAddrStorage::new();
+        let ip = Ipv4Addr::new(192, 0, 2, 1).to_ipv6_mapped();
+        let addr = SocketAddr::V6(SocketAddrV6::new(ip, 443, 0, 0));
+
+        unsafe { write_net_addr(buf.as_mut_ptr(), addr) };
+        let raw = unsafe { &*(buf.as_ptr() as *const ffi::PRNetAddrInet6) };
+        assert_eq!(raw.family, AF_INET6 as u16);
+        let octets: [u8; 16] = unsafe { mem::transmute(raw.ip) };
+        assert_eq!(
+            octets,
+            [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xff, 0xff, 192, 0, 2, 1]
+        );
+        assert_eq!(unsafe { read_net_addr(buf.as_ptr()) }.unwrap(), addr);
+    }
+
+    #[test]
+    fn link_local_addr_rdwr() {
+        let mut buf = NetAddrStorage::new();
+        let ip = Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 1);
+        let addr = SocketAddr::V6(SocketAddrV6::new(ip, 8080, 0, 3));
+
+        unsafe { write_net_addr(buf.as_mut_ptr(), addr) };
+        let raw = unsafe { &*(buf.as_ptr() as *const ffi::PRNetAddrInet6) };
+        assert_eq!(raw.scope_id, 3);
+        assert_eq!(unsafe { read_net_addr(buf.as_ptr()) }.unwrap(), addr);
+    }
+
+    #[test]
+    fn unsupported_family() {
+        let mut buf = NetAddrStorage::new();
+        unsafe { (*(buf.as_mut_ptr() as *mut ffi::PRNetAddrRaw)).family = libc::AF_UNIX as u16 };
+        let err = unsafe { read_net_addr(buf.as_ptr()) }.unwrap_err();
+        assert_eq!(err.nspr_error, PR_ADDRESS_NOT_SUPPORTED_ERROR);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn local_socket_name() {
+        use nspr::fd::FileMethods;
+        use std::os::unix::io::AsRawFd;
+        use std::os::unix::net::{UnixListener, UnixStream};
+
+        assert!(mem::size_of::<NetAddrStorage>() >= mem::size_of::<ffi::PRNetAddrLocal>());
+        let path = std::env::temp_dir().join(format!("nss-rs-local-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+        let client = UnixStream::connect(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        // NSPR writes the whole AF_LOCAL address, path and all, before
+        // `read_net_addr` gets to reject it.
+        let file = unsafe { import_tcp_socket_borrowed(client.as_raw_fd()) }.unwrap();
+        let err = file.getpeername().unwrap_err();
+        assert_eq!(err.nspr_error, PR_ADDRESS_NOT_SUPPORTED_ERROR);
+        drop(listener);
+    }
+
     // Need better tests that these are actually meaning-preserving, not just inverses.
 }
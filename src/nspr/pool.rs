@@ -0,0 +1,98 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+use std::mem;
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Mutex};
+
+// Recycles byte buffers between calls, e.g. for a `FileMethods` layer
+// that copies or transforms what passes through it, instead of
+// allocating one each time.  Clones share the same buffers.
+#[derive(Clone)]
+pub struct BufferPool(Arc<Shared>);
+
+struct Shared {
+    idle: Mutex<Vec<Vec<u8>>>,
+    max_idle: usize,
+}
+
+impl BufferPool {
+    // At most `max_idle` buffers are kept for reuse; any others are
+    // freed when they're returned.
+    pub fn new(max_idle: usize) -> Self {
+        BufferPool(Arc::new(Shared {
+            idle: Mutex::new(Vec::with_capacity(max_idle)),
+            max_idle,
+        }))
+    }
+
+    // A zero-filled buffer of `size` bytes, which goes back to the
+    // pool when dropped.  It can be resized like any `Vec`.
+    pub fn get(&self, size: usize) -> PooledBuffer {
+        let mut buf = self.0.idle.lock().unwrap().pop().unwrap_or_default();
+        buf.clear();
+        buf.resize(size, 0);
+        PooledBuffer {
+            buf,
+            pool: self.0.clone(),
+        }
+    }
+
+    // The number of buffers waiting to be reused.
+    pub fn idle(&self) -> usize {
+        self.0.idle.lock().unwrap().len()
+    }
+}
+
+pub struct PooledBuffer {
+    buf: Vec<u8>,
+    pool: Arc<Shared>,
+}
+
+impl Deref for PooledBuffer {
+    type Target = Vec<u8>;
+    fn deref(&self) -> &Vec<u8> {
+        &self.buf
+    }
+}
+
+impl DerefMut for PooledBuffer {
+    fn deref_mut(&mut self) -> &mut Vec<u8> {
+        &mut self.buf
+    }
+}
+
+impl Drop for PooledBuffer {
+    fn drop(&mut self) {
+        let buf = mem::take(&mut self.buf);
+        let mut idle = self.pool.idle.lock().unwrap();
+        if idle.len() < self.pool.max_idle {
+            idle.push(buf);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reuse() {
+        let pool = BufferPool::new(4);
+        let held: Vec<_> = (0..10).map(|_| pool.get(4096)).collect();
+        assert_eq!(pool.idle(), 0);
+        let addrs: Vec<_> = held.iter().map(|buf| buf.as_ptr()).collect();
+        drop(held);
+        assert_eq!(pool.idle(), 4);
+
+        for i in 0..1000 {
+            let mut buf = pool.get(1000 + i % 3000);
+            assert!(buf.iter().all(|&b| b == 0));
+            assert!(addrs.contains(&buf.as_ptr()));
+            buf[0] = 0xff;
+            assert_eq!(pool.idle(), 3);
+        }
+        assert_eq!(pool.idle(), 4);
+    }
+}
@@ -3,8 +3,7 @@
  * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
 
 use nss_sys::nspr as ffi;
-use std::time::Duration;
-use std::u32;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 lazy_static! {
     static ref NSPR_TICKS_PER_SEC: u32 = {
@@ -51,4 +50,21 @@ pub fn duration_opt_from_nspr(it: ffi::PRIntervalTime) -> Option<Duration> {
     }
 }
 
+// PRTime is microseconds since the Unix epoch.
+pub fn system_time_to_nspr(t: SystemTime) -> ffi::PRTime {
+    match t.duration_since(UNIX_EPOCH) {
+        Ok(d) => d.as_micros() as ffi::PRTime,
+        Err(err) => -(err.duration().as_micros() as ffi::PRTime),
+    }
+}
+
+pub fn system_time_from_nspr(t: ffi::PRTime) -> SystemTime {
+    let d = Duration::from_micros(t.unsigned_abs());
+    if t >= 0 {
+        UNIX_EPOCH + d
+    } else {
+        UNIX_EPOCH - d
+    }
+}
+
 // FIXME needs unit tests.
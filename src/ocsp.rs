@@ -0,0 +1,361 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+use super::{sec_item_as_slice, sec_item_from_slice, wrap_ffi, Certificate, GenStatus, Result};
+use error::{
+    SEC_ERROR_INVALID_ARGS, SEC_ERROR_OCSP_UNKNOWN_CERT, SEC_ERROR_REVOKED_CERTIFICATE,
+    SEC_ERROR_REVOKED_CERTIFICATE_OCSP,
+};
+use nspr::time::{system_time_from_nspr, system_time_to_nspr};
+use nss_sys as ffi;
+use std::convert::TryFrom;
+use std::ffi::CStr;
+use std::mem;
+use std::ptr;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::{Duration, SystemTime};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OcspStatus {
+    Good,
+    // The time is `None` if it couldn't be extracted from the response.
+    Revoked { time: Option<SystemTime> },
+    Unknown,
+}
+
+// What `Certificate::verify` does when the OCSP responder can't be
+// reached or gives no usable answer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OcspMode {
+    HardFail,
+    SoftFail,
+}
+
+// Turns NSS's own OCSP checking during verification on or off.  Like
+// the rest of the functions below, this affects the whole process.
+pub fn enable(enabled: bool, mode: OcspMode) -> Result<()> {
+    let mode = match mode {
+        OcspMode::HardFail => ffi::ocspMode_FailureIsVerificationFailure,
+        OcspMode::SoftFail => ffi::ocspMode_FailureIsNotAVerificationFailure,
+    };
+    wrap_ffi(|| unsafe {
+        let db = ffi::CERT_GetDefaultCertDB();
+        if enabled {
+            if ffi::CERT_EnableOCSPChecking(db) != ffi::SECSuccess {
+                return ffi::SECFailure;
+            }
+            ffi::CERT_SetOCSPFailureMode(mode)
+        } else {
+            ffi::CERT_DisableOCSPChecking(db)
+        }
+    })
+}
+
+// NSS has no getter for the timeout, so this keeps the last one set
+// here, starting from NSS's default of a minute.
+static TIMEOUT_SECS: AtomicU32 = AtomicU32::new(60);
+
+// NSS only has whole-second resolution; anything less is rounded up.
+pub fn set_timeout(timeout: Duration) -> Result<()> {
+    let mut secs = timeout.as_secs();
+    if timeout.subsec_nanos() > 0 {
+        secs += 1;
+    }
+    let secs = u32::try_from(secs).unwrap_or(u32::MAX);
+    wrap_ffi(|| unsafe { ffi::CERT_SetOCSPTimeout(secs) })?;
+    TIMEOUT_SECS.store(secs, Ordering::Relaxed);
+    Ok(())
+}
+
+// As last set with `set_timeout`, so it's wrong if anything has called
+// `CERT_SetOCSPTimeout` directly.
+pub fn timeout() -> Duration {
+    Duration::from_secs(TIMEOUT_SECS.load(Ordering::Relaxed).into())
+}
+
+// Sends every OCSP request to `url`, whose responses must be signed by
+// the cert with `signer_nickname` (which needs to be in the cert DB),
+// instead of the responder named by each cert.
+pub fn set_default_responder(url: &CStr, signer_nickname: &CStr) -> Result<()> {
+    wrap_ffi(|| unsafe {
+        let db = ffi::CERT_GetDefaultCertDB();
+        if ffi::CERT_SetOCSPDefaultResponder(db, url.as_ptr(), signer_nickname.as_ptr())
+            != ffi::SECSuccess
+        {
+            return ffi::SECFailure;
+        }
+        ffi::CERT_EnableOCSPDefaultResponder(db)
+    })
+}
+
+pub fn unset_default_responder() -> Result<()> {
+    wrap_ffi(|| unsafe { ffi::CERT_DisableOCSPDefaultResponder(ffi::CERT_GetDefaultCertDB()) })
+}
+
+struct Response(*mut ffi::CERTOCSPResponse);
+
+impl Response {
+    fn decode(der: &[u8]) -> Result<Self> {
+        let item = sec_item_from_slice(der);
+        wrap_ffi(|| unsafe {
+            let ptr = ffi::CERT_DecodeOCSPResponse(&item);
+            if ptr.is_null() {
+                GenStatus::ErrorFromC
+            } else {
+                GenStatus::Success(Response(ptr))
+            }
+        })
+    }
+}
+
+impl Drop for Response {
+    fn drop(&mut self) {
+        let ptr = mem::replace(&mut self.0, ptr::null_mut());
+        if !ptr.is_null() {
+            unsafe { ffi::CERT_DestroyOCSPResponse(ptr) }
+        }
+    }
+}
+
+struct CertId(*mut ffi::CERTOCSPCertID);
+
+impl CertId {
+    // This looks up the issuer in the cert DB, so it needs to have been
+    // imported (and still be referenced).
+    fn new(cert: &Certificate, time: ffi::nspr::PRTime) -> Result<Self> {
+        wrap_ffi(|| unsafe {
+            let ptr = ffi::CERT_CreateOCSPCertID(cert.as_raw_ptr() as *mut _, time);
+            if ptr.is_null() {
+                GenStatus::ErrorFromC
+            } else {
+                GenStatus::Success(CertId(ptr))
+            }
+        })
+    }
+}
+
+impl Drop for CertId {
+    fn drop(&mut self) {
+        let ptr = mem::replace(&mut self.0, ptr::null_mut());
+        if !ptr.is_null() {
+            unsafe {
+                ffi::CERT_DestroyOCSPCertID(ptr);
+            }
+        }
+    }
+}
+
+// Checks that `response_der` is a successful OCSP response, signed by
+// `issuer` or a responder it authorized, that is current at `time`
+// (default: now), and returns what it says about `cert`.  The signer
+// must itself verify, so `issuer` needs to chain to a trusted root.
+pub fn verify_response(
+    cert: &Certificate,
+    issuer: &Certificate,
+    response_der: &[u8],
+    time: Option<SystemTime>,
+) -> Result<OcspStatus> {
+    // NSS finds the issuer by name itself, so this is mostly to catch
+    // mistakes, and to make sure it's in the cert DB to be found.
+    let (cert_ref, issuer_ref) = (cert.as_ffi_ref(), issuer.as_ffi_ref());
+    if unsafe {
+        sec_item_as_slice(&cert_ref.derIssuer) != sec_item_as_slice(&issuer_ref.derSubject)
+    } {
+        return Err(SEC_ERROR_INVALID_ARGS.into());
+    }
+    let time = system_time_to_nspr(time.unwrap_or_else(SystemTime::now));
+    let response = Response::decode(response_der)?;
+    wrap_ffi(|| unsafe { ffi::CERT_GetOCSPResponseStatus(response.0) })?;
+    let db = unsafe { ffi::CERT_GetDefaultCertDB() };
+    let mut signer = ptr::null_mut();
+    wrap_ffi(|| unsafe {
+        ffi::CERT_VerifyOCSPResponseSignature(
+            response.0,
+            db,
+            ptr::null_mut(),
+            &mut signer,
+            issuer.as_raw_ptr() as *mut _,
+        )
+    })?;
+    let signer = unsafe { Certificate::from_raw_ptr(signer) };
+    let cert_id = CertId::new(cert, time)?;
+    let status = wrap_ffi(|| unsafe {
+        ffi::CERT_GetOCSPStatusForCertID(
+            db,
+            response.0,
+            cert_id.0,
+            signer.as_raw_ptr() as *mut _,
+            time,
+        )
+    });
+    match status {
+        Ok(()) => Ok(OcspStatus::Good),
+        Err(err)
+            if err.nspr_error == SEC_ERROR_REVOKED_CERTIFICATE
+                || err.nspr_error == SEC_ERROR_REVOKED_CERTIFICATE_OCSP =>
+        {
+            let serial = unsafe { sec_item_as_slice(&cert.as_ffi_ref().serialNumber) };
+            Ok(OcspStatus::Revoked {
+                time: revocation_time(response_der, serial),
+            })
+        }
+        Err(err) if err.nspr_error == SEC_ERROR_OCSP_UNKNOWN_CERT => Ok(OcspStatus::Unknown),
+        Err(err) => Err(err),
+    }
+}
+
+// NSS doesn't expose the parsed response, so this walks the DER just
+// far enough to find the `revocationTime` of the SingleResponse for
+// `serial`.  The response has already been decoded and verified by
+// the time this is used.
+fn revocation_time(response_der: &[u8], serial: &[u8]) -> Option<SystemTime> {
+    const SEQUENCE: u8 = 0x30;
+    const OCTET_STRING: u8 = 0x04;
+    const INTEGER: u8 = 0x02;
+    const GENERALIZED_TIME: u8 = 0x18;
+    const RESPONSE_BYTES: u8 = 0xa0;
+    const REVOKED: u8 = 0xa1;
+
+    let response = Der(response_der).expect(SEQUENCE)?;
+    let response_bytes = Der(response).find(RESPONSE_BYTES)?;
+    let response_bytes = Der(response_bytes).expect(SEQUENCE)?;
+    let basic = Der(response_bytes).find(OCTET_STRING)?;
+    let basic = Der(basic).expect(SEQUENCE)?;
+    let tbs = Der(basic).expect(SEQUENCE)?;
+    // Skips the optional version, responder ID, and producedAt.
+    let responses = Der(tbs).find(SEQUENCE)?;
+    let mut responses = Der(responses);
+    while let Some(single) = responses.expect(SEQUENCE) {
+        let mut single = Der(single);
+        let cert_id = single.expect(SEQUENCE)?;
+        let mut cert_id = Der(cert_id);
+        let mut this_serial = None;
+        while let Some((tag, value)) = cert_id.next() {
+            if tag == INTEGER {
+                this_serial = Some(value);
+            }
+        }
+        if this_serial != Some(serial) {
+            continue;
+        }
+        let revoked = single.expect(REVOKED)?;
+        let time = Der(revoked).expect(GENERALIZED_TIME)?;
+        let item = sec_item_from_slice(time);
+        let mut prtime = 0;
+        return match unsafe { ffi::DER_GeneralizedTimeToTime(&mut prtime, &item) } {
+            ffi::SECSuccess => Some(system_time_from_nspr(prtime)),
+            _ => None,
+        };
+    }
+    None
+}
+
+// A minimal DER reader: single-byte tags and definite lengths only,
+// which is all that OCSP responses use.
+struct Der<'a>(&'a [u8]);
+
+impl<'a> Der<'a> {
+    fn next(&mut self) -> Option<(u8, &'a [u8])> {
+        let (&tag, rest) = self.0.split_first()?;
+        let (&len0, mut rest) = rest.split_first()?;
+        let len = if len0 < 0x80 {
+            len0 as usize
+        } else {
+            let n = (len0 & 0x7f) as usize;
+            if n == 0 || n > mem::size_of::<usize>() || rest.len() < n {
+                return None;
+            }
+            let len = rest[..n]
+                .iter()
+                .fold(0usize, |acc, &b| (acc << 8) | b as usize);
+            rest = &rest[n..];
+            len
+        };
+        if rest.len() < len {
+            return None;
+        }
+        self.0 = &rest[len..];
+        Some((tag, &rest[..len]))
+    }
+
+    fn expect(&mut self, tag: u8) -> Option<&'a [u8]> {
+        match self.next()? {
+            (t, value) if t == tag => Some(value),
+            _ => None,
+        }
+    }
+
+    fn find(&mut self, tag: u8) -> Option<&'a [u8]> {
+        while let Some((t, value)) = self.next() {
+            if t == tag {
+                return Some(value);
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use init;
+    use std::time::{Duration, UNIX_EPOCH};
+    use test_util::{trust_test_root, Restore};
+
+    static LEAF_DER: &[u8] = include_bytes!("../testdata/leaf.der");
+    static INTERMEDIATE_DER: &[u8] = include_bytes!("../testdata/intermediate.der");
+    static GOOD: &[u8] = include_bytes!("../testdata/leaf-ocsp-good.der");
+    static REVOKED: &[u8] = include_bytes!("../testdata/leaf-ocsp-revoked.der");
+    static UNKNOWN: &[u8] = include_bytes!("../testdata/leaf-ocsp-unknown.der");
+
+    #[test]
+    fn responses() {
+        init().unwrap();
+        let _root = trust_test_root();
+        let leaf = Certificate::from_der(LEAF_DER).unwrap();
+        let issuer = Certificate::from_der(INTERMEDIATE_DER).unwrap();
+        assert_eq!(
+            verify_response(&leaf, &issuer, GOOD, None).unwrap(),
+            OcspStatus::Good
+        );
+        // 2024-01-01T00:00:00Z, as set by generate.sh.
+        let revoked_at = UNIX_EPOCH + Duration::from_secs(1_704_067_200);
+        assert_eq!(
+            verify_response(&leaf, &issuer, REVOKED, None).unwrap(),
+            OcspStatus::Revoked {
+                time: Some(revoked_at)
+            }
+        );
+        assert_eq!(
+            verify_response(&leaf, &issuer, UNKNOWN, None).unwrap(),
+            OcspStatus::Unknown
+        );
+    }
+
+    #[test]
+    fn soft_fail() {
+        init().unwrap();
+        let _root = trust_test_root();
+        let _intermediate = Certificate::from_der(INTERMEDIATE_DER).unwrap();
+        let leaf = Certificate::from_der(LEAF_DER).unwrap();
+        // `_root` holds the global lock until these have run.
+        let _restore = Restore(|| enable(false, OcspMode::SoftFail).unwrap());
+        let before = timeout();
+        let _restore_timeout = Restore(|| set_timeout(before).unwrap());
+        enable(true, OcspMode::SoftFail).unwrap();
+        set_timeout(Duration::from_millis(1500)).unwrap();
+        assert_eq!(timeout(), Duration::from_secs(2));
+        leaf.verify(None).unwrap();
+    }
+
+    #[test]
+    fn wrong_issuer() {
+        init().unwrap();
+        let leaf = Certificate::from_der(LEAF_DER).unwrap();
+        let _issuer = Certificate::from_der(INTERMEDIATE_DER).unwrap();
+        let root = trust_test_root();
+        let err = verify_response(&leaf, &root, GOOD, None).unwrap_err();
+        assert_eq!(err.nspr_error, SEC_ERROR_INVALID_ARGS);
+    }
+}
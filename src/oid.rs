@@ -0,0 +1,139 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+use super::{sec_item_as_slice, sec_item_from_slice, wrap_ffi, GenStatus, Result};
+use error::SEC_ERROR_INVALID_ARGS;
+use libc::c_char;
+use nss_sys as ffi;
+use std::ffi::CStr;
+use std::mem;
+use std::ptr;
+
+// An ASN.1 object identifier, as the contents of its DER encoding
+// (without the tag and length), which is how NSS keeps them.
+#[derive(Clone, Debug)]
+pub struct Oid(Vec<u8>);
+
+impl Oid {
+    // From "2.5.29.17", or the "OID.2.5.29.17" form used in names.
+    pub fn from_dotted(dotted: &str) -> Result<Self> {
+        // A zero length would mean it's NUL-terminated.
+        if dotted.is_empty() || dotted.len() > u32::MAX as usize {
+            return Err(SEC_ERROR_INVALID_ARGS.into());
+        }
+        unsafe {
+            let mut item = mem::zeroed::<ffi::SECItem>();
+            wrap_ffi(|| {
+                ffi::pk11::SEC_StringToOID(
+                    ptr::null_mut(),
+                    &mut item,
+                    dotted.as_ptr() as *const c_char,
+                    dotted.len() as u32,
+                )
+            })?;
+            let oid = Oid(sec_item_as_slice(&item).to_vec());
+            ffi::SECITEM_FreeItem(&mut item, ffi::nspr::PR_FALSE);
+            Ok(oid)
+        }
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        Oid(bytes.to_vec())
+    }
+
+    // `None` for `SEC_OID_UNKNOWN` and other tags NSS has no OID for.
+    pub fn from_tag(tag: ffi::SECOidTag) -> Option<Self> {
+        unsafe {
+            let data = ffi::pk11::SECOID_FindOIDByTag(tag);
+            if data.is_null() || (*data).oid.len == 0 {
+                None
+            } else {
+                Some(Oid(sec_item_as_slice(&(*data).oid).to_vec()))
+            }
+        }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    pub fn to_dotted(&self) -> Result<String> {
+        let item = sec_item_from_slice(&self.0);
+        unsafe {
+            let dotted = wrap_ffi(|| {
+                let dotted = ffi::cert::CERT_GetOidString(&item);
+                if dotted.is_null() {
+                    GenStatus::ErrorFromC
+                } else {
+                    GenStatus::Success(dotted)
+                }
+            })?;
+            let res = CStr::from_ptr(dotted).to_string_lossy().into_owned();
+            ffi::nspr::PR_smprintf_free(dotted);
+            // It's in the "OID.1.2.3" form used in RFC 1485 names.
+            Ok(res.trim_start_matches("OID.").to_owned())
+        }
+    }
+
+    // NSS's tag for it, if NSS knows it.
+    pub fn tag(&self) -> Option<ffi::SECOidTag> {
+        let item = sec_item_from_slice(&self.0);
+        match unsafe { ffi::pk11::SECOID_FindOIDTag(&item) } {
+            ffi::pk11::SEC_OID_UNKNOWN => None,
+            tag => Some(tag),
+        }
+    }
+
+    // NSS's description, like "Certificate Subject Alt Name".
+    pub fn name(&self) -> Option<&'static str> {
+        let item = sec_item_from_slice(&self.0);
+        unsafe {
+            let data = ffi::pk11::SECOID_FindOID(&item);
+            if data.is_null() || (*data).desc.is_null() {
+                None
+            } else {
+                CStr::from_ptr((*data).desc).to_str().ok()
+            }
+        }
+    }
+}
+
+impl PartialEq for Oid {
+    fn eq(&self, other: &Self) -> bool {
+        let (a, b) = (sec_item_from_slice(&self.0), sec_item_from_slice(&other.0));
+        unsafe { ffi::SECITEM_CompareItem(&a, &b) == ffi::SECEqual }
+    }
+}
+
+impl Eq for Oid {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use init;
+
+    #[test]
+    fn subject_alt_name() {
+        init().unwrap();
+        let san = Oid::from_dotted("2.5.29.17").unwrap();
+        assert_eq!(san.as_bytes(), &[0x55, 0x1d, 0x11]);
+        assert_eq!(san.to_dotted().unwrap(), "2.5.29.17");
+        assert_eq!(san.tag(), Some(ffi::cert::SEC_OID_X509_SUBJECT_ALT_NAME));
+        assert_eq!(san.name(), Some("Certificate Subject Alt Name"));
+        assert_eq!(
+            Oid::from_tag(ffi::cert::SEC_OID_X509_SUBJECT_ALT_NAME),
+            Some(san.clone())
+        );
+        assert_eq!(Oid::from_dotted("OID.2.5.29.17").unwrap(), san);
+        assert_ne!(Oid::from_dotted("2.5.29.19").unwrap(), san);
+
+        let unknown = Oid::from_dotted("1.3.6.1.4.1.99999.1").unwrap();
+        assert_eq!(unknown.tag(), None);
+        assert_eq!(unknown.name(), None);
+        assert_eq!(unknown.to_dotted().unwrap(), "1.3.6.1.4.1.99999.1");
+        assert_eq!(Oid::from_tag(ffi::pk11::SEC_OID_UNKNOWN), None);
+        assert!(Oid::from_dotted("2.5.x").is_err());
+        assert!(Oid::from_dotted("").is_err());
+    }
+}
@@ -0,0 +1,969 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+use super::{
+    owned_sec_item_to_vec, sec_item_from_slice, sec_item_to_vec, wrap_ffi, Error, GenStatus, Result,
+};
+use cert::{CertList, Certificate};
+use error::{SEC_ERROR_BAD_PASSWORD, SEC_ERROR_INVALID_ARGS, SEC_ERROR_INVALID_KEY};
+use libc::{c_int, c_uint, c_void};
+use nspr::bool_from_nspr;
+use nss_sys as ffi;
+use std::convert::TryFrom;
+use std::ffi::{CStr, CString};
+use std::mem;
+use std::ops::{Deref, DerefMut};
+use std::ptr;
+use std::sync::atomic;
+
+pub struct Slot(*mut ffi::PK11SlotInfo);
+
+impl Slot {
+    /// # Safety
+    ///
+    /// `ptr` must be a non-null slot reference that the caller owns;
+    /// it's freed on drop.
+    pub unsafe fn from_raw_ptr(ptr: *mut ffi::PK11SlotInfo) -> Self {
+        assert!(!ptr.is_null());
+        Slot(ptr)
+    }
+    /// # Safety
+    ///
+    /// As for `from_raw_ptr`, except that `ptr` may be null.
+    pub unsafe fn from_raw_ptr_err(ptr: *mut ffi::PK11SlotInfo) -> GenStatus<Self> {
+        if ptr.is_null() {
+            GenStatus::ErrorFromC
+        } else {
+            GenStatus::Success(Self::from_raw_ptr(ptr))
+        }
+    }
+    pub fn as_raw_ptr(&self) -> *mut ffi::PK11SlotInfo {
+        debug_assert!(!self.0.is_null());
+        self.0
+    }
+
+    pub fn internal() -> Result<Self> {
+        wrap_ffi(|| unsafe { Self::from_raw_ptr_err(ffi::PK11_GetInternalSlot()) })
+    }
+
+    // The one holding the cert and key DB, if NSS was initialized
+    // with one.
+    pub fn internal_key() -> Result<Self> {
+        wrap_ffi(|| unsafe { Self::from_raw_ptr_err(ffi::pk11::PK11_GetInternalKeySlot()) })
+    }
+
+    // The label of the token currently in the slot, if any.
+    pub fn token_name(&self) -> String {
+        unsafe { CStr::from_ptr(ffi::pk11::PK11_GetTokenName(self.as_raw_ptr())) }
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    // Whether the token wants a password (PIN) that hasn't been given
+    // yet, before its private objects can be used.
+    pub fn needs_login(&self) -> bool {
+        unsafe {
+            bool_from_nspr(ffi::pk11::PK11_NeedLogin(self.as_raw_ptr()))
+                && !bool_from_nspr(ffi::pk11::PK11_IsLoggedIn(
+                    self.as_raw_ptr(),
+                    ptr::null_mut(),
+                ))
+        }
+    }
+
+    // A wrong password fails with SEC_ERROR_BAD_PASSWORD.  The login
+    // is for the whole process, not just this `Slot`.
+    pub fn login(&self, password: &str) -> Result<()> {
+        let password = CString::new(password).map_err(|_| SEC_ERROR_INVALID_ARGS)?;
+        wrap_ffi(|| unsafe {
+            // Only a wrong password is reported as SECWouldBlock.
+            match ffi::pk11::PK11_CheckUserPassword(self.as_raw_ptr(), password.as_ptr()) {
+                ffi::SECWouldBlock => GenStatus::SpecificError(SEC_ERROR_BAD_PASSWORD.into()),
+                status => status.into(),
+            }
+        })
+    }
+
+    pub fn logout(&self) -> Result<()> {
+        wrap_ffi(|| unsafe { ffi::pk11::PK11_Logout(self.as_raw_ptr()) })
+    }
+
+    pub fn slot_name(&self) -> String {
+        unsafe { CStr::from_ptr(ffi::pk11::PK11_GetSlotName(self.as_raw_ptr())) }
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    // Fills `buf` from the token's own RNG, rather than NSS's software
+    // one (unless this is the internal slot).
+    pub fn generate_random(&self, buf: &mut [u8]) -> Result<()> {
+        for chunk in buf.chunks_mut(c_int::MAX as usize) {
+            wrap_ffi(|| unsafe {
+                ffi::pk11::PK11_GenerateRandomOnSlot(
+                    self.as_raw_ptr(),
+                    chunk.as_mut_ptr(),
+                    chunk.len() as c_int,
+                )
+            })?;
+        }
+        Ok(())
+    }
+
+    // Every certificate stored on the token.  A token that needs a
+    // login may hide some (or all) of them until then.
+    pub fn list_certs(&self) -> Result<Vec<Certificate>> {
+        let list = wrap_ffi(|| unsafe {
+            CertList::from_raw_ptr_opt(ffi::cert::PK11_ListCertsInSlot(self.as_raw_ptr()))
+                .map_or(GenStatus::ErrorFromC, GenStatus::Success)
+        })?;
+        Ok(list.into_iter().collect())
+    }
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        let ptr = mem::replace(&mut self.0, ptr::null_mut());
+        if !ptr.is_null() {
+            unsafe { ffi::PK11_FreeSlot(ptr) }
+        }
+    }
+}
+
+pub struct PrivateKey(*mut ffi::SECKEYPrivateKey);
+// NSS keys are reference-counted and locked internally.
+unsafe impl Send for PrivateKey {}
+unsafe impl Sync for PrivateKey {}
+
+impl PrivateKey {
+    /// # Safety
+    ///
+    /// `ptr` must be a non-null key that the caller owns; it's
+    /// destroyed on drop.
+    pub unsafe fn from_raw_ptr(ptr: *mut ffi::SECKEYPrivateKey) -> Self {
+        assert!(!ptr.is_null());
+        PrivateKey(ptr)
+    }
+    /// # Safety
+    ///
+    /// As for `from_raw_ptr`, except that `ptr` may be null.
+    pub unsafe fn from_raw_ptr_opt(ptr: *mut ffi::SECKEYPrivateKey) -> Option<Self> {
+        if ptr.is_null() {
+            None
+        } else {
+            Some(Self::from_raw_ptr(ptr))
+        }
+    }
+    pub fn into_raw_ptr(self) -> *mut ffi::SECKEYPrivateKey {
+        let ptr = self.0;
+        debug_assert!(!ptr.is_null());
+        mem::forget(self);
+        ptr
+    }
+    pub fn as_raw_ptr(&self) -> *mut ffi::SECKEYPrivateKey {
+        debug_assert!(!self.0.is_null());
+        self.0
+    }
+
+    // Imports an unencrypted PKCS#8 PrivateKeyInfo as a session
+    // (non-permanent) key on the internal slot.
+    pub fn from_pkcs8_der(der: &[u8]) -> Result<Self> {
+        let slot = Slot::internal()?;
+        let mut item = sec_item_from_slice(der);
+        let mut key = ptr::null_mut();
+        wrap_ffi(|| unsafe {
+            ffi::PK11_ImportDERPrivateKeyInfoAndReturnKey(
+                slot.as_raw_ptr(),
+                &mut item,
+                ptr::null_mut(),
+                ptr::null_mut(),
+                ffi::nspr::PR_FALSE,
+                ffi::nspr::PR_TRUE,
+                ffi::pk11::KU_ALL,
+                &mut key,
+                ptr::null_mut(),
+            )
+        })?;
+        Ok(unsafe { Self::from_raw_ptr(key) })
+    }
+
+    pub fn public_key(&self) -> Result<PublicKey> {
+        wrap_ffi(|| unsafe {
+            PublicKey::from_raw_ptr_err(ffi::SECKEY_ConvertToPublicKey(self.as_raw_ptr()))
+        })
+    }
+
+    // The mechanism hashes `data` itself.
+    pub fn sign(&self, data: &[u8], mechanism: CkMechanism) -> Result<Vec<u8>> {
+        if unsafe { ffi::pk11::SECKEY_GetPrivateKeyType(self.as_raw_ptr()) } != mechanism.key_type()
+        {
+            return Err(SEC_ERROR_INVALID_KEY.into());
+        }
+        // A SECItem's length is a c_uint.
+        if data.len() > c_uint::MAX as usize {
+            return Err(SEC_ERROR_INVALID_ARGS.into());
+        }
+        let len = unsafe { ffi::pk11::PK11_SignatureLen(self.as_raw_ptr()) };
+        if len <= 0 {
+            return Err(Error::last());
+        }
+        let mut sig = vec![0; len as usize];
+        let mut sig_item = ffi::SECItem {
+            type_: ffi::SECItemType::siBuffer,
+            data: sig.as_mut_ptr(),
+            len: sig.len() as c_uint,
+        };
+        wrap_ffi(|| unsafe {
+            ffi::pk11::PK11_SignWithMechanism(
+                self.as_raw_ptr(),
+                mechanism.to_ffi(),
+                ptr::null(),
+                &mut sig_item,
+                &sec_item_from_slice(data),
+            )
+        })?;
+        sig.truncate(sig_item.len as usize);
+        Ok(sig)
+    }
+}
+
+impl Drop for PrivateKey {
+    fn drop(&mut self) {
+        let ptr = mem::replace(&mut self.0, ptr::null_mut());
+        if !ptr.is_null() {
+            unsafe { ffi::SECKEY_DestroyPrivateKey(ptr) }
+        }
+    }
+}
+
+pub struct PublicKey(*mut ffi::SECKEYPublicKey);
+unsafe impl Send for PublicKey {}
+unsafe impl Sync for PublicKey {}
+
+impl PublicKey {
+    /// # Safety
+    ///
+    /// `ptr` must be a non-null key that the caller owns; it's
+    /// destroyed on drop.
+    pub unsafe fn from_raw_ptr(ptr: *mut ffi::SECKEYPublicKey) -> Self {
+        assert!(!ptr.is_null());
+        PublicKey(ptr)
+    }
+    /// # Safety
+    ///
+    /// As for `from_raw_ptr`, except that `ptr` may be null.
+    pub unsafe fn from_raw_ptr_err(ptr: *mut ffi::SECKEYPublicKey) -> GenStatus<Self> {
+        if ptr.is_null() {
+            GenStatus::ErrorFromC
+        } else {
+            GenStatus::Success(Self::from_raw_ptr(ptr))
+        }
+    }
+    pub fn as_raw_ptr(&self) -> *mut ffi::SECKEYPublicKey {
+        debug_assert!(!self.0.is_null());
+        self.0
+    }
+
+    // The DER SubjectPublicKeyInfo; two keys are the same key if and
+    // only if these are equal.
+    pub fn spki_der(&self) -> Result<Vec<u8>> {
+        unsafe {
+            let item = wrap_ffi(|| {
+                let item = ffi::SECKEY_EncodeDERSubjectPublicKeyInfo(self.as_raw_ptr());
+                if item.is_null() {
+                    GenStatus::ErrorFromC
+                } else {
+                    GenStatus::Success(item)
+                }
+            })?;
+            Ok(owned_sec_item_to_vec(item))
+        }
+    }
+
+    // Fails with SEC_ERROR_BAD_SIGNATURE if the signature doesn't match.
+    pub fn verify(&self, data: &[u8], signature: &[u8], mechanism: CkMechanism) -> Result<()> {
+        if unsafe { ffi::pk11::SECKEY_GetPublicKeyType(self.as_raw_ptr()) } != mechanism.key_type()
+        {
+            return Err(SEC_ERROR_INVALID_KEY.into());
+        }
+        // As in `sign`.
+        if data.len() > c_uint::MAX as usize || signature.len() > c_uint::MAX as usize {
+            return Err(SEC_ERROR_INVALID_ARGS.into());
+        }
+        wrap_ffi(|| unsafe {
+            ffi::pk11::PK11_VerifyWithMechanism(
+                self.as_raw_ptr(),
+                mechanism.to_ffi(),
+                ptr::null(),
+                &sec_item_from_slice(signature),
+                &sec_item_from_slice(data),
+                ptr::null_mut(),
+            )
+        })
+    }
+}
+
+impl Drop for PublicKey {
+    fn drop(&mut self) {
+        let ptr = mem::replace(&mut self.0, ptr::null_mut());
+        if !ptr.is_null() {
+            unsafe { ffi::SECKEY_DestroyPublicKey(ptr) }
+        }
+    }
+}
+
+// Signature mechanisms that include the hash.  ECDSA signatures are
+// the raw r || s, not DER-encoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CkMechanism {
+    RsaPkcs1Sha256,
+    RsaPkcs1Sha384,
+    RsaPkcs1Sha512,
+    EcdsaSha256,
+    EcdsaSha384,
+    EcdsaSha512,
+}
+
+impl CkMechanism {
+    pub fn to_ffi(self) -> ffi::pk11::CK_MECHANISM_TYPE {
+        match self {
+            CkMechanism::RsaPkcs1Sha256 => ffi::pk11::CKM_SHA256_RSA_PKCS,
+            CkMechanism::RsaPkcs1Sha384 => ffi::pk11::CKM_SHA384_RSA_PKCS,
+            CkMechanism::RsaPkcs1Sha512 => ffi::pk11::CKM_SHA512_RSA_PKCS,
+            CkMechanism::EcdsaSha256 => ffi::pk11::CKM_ECDSA_SHA256,
+            CkMechanism::EcdsaSha384 => ffi::pk11::CKM_ECDSA_SHA384,
+            CkMechanism::EcdsaSha512 => ffi::pk11::CKM_ECDSA_SHA512,
+        }
+    }
+    fn key_type(self) -> ffi::pk11::KeyType {
+        match self {
+            CkMechanism::RsaPkcs1Sha256
+            | CkMechanism::RsaPkcs1Sha384
+            | CkMechanism::RsaPkcs1Sha512 => ffi::pk11::rsaKey,
+            CkMechanism::EcdsaSha256 | CkMechanism::EcdsaSha384 | CkMechanism::EcdsaSha512 => {
+                ffi::pk11::ecKey
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EcCurve {
+    P256,
+    P384,
+    P521,
+    // Only for key agreement (e.g., ECH), not signatures.
+    X25519,
+}
+
+impl EcCurve {
+    // The DER-encoded OID, as PK11_GenerateKeyPair wants it.
+    fn oid_der(self) -> &'static [u8] {
+        match self {
+            EcCurve::P256 => &[0x06, 0x08, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07],
+            EcCurve::P384 => &[0x06, 0x05, 0x2b, 0x81, 0x04, 0x00, 0x22],
+            EcCurve::P521 => &[0x06, 0x05, 0x2b, 0x81, 0x04, 0x00, 0x23],
+            // NSS's (pre-RFC 8410) OID, 1.3.6.1.4.1.11591.15.1.
+            EcCurve::X25519 => &[
+                0x06, 0x09, 0x2b, 0x06, 0x01, 0x04, 0x01, 0xda, 0x47, 0x0f, 0x01,
+            ],
+        }
+    }
+}
+
+// Generated keys are session keys on the internal slot, like
+// imported ones.
+fn generate_keypair(
+    mechanism: ffi::pk11::CK_MECHANISM_TYPE,
+    param: *mut c_void,
+) -> Result<(PublicKey, PrivateKey)> {
+    let slot = Slot::internal()?;
+    let mut public = ptr::null_mut();
+    let private = wrap_ffi(|| unsafe {
+        let private = ffi::pk11::PK11_GenerateKeyPair(
+            slot.as_raw_ptr(),
+            mechanism,
+            param,
+            &mut public,
+            ffi::nspr::PR_FALSE,
+            ffi::nspr::PR_FALSE,
+            ptr::null_mut(),
+        );
+        PrivateKey::from_raw_ptr_opt(private).map_or(GenStatus::ErrorFromC, GenStatus::Success)
+    })?;
+    Ok((unsafe { PublicKey::from_raw_ptr(public) }, private))
+}
+
+// With the usual public exponent, 65537.
+pub fn generate_rsa_keypair(bits: u32) -> Result<(PublicKey, PrivateKey)> {
+    if !(1024..=16384).contains(&bits) || bits % 8 != 0 {
+        return Err(SEC_ERROR_INVALID_ARGS.into());
+    }
+    let mut params = ffi::pk11::PK11RSAGenParams {
+        keySizeInBits: bits as c_int,
+        pe: 65537,
+    };
+    generate_keypair(
+        ffi::pk11::CKM_RSA_PKCS_KEY_PAIR_GEN,
+        &mut params as *mut _ as *mut c_void,
+    )
+}
+
+pub fn generate_ec_keypair(curve: EcCurve) -> Result<(PublicKey, PrivateKey)> {
+    let mut params = sec_item_from_slice(curve.oid_der());
+    generate_keypair(
+        ffi::pk11::CKM_EC_KEY_PAIR_GEN,
+        &mut params as *mut _ as *mut c_void,
+    )
+}
+
+// What a symmetric key is for.  AES keys can be used with any of the
+// AES mechanisms, including wrapping other keys.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SymMechanism {
+    AesGcm,
+    AesCbc,
+    AesKeyWrap,
+    HmacSha256,
+}
+
+impl SymMechanism {
+    pub fn to_ffi(self) -> ffi::pk11::CK_MECHANISM_TYPE {
+        match self {
+            SymMechanism::AesGcm => ffi::pk11::CKM_AES_GCM,
+            SymMechanism::AesCbc => ffi::pk11::CKM_AES_CBC,
+            SymMechanism::AesKeyWrap => ffi::pk11::CKM_AES_KEY_WRAP,
+            SymMechanism::HmacSha256 => ffi::pk11::CKM_SHA256_HMAC,
+        }
+    }
+    // Generating needs the key type's mechanism, not the key's.
+    fn key_gen(self) -> ffi::pk11::CK_MECHANISM_TYPE {
+        match self {
+            SymMechanism::AesGcm | SymMechanism::AesCbc | SymMechanism::AesKeyWrap => {
+                ffi::pk11::CKM_AES_KEY_GEN
+            }
+            SymMechanism::HmacSha256 => ffi::pk11::CKM_GENERIC_SECRET_KEY_GEN,
+        }
+    }
+    // The key's main use, and all of the uses allowed.
+    fn usage(self) -> (ffi::pk11::CK_ATTRIBUTE_TYPE, ffi::pk11::CK_FLAGS) {
+        use ffi::pk11::*;
+        match self {
+            SymMechanism::AesGcm | SymMechanism::AesCbc | SymMechanism::AesKeyWrap => (
+                CKA_ENCRYPT,
+                CKF_ENCRYPT | CKF_DECRYPT | CKF_WRAP | CKF_UNWRAP,
+            ),
+            SymMechanism::HmacSha256 => (CKA_SIGN, CKF_SIGN | CKF_VERIFY),
+        }
+    }
+}
+
+// A secret key; like the other keys, a session key on the internal
+// slot.
+pub struct SymKey(*mut ffi::pk11::PK11SymKey);
+unsafe impl Send for SymKey {}
+unsafe impl Sync for SymKey {}
+
+impl SymKey {
+    /// # Safety
+    ///
+    /// `ptr` must be a non-null key that the caller owns; it's freed on
+    /// drop.
+    pub unsafe fn from_raw_ptr(ptr: *mut ffi::pk11::PK11SymKey) -> Self {
+        assert!(!ptr.is_null());
+        SymKey(ptr)
+    }
+    /// # Safety
+    ///
+    /// As for `from_raw_ptr`, except that `ptr` may be null.
+    pub unsafe fn from_raw_ptr_err(ptr: *mut ffi::pk11::PK11SymKey) -> GenStatus<Self> {
+        if ptr.is_null() {
+            GenStatus::ErrorFromC
+        } else {
+            GenStatus::Success(Self::from_raw_ptr(ptr))
+        }
+    }
+    pub fn as_raw_ptr(&self) -> *mut ffi::pk11::PK11SymKey {
+        debug_assert!(!self.0.is_null());
+        self.0
+    }
+
+    // `len` is in bytes.
+    pub fn generate(mechanism: SymMechanism, len: usize) -> Result<Self> {
+        if len == 0 || len > c_int::MAX as usize {
+            return Err(SEC_ERROR_INVALID_ARGS.into());
+        }
+        let slot = Slot::internal()?;
+        wrap_ffi(|| unsafe {
+            Self::from_raw_ptr_err(ffi::pk11::PK11_TokenKeyGenWithFlags(
+                slot.as_raw_ptr(),
+                mechanism.key_gen(),
+                ptr::null_mut(),
+                len as c_int,
+                ptr::null_mut(),
+                mechanism.usage().1,
+                0,
+                ptr::null_mut(),
+            ))
+        })
+    }
+
+    pub fn import(mechanism: SymMechanism, key: &[u8]) -> Result<Self> {
+        let (operation, flags) = mechanism.usage();
+        Self::import_raw(mechanism.to_ffi(), operation, flags, key)
+    }
+
+    fn import_raw(
+        mechanism: ffi::pk11::CK_MECHANISM_TYPE,
+        operation: ffi::pk11::CK_ATTRIBUTE_TYPE,
+        flags: ffi::pk11::CK_FLAGS,
+        key: &[u8],
+    ) -> Result<Self> {
+        if key.len() > c_uint::MAX as usize {
+            return Err(SEC_ERROR_INVALID_ARGS.into());
+        }
+        let slot = Slot::internal()?;
+        let mut item = sec_item_from_slice(key);
+        wrap_ffi(|| unsafe {
+            Self::from_raw_ptr_err(ffi::pk11::PK11_ImportSymKeyWithFlags(
+                slot.as_raw_ptr(),
+                mechanism,
+                ffi::pk11::PK11_OriginUnwrap,
+                operation,
+                &mut item,
+                flags,
+                ffi::nspr::PR_FALSE,
+                ptr::null_mut(),
+            ))
+        })
+    }
+
+    // The raw key, which fails for keys that are sensitive (e.g., from
+    // a token that won't reveal them).
+    pub fn key_data(&self) -> Result<Vec<u8>> {
+        unsafe {
+            wrap_ffi(|| ffi::pk11::PK11_ExtractKeyValue(self.as_raw_ptr()))?;
+            Ok(sec_item_to_vec(&*ffi::pk11::PK11_GetKeyData(
+                self.as_raw_ptr(),
+            )))
+        }
+    }
+
+    // With AES key wrap (RFC 3394), so `wrapping_key` has to be an AES
+    // key; the result is 8 bytes longer than the key.
+    pub fn wrap(&self, wrapping_key: &SymKey) -> Result<Vec<u8>> {
+        let len = unsafe { ffi::pk11::PK11_GetKeyLength(self.as_raw_ptr()) } as usize;
+        let mut buf = vec![0; len + 16];
+        let mut item = ffi::SECItem {
+            type_: ffi::SECItemType::siBuffer,
+            data: buf.as_mut_ptr(),
+            len: buf.len() as c_uint,
+        };
+        wrap_ffi(|| unsafe {
+            ffi::pk11::PK11_WrapSymKey(
+                ffi::pk11::CKM_AES_KEY_WRAP,
+                ptr::null_mut(),
+                wrapping_key.as_raw_ptr(),
+                self.as_raw_ptr(),
+                &mut item,
+            )
+        })?;
+        buf.truncate(item.len as usize);
+        Ok(buf)
+    }
+
+    // The inverse of `wrap`, for a key to be used with `mechanism`.  A
+    // wrong `wrapping_key` (or corrupted input) fails the integrity
+    // check.
+    pub fn unwrap(wrapped: &[u8], wrapping_key: &SymKey, mechanism: SymMechanism) -> Result<Self> {
+        if wrapped.len() > c_uint::MAX as usize {
+            return Err(SEC_ERROR_INVALID_ARGS.into());
+        }
+        let (operation, flags) = mechanism.usage();
+        let mut item = sec_item_from_slice(wrapped);
+        wrap_ffi(|| unsafe {
+            Self::from_raw_ptr_err(ffi::pk11::PK11_UnwrapSymKeyWithFlags(
+                wrapping_key.as_raw_ptr(),
+                ffi::pk11::CKM_AES_KEY_WRAP,
+                ptr::null_mut(),
+                &mut item,
+                mechanism.to_ffi(),
+                operation,
+                0,
+                flags,
+            ))
+        })
+    }
+}
+
+impl Drop for SymKey {
+    fn drop(&mut self) {
+        let ptr = mem::replace(&mut self.0, ptr::null_mut());
+        if !ptr.is_null() {
+            unsafe { ffi::pk11::PK11_FreeSymKey(ptr) }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HashAlgorithm {
+    Sha256,
+    Sha384,
+    Sha512,
+}
+
+impl HashAlgorithm {
+    pub fn to_ffi(self) -> ffi::pk11::CK_MECHANISM_TYPE {
+        match self {
+            HashAlgorithm::Sha256 => ffi::pk11::CKM_SHA256,
+            HashAlgorithm::Sha384 => ffi::pk11::CKM_SHA384,
+            HashAlgorithm::Sha512 => ffi::pk11::CKM_SHA512,
+        }
+    }
+    pub fn to_oid_tag(self) -> ffi::SECOidTag {
+        match self {
+            HashAlgorithm::Sha256 => ffi::SEC_OID_SHA256,
+            HashAlgorithm::Sha384 => ffi::pk11::SEC_OID_SHA384,
+            HashAlgorithm::Sha512 => ffi::pk11::SEC_OID_SHA512,
+        }
+    }
+    // The digest length, in bytes.
+    pub fn digest_len(self) -> usize {
+        match self {
+            HashAlgorithm::Sha256 => 32,
+            HashAlgorithm::Sha384 => 48,
+            HashAlgorithm::Sha512 => 64,
+        }
+    }
+}
+
+// HKDF (RFC 5869), extract and expand in one go.  `out_len` can be at
+// most 255 times the hash length.
+pub fn hkdf(
+    hash: HashAlgorithm,
+    salt: &[u8],
+    ikm: &[u8],
+    info: &[u8],
+    out_len: usize,
+) -> Result<Vec<u8>> {
+    if out_len == 0 || out_len > 255 * hash.digest_len() {
+        return Err(SEC_ERROR_INVALID_ARGS.into());
+    }
+    // No salt means a string of zeros as long as the hash.
+    let zero_salt;
+    let salt = if salt.is_empty() {
+        zero_salt = vec![0; hash.digest_len()];
+        &zero_salt[..]
+    } else {
+        salt
+    };
+    let salt_len = ffi::CK_ULONG::try_from(salt.len()).map_err(|_| SEC_ERROR_INVALID_ARGS)?;
+    let info_len = ffi::CK_ULONG::try_from(info.len()).map_err(|_| SEC_ERROR_INVALID_ARGS)?;
+    let ikm = SymKey::import_raw(ffi::pk11::CKM_HKDF_DERIVE, ffi::pk11::CKA_DERIVE, 0, ikm)?;
+    let mut params = ffi::pk11::CK_HKDF_PARAMS {
+        bExtract: ffi::pk11::CK_TRUE,
+        bExpand: ffi::pk11::CK_TRUE,
+        prfHashMechanism: hash.to_ffi(),
+        ulSaltType: ffi::pk11::CKF_HKDF_SALT_DATA,
+        pSalt: salt.as_ptr() as *mut _,
+        ulSaltLen: salt_len,
+        hSaltKey: ffi::cert::CK_INVALID_HANDLE,
+        pInfo: info.as_ptr() as *mut _,
+        ulInfoLen: info_len,
+    };
+    let mut param = ffi::SECItem {
+        type_: ffi::SECItemType::siBuffer,
+        data: &mut params as *mut _ as *mut _,
+        len: mem::size_of_val(&params) as c_uint,
+    };
+    let okm = wrap_ffi(|| unsafe {
+        SymKey::from_raw_ptr_err(ffi::pk11::PK11_Derive(
+            ikm.as_raw_ptr(),
+            ffi::pk11::CKM_HKDF_DATA,
+            &mut param,
+            ffi::pk11::CKM_HKDF_DERIVE,
+            ffi::pk11::CKA_DERIVE,
+            out_len as c_int,
+        ))
+    })?;
+    okm.key_data()
+}
+
+pub fn hash(hash: HashAlgorithm, data: &[u8]) -> Result<Vec<u8>> {
+    let mut out = vec![0u8; hash.digest_len()];
+    // PK11_HashBuf takes the length as an i32.
+    if data.len() > i32::MAX as usize {
+        return Err(SEC_ERROR_INVALID_ARGS.into());
+    }
+    wrap_ffi(|| unsafe {
+        ffi::PK11_HashBuf(
+            hash.to_oid_tag(),
+            out.as_mut_ptr(),
+            data.as_ptr(),
+            data.len() as i32,
+        )
+    })?;
+    Ok(out)
+}
+
+pub fn sha256(data: &[u8]) -> Result<[u8; 32]> {
+    let mut out = [0u8; 32];
+    out.copy_from_slice(&hash(HashAlgorithm::Sha256, data)?);
+    Ok(out)
+}
+
+// Compares in constant time, for MACs and the like; only the lengths
+// can make it return early.
+pub fn secure_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    unsafe { ffi::NSS_SecureMemcmp(a.as_ptr() as *const _, b.as_ptr() as *const _, a.len()) == 0 }
+}
+
+// Zeroes `buf` in a way the compiler can't drop as a dead store.
+pub fn secure_zero(buf: &mut [u8]) {
+    for byte in buf.iter_mut() {
+        unsafe { ptr::write_volatile(byte, 0) }
+    }
+    atomic::compiler_fence(atomic::Ordering::SeqCst);
+}
+
+// Key material, passwords, etc., which are wiped when dropped.  Only
+// the current contents are: a `Vec` that has been grown may have left
+// copies behind, so reserve enough up front.
+pub struct Zeroizing<T: AsMut<[u8]>>(pub T);
+
+impl<T: AsMut<[u8]>> Deref for Zeroizing<T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T: AsMut<[u8]>> DerefMut for Zeroizing<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+impl<T: AsMut<[u8]>> Drop for Zeroizing<T> {
+    fn drop(&mut self) {
+        secure_zero(self.0.as_mut())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use error::SEC_ERROR_BAD_SIGNATURE;
+    use init;
+    use test_util::TempDb;
+
+    #[test]
+    fn generate_random() {
+        init().unwrap();
+        let slot = Slot::internal().unwrap();
+        let (mut a, mut b) = ([0u8; 64], [0u8; 64]);
+        slot.generate_random(&mut a).unwrap();
+        slot.generate_random(&mut b).unwrap();
+        assert_ne!(a, [0; 64]);
+        assert_ne!(a, b);
+        slot.generate_random(&mut []).unwrap();
+    }
+
+    // A password-protected key DB of its own.
+    #[test]
+    fn login() {
+        init().unwrap();
+        let db = TempDb::new("login");
+        let slot = &db.slot;
+        let password = CString::new("hunter2").unwrap();
+        wrap_ffi(|| unsafe {
+            ffi::pk11::PK11_ChangePW(
+                slot.as_raw_ptr(),
+                b"\0".as_ptr() as *const _,
+                password.as_ptr(),
+            )
+        })
+        .unwrap();
+        slot.logout().unwrap();
+        assert!(slot.needs_login());
+
+        // Permanent private keys need a login.
+        let generate = || {
+            let mut curve = sec_item_from_slice(EcCurve::P256.oid_der());
+            let mut public = ptr::null_mut();
+            wrap_ffi(|| unsafe {
+                let private = ffi::pk11::PK11_GenerateKeyPair(
+                    slot.as_raw_ptr(),
+                    ffi::pk11::CKM_EC_KEY_PAIR_GEN,
+                    &mut curve as *mut _ as *mut c_void,
+                    &mut public,
+                    ffi::nspr::PR_TRUE,
+                    ffi::nspr::PR_TRUE,
+                    ptr::null_mut(),
+                );
+                PrivateKey::from_raw_ptr_opt(private)
+                    .map_or(GenStatus::ErrorFromC, GenStatus::Success)
+            })
+            .map(|private| (unsafe { PublicKey::from_raw_ptr(public) }, private))
+        };
+        assert!(generate().is_err());
+        assert_eq!(
+            slot.login("hunter3").unwrap_err().nspr_error,
+            SEC_ERROR_BAD_PASSWORD
+        );
+        assert!(slot.needs_login());
+        slot.login("hunter2").unwrap();
+        assert!(!slot.needs_login());
+        let (public, private) = generate().unwrap();
+        let sig = private.sign(b"data", CkMechanism::EcdsaSha256).unwrap();
+        public
+            .verify(b"data", &sig, CkMechanism::EcdsaSha256)
+            .unwrap();
+        drop((public, private));
+
+        slot.logout().unwrap();
+        assert!(slot.needs_login());
+    }
+
+    #[test]
+    fn list_certs() {
+        use cert::{RdnOid, TrustFlags};
+
+        init().unwrap();
+        let db = TempDb::new("list");
+        assert!(db.slot.list_certs().unwrap().is_empty());
+        let client = Certificate::from_der(include_bytes!("../testdata/client.der")).unwrap();
+        let nickname = CStr::from_bytes_with_nul(b"nss-rs list client\0").unwrap();
+        client
+            .import_to_slot(&db.slot, nickname, TrustFlags::empty())
+            .unwrap();
+        let certs = db.slot.list_certs().unwrap();
+        let found = certs
+            .iter()
+            .find(|cert| {
+                cert.subject_component(RdnOid::CommonName).unwrap() == Some("client".to_owned())
+            })
+            .unwrap();
+        assert_eq!(found.as_der(), client.as_der());
+    }
+
+    // RFC 5869, test cases 1 and 3.
+    #[test]
+    fn hkdf_sha256() {
+        init().unwrap();
+        let ikm = [0x0b; 22];
+        let salt: Vec<u8> = (0..13).collect();
+        let info: Vec<u8> = (0xf0..0xfa).collect();
+        let okm = hkdf(HashAlgorithm::Sha256, &salt, &ikm, &info, 42).unwrap();
+        assert_eq!(
+            okm,
+            &b"\x3c\xb2\x5f\x25\xfa\xac\xd5\x7a\x90\x43\x4f\x64\xd0\x36\x2f\x2a\
+               \x2d\x2d\x0a\x90\xcf\x1a\x5a\x4c\x5d\xb0\x2d\x56\xec\xc4\xc5\xbf\
+               \x34\x00\x72\x08\xd5\xb8\x87\x18\x58\x65"[..]
+        );
+        let okm = hkdf(HashAlgorithm::Sha256, &[], &ikm, &[], 42).unwrap();
+        assert_eq!(
+            okm,
+            &b"\x8d\xa4\xe7\x75\xa5\x63\xc1\x8f\x71\x5f\x80\x2a\x06\x3c\x5a\x31\
+               \xb8\xa1\x1f\x5c\x5e\xe1\x87\x9e\xc3\x45\x4e\x5f\x3c\x73\x8d\x2d\
+               \x9d\x20\x13\x95\xfa\xa4\xb6\x1a\x96\xc8"[..]
+        );
+        let err = hkdf(HashAlgorithm::Sha256, &salt, &ikm, &info, 255 * 32 + 1)
+            .err()
+            .unwrap();
+        assert_eq!(err.nspr_error, SEC_ERROR_INVALID_ARGS);
+    }
+
+    #[test]
+    fn secure_eq_slices() {
+        init().unwrap();
+        let mac = sha256(b"message").unwrap();
+        let mut other = mac;
+        assert!(secure_eq(&mac, &other));
+        other[31] ^= 1;
+        assert!(!secure_eq(&mac, &other));
+        assert!(!secure_eq(&mac, &mac[..31]));
+        assert!(secure_eq(&[], &[]));
+    }
+
+    #[test]
+    fn zeroizing() {
+        let mut key = [0x5a; 32];
+        {
+            let mut held = Zeroizing(&mut key[..]);
+            held[0] = 1;
+            assert!(held.iter().any(|&b| b != 0));
+        }
+        assert_eq!(key, [0; 32]);
+
+        let mut password = Zeroizing(Vec::with_capacity(16));
+        password.extend_from_slice(b"hunter2");
+        assert_eq!(&password[..], b"hunter2");
+        secure_zero(&mut password);
+        assert_eq!(&password[..], &[0; 7]);
+    }
+
+    #[test]
+    fn wrap_sym_key() {
+        init().unwrap();
+        let kek = SymKey::generate(SymMechanism::AesKeyWrap, 32).unwrap();
+        let data_key: Vec<u8> = (0..32).collect();
+        let key = SymKey::import(SymMechanism::AesGcm, &data_key).unwrap();
+        let wrapped = key.wrap(&kek).unwrap();
+        assert_eq!(wrapped.len(), 40);
+        assert!(!wrapped.windows(data_key.len()).any(|w| w == &data_key[..]));
+
+        let unwrapped = SymKey::unwrap(&wrapped, &kek, SymMechanism::AesGcm).unwrap();
+        assert_eq!(unwrapped.key_data().unwrap(), data_key);
+        let other = SymKey::generate(SymMechanism::AesKeyWrap, 32).unwrap();
+        assert!(SymKey::unwrap(&wrapped, &other, SymMechanism::AesGcm).is_err());
+    }
+
+    #[test]
+    fn sign_verify() {
+        init().unwrap();
+        let key = PrivateKey::from_pkcs8_der(include_bytes!("../testdata/leaf.key.der")).unwrap();
+        let public = key.public_key().unwrap();
+        let sig = key.sign(b"data", CkMechanism::RsaPkcs1Sha256).unwrap();
+        assert_eq!(sig.len(), 256);
+        public
+            .verify(b"data", &sig, CkMechanism::RsaPkcs1Sha256)
+            .unwrap();
+        let err = public
+            .verify(b"date", &sig, CkMechanism::RsaPkcs1Sha256)
+            .unwrap_err();
+        assert_eq!(err.nspr_error, SEC_ERROR_BAD_SIGNATURE);
+        let err = key.sign(b"data", CkMechanism::EcdsaSha256).unwrap_err();
+        assert_eq!(err.nspr_error, SEC_ERROR_INVALID_KEY);
+    }
+
+    #[test]
+    fn generate() {
+        init().unwrap();
+        let (public, private) = generate_rsa_keypair(2048).unwrap();
+        let sig = private.sign(b"data", CkMechanism::RsaPkcs1Sha256).unwrap();
+        assert_eq!(sig.len(), 256);
+        public
+            .verify(b"data", &sig, CkMechanism::RsaPkcs1Sha256)
+            .unwrap();
+        assert_eq!(
+            private.public_key().unwrap().spki_der().unwrap(),
+            public.spki_der().unwrap()
+        );
+
+        let (public, private) = generate_ec_keypair(EcCurve::P256).unwrap();
+        let sig = private.sign(b"data", CkMechanism::EcdsaSha256).unwrap();
+        assert_eq!(sig.len(), 64);
+        public
+            .verify(b"data", &sig, CkMechanism::EcdsaSha256)
+            .unwrap();
+
+        match generate_rsa_keypair(1000) {
+            Err(err) => assert_eq!(err.nspr_error, SEC_ERROR_INVALID_ARGS),
+            Ok(_) => panic!("odd key size accepted"),
+        }
+    }
+}
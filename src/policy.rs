@@ -0,0 +1,157 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+// Process-wide restrictions on which algorithms NSS will use; these
+// apply to every socket and certificate verification, including ones
+// already in progress.
+
+use error::SEC_ERROR_INVALID_ARGS;
+use nss_sys as ffi;
+use {wrap_ffi, Result};
+
+pub use nss_sys::pk11::{
+    NSS_USE_ALG_IN_ANY_SIGNATURE, NSS_USE_ALG_IN_CERT_SIGNATURE, NSS_USE_ALG_IN_CMS_SIGNATURE,
+    NSS_USE_ALG_IN_PKCS12, NSS_USE_ALG_IN_SIGNATURE, NSS_USE_ALG_IN_SSL, NSS_USE_ALG_IN_SSL_KX,
+    NSS_USE_POLICY_IN_SSL,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AlgOid {
+    Md5,
+    Sha1,
+    Sha224,
+    Sha256,
+    Sha384,
+    Sha512,
+}
+
+impl AlgOid {
+    fn to_ffi(self) -> ffi::SECOidTag {
+        use ffi::pk11::*;
+        match self {
+            AlgOid::Md5 => SEC_OID_MD5,
+            AlgOid::Sha1 => SEC_OID_SHA1,
+            AlgOid::Sha224 => SEC_OID_SHA224,
+            AlgOid::Sha256 => SEC_OID_SHA256,
+            AlgOid::Sha384 => SEC_OID_SHA384,
+            AlgOid::Sha512 => SEC_OID_SHA512,
+        }
+    }
+}
+
+// The `NSS_USE_*` flags currently set for `oid`.
+pub fn algorithm_policy(oid: AlgOid) -> Result<u32> {
+    let mut flags = 0;
+    wrap_ffi(|| unsafe { ffi::pk11::NSS_GetAlgorithmPolicy(oid.to_ffi(), &mut flags) })?;
+    Ok(flags)
+}
+
+// E.g., clearing NSS_USE_ALG_IN_CERT_SIGNATURE for `AlgOid::Sha1`
+// makes certificates signed with SHA-1 fail verification with
+// SEC_ERROR_CERT_SIGNATURE_ALGORITHM_DISABLED.  The TLS flags only
+// take effect on sockets with NSS_USE_POLICY_IN_SSL set.
+pub fn set_algorithm_policy(oid: AlgOid, set_flags: u32, clear_flags: u32) -> Result<()> {
+    wrap_ffi(|| unsafe { ffi::pk11::NSS_SetAlgorithmPolicy(oid.to_ffi(), set_flags, clear_flags) })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum KeyType {
+    Rsa,
+    // For (finite field) DHE key exchange.
+    Dh,
+    Dsa,
+}
+
+impl KeyType {
+    fn option(self) -> i32 {
+        match self {
+            KeyType::Rsa => ffi::NSS_RSA_MIN_KEY_SIZE,
+            KeyType::Dh => ffi::NSS_DH_MIN_KEY_SIZE,
+            KeyType::Dsa => ffi::NSS_DSA_MIN_KEY_SIZE,
+        }
+    }
+}
+
+pub fn min_key_size(key_type: KeyType) -> Result<u32> {
+    let mut bits = 0;
+    wrap_ffi(|| unsafe { ffi::NSS_OptionGet(key_type.option(), &mut bits) })?;
+    Ok(bits as u32)
+}
+
+// Smaller keys are rejected in TLS handshakes (a server certificate's
+// key, or DHE parameters) and in certificate verification.
+pub fn set_min_key_size(key_type: KeyType, bits: u32) -> Result<()> {
+    if bits > i32::MAX as u32 {
+        return Err(SEC_ERROR_INVALID_ARGS.into());
+    }
+    wrap_ffi(|| unsafe { ffi::NSS_OptionSet(key_type.option(), bits as i32) })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cert::Certificate;
+    use error::SEC_ERROR_CERT_SIGNATURE_ALGORITHM_DISABLED;
+    use init;
+    use test_util::{lock_global, trust_test_root, Restore};
+
+    #[test]
+    fn sha1_cert_signature() {
+        init().unwrap();
+        let _root = trust_test_root();
+        let _intermediate =
+            Certificate::from_der(include_bytes!("../testdata/intermediate.der")).unwrap();
+        let leaf = Certificate::from_der(include_bytes!("../testdata/sha1-leaf.der")).unwrap();
+        let before = algorithm_policy(AlgOid::Sha1).unwrap();
+        let _restore = Restore(|| set_algorithm_policy(AlgOid::Sha1, before, !before).unwrap());
+        set_algorithm_policy(AlgOid::Sha1, NSS_USE_ALG_IN_CERT_SIGNATURE, 0).unwrap();
+        leaf.verify(None).unwrap();
+
+        set_algorithm_policy(AlgOid::Sha1, 0, NSS_USE_ALG_IN_CERT_SIGNATURE).unwrap();
+        assert_eq!(
+            algorithm_policy(AlgOid::Sha1).unwrap() & NSS_USE_ALG_IN_CERT_SIGNATURE,
+            0
+        );
+        let err = leaf.verify(None).unwrap_err();
+        assert_eq!(err.nspr_error, SEC_ERROR_CERT_SIGNATURE_ALGORITHM_DISABLED);
+    }
+
+    #[test]
+    fn min_rsa_key_size() {
+        use cert::generate_self_signed;
+        use error::SSL_ERROR_WEAK_SERVER_CERT_KEY;
+        use pk11::generate_rsa_keypair;
+        use std::time::Duration;
+        use testing::{handshake, memory_socket_pair, new_test_pair};
+        use {TLSConfig, SIG_RSA_PSS_RSAE_SHA256};
+
+        init().unwrap();
+        let _lock = lock_global();
+        // Other tests use 2048-bit keys, so this doesn't affect them.
+        let (public, private) = generate_rsa_keypair(1024).unwrap();
+        let cert = generate_self_signed(
+            "CN=localhost",
+            &private,
+            &public,
+            &[],
+            Duration::from_secs(3600),
+        )
+        .unwrap();
+        let before = min_key_size(KeyType::Rsa).unwrap();
+        assert!(before <= 1024);
+        let _restore = Restore(|| set_min_key_size(KeyType::Rsa, before).unwrap());
+        set_min_key_size(KeyType::Rsa, 2048).unwrap();
+        assert_eq!(min_key_size(KeyType::Rsa).unwrap(), 2048);
+
+        let (mut client, mut server) =
+            new_test_pair(memory_socket_pair(), &TLSConfig::new(), (), ()).unwrap();
+        server.config_server_cert(&cert, &private, &[]).unwrap();
+        // So the server uses that, and not its own EC certificate.
+        client
+            .set_signature_schemes(&[SIG_RSA_PSS_RSAE_SHA256])
+            .unwrap();
+        let err = handshake(&client, &server).unwrap_err();
+        assert_eq!(err.nspr_error, SSL_ERROR_WEAK_SERVER_CERT_KEY);
+    }
+}
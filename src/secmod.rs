@@ -0,0 +1,145 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+// External PKCS#11 modules (HSMs, smartcards, etc.), which provide
+// more slots alongside NSS's own.
+
+use error::{SEC_ERROR_INVALID_ARGS, SEC_ERROR_LIBRARY_FAILURE};
+use nss_sys::nspr::PR_FALSE;
+use nss_sys::pk11 as ffi;
+use pk11::Slot;
+use std::ffi::{CStr, CString};
+use std::mem;
+use std::path::Path;
+use std::ptr;
+use {wrap_ffi, GenStatus, Result};
+
+pub struct Module(*mut ffi::SECMODModule);
+// Modules are reference-counted, and their slot list is locked.
+unsafe impl Send for Module {}
+unsafe impl Sync for Module {}
+
+// Quoting for a module spec's values.
+fn spec_quote(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for c in value.chars() {
+        if c == '"' || c == '\\' {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+    quoted
+}
+
+// Loads the PKCS#11 library at `library` (a bare file name is looked
+// up the usual way for shared libraries), which stays loaded until
+// `unload` even if the `Module` is dropped.  `name` is what NSS will
+// call it, and must be unique.
+pub fn load_module(name: &str, library: &Path) -> Result<Module> {
+    let library = library.to_str().ok_or(SEC_ERROR_INVALID_ARGS)?;
+    let spec = format!("name={} library={}", spec_quote(name), spec_quote(library));
+    let spec = CString::new(spec).map_err(|_| SEC_ERROR_INVALID_ARGS)?;
+    let module = wrap_ffi(|| unsafe {
+        let module = ffi::SECMOD_LoadUserModule(spec.as_ptr() as *mut _, ptr::null_mut(), PR_FALSE);
+        if module.is_null() {
+            GenStatus::ErrorFromC
+        } else {
+            GenStatus::Success(Module(module))
+        }
+    })?;
+    // A module that failed to load is still returned, just not loaded.
+    if unsafe { (*module.0).loaded } == PR_FALSE {
+        return Err(SEC_ERROR_LIBRARY_FAILURE.into());
+    }
+    Ok(module)
+}
+
+impl Module {
+    pub fn as_raw_ptr(&self) -> *mut ffi::SECMODModule {
+        debug_assert!(!self.0.is_null());
+        self.0
+    }
+
+    pub fn name(&self) -> String {
+        unsafe { CStr::from_ptr((*self.as_raw_ptr()).commonName) }
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    pub fn slots(&self) -> Vec<Slot> {
+        unsafe {
+            let lock = ffi::SECMOD_GetDefaultModuleListLock();
+            ffi::SECMOD_GetReadLock(lock);
+            let module = &*self.as_raw_ptr();
+            let slots = (0..module.slotCount as isize)
+                .map(|i| Slot::from_raw_ptr(ffi::PK11_ReferenceSlot(*module.slots.offset(i))))
+                .collect();
+            ffi::SECMOD_ReleaseReadLock(lock);
+            slots
+        }
+    }
+
+    // Removes the module from NSS; its slots (and any keys or certs
+    // from them) can't be used afterwards.
+    pub fn unload(self) -> Result<()> {
+        wrap_ffi(|| unsafe { ffi::SECMOD_UnloadUserModule(self.as_raw_ptr()) })
+    }
+}
+
+impl Drop for Module {
+    fn drop(&mut self) {
+        let module = mem::replace(&mut self.0, ptr::null_mut());
+        if !module.is_null() {
+            unsafe { ffi::SECMOD_DestroyModule(module) }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use init;
+    use test_util::lock_global;
+
+    // NSS's built-in root certificates module is usually installed
+    // alongside it, and has one slot.
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn load_builtins() {
+        use libc::{dlclose, dlopen, RTLD_LAZY};
+
+        init().unwrap();
+        // Some distributions package it separately.
+        let handle = unsafe { dlopen(b"libnssckbi.so\0".as_ptr() as *const _, RTLD_LAZY) };
+        if handle.is_null() {
+            return;
+        }
+        unsafe { dlclose(handle) };
+        // The roots' slot is process-wide until it's unloaded, which
+        // happens before anything is checked.
+        let _lock = lock_global();
+        let module = load_module("nss-rs \"test\" roots", Path::new("libnssckbi.so")).unwrap();
+        let name = module.name();
+        let tokens: Vec<String> = module
+            .slots()
+            .iter()
+            .map(|slot| slot.token_name())
+            .collect();
+        module.unload().unwrap();
+        assert_eq!(name, "nss-rs \"test\" roots");
+        assert!(!tokens.is_empty());
+        assert!(tokens.iter().any(|token| token == "Builtin Object Token"));
+    }
+
+    #[test]
+    fn load_missing() {
+        init().unwrap();
+        let err = load_module("nss-rs missing", Path::new("/nonexistent/libpkcs11.so"))
+            .err()
+            .unwrap();
+        assert_eq!(err.nspr_error, SEC_ERROR_LIBRARY_FAILURE);
+    }
+}
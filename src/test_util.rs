@@ -0,0 +1,124 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+// Helpers shared by the unit tests in several modules.
+
+use nss_sys as ffi;
+use pk11::Slot;
+use std::ffi::CString;
+use std::ops::Deref;
+use std::path::PathBuf;
+use std::ptr;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::{env, fs, mem, process};
+use {wrap_ffi, Certificate};
+
+pub static ROOT_DER: &[u8] = include_bytes!("../testdata/root-ca.der");
+
+lazy_static! {
+    // Held by tests that change process-wide NSS state (trust,
+    // policy, OCSP, the session cache, extra slots) or that depend on
+    // which certificates NSS can find, so they don't run into each
+    // other.  Not reentrant: take it once per test.
+    pub static ref NSS_GLOBAL: Mutex<()> = Mutex::new(());
+}
+
+pub type GlobalLock = MutexGuard<'static, ()>;
+
+// A test that failed while holding the lock doesn't matter to the
+// next one.
+pub fn lock_global() -> GlobalLock {
+    NSS_GLOBAL.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+// Runs `F` on drop, to undo a process-wide change even if the test
+// panics; declare it after the lock so it runs first.
+pub struct Restore<F: FnMut()>(pub F);
+
+impl<F: FnMut()> Drop for Restore<F> {
+    fn drop(&mut self) {
+        (self.0)()
+    }
+}
+
+// The test root, holding `NSS_GLOBAL` for as long as it's kept.
+pub struct TrustedRoot {
+    cert: Certificate,
+    _lock: GlobalLock,
+}
+
+impl Deref for TrustedRoot {
+    type Target = Certificate;
+    fn deref(&self) -> &Certificate {
+        &self.cert
+    }
+}
+
+// Makes the test root CA a trust anchor.  This is process-wide, but
+// there's no harm in repeating it.
+pub fn trust_test_root() -> TrustedRoot {
+    let lock = lock_global();
+    let root = Certificate::from_der(ROOT_DER).unwrap();
+    unsafe {
+        let mut trust = mem::zeroed();
+        let flags = b"C,,\0";
+        wrap_ffi(|| ffi::CERT_DecodeTrustString(&mut trust, flags.as_ptr() as *const _)).unwrap();
+        wrap_ffi(|| {
+            ffi::CERT_ChangeCertTrust(
+                ffi::CERT_GetDefaultCertDB(),
+                root.as_raw_ptr() as *mut _,
+                &mut trust,
+            )
+        })
+        .unwrap();
+    }
+    TrustedRoot {
+        cert: root,
+        _lock: lock,
+    }
+}
+
+// A new, empty cert and key DB in a temporary directory, opened as an
+// extra slot alongside the no-DB ones, with an empty password (as
+// `certutil -N --empty-password` would); closed and deleted on drop.
+// Holds `NSS_GLOBAL`, since the slot is visible to everything.
+pub struct TempDb {
+    pub slot: Slot,
+    dir: PathBuf,
+    _lock: GlobalLock,
+}
+
+impl TempDb {
+    pub fn new(name: &str) -> Self {
+        let lock = lock_global();
+        let dir = env::temp_dir().join(format!("nss-rs-{}-{}", name, process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let spec = format!(
+            "configdir='sql:{}' tokenDescription='nss-rs {}'",
+            dir.display(),
+            name
+        );
+        let spec = CString::new(spec).unwrap();
+        let slot = wrap_ffi(|| unsafe {
+            Slot::from_raw_ptr_err(ffi::pk11::SECMOD_OpenUserDB(spec.as_ptr()))
+        })
+        .unwrap();
+        wrap_ffi(|| unsafe {
+            ffi::pk11::PK11_InitPin(slot.as_raw_ptr(), ptr::null(), b"\0".as_ptr() as *const _)
+        })
+        .unwrap();
+        TempDb {
+            slot,
+            dir,
+            _lock: lock,
+        }
+    }
+}
+
+impl Drop for TempDb {
+    fn drop(&mut self) {
+        let _ = wrap_ffi(|| unsafe { ffi::pk11::SECMOD_CloseUserDB(self.slot.as_raw_ptr()) });
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
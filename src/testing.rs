@@ -0,0 +1,221 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+// A connected TLS client and server in one process, for tests (and as
+// an example of the plumbing).
+
+use super::{init, Result, TLSConfig, TLSSocket, SSL_NO_CACHE};
+use cert::{generate_self_signed, GeneralName};
+use error::{PR_UNKNOWN_ERROR, PR_WOULD_BLOCK_ERROR};
+use nspr::fd::{File, FileMethods, FileWrapper, PR_DESC_SOCKET_TCP};
+use pk11::{generate_ec_keypair, EcCurve};
+use std::cmp;
+use std::collections::VecDeque;
+use std::ffi::CStr;
+use std::net::{Ipv4Addr, Shutdown, SocketAddr, SocketAddrV4};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+// One direction of a `MemorySocket` pair.
+#[derive(Default)]
+struct Channel {
+    data: VecDeque<u8>,
+    closed: bool,
+}
+
+// A non-blocking in-memory stream socket: reads with nothing buffered
+// fail with `PR_WOULD_BLOCK_ERROR` until the other end shuts down
+// writing or is closed.
+struct MemorySocket {
+    incoming: Arc<Mutex<Channel>>,
+    outgoing: Arc<Mutex<Channel>>,
+}
+
+impl MemorySocket {
+    fn pair() -> (Self, Self) {
+        let (a, b) = (Arc::default(), Arc::default());
+        (
+            MemorySocket {
+                incoming: Arc::clone(&a),
+                outgoing: Arc::clone(&b),
+            },
+            MemorySocket {
+                incoming: b,
+                outgoing: a,
+            },
+        )
+    }
+}
+
+impl Drop for MemorySocket {
+    fn drop(&mut self) {
+        self.outgoing.lock().unwrap().closed = true;
+    }
+}
+
+impl FileMethods for MemorySocket {
+    fn read(&self, buf: &mut [u8]) -> Result<usize> {
+        let mut incoming = self.incoming.lock().unwrap();
+        if incoming.data.is_empty() && !buf.is_empty() {
+            return if incoming.closed {
+                Ok(0)
+            } else {
+                Err(PR_WOULD_BLOCK_ERROR.into())
+            };
+        }
+        let len = cmp::min(buf.len(), incoming.data.len());
+        for (dst, src) in buf.iter_mut().zip(incoming.data.drain(..len)) {
+            *dst = src;
+        }
+        Ok(len)
+    }
+    fn write(&self, buf: &[u8]) -> Result<usize> {
+        let mut outgoing = self.outgoing.lock().unwrap();
+        if outgoing.closed {
+            return Err(PR_UNKNOWN_ERROR.into());
+        }
+        outgoing.data.extend(buf);
+        Ok(buf.len())
+    }
+    fn recv(&self, buf: &mut [u8], peek: bool, _timeout: Option<Duration>) -> Result<usize> {
+        if peek {
+            let incoming = self.incoming.lock().unwrap();
+            let len = cmp::min(buf.len(), incoming.data.len());
+            for (dst, src) in buf.iter_mut().zip(incoming.data.iter()) {
+                *dst = *src;
+            }
+            return if len == 0 && !buf.is_empty() && !incoming.closed {
+                Err(PR_WOULD_BLOCK_ERROR.into())
+            } else {
+                Ok(len)
+            };
+        }
+        self.read(buf)
+    }
+    fn send(&self, buf: &[u8], _timeout: Option<Duration>) -> Result<usize> {
+        self.write(buf)
+    }
+    fn shutdown(&self, how: Shutdown) -> Result<()> {
+        if how != Shutdown::Read {
+            self.outgoing.lock().unwrap().closed = true;
+        }
+        Ok(())
+    }
+    // There are no real addresses.
+    fn getsockname(&self) -> Result<SocketAddr> {
+        Ok(SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 0)))
+    }
+    fn getpeername(&self) -> Result<SocketAddr> {
+        self.getsockname()
+    }
+    fn get_nonblocking(&self) -> Result<bool> {
+        Ok(true)
+    }
+}
+
+// A pair of connected, non-blocking in-memory sockets.
+pub fn memory_socket_pair() -> (File, File) {
+    let wrapper = FileWrapper::new(PR_DESC_SOCKET_TCP);
+    let (a, b) = MemorySocket::pair();
+    (wrapper.wrap(a), wrapper.wrap(b))
+}
+
+// Handshake steps are bounded, so a stuck handshake fails instead of
+// spinning forever.
+const MAX_HANDSHAKE_ROUNDS: usize = 100;
+
+// A client and server, in that order, connected over
+// `memory_socket_pair` with the handshake done.  `config` is applied
+// to both; the server has a new self-signed certificate for
+// "localhost", which the client doesn't check.  Both are non-blocking,
+// so a read with nothing to read fails with `PR_WOULD_BLOCK_ERROR`.
+pub fn test_pair(config: &TLSConfig) -> Result<(TLSSocket<()>, TLSSocket<()>)> {
+    let (client, server) = new_test_pair(memory_socket_pair(), config, (), ())?;
+    handshake(&client, &server)?;
+    Ok((client, server))
+}
+
+// The sockets `test_pair` would make over `files` (client first, which
+// should be connected and non-blocking), before the handshake, for
+// setting up anything more first.
+pub fn new_test_pair<C, S>(
+    files: (File, File),
+    config: &TLSConfig,
+    client_callbacks: C,
+    server_callbacks: S,
+) -> Result<(TLSSocket<C>, TLSSocket<S>)> {
+    init()?;
+    let (client_file, server_file) = files;
+
+    let (public, key) = generate_ec_keypair(EcCurve::P256)?;
+    let cert = generate_self_signed(
+        "CN=localhost",
+        &key,
+        &public,
+        &[GeneralName::Dns("localhost".to_owned())],
+        Duration::from_secs(24 * 60 * 60),
+    )?;
+    let mut server = TLSSocket::new(server_file, server_callbacks)?;
+    // There's no session cache unless the caller's config set one up.
+    server.set_option(SSL_NO_CACHE, true)?;
+    config.apply(&mut server)?;
+    server.config_server_cert(&cert, &key, &[])?;
+    server.reset_handshake(true)?;
+
+    let mut client = TLSSocket::new(client_file, client_callbacks)?;
+    config.apply(&mut client)?;
+    if client.url()?.is_none() {
+        client.set_url(CStr::from_bytes_with_nul(b"localhost\0").unwrap())?;
+    }
+    client.disable_security()?;
+    client.reset_handshake(false)?;
+    Ok((client, server))
+}
+
+// Takes turns at the handshake on both ends of a `new_test_pair`; the
+// first end to fail, fails it.  An end that's done is left alone,
+// since forcing a finished handshake reads (and would block).
+pub fn handshake<C, S>(client: &TLSSocket<C>, server: &TLSSocket<S>) -> Result<()> {
+    let (mut client_done, mut server_done) = (false, false);
+    for _ in 0..MAX_HANDSHAKE_ROUNDS {
+        client_done = client_done || handshake_step(client)?;
+        server_done = server_done || handshake_step(server)?;
+        if client_done && server_done {
+            return Ok(());
+        }
+    }
+    Err(PR_WOULD_BLOCK_ERROR.into())
+}
+
+fn handshake_step<Callbacks>(sock: &TLSSocket<Callbacks>) -> Result<bool> {
+    match sock.force_handshake() {
+        Ok(()) => Ok(true),
+        Err(err) if err.nspr_error == PR_WOULD_BLOCK_ERROR => Ok(false),
+        Err(err) => Err(err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let (client, server) = test_pair(&TLSConfig::new()).unwrap();
+        let mut buf = [0; 16];
+        assert_eq!(
+            server.read(&mut buf).unwrap_err().nspr_error,
+            PR_WOULD_BLOCK_ERROR
+        );
+        assert_eq!(client.write(b"ping").unwrap(), 4);
+        assert_eq!(server.read(&mut buf).unwrap(), 4);
+        assert_eq!(&buf[..4], b"ping");
+        assert_eq!(server.write(b"pong").unwrap(), 4);
+        assert_eq!(client.read(&mut buf).unwrap(), 4);
+        assert_eq!(&buf[..4], b"pong");
+
+        drop(client);
+        assert_eq!(server.read(&mut buf).unwrap(), 0);
+    }
+}
@@ -0,0 +1,177 @@
+#!/bin/sh
+# Regenerates the certificates and keys used by the unit tests.  The
+# outputs are checked in, so this only needs to be rerun if the set of
+# test certificates changes.
+set -e
+cd "$(dirname "$0")"
+DAYS=36500
+
+cat > ext.cnf <<CNF
+[ca]
+basicConstraints = critical, CA:TRUE
+keyUsage = critical, keyCertSign, cRLSign
+subjectKeyIdentifier = hash
+
+[intermediate]
+basicConstraints = critical, CA:TRUE, pathlen:0
+keyUsage = critical, keyCertSign, cRLSign
+subjectKeyIdentifier = hash
+authorityKeyIdentifier = keyid
+
+[leaf]
+basicConstraints = critical, CA:FALSE
+keyUsage = critical, digitalSignature, keyEncipherment
+extendedKeyUsage = serverAuth
+subjectAltName = DNS:localhost, IP:127.0.0.1
+certificatePolicies = 2.23.140.1.2.2
+subjectKeyIdentifier = hash
+authorityKeyIdentifier = keyid
+
+[ev-leaf]
+basicConstraints = critical, CA:FALSE
+keyUsage = critical, digitalSignature, keyEncipherment
+extendedKeyUsage = serverAuth
+subjectAltName = DNS:ev.localhost
+certificatePolicies = 2.23.140.1.1
+subjectKeyIdentifier = hash
+authorityKeyIdentifier = keyid
+
+[client]
+basicConstraints = critical, CA:FALSE
+keyUsage = critical, digitalSignature
+extendedKeyUsage = clientAuth
+subjectKeyIdentifier = hash
+authorityKeyIdentifier = keyid
+CNF
+
+openssl req -x509 -newkey rsa:2048 -nodes -sha256 -days $DAYS \
+    -keyout root-ca.key -out root-ca.pem \
+    -subj "/C=US/O=nss-rs Test/CN=nss-rs Test Root CA" \
+    -extensions ca -config ext.cnf
+
+issue() {
+    name=$1; issuer=$2; ext=$3; subj=$4; md=${5:-sha256}
+    openssl req -newkey rsa:2048 -nodes -keyout $name.key -out $name.csr -subj "$subj"
+    openssl x509 -req -$md -days $DAYS -in $name.csr \
+        -CA $issuer.pem -CAkey $issuer.key -set_serial 0x$(openssl rand -hex 8) \
+        -extfile ext.cnf -extensions $ext -out $name.pem
+    rm $name.csr
+}
+
+issue intermediate root-ca intermediate "/C=US/O=nss-rs Test/CN=nss-rs Test Intermediate CA"
+issue leaf intermediate leaf "/C=CA/ST=Ontario/L=Toronto/O=nss-rs Test/OU=Servers/CN=localhost"
+issue revoked intermediate leaf \
+    "/C=CA/ST=Ontario/L=Toronto/O=nss-rs Test/OU=Servers/CN=revoked.localhost"
+# Same as the leaf, but with a SHA-1 signature, for algorithm policy.
+issue sha1-leaf intermediate leaf \
+    "/C=CA/ST=Ontario/L=Toronto/O=nss-rs Test/OU=Servers/CN=sha1.localhost" sha1
+openssl x509 -in sha1-leaf.pem -outform DER -out sha1-leaf.der
+rm sha1-leaf.pem sha1-leaf.key
+# Same as the leaf, but with the CA/Browser Forum's EV policy.
+issue ev-leaf intermediate ev-leaf \
+    "/C=CA/ST=Ontario/L=Toronto/O=nss-rs Test/OU=Servers/CN=ev.localhost"
+openssl x509 -in ev-leaf.pem -outform DER -out ev-leaf.der
+rm ev-leaf.pem ev-leaf.key
+
+# A TLS client certificate, for usage checks.
+issue client intermediate client \
+    "/C=CA/ST=Ontario/L=Toronto/O=nss-rs Test/OU=Clients/CN=client"
+openssl x509 -in client.pem -outform DER -out client.der
+rm client.pem client.key
+
+# An intermediate that expires a day after it's issued, and a leaf
+# from it that lasts as long as the others, for tests that need an
+# intermediate no other test loads.
+openssl req -newkey rsa:2048 -nodes -keyout short-intermediate.key -out short-intermediate.csr \
+    -subj "/C=US/O=nss-rs Test/CN=nss-rs Test Short-Lived Intermediate CA"
+openssl x509 -req -sha256 -days 1 -in short-intermediate.csr \
+    -CA root-ca.pem -CAkey root-ca.key -set_serial 0x$(openssl rand -hex 8) \
+    -extfile ext.cnf -extensions intermediate -out short-intermediate.pem
+rm short-intermediate.csr
+issue short-leaf short-intermediate leaf \
+    "/C=CA/ST=Ontario/L=Toronto/O=nss-rs Test/OU=Servers/CN=short.localhost"
+for name in short-intermediate short-leaf; do
+    openssl x509 -in $name.pem -outform DER -out $name.der
+    rm $name.pem $name.key
+done
+# The same again, for checking chains after the intermediate has
+# expired; libpkix remembers the chains it builds, so this can't share
+# the one above.
+openssl req -newkey rsa:2048 -nodes -keyout expired-intermediate.key \
+    -out expired-intermediate.csr \
+    -subj "/C=US/O=nss-rs Test/CN=nss-rs Test Expired Intermediate CA"
+openssl x509 -req -sha256 -days 1 -in expired-intermediate.csr \
+    -CA root-ca.pem -CAkey root-ca.key -set_serial 0x$(openssl rand -hex 8) \
+    -extfile ext.cnf -extensions intermediate -out expired-intermediate.pem
+rm expired-intermediate.csr
+issue expired-leaf expired-intermediate leaf \
+    "/C=CA/ST=Ontario/L=Toronto/O=nss-rs Test/OU=Servers/CN=expired.localhost"
+for name in expired-intermediate expired-leaf; do
+    openssl x509 -in $name.pem -outform DER -out $name.der
+    rm $name.pem $name.key
+done
+
+# A separate root and leaf, for importing the root into a cert DB
+# without affecting the other tests' chains.
+openssl req -x509 -newkey rsa:2048 -nodes -sha256 -days $DAYS \
+    -keyout import-root.key -out import-root.pem \
+    -subj "/C=US/O=nss-rs Test/CN=nss-rs Test Import Root CA" \
+    -extensions ca -config ext.cnf
+issue import-leaf import-root leaf \
+    "/C=CA/ST=Ontario/L=Toronto/O=nss-rs Test/OU=Servers/CN=import.localhost"
+for name in import-root import-leaf; do
+    openssl x509 -in $name.pem -outform DER -out $name.der
+    rm $name.pem $name.key
+done
+
+cat leaf.pem intermediate.pem root-ca.pem > chain.pem
+
+# OCSP responses for the leaf, signed directly by its issuer.
+serial=$(openssl x509 -in leaf.pem -noout -serial | cut -d= -f2)
+subject="/C=CA/ST=Ontario/L=Toronto/O=nss-rs Test/OU=Servers/CN=localhost"
+printf 'V\t21260101000000Z\t\t%s\tunknown\t%s\n' $serial "$subject" > good.txt
+printf 'R\t21260101000000Z\t240101000000Z,keyCompromise\t%s\tunknown\t%s\n' \
+    $serial "$subject" > revoked.txt
+: > unknown.txt
+for status in good revoked unknown; do
+    openssl ocsp -index $status.txt -CA intermediate.pem \
+        -rsigner intermediate.pem -rkey intermediate.key \
+        -issuer intermediate.pem -cert leaf.pem -ndays $DAYS \
+        -respout leaf-ocsp-$status.der
+    rm $status.txt
+done
+# A CRL from the intermediate that revokes only the "revoked" leaf, so
+# importing it doesn't affect tests using the other one.
+cat > crl.cnf <<CNF
+[ca]
+default_ca = crl
+[crl]
+database = crl-index.txt
+default_md = sha256
+crl_extensions = crl_ext
+[crl_ext]
+authorityKeyIdentifier = keyid
+CNF
+serial=$(openssl x509 -in revoked.pem -noout -serial | cut -d= -f2)
+subject="/C=CA/ST=Ontario/L=Toronto/O=nss-rs Test/OU=Servers/CN=revoked.localhost"
+printf 'R\t21260101000000Z\t240101000000Z,keyCompromise\t%s\tunknown\t%s\n' \
+    $serial "$subject" > crl-index.txt
+openssl ca -gencrl -config crl.cnf -cert intermediate.pem -keyfile intermediate.key \
+    -crldays $DAYS -out intermediate.crl
+openssl crl -in intermediate.crl -outform DER -out intermediate.crl.der
+rm crl.cnf crl-index.txt* intermediate.crl
+openssl x509 -in revoked.pem -outform DER -out revoked.der
+rm revoked.pem
+
+# A request made by OpenSSL, with each kind of name that
+# cert::CertRequest decodes.
+openssl req -new -key leaf.key -subj "/CN=csr.example/O=Example" \
+    -addext "subjectAltName=DNS:csr.example,IP:127.0.0.1,IP:::1,email:admin@csr.example,URI:https://csr.example/" \
+    -outform DER -out csr.der
+
+for name in root-ca intermediate leaf; do
+    openssl pkcs8 -topk8 -nocrypt -in $name.key -outform DER -out $name.key.der
+    openssl x509 -in $name.pem -outform DER -out $name.der
+done
+rm *.key
+rm ext.cnf